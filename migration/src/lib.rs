@@ -11,6 +11,20 @@ mod m20220908_204553_create_clients;
 mod m20220913_213320_create_certificates;
 mod m20220914_000156_add_certificates_proxy;
 mod m20220914_000706_add_certificates_client;
+mod m20220920_192410_add_zones_delegation_check;
+mod m20220923_201532_create_dnssec_keys;
+mod m20220923_201845_add_zones_dnssec;
+mod m20220925_174208_create_change_sequence;
+mod m20220926_191537_create_change_journal;
+mod m20220928_154012_allow_certificates_without_key;
+mod m20220928_154530_create_join_tokens;
+mod m20221001_143207_add_certificates_revocation;
+mod m20221003_190841_add_certificates_retiring;
+mod m20221005_172214_create_acme_accounts;
+mod m20221005_172530_add_certificates_chain;
+mod m20221007_091530_add_proxies_upstreams;
+mod m20221009_143052_create_certificate_history;
+mod m20221009_171204_add_certificates_fetched;
 
 pub struct Migrator;
 
@@ -29,6 +43,20 @@ impl MigratorTrait for Migrator {
             Box::new(m20220913_213320_create_certificates::Migration),
             Box::new(m20220914_000156_add_certificates_proxy::Migration),
             Box::new(m20220914_000706_add_certificates_client::Migration),
+            Box::new(m20220920_192410_add_zones_delegation_check::Migration),
+            Box::new(m20220923_201532_create_dnssec_keys::Migration),
+            Box::new(m20220923_201845_add_zones_dnssec::Migration),
+            Box::new(m20220925_174208_create_change_sequence::Migration),
+            Box::new(m20220926_191537_create_change_journal::Migration),
+            Box::new(m20220928_154012_allow_certificates_without_key::Migration),
+            Box::new(m20220928_154530_create_join_tokens::Migration),
+            Box::new(m20221001_143207_add_certificates_revocation::Migration),
+            Box::new(m20221003_190841_add_certificates_retiring::Migration),
+            Box::new(m20221005_172214_create_acme_accounts::Migration),
+            Box::new(m20221005_172530_add_certificates_chain::Migration),
+            Box::new(m20221007_091530_add_proxies_upstreams::Migration),
+            Box::new(m20221009_143052_create_certificate_history::Migration),
+            Box::new(m20221009_171204_add_certificates_fetched::Migration),
         ]
     }
 }
@@ -0,0 +1,29 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220907_223637_create_zones::Zone;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220920_192410_add_zones_delegation_check"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Zone::Table)
+                    .add_column(ColumnDef::new(Alias::new("delegation_checked"))
+                        .timestamp()
+                    )
+                    .add_column(ColumnDef::new(Alias::new("delegation_error"))
+                        .string()
+                    )
+                    .to_owned()
+            )
+            .await
+    }
+}
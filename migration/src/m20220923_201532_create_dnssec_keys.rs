@@ -0,0 +1,99 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220907_223637_create_zones::Zone;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220923_201532_create_dnssec_keys"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DnssecKey::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(DnssecKey::Id)
+                        .string()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Zone)
+                        .string()
+                        .not_null()
+                    )
+                    .foreign_key(ForeignKey::create()
+                        .name("fk-dnssec_key-zone-id")
+                        .from(DnssecKey::Table, DnssecKey::Zone)
+                        .to(Zone::Table, Zone::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .col(ColumnDef::new(DnssecKey::KeyType)
+                        .string()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Algorithm)
+                        .small_integer()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::KeyTag)
+                        .integer()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Data)
+                        .binary()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Key)
+                        .binary()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Nonce)
+                        .binary()
+                        .not_null()
+                        .unique_key()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Status)
+                        .string()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::Created)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(DnssecKey::StatusChanged)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DnssecKey::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum DnssecKey {
+    Table,
+    Id,
+    Zone,
+    KeyType,
+    Algorithm,
+    KeyTag,
+    Data,
+    Key,
+    Nonce,
+    Status,
+    Created,
+    StatusChanged
+}
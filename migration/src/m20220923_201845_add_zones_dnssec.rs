@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220907_223637_create_zones::Zone;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220923_201845_add_zones_dnssec"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Zone::Table)
+                    .add_column(ColumnDef::new(Alias::new("dnssec"))
+                        .boolean()
+                        .not_null()
+                        .default(false)
+                    )
+                    .to_owned()
+            )
+            .await
+    }
+}
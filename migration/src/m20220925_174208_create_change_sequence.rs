@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220925_174208_create_change_sequence"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChangeSequence::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ChangeSequence::Id)
+                        .integer()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(ChangeSequence::Sequence)
+                        .big_integer()
+                        .not_null()
+                        .default(0)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The table only ever holds this one row, which change events count up from
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(ChangeSequence::Table)
+                    .columns([ChangeSequence::Id, ChangeSequence::Sequence])
+                    .values_panic([1.into(), 0.into()])
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChangeSequence::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum ChangeSequence {
+    Table,
+    Id,
+    Sequence
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220926_191537_create_change_journal"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Entries outlive their zones, so clients can still be told about deletions
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChangeJournal::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ChangeJournal::Sequence)
+                        .big_integer()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(ChangeJournal::Zone)
+                        .string()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(ChangeJournal::Data)
+                        .binary()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(ChangeJournal::Created)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChangeJournal::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum ChangeJournal {
+    Table,
+    Sequence,
+    Zone,
+    Data,
+    Created
+}
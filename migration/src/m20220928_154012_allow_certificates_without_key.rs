@@ -0,0 +1,32 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220913_213320_create_certificates::Certificate;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220928_154012_allow_certificates_without_key"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Certificates issued from a CSR never have their private key leave the requester
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Certificate::Table)
+                    .modify_column(ColumnDef::new(Certificate::Key)
+                        .binary()
+                        .null()
+                    )
+                    .modify_column(ColumnDef::new(Certificate::Nonce)
+                        .binary()
+                        .null()
+                    )
+                    .to_owned()
+            )
+            .await
+    }
+}
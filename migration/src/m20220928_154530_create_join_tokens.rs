@@ -0,0 +1,99 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220907_223615_create_users::User;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220928_154530_create_join_tokens"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(JoinToken::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(JoinToken::Id)
+                        .string()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(JoinToken::TokenHash)
+                        .string()
+                        .not_null()
+                        .unique_key()
+                    )
+                    .col(ColumnDef::new(JoinToken::Name)
+                        .string()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(JoinToken::Dns)
+                        .boolean()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(JoinToken::Proxy)
+                        .boolean()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(JoinToken::CreatedBy)
+                        .string()
+                        .not_null()
+                    )
+                    .foreign_key(ForeignKey::create()
+                        .name("fk-join_token-created_by-id")
+                        .from(JoinToken::Table, JoinToken::CreatedBy)
+                        .to(User::Table, User::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .col(ColumnDef::new(JoinToken::Created)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(JoinToken::Expiry)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(JoinToken::Used)
+                        .timestamp()
+                        .null()
+                    )
+                    .col(ColumnDef::new(JoinToken::UsedIp)
+                        .string()
+                        .null()
+                    )
+                    .col(ColumnDef::new(JoinToken::Client)
+                        .string()
+                        .null()
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(JoinToken::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum JoinToken {
+    Table,
+    Id,
+    TokenHash,
+    Name,
+    Dns,
+    Proxy,
+    CreatedBy,
+    Created,
+    Expiry,
+    Used,
+    UsedIp,
+    Client
+}
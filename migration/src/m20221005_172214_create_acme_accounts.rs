@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221005_172214_create_acme_accounts"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AcmeAccount::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(AcmeAccount::Id)
+                        .string()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(AcmeAccount::Directory)
+                        .string()
+                        .not_null()
+                        .unique_key()
+                    )
+                    .col(ColumnDef::new(AcmeAccount::Url)
+                        .string()
+                    )
+                    .col(ColumnDef::new(AcmeAccount::Key)
+                        .binary()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(AcmeAccount::Nonce)
+                        .binary()
+                        .not_null()
+                        .unique_key()
+                    )
+                    .col(ColumnDef::new(AcmeAccount::Created)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AcmeAccount::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum AcmeAccount {
+    Table,
+    Id,
+    Directory,
+    Url,
+    Key,
+    Nonce,
+    Created
+}
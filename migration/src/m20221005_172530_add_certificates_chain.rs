@@ -0,0 +1,26 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220913_213320_create_certificates::Certificate;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221005_172530_add_certificates_chain"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Certificate::Table)
+                    .add_column(ColumnDef::new(Alias::new("chain"))
+                        .binary()
+                    )
+                    .to_owned()
+            )
+            .await
+    }
+}
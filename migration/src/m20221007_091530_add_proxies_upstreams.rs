@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220907_223653_create_proxies::Proxy;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221007_091530_add_proxies_upstreams"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Proxy::Table)
+                    .add_column(ColumnDef::new(Alias::new("upstreams"))
+                        .string()
+                        .not_null()
+                        .default("")
+                    )
+                    .to_owned()
+            )
+            .await
+    }
+}
@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+use crate::m20220913_213320_create_certificates::Certificate;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221009_143052_create_certificate_history"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Certificates replaced by a renewal are kept until they expire, so they can still be revoked
+        manager
+            .create_table(
+                Table::create()
+                    .table(CertificateHistory::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(CertificateHistory::Id)
+                        .string()
+                        .not_null()
+                        .primary_key()
+                    )
+                    .col(ColumnDef::new(CertificateHistory::Certificate)
+                        .string()
+                        .not_null()
+                    )
+                    .foreign_key(ForeignKey::create()
+                        .name("fk-certificate_history-certificate-id")
+                        .from(CertificateHistory::Table, CertificateHistory::Certificate)
+                        .to(Certificate::Table, Certificate::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .col(ColumnDef::new(CertificateHistory::Data)
+                        .binary()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(CertificateHistory::NotAfter)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .col(ColumnDef::new(CertificateHistory::Superseded)
+                        .timestamp()
+                        .not_null()
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CertificateHistory::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+pub enum CertificateHistory {
+    Table,
+    Id,
+    Certificate,
+    Data,
+    NotAfter,
+    Superseded
+}
@@ -0,0 +1,1075 @@
+use std::{env, fmt, fs, io};
+use std::fmt::Formatter;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64ct::{Base64UrlUnpadded, Encoding};
+use chrono::Utc;
+use hyper::{Body, Method, Request, StatusCode, Uri};
+use hyper::client::conn;
+use lapin::Channel;
+use picky::key::KeyError;
+use picky::signature::SignatureError;
+use picky::x509::Cert;
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use sea_orm::*;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tokio_rustls::rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use ulid::Ulid;
+use zeroize::Zeroizing;
+
+use crate::cert::algorithm::{KeyAlgorithm, SigningKey};
+use crate::cert::{decrypt_priv_key, encrypt_priv_key, Types};
+use crate::cert::der::*;
+use crate::cert::generate::dns_names;
+use crate::cert::renewal::{not_after, publish_renewal, renewal_threshold};
+use crate::entities::{acme_account, certificate};
+use crate::entities::prelude::AcmeAccount;
+
+/// How often ACME certificates are checked for renewal
+const RENEWAL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How long to wait for the CA to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait between checks on an authorization or order the CA is still working on
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+/// How many times a request rejected for its nonce is sent again
+const NONCE_ATTEMPTS: usize = 3;
+const BAD_NONCE: &str = "urn:ietf:params:acme:error:badNonce";
+const USER_AGENT: &str = concat!("driptorch-controller/", env!("CARGO_PKG_VERSION"));
+
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_EXTENSION_REQUEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+/// Longest common name X.520 allows, longer names are only put in the subject alternative names
+const COMMON_NAME_LENGTH: usize = 64;
+
+/// Where publicly trusted certificates are ordered from, set with ACME_DIRECTORY and friends
+#[derive(Clone)]
+pub struct AcmeSettings {
+    pub directory_url: String,
+    /// Email address the CA can reach us on about the account
+    pub contact: Option<String>,
+    /// An extra root to trust the directory's TLS certificate with, such as Pebble's
+    pub ca_cert: Option<PathBuf>,
+    /// How long challenge records are given to reach every nameserver before the CA checks them
+    pub propagation: Duration
+}
+
+impl AcmeSettings {
+    /// Returns None when ACME_DIRECTORY isn't set, in which case no ACME certificates are ordered
+    pub fn from_env() -> Option<AcmeSettings> {
+        let directory_url = env::var("ACME_DIRECTORY").ok()?;
+
+        let propagation = env::var("ACME_PROPAGATION_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .unwrap_or(10);
+
+        Some(AcmeSettings {
+            directory_url,
+            contact: env::var("ACME_CONTACT").ok(),
+            ca_cert: env::var("ACME_CA_CERT").ok().map(PathBuf::from),
+            propagation: Duration::from_secs(propagation)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum AcmeError {
+    /// The CA couldn't be reached, or didn't answer over HTTP
+    Http(String),
+    /// The CA answered with an RFC 7807 problem document
+    Problem { problem_type: String, detail: String },
+    /// The CA answered with something RFC 8555 doesn't allow
+    Protocol(String),
+    /// A challenge record couldn't be published
+    Challenge(String),
+    /// An authorization or order was found invalid, or never finished
+    Failed(String),
+    Database(DbErr),
+    Key(KeyError),
+    Signing(SignatureError)
+}
+
+impl fmt::Display for AcmeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AcmeError::Http(err) => write!(f, "Failed to reach the ACME CA: {}", err),
+            AcmeError::Problem { problem_type, detail } => write!(f, "The ACME CA refused the request ({}): {}", problem_type, detail),
+            AcmeError::Protocol(err) => write!(f, "The ACME CA sent an invalid response: {}", err),
+            AcmeError::Challenge(err) => write!(f, "Failed to publish a DNS-01 challenge: {}", err),
+            AcmeError::Failed(err) => write!(f, "{}", err),
+            AcmeError::Database(err) => write!(f, "Database error: {}", err),
+            AcmeError::Key(err) => write!(f, "Key error: {}", err),
+            AcmeError::Signing(err) => write!(f, "Failed to sign: {}", err)
+        }
+    }
+}
+
+impl From<DbErr> for AcmeError {
+    fn from(err: DbErr) -> Self {
+        AcmeError::Database(err)
+    }
+}
+
+impl From<KeyError> for AcmeError {
+    fn from(err: KeyError) -> Self {
+        AcmeError::Key(err)
+    }
+}
+
+impl From<SignatureError> for AcmeError {
+    fn from(err: SignatureError) -> Self {
+        AcmeError::Signing(err)
+    }
+}
+
+fn http_error(err: impl fmt::Display) -> AcmeError {
+    AcmeError::Http(err.to_string())
+}
+
+fn base64url(data: &[u8]) -> String {
+    Base64UrlUnpadded::encode_string(data)
+}
+
+/// Publishes the TXT records DNS-01 challenges are answered with
+///
+/// This is a trait so the client can be pointed at a stand-in CA without touching any zones.
+#[async_trait]
+pub trait ChallengeSolver: Send + Sync {
+    /// Publishes `value` as a TXT record at `_acme-challenge.` prefixed to `name`, returning an ID to
+    /// remove it with
+    async fn present(&self, name: &str, value: &str) -> Result<String, String>;
+
+    /// Removes a record published by `present`
+    async fn clean_up(&self, id: &str) -> Result<(), String>;
+}
+
+pub type SharedSolver = Arc<dyn ChallengeSolver>;
+
+/// What proxies order their certificates with as they're created, shared with the routes
+pub struct AcmeIssuer {
+    pub settings: AcmeSettings,
+    pub solver: SharedSolver
+}
+
+/// None when ACME_DIRECTORY isn't set, in which case proxies keep certificates from our intermediate
+pub type SharedAcmeIssuer = Option<Arc<AcmeIssuer>>;
+
+/// The P-256 key requests to the CA are signed with, which identifies our account
+pub struct AccountKey {
+    pkcs8: Zeroizing<Vec<u8>>,
+    key_pair: EcdsaKeyPair
+}
+
+impl AccountKey {
+    pub fn generate() -> Result<AccountKey, KeyError> {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+            .map_err(|_| KeyError::EC { context: "failed to generate an ACME account key".to_string() })?;
+
+        AccountKey::from_pkcs8(pkcs8.as_ref())
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<AccountKey, KeyError> {
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8)
+            .map_err(|_| KeyError::EC { context: "ACME account keys must be P-256".to_string() })?;
+
+        Ok(AccountKey { pkcs8: Zeroizing::new(pkcs8.to_vec()), key_pair })
+    }
+
+    pub fn to_pkcs8(&self) -> Vec<u8> {
+        self.pkcs8.to_vec()
+    }
+
+    /// The public key as a JWK, with its members in the order RFC 7638 thumbprints are taken in
+    fn jwk(&self) -> String {
+        // An uncompressed point, 0x04 followed by the X and Y coordinates
+        let point = self.key_pair.public_key().as_ref();
+
+        format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, base64url(&point[1..33]), base64url(&point[33..65]))
+    }
+
+    fn thumbprint(&self) -> String {
+        base64url(digest(&SHA256, self.jwk().as_bytes()).as_ref())
+    }
+
+    /// The TXT record value answering a DNS-01 challenge's token, see RFC 8555 section 8.4
+    pub fn dns01_value(&self, token: &str) -> String {
+        base64url(digest(&SHA256, format!("{}.{}", token, self.thumbprint()).as_bytes()).as_ref())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AcmeError> {
+        self.key_pair.sign(&SystemRandom::new(), message)
+            .map(|signature| signature.as_ref().to_vec())
+            .map_err(|_| AcmeError::Key(KeyError::EC { context: "failed to sign an ACME request".to_string() }))
+    }
+}
+
+/// Encodes a PKCS#10 request for a certificate naming every one of `names`
+fn build_csr(names: &[String], key: &SigningKey) -> Result<Vec<u8>, AcmeError> {
+    let subject = match names.iter().find(|name| name.len() <= COMMON_NAME_LENGTH) {
+        Some(common_name) => sequence(&[set(&[sequence(&[oid(OID_COMMON_NAME), utf8_string(common_name)])])]),
+        None => sequence(&[])
+    };
+
+    let alt_names: Vec<Vec<u8>> = names.iter()
+        .map(|name| encode(context_primitive(2), name.as_bytes()))
+        .collect();
+    let extensions = sequence(&[sequence(&[oid(OID_SUBJECT_ALT_NAME), octet_string(&sequence(&alt_names))])]);
+    let attributes = encode(context_constructed(0), &sequence(&[oid(OID_EXTENSION_REQUEST), set(&[extensions])]));
+
+    let request_info = sequence(&[integer(0), subject, key.public_key().to_der()?, attributes]);
+    let signature = key.sign(&request_info)?;
+
+    Ok(sequence(&[request_info, key.signature_algorithm(), bit_string(&signature)]))
+}
+
+struct HttpResponse {
+    status: StatusCode,
+    location: Option<String>,
+    nonce: Option<String>,
+    body: Vec<u8>
+}
+
+impl HttpResponse {
+    fn json<T: DeserializeOwned>(&self) -> Result<T, AcmeError> {
+        serde_json::from_slice(&self.body).map_err(|err| AcmeError::Protocol(err.to_string()))
+    }
+
+    fn problem(&self) -> AcmeError {
+        match self.json::<Problem>() {
+            Ok(problem) => AcmeError::Problem { problem_type: problem.problem_type, detail: problem.detail },
+            Err(_) => AcmeError::Http(format!("Unexpected {} response", self.status))
+        }
+    }
+}
+
+async fn send<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(io: T, request: Request<Body>) -> Result<HttpResponse, AcmeError> {
+    let (mut sender, connection) = conn::handshake(io).await.map_err(http_error)?;
+
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            debug!("ACME connection closed with an error: {}", err);
+        }
+    });
+
+    let response = sender.send_request(request).await.map_err(http_error)?;
+    let header = |name: &str| response.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let (status, location, nonce) = (response.status(), header("location"), header("replay-nonce"));
+    let body = hyper::body::to_bytes(response.into_body()).await.map_err(http_error)?;
+
+    Ok(HttpResponse { status, location, nonce, body: body.to_vec() })
+}
+
+/// Sends one request per connection, as only a handful are made for each order
+struct Transport {
+    tls: TlsConnector
+}
+
+impl Transport {
+    /// Trusts the usual web PKI roots, plus `ca_cert` when given
+    fn new(ca_cert: Option<&Path>) -> io::Result<Transport> {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+        }));
+
+        if let Some(ca_cert) = ca_cert {
+            for cert in rustls_pemfile::certs(&mut BufReader::new(fs::File::open(ca_cert)?))? {
+                roots.add(&Certificate(cert))
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+            }
+        }
+
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Ok(Transport { tls: TlsConnector::from(Arc::new(config)) })
+    }
+
+    /// Plain HTTP is only meant for stand-in CAs, as RFC 8555 requires HTTPS
+    async fn request(&self, method: Method, url: &str, body: Option<Vec<u8>>) -> Result<HttpResponse, AcmeError> {
+        let uri: Uri = url.parse().map_err(|_| AcmeError::Protocol(format!("{} isn't a URL", url)))?;
+        let https = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(AcmeError::Protocol(format!("{} isn't an HTTP URL", url)))
+        };
+
+        let authority = uri.authority().ok_or_else(|| AcmeError::Protocol(format!("{} has no host", url)))?;
+        let host = authority.host().trim_start_matches('[').trim_end_matches(']').to_string();
+        let port = authority.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri.path_and_query().map_or("/", |path| path.as_str()))
+            .header("host", authority.as_str())
+            .header("user-agent", USER_AGENT);
+
+        let request = match body {
+            Some(body) => builder.header("content-type", "application/jose+json").body(Body::from(body)),
+            None => builder.body(Body::empty())
+        }.map_err(http_error)?;
+
+        let exchange = async {
+            let stream = TcpStream::connect((host.as_str(), port)).await.map_err(http_error)?;
+
+            if https {
+                let server_name = ServerName::try_from(host.as_str()).map_err(http_error)?;
+                send(self.tls.connect(server_name, stream).await.map_err(http_error)?, request).await
+            } else {
+                send(stream, request).await
+            }
+        };
+
+        timeout(REQUEST_TIMEOUT, exchange)
+            .await
+            .map_err(|_| AcmeError::Http(format!("{} didn't answer in time", host)))?
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String
+}
+
+#[derive(Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    problem_type: String,
+    #[serde(default)]
+    detail: String
+}
+
+#[derive(Deserialize)]
+struct Identifier {
+    value: String
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    challenge_type: String,
+    url: String,
+    token: Option<String>,
+    error: Option<Problem>
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    identifier: Identifier,
+    status: String,
+    challenges: Vec<Challenge>
+}
+
+#[derive(Deserialize)]
+struct Order {
+    status: String,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>
+}
+
+/// An ACME resource the CA works on in the background
+trait Pending {
+    fn status(&self) -> &str;
+}
+
+impl Pending for Authorization {
+    fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+impl Pending for Order {
+    fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+/// A client for an RFC 8555 CA, registered with an account key
+pub struct AcmeClient {
+    transport: Transport,
+    directory: Directory,
+    key: AccountKey,
+    account_url: Option<String>,
+    propagation: Duration,
+    /// The nonce handed out with the last response, which saves asking for one
+    nonce: Mutex<Option<String>>
+}
+
+impl AcmeClient {
+    /// Fetches the CA's directory and registers the account key, which finds the existing account
+    /// when the key already has one
+    pub async fn connect(settings: &AcmeSettings, key: AccountKey) -> Result<AcmeClient, AcmeError> {
+        let transport = Transport::new(settings.ca_cert.as_deref()).map_err(http_error)?;
+
+        let response = transport.request(Method::GET, &settings.directory_url, None).await?;
+        if !response.status.is_success() {
+            return Err(response.problem());
+        }
+
+        let mut client = AcmeClient {
+            transport,
+            directory: response.json()?,
+            key,
+            account_url: None,
+            propagation: settings.propagation,
+            nonce: Mutex::new(None)
+        };
+
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(contact) = &settings.contact {
+            account["contact"] = json!([format!("mailto:{}", contact)]);
+        }
+
+        let new_account = client.directory.new_account.clone();
+        let response = client.post(&new_account, Some(&account)).await?;
+
+        client.account_url = Some(response.location.ok_or_else(|| AcmeError::Protocol("No account URL was given".to_string()))?);
+
+        Ok(client)
+    }
+
+    pub fn account_url(&self) -> &str {
+        self.account_url.as_deref().unwrap_or_default()
+    }
+
+    async fn take_nonce(&self) -> Result<String, AcmeError> {
+        if let Some(nonce) = self.nonce.lock().await.take() {
+            return Ok(nonce);
+        }
+
+        self.transport.request(Method::HEAD, &self.directory.new_nonce, None)
+            .await?
+            .nonce
+            .ok_or_else(|| AcmeError::Protocol("No nonce was given".to_string()))
+    }
+
+    /// Sends a JWS signed request, where a missing payload makes it a POST-as-GET
+    ///
+    /// CAs can reject any nonce, and Pebble does so on purpose, so requests failing with badNonce
+    /// are sent again with the nonce handed back.
+    async fn post(&self, url: &str, payload: Option<&Value>) -> Result<HttpResponse, AcmeError> {
+        let payload = payload.map_or_else(String::new, |payload| base64url(payload.to_string().as_bytes()));
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let mut protected = json!({ "alg": "ES256", "nonce": self.take_nonce().await?, "url": url });
+            match &self.account_url {
+                Some(account_url) => protected["kid"] = json!(account_url),
+                None => protected["jwk"] = serde_json::from_str(&self.key.jwk()).expect("Failed to encode JWK!")
+            }
+
+            let protected = base64url(protected.to_string().as_bytes());
+            let signature = self.key.sign(format!("{}.{}", protected, payload).as_bytes())?;
+            let body = json!({ "protected": protected, "payload": payload, "signature": base64url(&signature) });
+
+            let response = self.transport.request(Method::POST, url, Some(body.to_string().into_bytes())).await?;
+
+            if let Some(nonce) = &response.nonce {
+                *self.nonce.lock().await = Some(nonce.clone());
+            }
+
+            if response.status.is_success() {
+                return Ok(response);
+            }
+
+            match response.problem() {
+                AcmeError::Problem { problem_type, .. } if problem_type == BAD_NONCE && attempts < NONCE_ATTEMPTS => continue,
+                err => return Err(err)
+            }
+        }
+    }
+
+    /// Fetches an authorization or order until the CA has finished with it
+    async fn poll<T: DeserializeOwned + Pending>(&self, url: &str) -> Result<T, AcmeError> {
+        for _ in 0..POLL_ATTEMPTS {
+            let resource: T = self.post(url, None).await?.json()?;
+
+            if resource.status() != "pending" && resource.status() != "processing" {
+                return Ok(resource);
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        Err(AcmeError::Failed(format!("The ACME CA didn't finish with {} in time", url)))
+    }
+
+    /// Publishes the DNS-01 records of every pending authorization, then asks for them to be checked
+    ///
+    /// Published records are added to `presented` as they go, so they can be removed however this ends.
+    async fn answer_challenges(&self, pending: &[Authorization], authorization_urls: &[&String], solver: &dyn ChallengeSolver, presented: &mut Vec<String>) -> Result<(), AcmeError> {
+        let mut challenge_urls = vec![];
+
+        for authorization in pending {
+            let name = &authorization.identifier.value;
+            let (challenge_url, token) = authorization.challenges.iter()
+                .find(|challenge| challenge.challenge_type == "dns-01")
+                .and_then(|challenge| challenge.token.as_ref().map(|token| (&challenge.url, token)))
+                .ok_or_else(|| AcmeError::Failed(format!("{} can't be validated over DNS-01", name)))?;
+
+            presented.push(solver.present(name, &self.key.dns01_value(token)).await.map_err(AcmeError::Challenge)?);
+            challenge_urls.push(challenge_url);
+        }
+
+        tokio::time::sleep(self.propagation).await;
+
+        for challenge_url in challenge_urls {
+            self.post(challenge_url, Some(&json!({}))).await?;
+        }
+
+        for authorization_url in authorization_urls {
+            let authorization: Authorization = self.poll(authorization_url).await?;
+
+            if authorization.status != "valid" {
+                let reason = authorization.challenges.iter()
+                    .find_map(|challenge| challenge.error.as_ref())
+                    .map_or("no reason was given", |problem| problem.detail.as_str());
+
+                return Err(AcmeError::Failed(format!("{} couldn't be validated: {}", authorization.identifier.value, reason)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Orders a certificate for `names` and its `key`, answering DNS-01 challenges through `solver`
+    ///
+    /// Returns the DER encoded chain, leaf first.
+    pub async fn order_certificate(&self, names: &[String], key: &SigningKey, solver: &dyn ChallengeSolver) -> Result<Vec<Vec<u8>>, AcmeError> {
+        let identifiers: Vec<Value> = names.iter()
+            .map(|name| json!({ "type": "dns", "value": name }))
+            .collect();
+
+        let response = self.post(&self.directory.new_order, Some(&json!({ "identifiers": identifiers }))).await?;
+        let order_url = response.location.clone().ok_or_else(|| AcmeError::Protocol("No order URL was given".to_string()))?;
+        let order: Order = response.json()?;
+
+        let mut pending = vec![];
+        let mut pending_urls = vec![];
+
+        for authorization_url in &order.authorizations {
+            let authorization: Authorization = self.post(authorization_url, None).await?.json()?;
+
+            // Authorizations stay valid for a while, so renewals can often skip the challenges
+            if authorization.status != "valid" {
+                pending.push(authorization);
+                pending_urls.push(authorization_url);
+            }
+        }
+
+        if !pending.is_empty() {
+            let mut presented = vec![];
+            let answered = self.answer_challenges(&pending, &pending_urls, solver, &mut presented).await;
+
+            for record_id in presented {
+                if let Err(err) = solver.clean_up(&record_id).await {
+                    warn!("Failed to remove DNS-01 challenge record {}: {}", record_id, err);
+                }
+            }
+
+            answered?;
+        }
+
+        self.post(&order.finalize, Some(&json!({ "csr": base64url(&build_csr(names, key)?) }))).await?;
+
+        let order: Order = self.poll(&order_url).await?;
+        let certificate_url = match (order.status.as_str(), &order.certificate) {
+            ("valid", Some(certificate_url)) => certificate_url,
+            _ => {
+                let reason = order.error.as_ref().map_or("no reason was given", |problem| problem.detail.as_str());
+                return Err(AcmeError::Failed(format!("The ACME CA didn't issue the certificate: {}", reason)));
+            }
+        };
+
+        let response = self.post(certificate_url, None).await?;
+        let chain = rustls_pemfile::certs(&mut response.body.as_slice())
+            .map_err(|err| AcmeError::Protocol(err.to_string()))?;
+
+        match chain.first().map(Cert::from_der) {
+            Some(Ok(_)) => Ok(chain),
+            _ => Err(AcmeError::Protocol("The certificate chain can't be decoded".to_string()))
+        }
+    }
+}
+
+/// Connects to the CA with the account key stored for its directory, creating one the first time
+pub async fn connect_account<C: ConnectionTrait>(settings: &AcmeSettings, connection: &C) -> Result<AcmeClient, AcmeError> {
+    let stored_account = AcmeAccount::find()
+        .filter(acme_account::Column::Directory.eq(settings.directory_url.clone()))
+        .one(connection)
+        .await?;
+
+    let (account_model, account_key) = match stored_account {
+        Some(account_model) => {
+            let account_key = AccountKey::from_pkcs8(&decrypt_priv_key(&account_model.nonce, &account_model.key).await)?;
+            (account_model, account_key)
+        }
+        None => {
+            let account_key = AccountKey::generate()?;
+            let (nonce, key) = encrypt_priv_key(account_key.to_pkcs8()).await;
+
+            let account_model = acme_account::ActiveModel {
+                id: ActiveValue::Set(Ulid::new().to_string()),
+                directory: ActiveValue::Set(settings.directory_url.clone()),
+                url: ActiveValue::Set(None),
+                key: ActiveValue::Set(key),
+                nonce: ActiveValue::Set(nonce),
+                created: ActiveValue::Set(Utc::now().naive_utc())
+            }
+                .insert(connection)
+                .await?;
+
+            (account_model, account_key)
+        }
+    };
+
+    let client = AcmeClient::connect(settings, account_key).await?;
+
+    if account_model.url.as_deref() != Some(client.account_url()) {
+        info!("Registered ACME account {} with {}", client.account_url(), settings.directory_url);
+
+        let mut updated_account: acme_account::ActiveModel = account_model.into();
+        updated_account.url = ActiveValue::Set(Some(client.account_url().to_string()));
+        updated_account.update(connection).await?;
+    }
+
+    Ok(client)
+}
+
+/// Orders a certificate with a fresh PROXYLEAF key, returning the key and the DER chain
+async fn order_leaf(client: &AcmeClient, solver: &dyn ChallengeSolver, names: &[String]) -> Result<(SigningKey, Vec<Vec<u8>>), AcmeError> {
+    let leaf_key = SigningKey::generate(KeyAlgorithm::for_type(&Types::PROXYLEAF))?;
+    let chain = client.order_certificate(names, &leaf_key, solver).await?;
+
+    Ok((leaf_key, chain))
+}
+
+/// Orders a publicly trusted PROXYLEAF for `names` and stores it
+///
+/// The CA's chain is kept alongside the certificate, which also tells it apart from leaves our
+/// intermediate signed.
+pub async fn issue_acme_leaf<C: ConnectionTrait>(client: &AcmeClient, solver: &dyn ChallengeSolver, names: &[String], connection: &C) -> Result<certificate::Model, AcmeError> {
+    let (leaf_key, chain) = order_leaf(client, solver, names).await?;
+    let (nonce, key) = encrypt_priv_key(leaf_key.to_pkcs8()?).await;
+
+    let leaf_model = certificate::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        data: ActiveValue::Set(chain[0].clone()),
+        key: ActiveValue::Set(Some(key)),
+        nonce: ActiveValue::Set(Some(nonce)),
+        cert_type: ActiveValue::Set(Types::PROXYLEAF.to_string()),
+        revoked: ActiveValue::Set(None),
+        revocation_reason: ActiveValue::Set(None),
+        retiring: ActiveValue::Set(None),
+        chain: ActiveValue::Set(Some(chain[1..].concat())),
+        fetched: ActiveValue::Set(None)
+    }
+        .insert(connection)
+        .await?;
+
+    info!("Issued ACME cert {} for {}", leaf_model.id, names.join(", "));
+
+    Ok(leaf_model)
+}
+
+/// Splits a stored chain back into the DER certificates it was concatenated from
+pub fn split_chain(chain: &[u8]) -> Result<Vec<Vec<u8>>, DerError> {
+    let mut reader = DerReader::new(chain);
+    let mut certificates = vec![];
+
+    while !reader.is_empty() {
+        let (_, element) = reader.read_element(TAG_SEQUENCE)?;
+        certificates.push(element.to_vec());
+    }
+
+    Ok(certificates)
+}
+
+/// Orders a stored ACME certificate again for the same names, replacing it in place so anything
+/// referencing it picks up the new one
+pub async fn renew_acme_leaf<C: ConnectionTrait>(client: &AcmeClient, solver: &dyn ChallengeSolver, leaf_model: certificate::Model, connection: &C) -> Result<certificate::Model, AcmeError> {
+    let names = Cert::from_der(&leaf_model.data)
+        .map(|leaf_cert| dns_names(&leaf_cert))
+        .unwrap_or_default();
+
+    if names.is_empty() {
+        return Err(AcmeError::Failed(format!("ACME cert {} has no names to renew it for", leaf_model.id)));
+    }
+
+    let (leaf_key, chain) = order_leaf(client, solver, &names).await?;
+    let (nonce, key) = encrypt_priv_key(leaf_key.to_pkcs8()?).await;
+
+    let mut renewed_model: certificate::ActiveModel = leaf_model.into();
+    renewed_model.data = ActiveValue::Set(chain[0].clone());
+    renewed_model.key = ActiveValue::Set(Some(key));
+    renewed_model.nonce = ActiveValue::Set(Some(nonce));
+    renewed_model.chain = ActiveValue::Set(Some(chain[1..].concat()));
+
+    Ok(renewed_model.update(connection).await?)
+}
+
+async fn renew_acme_certificates(connection: &DatabaseConnection, amqp_channel: &Channel, settings: &AcmeSettings, solver: &dyn ChallengeSolver) -> Result<(), AcmeError> {
+    let threshold = Utc::now() + renewal_threshold();
+
+    // The rustls certificate shadows the entity's name
+    let due: Vec<certificate::Model> = certificate::Entity::find()
+        .filter(certificate::Column::CertType.eq(Types::PROXYLEAF.to_string()))
+        .filter(certificate::Column::Chain.is_not_null())
+        .filter(certificate::Column::Revoked.is_null())
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter(|model| matches!(not_after(model), Some(not_after) if not_after < threshold))
+        .collect();
+
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let client = connect_account(settings, connection).await?;
+
+    for leaf_model in due {
+        let leaf_id = leaf_model.id.clone();
+
+        match renew_acme_leaf(&client, solver, leaf_model, connection).await {
+            Ok(renewed_model) => {
+                info!("Renewed ACME cert {}", renewed_model.id);
+                publish_renewal(amqp_channel, &renewed_model, connection).await;
+            }
+            Err(err) => error!("Failed to renew ACME cert {}: {}", leaf_id, err)
+        }
+    }
+
+    Ok(())
+}
+
+/// Orders ACME certificates again before they expire, for the lifetime of the controller
+pub async fn run_acme_renewal(connection: DatabaseConnection, amqp_channel: Channel, settings: AcmeSettings, solver: SharedSolver) {
+    let mut interval = tokio::time::interval(RENEWAL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = renew_acme_certificates(&connection, &amqp_channel, &settings, solver.as_ref()).await {
+            error!("Failed to renew ACME certificates: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::Mutex as StdMutex;
+
+    use axum::body::Bytes;
+    use axum::extract::{Extension, Path as UrlPath};
+    use axum::http::{HeaderMap, StatusCode as HttpStatus};
+    use axum::response::{IntoResponse, Response};
+    use axum::Router;
+    use axum::routing::{get, post};
+    use picky::x509::Csr;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+
+    use crate::cert::generate::{generate_leaf_cert, generate_root_cert, LeafOptions, LeafTarget};
+
+    use super::*;
+
+    /// Holds presented records in memory, keyed by the ID handed back
+    #[derive(Default)]
+    struct MemorySolver {
+        records: StdMutex<HashMap<String, (String, String)>>,
+        presented: StdMutex<usize>
+    }
+
+    #[async_trait]
+    impl ChallengeSolver for MemorySolver {
+        async fn present(&self, name: &str, value: &str) -> Result<String, String> {
+            let id = Ulid::new().to_string();
+            self.records.lock().unwrap().insert(id.clone(), (format!("_acme-challenge.{}", name), value.to_string()));
+            *self.presented.lock().unwrap() += 1;
+
+            Ok(id)
+        }
+
+        async fn clean_up(&self, id: &str) -> Result<(), String> {
+            self.records.lock().unwrap().remove(id).map(|_| ()).ok_or_else(|| format!("No record {}", id))
+        }
+    }
+
+    /// Just enough of an RFC 8555 CA for a single order, checking requests the way Pebble does
+    struct StandIn {
+        base: String,
+        solver: Arc<MemorySolver>,
+        ca: (Cert, SigningKey),
+        nonces: StdMutex<Vec<String>>,
+        rejected_nonce: StdMutex<bool>,
+        jwk: StdMutex<Option<Value>>,
+        names: StdMutex<Vec<String>>,
+        validated: StdMutex<Vec<bool>>,
+        chain: StdMutex<Option<String>>
+    }
+
+    impl StandIn {
+        fn nonce(&self) -> HeaderMap {
+            let nonce = Ulid::new().to_string();
+            self.nonces.lock().unwrap().push(nonce.clone());
+
+            let mut headers = HeaderMap::new();
+            headers.insert("replay-nonce", nonce.parse().unwrap());
+            headers
+        }
+
+        fn reply(&self, status: HttpStatus, location: Option<&str>, body: String) -> Response {
+            let mut headers = self.nonce();
+            if let Some(location) = location {
+                headers.insert("location", format!("{}/{}", self.base, location).parse().unwrap());
+            }
+
+            (status, headers, body).into_response()
+        }
+
+        fn problem(&self, problem_type: &str, detail: &str) -> Response {
+            let problem = json!({ "type": format!("urn:ietf:params:acme:error:{}", problem_type), "detail": detail });
+            self.reply(HttpStatus::BAD_REQUEST, None, problem.to_string())
+        }
+
+        fn order(&self) -> Value {
+            let names = self.names.lock().unwrap();
+            let validated = self.validated.lock().unwrap();
+            let issued = self.chain.lock().unwrap().is_some();
+
+            json!({
+                "status": if issued { "valid" } else if validated.iter().all(|valid| *valid) { "ready" } else { "pending" },
+                "identifiers": names.iter().map(|name| json!({ "type": "dns", "value": name })).collect::<Vec<Value>>(),
+                "authorizations": (0..names.len()).map(|index| format!("{}/authz/{}", self.base, index)).collect::<Vec<String>>(),
+                "finalize": format!("{}/finalize", self.base),
+                "certificate": if issued { Some(format!("{}/cert", self.base)) } else { None }
+            })
+        }
+
+        fn authorization(&self, index: usize) -> Value {
+            let status = if self.validated.lock().unwrap()[index] { "valid" } else { "pending" };
+
+            json!({
+                "status": status,
+                "identifier": { "type": "dns", "value": self.names.lock().unwrap()[index] },
+                "challenges": [
+                    { "type": "http-01", "url": format!("{}/http/{}", self.base, index), "token": format!("http-{}", index), "status": "pending" },
+                    { "type": "dns-01", "url": format!("{}/chall/{}", self.base, index), "token": format!("token-{}", index), "status": status }
+                ]
+            })
+        }
+
+        /// Checks the JWS the way RFC 8555 section 6.2 asks, returning its payload or the problem with it
+        fn verify(&self, path: &str, body: &[u8]) -> Result<Option<Value>, (&'static str, &'static str)> {
+            let jws: Value = serde_json::from_slice(body).map_err(|_| ("malformed", "Not JSON"))?;
+            let field = |name: &str| jws[name].as_str().unwrap_or_default().to_string();
+            let decode = |value: &str| Base64UrlUnpadded::decode_vec(value).map_err(|_| ("malformed", "Not base64url"));
+
+            let protected: Value = serde_json::from_slice(&decode(&field("protected"))?).unwrap();
+
+            let nonce = protected["nonce"].as_str().unwrap_or_default();
+            let mut nonces = self.nonces.lock().unwrap();
+            match nonces.iter().position(|issued| issued == nonce) {
+                Some(position) => { nonces.remove(position); }
+                None => return Err(("badNonce", "Unknown nonce"))
+            }
+            drop(nonces);
+
+            // Pebble rejects a share of good nonces too, so clients have to cope
+            if !*self.rejected_nonce.lock().unwrap() {
+                *self.rejected_nonce.lock().unwrap() = true;
+                return Err(("badNonce", "Rejected on purpose"));
+            }
+
+            assert_eq!(protected["url"], json!(format!("{}/{}", self.base, path)));
+            assert_eq!(protected["alg"], json!("ES256"));
+
+            let jwk = match path {
+                "account" => protected["jwk"].clone(),
+                _ => {
+                    assert_eq!(protected["kid"], json!(format!("{}/account/1", self.base)));
+                    self.jwk.lock().unwrap().clone().expect("No account was created")
+                }
+            };
+
+            let point = [vec![4], decode(jwk["x"].as_str().unwrap())?, decode(jwk["y"].as_str().unwrap())?].concat();
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                .verify(format!("{}.{}", field("protected"), field("payload")).as_bytes(), &decode(&field("signature"))?)
+                .map_err(|_| ("unauthorized", "Bad signature"))?;
+
+            if path == "account" {
+                *self.jwk.lock().unwrap() = Some(jwk);
+            }
+
+            match field("payload").as_str() {
+                "" => Ok(None),
+                payload => Ok(Some(serde_json::from_slice(&decode(payload)?).unwrap()))
+            }
+        }
+
+        async fn handle(&self, path: &str, body: &[u8]) -> Response {
+            let payload = match self.verify(path, body) {
+                Ok(payload) => payload,
+                Err((problem_type, detail)) => return self.problem(problem_type, detail)
+            };
+
+            let (resource, index) = match path.split_once('/') {
+                Some((resource, index)) => (resource, index.parse::<usize>().unwrap_or_default()),
+                None => (path, 0)
+            };
+
+            match resource {
+                "account" => self.reply(HttpStatus::CREATED, Some("account/1"), json!({ "status": "valid" }).to_string()),
+                "order" if payload.is_some() => {
+                    let names: Vec<String> = payload.unwrap()["identifiers"].as_array().unwrap().iter()
+                        .map(|identifier| identifier["value"].as_str().unwrap().to_string())
+                        .collect();
+
+                    *self.validated.lock().unwrap() = vec![false; names.len()];
+                    *self.names.lock().unwrap() = names;
+
+                    self.reply(HttpStatus::CREATED, Some("order/1"), self.order().to_string())
+                }
+                "order" => self.reply(HttpStatus::OK, None, self.order().to_string()),
+                "authz" => self.reply(HttpStatus::OK, None, self.authorization(index).to_string()),
+                "chall" => {
+                    // Worked out independently of the client, serde_json sorts the JWK's members
+                    let thumbprint = base64url(digest(&SHA256, self.jwk.lock().unwrap().as_ref().unwrap().to_string().as_bytes()).as_ref());
+                    let expected = (
+                        format!("_acme-challenge.{}", self.names.lock().unwrap()[index]),
+                        base64url(digest(&SHA256, format!("token-{}.{}", index, thumbprint).as_bytes()).as_ref())
+                    );
+
+                    let presented = self.solver.records.lock().unwrap().values().any(|record| *record == expected);
+                    self.validated.lock().unwrap()[index] = presented;
+
+                    self.reply(HttpStatus::OK, None, self.authorization(index)["challenges"][1].to_string())
+                }
+                "finalize" => {
+                    let csr = Base64UrlUnpadded::decode_vec(payload.unwrap()["csr"].as_str().unwrap()).unwrap();
+                    let csr = Csr::from_der(&csr).expect("Failed to decode the CSR");
+                    csr.verify().expect("Failed to verify the CSR");
+
+                    if !self.validated.lock().unwrap().iter().all(|valid| *valid) {
+                        return self.problem("orderNotReady", "Authorizations aren't valid");
+                    }
+
+                    let names = self.names.lock().unwrap().clone();
+                    let options = LeafOptions {
+                        common_name: names[0].clone(),
+                        dns_names: names,
+                        ip_addresses: vec![],
+                        validity: chrono::Duration::days(90)
+                    };
+
+                    let leaf = generate_leaf_cert(csr.public_key().clone(), &LeafTarget::PROXY, &options, (&self.ca.0, &self.ca.1)).await.unwrap();
+                    *self.chain.lock().unwrap() = Some(format!("{}\n{}\n", leaf.to_pem().unwrap(), self.ca.0.to_pem().unwrap()));
+
+                    self.reply(HttpStatus::OK, None, self.order().to_string())
+                }
+                "cert" => self.reply(HttpStatus::OK, None, self.chain.lock().unwrap().clone().unwrap()),
+                _ => self.problem("malformed", "Unknown resource")
+            }
+        }
+    }
+
+    async fn directory(Extension(stand_in): Extension<Arc<StandIn>>) -> String {
+        json!({
+            "newNonce": format!("{}/nonce", stand_in.base),
+            "newAccount": format!("{}/account", stand_in.base),
+            "newOrder": format!("{}/order", stand_in.base)
+        }).to_string()
+    }
+
+    async fn new_nonce(Extension(stand_in): Extension<Arc<StandIn>>) -> HeaderMap {
+        stand_in.nonce()
+    }
+
+    async fn resource(Extension(stand_in): Extension<Arc<StandIn>>, UrlPath(path): UrlPath<String>, body: Bytes) -> Response {
+        stand_in.handle(path.trim_start_matches('/'), &body).await
+    }
+
+    #[tokio::test]
+    async fn orders_from_a_stand_in_ca() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let ca_key = SigningKey::generate(KeyAlgorithm::P256).unwrap();
+        let ca_cert = generate_root_cert(&ca_key).await.unwrap();
+        let solver = Arc::new(MemorySolver::default());
+
+        let stand_in = Arc::new(StandIn {
+            base: base.clone(),
+            solver: solver.clone(),
+            ca: (ca_cert.clone(), ca_key),
+            nonces: StdMutex::new(vec![]),
+            rejected_nonce: StdMutex::new(false),
+            jwk: StdMutex::new(None),
+            names: StdMutex::new(vec![]),
+            validated: StdMutex::new(vec![]),
+            chain: StdMutex::new(None)
+        });
+
+        let app = Router::new()
+            .route("/dir", get(directory))
+            .route("/nonce", get(new_nonce))
+            .route("/*path", post(resource))
+            .layer(Extension(stand_in.clone()));
+
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        let settings = AcmeSettings {
+            directory_url: format!("{}/dir", base),
+            contact: Some("admin@example.test".to_string()),
+            ca_cert: None,
+            propagation: Duration::ZERO
+        };
+
+        let client = AcmeClient::connect(&settings, AccountKey::generate().unwrap()).await.unwrap();
+        assert_eq!(client.account_url(), format!("{}/account/1", base));
+
+        let names = vec!["example.test".to_string(), "www.example.test".to_string()];
+        let leaf_key = SigningKey::generate(KeyAlgorithm::P256).unwrap();
+        let chain = client.order_certificate(&names, &leaf_key, solver.as_ref()).await.unwrap();
+
+        let leaf = Cert::from_der(&chain[0]).unwrap();
+        assert_eq!(dns_names(&leaf), names);
+        assert_eq!(leaf.public_key(), &leaf_key.public_key());
+        assert_eq!(chain[1..], [ca_cert.to_der().unwrap()]);
+        assert_eq!(split_chain(&chain[1..].concat()).unwrap(), chain[1..]);
+
+        assert!(*stand_in.rejected_nonce.lock().unwrap());
+        assert_eq!(*solver.presented.lock().unwrap(), 2);
+        assert!(solver.records.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{env, fmt};
+use std::fmt::Formatter;
+
+use picky::hash::HashAlgorithm;
+use picky::key::{KeyError, PrivateKey, PublicKey};
+use picky::pem::Pem;
+use picky::signature::{SignatureAlgorithm, SignatureError};
+use picky::AlgorithmIdentifier;
+use picky_asn1::bit_string::BitString;
+use picky_asn1_x509::SubjectPublicKeyInfo;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P384_SHA384_ASN1_SIGNING, EcdsaKeyPair, EcdsaSigningAlgorithm, Ed25519KeyPair, KeyPair};
+use zeroize::Zeroizing;
+
+use crate::cert::der::*;
+use crate::cert::Types;
+
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_SECP256R1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+
+/// The algorithm a certificate type's keys are generated with, which also decides how they sign
+///
+/// RSA keys sign with PKCS#1 v1.5 and SHA-512, P-256 and P-384 keys with ECDSA and SHA-256 and
+/// SHA-384 respectively, and Ed25519 keys with Ed25519.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAlgorithm {
+    RSA2048,
+    RSA3072,
+    RSA4096,
+    P256,
+    P384,
+    ED25519
+}
+
+impl KeyAlgorithm {
+    pub fn from_name(name: &str) -> Option<KeyAlgorithm> {
+        match name.to_uppercase().as_str() {
+            "RSA2048" => Some(KeyAlgorithm::RSA2048),
+            "RSA3072" => Some(KeyAlgorithm::RSA3072),
+            "RSA4096" => Some(KeyAlgorithm::RSA4096),
+            "P256" => Some(KeyAlgorithm::P256),
+            "P384" => Some(KeyAlgorithm::P384),
+            "ED25519" => Some(KeyAlgorithm::ED25519),
+            _ => None
+        }
+    }
+
+    /// The algorithm new keys of a certificate type are generated with, set as ROOT_KEY_ALGORITHM,
+    /// CLIENTINTER_KEY_ALGORITHM and so on
+    ///
+    /// Only applies to newly generated keys, certificates already issued keep theirs.
+    pub fn for_type(cert_type: &Types) -> KeyAlgorithm {
+        let default_algorithm = match cert_type {
+            Types::CLIENTLEAF | Types::PROXYLEAF => KeyAlgorithm::RSA2048,
+            _ => KeyAlgorithm::RSA4096
+        };
+
+        let variable = format!("{}_KEY_ALGORITHM", cert_type);
+
+        match env::var(&variable) {
+            Err(_) => default_algorithm,
+            Ok(name) => KeyAlgorithm::from_name(&name).unwrap_or_else(|| {
+                warn!("{} isn't a supported key algorithm, {} defaults to {}", name, variable, default_algorithm);
+                default_algorithm
+            })
+        }
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyAlgorithm::RSA2048 => write!(f, "RSA2048"),
+            KeyAlgorithm::RSA3072 => write!(f, "RSA3072"),
+            KeyAlgorithm::RSA4096 => write!(f, "RSA4096"),
+            KeyAlgorithm::P256 => write!(f, "P256"),
+            KeyAlgorithm::P384 => write!(f, "P384"),
+            KeyAlgorithm::ED25519 => write!(f, "ED25519")
+        }
+    }
+}
+
+fn ring_error(context: &str) -> KeyError {
+    KeyError::EC { context: context.to_string() }
+}
+
+/// Picky can't generate EC keys, so ring does and picky loads them
+fn generate_ecdsa(signing_algorithm: &'static EcdsaSigningAlgorithm) -> Result<PrivateKey, KeyError> {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(signing_algorithm, &SystemRandom::new())
+        .map_err(|_| ring_error("failed to generate an ECDSA key"))?;
+
+    PrivateKey::from_pkcs8(pkcs8.as_ref())
+}
+
+/// Reads the algorithm OID and, for EC keys, the curve OID of a key's AlgorithmIdentifier
+///
+/// Private keys are PKCS#8, which puts a version before it, and public keys are subject public key
+/// info, which doesn't.
+fn key_algorithm_of(der: &[u8], versioned: bool) -> Result<(Vec<u8>, Option<Vec<u8>>), DerError> {
+    let mut info_reader = DerReader::new(DerReader::new(der).read(TAG_SEQUENCE)?);
+    if versioned {
+        info_reader.read(TAG_INTEGER)?;
+    }
+
+    let mut algorithm_reader = DerReader::new(info_reader.read(TAG_SEQUENCE)?);
+    let algorithm = algorithm_reader.read(TAG_OID)?.to_vec();
+    let curve = algorithm_reader.read_optional(TAG_OID)?.map(|curve| curve.to_vec());
+
+    Ok((algorithm, curve))
+}
+
+/// Works out how a private key picky loaded signs
+///
+/// This goes by the PKCS#8 encoding, as picky loses the curve when it derives an EC public key.
+fn signature_algorithm_of(private_key: &PrivateKey) -> Result<SignatureAlgorithm, KeyError> {
+    match key_algorithm_of(&private_key.to_pkcs8()?, true) {
+        Ok((algorithm, _)) if algorithm == OID_RSA_ENCRYPTION => Ok(SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_512)),
+        Ok((algorithm, Some(curve))) if algorithm == OID_EC_PUBLIC_KEY && curve == OID_SECP256R1 => Ok(SignatureAlgorithm::Ecdsa(HashAlgorithm::SHA2_256)),
+        Ok((algorithm, Some(curve))) if algorithm == OID_EC_PUBLIC_KEY && curve == OID_SECP384R1 => Ok(SignatureAlgorithm::Ecdsa(HashAlgorithm::SHA2_384)),
+        _ => Err(KeyError::UnsupportedAlgorithm { algorithm: "only RSA, P-256 and P-384 keys can be loaded by picky" })
+    }
+}
+
+/// Whether a public key is RSA, the only kind that can encipher keys
+pub fn is_rsa_key(public_key: &PublicKey) -> bool {
+    let public_key = match public_key.to_der() {
+        Ok(public_key) => public_key,
+        Err(_) => return false
+    };
+
+    matches!(key_algorithm_of(&public_key, false), Ok((algorithm, _)) if algorithm == OID_RSA_ENCRYPTION)
+}
+
+/// Encodes a subject public key info from an algorithm identifier and the key itself
+fn public_key_info(algorithm: Vec<u8>, key: &[u8]) -> PublicKey {
+    PublicKey::from_der(&sequence(&[algorithm, bit_string(key)])).expect("Failed to encode public key!")
+}
+
+/// A private key certificates, CRLs and OCSP responses are signed with
+#[derive(Clone)]
+pub enum SigningKey {
+    /// RSA and ECDSA keys, which picky signs with itself
+    Picky(Box<PrivateKey>, SignatureAlgorithm),
+    /// PKCS#8 encoded Ed25519 key, which picky can't load, so ring signs with it instead
+    Ed25519(Zeroizing<Vec<u8>>)
+}
+
+impl SigningKey {
+    pub fn generate(algorithm: KeyAlgorithm) -> Result<SigningKey, KeyError> {
+        let private_key = match algorithm {
+            KeyAlgorithm::RSA2048 => PrivateKey::generate_rsa(2048)?,
+            KeyAlgorithm::RSA3072 => PrivateKey::generate_rsa(3072)?,
+            KeyAlgorithm::RSA4096 => PrivateKey::generate_rsa(4096)?,
+            KeyAlgorithm::P256 => generate_ecdsa(&ECDSA_P256_SHA256_ASN1_SIGNING)?,
+            KeyAlgorithm::P384 => generate_ecdsa(&ECDSA_P384_SHA384_ASN1_SIGNING)?,
+            KeyAlgorithm::ED25519 => {
+                let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                    .map_err(|_| ring_error("failed to generate an Ed25519 key"))?;
+
+                return Ok(SigningKey::Ed25519(Zeroizing::new(pkcs8.as_ref().to_vec())));
+            }
+        };
+
+        SigningKey::from_private_key(private_key)
+    }
+
+    pub fn from_private_key(private_key: PrivateKey) -> Result<SigningKey, KeyError> {
+        let signature_algorithm = signature_algorithm_of(&private_key)?;
+
+        Ok(SigningKey::Picky(Box::new(private_key), signature_algorithm))
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<SigningKey, KeyError> {
+        if Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).is_ok() {
+            return Ok(SigningKey::Ed25519(Zeroizing::new(pkcs8.to_vec())));
+        }
+
+        SigningKey::from_private_key(PrivateKey::from_pkcs8(pkcs8)?)
+    }
+
+    /// Reads a PKCS#8 key of any supported algorithm, or a PKCS#1 RSA key
+    pub fn from_pem_str(pem: &str) -> Result<SigningKey, KeyError> {
+        let pem: Pem = pem.parse().map_err(|source| KeyError::Pem { source })?;
+
+        match pem.label() {
+            "PRIVATE KEY" => SigningKey::from_pkcs8(pem.data()),
+            _ => SigningKey::from_private_key(PrivateKey::from_pem(&pem)?)
+        }
+    }
+
+    pub fn to_pkcs8(&self) -> Result<Vec<u8>, KeyError> {
+        match self {
+            SigningKey::Picky(private_key, _) => private_key.to_pkcs8(),
+            SigningKey::Ed25519(pkcs8) => Ok(pkcs8.to_vec())
+        }
+    }
+
+    fn ed25519_key_pair(pkcs8: &[u8]) -> Ed25519KeyPair {
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8)
+            .expect("Ed25519 key was validated when it was loaded!")
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            SigningKey::Picky(private_key, SignatureAlgorithm::Ecdsa(hash_algorithm)) => {
+                let curve = match hash_algorithm {
+                    HashAlgorithm::SHA2_256 => OID_SECP256R1,
+                    _ => OID_SECP384R1
+                };
+
+                // Picky's own EC public keys leave out the curve, so only the point is kept
+                let public_key = private_key.to_public_key()
+                    .to_der()
+                    .expect("Failed to encode EC public key!");
+                let mut info_reader = DerReader::new(DerReader::new(&public_key).read(TAG_SEQUENCE).expect("Failed to decode EC public key!"));
+                info_reader.read(TAG_SEQUENCE).expect("Failed to decode EC public key!");
+                let point = info_reader.read(TAG_BIT_STRING).expect("Failed to decode EC public key!");
+
+                // The first byte is the count of unused bits, which is always zero for a point
+                public_key_info(sequence(&[oid(OID_EC_PUBLIC_KEY), oid(curve)]), &point[1..])
+            }
+            SigningKey::Picky(private_key, _) => private_key.to_public_key(),
+            SigningKey::Ed25519(pkcs8) => {
+                let key_pair = SigningKey::ed25519_key_pair(pkcs8);
+
+                PublicKey::from(SubjectPublicKeyInfo {
+                    algorithm: AlgorithmIdentifier::new_ed25519(),
+                    subject_public_key: picky_asn1_x509::PublicKey::Ed(BitString::with_bytes(key_pair.public_key().as_ref()).into())
+                })
+            }
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        match self {
+            SigningKey::Picky(private_key, signature_algorithm) => signature_algorithm.sign(message, private_key),
+            SigningKey::Ed25519(pkcs8) => Ok(SigningKey::ed25519_key_pair(pkcs8).sign(message).as_ref().to_vec())
+        }
+    }
+
+    /// The AlgorithmIdentifier of the signatures this key makes
+    pub fn algorithm_identifier(&self) -> AlgorithmIdentifier {
+        match self {
+            SigningKey::Picky(_, SignatureAlgorithm::Ecdsa(HashAlgorithm::SHA2_256)) => AlgorithmIdentifier::new_ecdsa_with_sha256(),
+            SigningKey::Picky(_, SignatureAlgorithm::Ecdsa(_)) => AlgorithmIdentifier::new_ecdsa_with_sha384(),
+            SigningKey::Picky(..) => AlgorithmIdentifier::new_sha512_with_rsa_encryption(),
+            SigningKey::Ed25519(_) => AlgorithmIdentifier::new_ed25519()
+        }
+    }
+
+    /// The DER encoded AlgorithmIdentifier of the signatures this key makes
+    pub fn signature_algorithm(&self) -> Vec<u8> {
+        picky_asn1_der::to_vec(&self.algorithm_identifier()).expect("Failed to encode signature algorithm!")
+    }
+}
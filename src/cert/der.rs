@@ -0,0 +1,301 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use chrono::{DateTime, Utc};
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_ENUMERATED: u8 = 0x0a;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+/// Tag of an explicit, or implicitly tagged constructed, context specific field
+pub fn context_constructed(number: u8) -> u8 {
+    0xa0 | number
+}
+
+/// Tag of an implicitly tagged primitive context specific field
+pub fn context_primitive(number: u8) -> u8 {
+    0x80 | number
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DerError {
+    Truncated,
+    UnsupportedLength,
+    UnexpectedTag(u8)
+}
+
+impl fmt::Display for DerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DerError::Truncated => write!(f, "DER element is truncated"),
+            DerError::UnsupportedLength => write!(f, "DER element is too long"),
+            DerError::UnexpectedTag(tag) => write!(f, "Unexpected DER tag {:#04x}", tag)
+        }
+    }
+}
+
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+
+    match content.len() {
+        length if length < 0x80 => element.push(length as u8),
+        length => {
+            let length_bytes: Vec<u8> = (length as u64).to_be_bytes()
+                .iter()
+                .copied()
+                .skip_while(|byte| *byte == 0)
+                .collect();
+
+            element.push(0x80 | length_bytes.len() as u8);
+            element.extend(length_bytes);
+        }
+    }
+
+    element.extend_from_slice(content);
+    element
+}
+
+pub fn sequence(elements: &[Vec<u8>]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &elements.concat())
+}
+
+/// DER sorts the elements of a set, which is left to the caller
+pub fn set(elements: &[Vec<u8>]) -> Vec<u8> {
+    encode(TAG_SET, &elements.concat())
+}
+
+pub fn integer(value: u64) -> Vec<u8> {
+    let mut content: Vec<u8> = value.to_be_bytes()
+        .iter()
+        .copied()
+        .skip_while(|byte| *byte == 0)
+        .collect();
+
+    // Keep the value positive when its top bit is set
+    if !matches!(content.first(), Some(byte) if byte & 0x80 == 0) {
+        content.insert(0, 0);
+    }
+
+    encode(TAG_INTEGER, &content)
+}
+
+pub fn enumerated(value: u8) -> Vec<u8> {
+    encode(TAG_ENUMERATED, &[value])
+}
+
+pub fn octet_string(content: &[u8]) -> Vec<u8> {
+    encode(TAG_OCTET_STRING, content)
+}
+
+pub fn bit_string(content: &[u8]) -> Vec<u8> {
+    encode(TAG_BIT_STRING, &[&[0], content].concat())
+}
+
+pub fn utf8_string(value: &str) -> Vec<u8> {
+    encode(TAG_UTF8_STRING, value.as_bytes())
+}
+
+/// Takes an already encoded object identifier, without its tag and length
+pub fn oid(encoded: &[u8]) -> Vec<u8> {
+    encode(TAG_OID, encoded)
+}
+
+/// Only valid until 2049, after which RFC 5280 requires GeneralizedTime
+pub fn utc_time(date: DateTime<Utc>) -> Vec<u8> {
+    encode(TAG_UTC_TIME, date.format("%y%m%d%H%M%SZ").to_string().as_bytes())
+}
+
+pub fn generalized_time(date: DateTime<Utc>) -> Vec<u8> {
+    encode(TAG_GENERALIZED_TIME, date.format("%Y%m%d%H%M%SZ").to_string().as_bytes())
+}
+
+/// Walks the elements of a DER encoded structure in order
+pub struct DerReader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> DerReader<'a> {
+        DerReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Reads the next element as its tag, content and complete encoding
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), DerError> {
+        let tag = *self.data.first().ok_or(DerError::Truncated)?;
+        let first_length = *self.data.get(1).ok_or(DerError::Truncated)?;
+
+        let (length, header_length) = match first_length {
+            length if length < 0x80 => (length as usize, 2),
+            0x81..=0x84 => {
+                let length_size = (first_length & 0x7f) as usize;
+                let length_bytes = self.data.get(2..2 + length_size).ok_or(DerError::Truncated)?;
+
+                (length_bytes.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize), 2 + length_size)
+            }
+            _ => return Err(DerError::UnsupportedLength)
+        };
+
+        let end = header_length.checked_add(length).ok_or(DerError::UnsupportedLength)?;
+        let element = self.data.get(..end).ok_or(DerError::Truncated)?;
+        self.data = &self.data[end..];
+
+        Ok((tag, &element[header_length..], element))
+    }
+
+    /// Reads the content of the next element, which must have the given tag
+    pub fn read(&mut self, expected_tag: u8) -> Result<&'a [u8], DerError> {
+        self.read_element(expected_tag).map(|(content, _)| content)
+    }
+
+    /// Reads the content and complete encoding of the next element, which must have the given tag
+    pub fn read_element(&mut self, expected_tag: u8) -> Result<(&'a [u8], &'a [u8]), DerError> {
+        match self.read_any()? {
+            (tag, content, element) if tag == expected_tag => Ok((content, element)),
+            (tag, _, _) => Err(DerError::UnexpectedTag(tag))
+        }
+    }
+
+    /// Reads the content of the next element if it has the given tag
+    pub fn read_optional(&mut self, expected_tag: u8) -> Result<Option<&'a [u8]>, DerError> {
+        match self.peek_tag() {
+            Some(tag) if tag == expected_tag => self.read(expected_tag).map(Some),
+            _ => Ok(None)
+        }
+    }
+}
+
+/// The parts of a certificate revocation needs, borrowed from its DER encoding
+pub struct CertificateParts<'a> {
+    /// Content of the serial number INTEGER
+    pub serial: &'a [u8],
+    /// Complete encoding of the subject Name
+    pub subject: &'a [u8],
+    /// The subject public key BIT STRING, without its unused bits byte
+    pub public_key: &'a [u8]
+}
+
+pub fn certificate_parts(certificate: &[u8]) -> Result<CertificateParts<'_>, DerError> {
+    let mut certificate_reader = DerReader::new(DerReader::new(certificate).read(TAG_SEQUENCE)?);
+    let mut tbs_reader = DerReader::new(certificate_reader.read(TAG_SEQUENCE)?);
+
+    tbs_reader.read_optional(context_constructed(0))?;
+    let serial = tbs_reader.read(TAG_INTEGER)?;
+    tbs_reader.read(TAG_SEQUENCE)?;
+    tbs_reader.read(TAG_SEQUENCE)?;
+    tbs_reader.read(TAG_SEQUENCE)?;
+    let (_, subject) = tbs_reader.read_element(TAG_SEQUENCE)?;
+
+    let mut public_key_reader = DerReader::new(tbs_reader.read(TAG_SEQUENCE)?);
+    public_key_reader.read(TAG_SEQUENCE)?;
+    let public_key = public_key_reader.read(TAG_BIT_STRING)?
+        .get(1..)
+        .ok_or(DerError::Truncated)?;
+
+    Ok(CertificateParts { serial, subject, public_key })
+}
+
+#[cfg(test)]
+mod tests {
+    use base64ct::{Base64, Encoding};
+
+    use super::*;
+
+    #[test]
+    fn reads_short_lengths() {
+        let mut reader = DerReader::new(&[0x04, 0x02, 0xaa, 0xbb, 0x05, 0x00]);
+
+        assert_eq!(reader.read_any(), Ok((TAG_OCTET_STRING, &[0xaa, 0xbb][..], &[0x04, 0x02, 0xaa, 0xbb][..])));
+        assert_eq!(reader.read_any(), Ok((0x05, &[][..], &[0x05, 0x00][..])));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn reads_long_lengths() {
+        for length in [0x80, 0xff, 0x100, 0x1_0000] {
+            let content = vec![0x5a; length];
+            let element = encode(TAG_OCTET_STRING, &content);
+
+            assert_eq!(DerReader::new(&element).read(TAG_OCTET_STRING), Ok(&content[..]));
+        }
+    }
+
+    #[test]
+    fn encodes_the_shortest_long_length() {
+        assert_eq!(&encode(TAG_OCTET_STRING, &[0; 0x80])[..3], &[0x04, 0x81, 0x80]);
+        assert_eq!(&encode(TAG_OCTET_STRING, &[0; 0x100])[..4], &[0x04, 0x82, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn rejects_truncated_elements() {
+        let truncated: [&[u8]; 5] = [
+            &[],
+            &[0x30],
+            &[0x30, 0x03, 0x02, 0x01],
+            &[0x04, 0x82, 0x01],
+            &[0x04, 0x81, 0x80, 0x00]
+        ];
+
+        for data in truncated {
+            assert_eq!(DerReader::new(data).read_any(), Err(DerError::Truncated), "{:02x?}", data);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_lengths() {
+        // Indefinite lengths aren't DER, and nothing here needs more than four length bytes
+        assert_eq!(DerReader::new(&[0x30, 0x80, 0x00, 0x00]).read_any(), Err(DerError::UnsupportedLength));
+        assert_eq!(DerReader::new(&[0x04, 0x85, 0, 0, 0, 0, 1, 0]).read_any(), Err(DerError::UnsupportedLength));
+    }
+
+    #[test]
+    fn rejects_unexpected_tags() {
+        let element = integer(1);
+
+        assert_eq!(DerReader::new(&element).read(TAG_SEQUENCE), Err(DerError::UnexpectedTag(TAG_INTEGER)));
+    }
+
+    #[test]
+    fn skips_missing_optional_elements() {
+        let elements = [integer(1), octet_string(&[1])].concat();
+        let mut reader = DerReader::new(&elements);
+
+        assert_eq!(reader.read_optional(context_constructed(0)), Ok(None));
+        assert_eq!(reader.read_optional(TAG_INTEGER), Ok(Some(&[1][..])));
+        assert_eq!(reader.read(TAG_OCTET_STRING), Ok(&[1][..]));
+    }
+
+    #[test]
+    fn keeps_integers_positive() {
+        assert_eq!(integer(0x7f), vec![0x02, 0x01, 0x7f]);
+        assert_eq!(integer(0x80), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(0), vec![0x02, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn finds_certificate_parts() {
+        // openssl req -x509 -subj "/CN=Driptorch PROXY" -set_serial 0x1001 with an Ed25519 key
+        let certificate = Base64::decode_vec("MIIBSTCB/KADAgECAgIQATAFBgMrZXAwGjEYMBYGA1UEAwwPRHJpcHRvcmNoIFBST1hZMB4XDTI2MTAxODEwMzA0OFoXDTM2MTAxNTEwMzA0OFowGjEYMBYGA1UEAwwPRHJpcHRvcmNoIFBST1hZMCowBQYDK2VwAyEANUU7xxHS+FkEFutbtxBBtXgsKCUpb6U6lv/kDghh0WmjZjBkMB0GA1UdDgQWBBTzpzxEgYh6w701kyGdwIq6oAgkVDAfBgNVHSMEGDAWgBTzpzxEgYh6w701kyGdwIq6oAgkVDASBgNVHRMBAf8ECDAGAQH/AgEAMA4GA1UdDwEB/wQEAwIBBjAFBgMrZXADQQC0b7Wp47gdNojwsmGxUrJICkdCVvZgx3E9IWwzB3I7Xez8yKF3BdwuPtD8+eAkhoCXo8QyKVZ/VwztUsPF4uUG").unwrap();
+        let parts = certificate_parts(&certificate).unwrap();
+
+        assert_eq!(parts.serial, &[0x10, 0x01]);
+        assert_eq!(parts.subject, sequence(&[set(&[sequence(&[oid(&[0x55, 0x04, 0x03]), utf8_string("Driptorch PROXY")])])]));
+        assert_eq!(parts.public_key.len(), 32);
+
+        assert_eq!(certificate_parts(&certificate[..certificate.len() - 1]).err(), Some(DerError::Truncated));
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use lapin::Channel;
+use picky::key::PrivateKey;
+use sea_orm::*;
+use ulid::Ulid;
+
+use crate::cert::{decrypt_priv_key, encrypt_priv_key};
+use crate::dns::dnssec::{ALGORITHM_RSASHA256, dnskey_record, FLAGS_KSK, FLAGS_ZSK, key_tag, rsa_public_key, SigningKey};
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::bump_serial;
+use crate::dns::wire::encode_rdata;
+use crate::entities::{dnssec_key, zone};
+use crate::entities::prelude::{DnssecKey, Zone};
+use crate::rpc::changes::{Change, ChangeEvent, publish_change, record_changes};
+
+/// RSA modulus size for both key types (RFC 8624 section 3.1)
+const KEY_BITS: usize = 2048;
+/// How long a zone signing key signs for before it's rolled
+const ZSK_LIFETIME_DAYS: i64 = 30;
+/// How long a key signing key signs for before it's rolled
+const KSK_LIFETIME_DAYS: i64 = 365;
+/// How long a new zone signing key is published before it starts signing, long enough for the
+/// old DNSKEY RRset to expire from caches
+const PUBLISH_DELAY_DAYS: i64 = 2;
+/// How long a replaced zone signing key stays published, long enough for signatures made with
+/// it to expire from caches
+const RETIRE_DELAY_DAYS: i64 = 8;
+/// How long both key signing keys sign the DNSKEY RRset, giving time to swap the DS at the
+/// registrar and for the old DS to expire from caches
+const DOUBLE_SIGNATURE_DAYS: i64 = 14;
+/// How often zones are checked for keys that need rolling
+const ROLLOVER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(PartialEq)]
+pub enum KeyTypes {
+    KSK,
+    ZSK
+}
+
+impl fmt::Display for KeyTypes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyTypes::KSK => write!(f, "KSK"),
+            KeyTypes::ZSK => write!(f, "ZSK")
+        }
+    }
+}
+
+pub enum KeyStatus {
+    /// In the DNSKEY RRset but not signing yet
+    PUBLISHED,
+    /// Signing
+    ACTIVE,
+    /// Still in the DNSKEY RRset but no longer signing
+    RETIRED
+}
+
+impl fmt::Display for KeyStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStatus::PUBLISHED => write!(f, "PUBLISHED"),
+            KeyStatus::ACTIVE => write!(f, "ACTIVE"),
+            KeyStatus::RETIRED => write!(f, "RETIRED")
+        }
+    }
+}
+
+/// Generates a key for a zone, storing its private key encrypted alongside its DNSKEY data
+pub async fn generate_key<C: ConnectionTrait>(zone_id: &str, key_type: KeyTypes, status: KeyStatus, connection: &C) -> Result<dnssec_key::Model, DbErr> {
+    let priv_key = PrivateKey::generate_rsa(KEY_BITS)
+        .expect("Failed to generate a key");
+
+    let public_key = rsa_public_key(&priv_key.to_public_key())
+        .expect("Failed to convert generated public key to DNSKEY format!");
+
+    let flags = if key_type == KeyTypes::KSK { FLAGS_KSK } else { FLAGS_ZSK };
+    let rdata = encode_rdata(&dnskey_record(".", flags, &public_key))
+        .expect("Failed to encode generated DNSKEY!");
+
+    let encrypted_priv_key = encrypt_priv_key(priv_key
+        .to_pkcs8()
+        .expect("Failed to convert generated private key to pkcs8!")
+    ).await;
+
+    let now = Utc::now().naive_utc();
+
+    dnssec_key::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        zone: ActiveValue::Set(zone_id.to_string()),
+        key_type: ActiveValue::Set(key_type.to_string()),
+        algorithm: ActiveValue::Set(ALGORITHM_RSASHA256 as i16),
+        key_tag: ActiveValue::Set(key_tag(&rdata) as i32),
+        data: ActiveValue::Set(public_key),
+        key: ActiveValue::Set(encrypted_priv_key.1),
+        nonce: ActiveValue::Set(encrypted_priv_key.0),
+        status: ActiveValue::Set(status.to_string()),
+        created: ActiveValue::Set(now),
+        status_changed: ActiveValue::Set(now)
+    }
+        .insert(connection)
+        .await
+}
+
+/// Gets the DNSKEY record a stored key is published as
+pub fn key_dnskey(key: &dnssec_key::Model, origin: &str) -> RecordTypes {
+    let flags = if key.key_type == KeyTypes::KSK.to_string() { FLAGS_KSK } else { FLAGS_ZSK };
+
+    dnskey_record(origin, flags, &key.data)
+}
+
+/// Gets a zone's keys, oldest first
+pub async fn get_zone_keys<C: ConnectionTrait>(zone_id: &str, connection: &C) -> Result<Vec<dnssec_key::Model>, DbErr> {
+    DnssecKey::find()
+        .filter(dnssec_key::Column::Zone.eq(zone_id))
+        .order_by_asc(dnssec_key::Column::Created)
+        .all(connection)
+        .await
+}
+
+/// Decrypts a zone's keys so the zone can be signed
+pub async fn load_signing_keys<C: ConnectionTrait>(signed_zone: &zone::Model, connection: &C) -> Result<Vec<SigningKey>, DbErr> {
+    let mut signing_keys = vec![];
+
+    for key in get_zone_keys(&signed_zone.id, connection).await? {
+        let private_key = PrivateKey::from_pkcs8(&decrypt_priv_key(&key.nonce, &key.key).await)
+            .expect("Failed to decode DNSSEC private key!");
+
+        signing_keys.push(SigningKey {
+            dnskey: key_dnskey(&key, &signed_zone.origin),
+            key_tag: key.key_tag as u16,
+            private_key,
+            signing: key.status == KeyStatus::ACTIVE.to_string()
+        });
+    }
+
+    Ok(signing_keys)
+}
+
+/// Turns DNSSEC on for a zone with a fresh KSK and ZSK, to be called within a transaction
+pub async fn enable_dnssec<C: ConnectionTrait>(signed_zone: zone::Model, connection: &C) -> Result<zone::Model, DbErr> {
+    generate_key(&signed_zone.id, KeyTypes::KSK, KeyStatus::ACTIVE, connection).await?;
+    generate_key(&signed_zone.id, KeyTypes::ZSK, KeyStatus::ACTIVE, connection).await?;
+
+    let mut updated_zone: zone::ActiveModel = signed_zone.into();
+    updated_zone.dnssec = ActiveValue::Set(true);
+    let updated_zone = updated_zone.update(connection).await?;
+
+    bump_serial(&updated_zone, connection).await?;
+
+    Ok(updated_zone)
+}
+
+/// Turns DNSSEC off for a zone and throws its keys away, to be called within a transaction
+///
+/// The DS has to be removed from the parent first or resolvers will treat the zone as bogus.
+pub async fn disable_dnssec<C: ConnectionTrait>(signed_zone: zone::Model, connection: &C) -> Result<zone::Model, DbErr> {
+    DnssecKey::delete_many()
+        .filter(dnssec_key::Column::Zone.eq(signed_zone.id.clone()))
+        .exec(connection)
+        .await?;
+
+    let mut updated_zone: zone::ActiveModel = signed_zone.into();
+    updated_zone.dnssec = ActiveValue::Set(false);
+    let updated_zone = updated_zone.update(connection).await?;
+
+    bump_serial(&updated_zone, connection).await?;
+
+    Ok(updated_zone)
+}
+
+async fn set_status<C: ConnectionTrait>(key: dnssec_key::Model, status: KeyStatus, now: NaiveDateTime, connection: &C) -> Result<(), DbErr> {
+    let mut updated_key: dnssec_key::ActiveModel = key.into();
+    updated_key.status = ActiveValue::Set(status.to_string());
+    updated_key.status_changed = ActiveValue::Set(now);
+    updated_key.update(connection).await?;
+
+    Ok(())
+}
+
+/// Moves a zone's keys through their rollovers, returning whether its DNSKEY RRset changed
+///
+/// Zone signing keys use pre-publication (RFC 6781 section 4.1.1.1): the new key is published,
+/// then takes over signing, then the old key is removed once its signatures have expired.
+/// Key signing keys use double signatures (RFC 6781 section 4.1.2): the new key signs alongside
+/// the old one until the DS at the parent has been swapped over.
+pub async fn roll_keys<C: ConnectionTrait>(signed_zone: &zone::Model, connection: &C) -> Result<bool, DbErr> {
+    let now = Utc::now().naive_utc();
+    let keys = get_zone_keys(&signed_zone.id, connection).await?;
+    let mut changed = false;
+
+    let (ksks, zsks): (Vec<dnssec_key::Model>, Vec<dnssec_key::Model>) = keys.into_iter()
+        .partition(|key| key.key_type == KeyTypes::KSK.to_string());
+
+    let with_status = |keys: &[dnssec_key::Model], status: KeyStatus| -> Vec<dnssec_key::Model> {
+        keys.iter().filter(|key| key.status == status.to_string()).cloned().collect()
+    };
+
+    // Zone signing keys
+    for retired in with_status(&zsks, KeyStatus::RETIRED) {
+        if retired.status_changed + Duration::days(RETIRE_DELAY_DAYS) <= now {
+            retired.delete(connection).await?;
+            changed = true;
+        }
+    }
+
+    let active_zsks = with_status(&zsks, KeyStatus::ACTIVE);
+    let published_zsks = with_status(&zsks, KeyStatus::PUBLISHED);
+
+    match (active_zsks.last(), published_zsks.last()) {
+        (_, Some(published)) if published.status_changed + Duration::days(PUBLISH_DELAY_DAYS) <= now => {
+            set_status(published.clone(), KeyStatus::ACTIVE, now, connection).await?;
+
+            for active in active_zsks {
+                set_status(active, KeyStatus::RETIRED, now, connection).await?;
+            }
+
+            changed = true;
+        }
+        (Some(active), None) if active.status_changed + Duration::days(ZSK_LIFETIME_DAYS) <= now => {
+            generate_key(&signed_zone.id, KeyTypes::ZSK, KeyStatus::PUBLISHED, connection).await?;
+            changed = true;
+        }
+        (None, None) => {
+            warn!("Zone {} had no ZSK, generating a new one", signed_zone.id);
+            generate_key(&signed_zone.id, KeyTypes::ZSK, KeyStatus::ACTIVE, connection).await?;
+            changed = true;
+        }
+        _ => {}
+    }
+
+    // Key signing keys
+    let active_ksks = with_status(&ksks, KeyStatus::ACTIVE);
+
+    match active_ksks.as_slice() {
+        [] => {
+            warn!("Zone {} had no KSK, generating a new one", signed_zone.id);
+            generate_key(&signed_zone.id, KeyTypes::KSK, KeyStatus::ACTIVE, connection).await?;
+            changed = true;
+        }
+        [active] if active.status_changed + Duration::days(KSK_LIFETIME_DAYS) <= now => {
+            let new_ksk = generate_key(&signed_zone.id, KeyTypes::KSK, KeyStatus::ACTIVE, connection).await?;
+            info!("Rolling the KSK of zone {}, its DS needs replacing with one for key tag {}", signed_zone.id, new_ksk.key_tag);
+            changed = true;
+        }
+        [old @ .., newest] if !old.is_empty() && newest.status_changed + Duration::days(DOUBLE_SIGNATURE_DAYS) <= now => {
+            for old_ksk in old {
+                old_ksk.clone().delete(connection).await?;
+            }
+            changed = true;
+        }
+        _ => {}
+    }
+
+    if changed {
+        bump_serial(signed_zone, connection).await?;
+    }
+
+    Ok(changed)
+}
+
+/// Rolls every signed zone's keys on an interval, for the lifetime of the controller
+pub async fn run_rollover(connection: DatabaseConnection, amqp_channel: Channel) {
+    let mut interval = tokio::time::interval(ROLLOVER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let signed_zones = match Zone::find().filter(zone::Column::Dnssec.eq(true)).all(&connection).await {
+            Ok(signed_zones) => signed_zones,
+            Err(err) => {
+                error!("Failed to retrieve signed zones for key rollover: {}", err);
+                continue;
+            }
+        };
+
+        for signed_zone in signed_zones {
+            let rolled = async {
+                let txn = connection.begin().await?;
+
+                let change_event = match roll_keys(&signed_zone, &txn).await? {
+                    true => Some(record_changes(&signed_zone, vec![Change::ZoneResync], &txn).await?),
+                    false => None
+                };
+
+                txn.commit().await?;
+
+                Ok::<Option<ChangeEvent>, DbErr>(change_event)
+            };
+
+            match rolled.await {
+                Ok(Some(change_event)) => {
+                    info!("Rolled DNSSEC keys of zone {}", signed_zone.id);
+                    publish_change(&amqp_channel, &change_event).await;
+                }
+                Ok(None) => {}
+                Err(err) => error!("Failed to roll DNSSEC keys of zone {}: {}", signed_zone.id, err)
+            }
+        }
+    }
+}
@@ -1,20 +1,39 @@
 use std::fmt;
 use std::fmt::Formatter;
-use picky::x509::{Cert, KeyIdGenMethod};
-use picky::x509::certificate::{CertError, CertificateBuilder};
+use std::net::IpAddr;
+use picky::x509::{Cert, Extension, Extensions, KeyIdGenMethod};
+use picky::x509::certificate::CertError;
 use picky::x509::date::UtcDate;
 
+use chrono::{Duration, Months};
 use chrono::prelude::*;
 use picky::hash::HashAlgorithm;
-use picky::key::PrivateKey;
-use picky::signature::SignatureAlgorithm;
-use picky::x509::name::DirectoryName;
+use picky::key::PublicKey;
+use picky::oids;
+use picky::x509::extension::{ExtendedKeyUsage, ExtensionView, KeyUsage};
+use picky::x509::name::{DirectoryName, GeneralName, GeneralNames};
+use picky_asn1::bit_string::BitString;
+use picky_asn1::wrapper::{ExplicitContextTag0, ExplicitContextTag3, IntegerAsn1};
+use picky_asn1_x509::{Certificate, KeyIdentifier, Name, SubjectPublicKeyInfo, TbsCertificate, Validity, Version};
+use rand::RngCore;
+
+use crate::cert::algorithm::{is_rsa_key, SigningKey};
+use crate::cert::Types;
 
 pub enum InterTarget {
     CLIENT,
     PROXY
 }
 
+impl InterTarget {
+    pub fn cert_type(&self) -> Types {
+        match self {
+            InterTarget::CLIENT => Types::CLIENTINTER,
+            InterTarget::PROXY => Types::PROXYINTER
+        }
+    }
+}
+
 impl fmt::Display for InterTarget {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -24,53 +43,345 @@ impl fmt::Display for InterTarget {
     }
 }
 
-pub async fn generate_root_cert(key: &PrivateKey) -> Result<Cert, CertError> {
-    let current_date: DateTime<Utc> = Utc::now();
+/// Everything in a certificate besides its issuer and serial number
+struct CertContents {
+    subject: DirectoryName,
+    subject_key: PublicKey,
+    not_before: UtcDate,
+    not_after: UtcDate,
+    ca: bool,
+    pathlen: Option<u8>,
+    key_usage: Option<KeyUsage>,
+    extended_key_usage: Option<ExtendedKeyUsage>,
+    subject_alt_name: Option<GeneralNames>
+}
+
+impl CertContents {
+    fn new(subject: DirectoryName, subject_key: PublicKey, not_before: UtcDate, not_after: UtcDate) -> CertContents {
+        CertContents {
+            subject,
+            subject_key,
+            not_before,
+            not_after,
+            ca: false,
+            pathlen: None,
+            key_usage: None,
+            extended_key_usage: None,
+            subject_alt_name: None
+        }
+    }
+}
+
+/// Positive serial numbers with 128 bits of randomness
+fn generate_serial_number() -> IntegerAsn1 {
+    let mut serial_number = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut serial_number);
+
+    IntegerAsn1::from_bytes_be_unsigned(serial_number.to_vec())
+}
+
+/// Builds a certificate signed by an issuer's key
+///
+/// Picky's certificate builder only signs with keys picky can load, which leaves out Ed25519, so
+/// the certificate is put together from picky's ASN.1 types and signed by the issuer key itself.
+fn build_signed_cert(issuer_name: DirectoryName, issuer_key_id: Vec<u8>, issuer_key: &SigningKey, contents: CertContents) -> Result<Cert, CertError> {
+    let key_id_gen_method = KeyIdGenMethod::SPKFullDER(HashAlgorithm::SHA2_512);
+    let subject_key_id = key_id_gen_method
+        .generate_from(&contents.subject_key)
+        .map_err(|source| CertError::KeyIdGen { source })?;
+
+    let mut extensions = vec![];
+
+    // Basic constraints are only critical alongside a digital signature key usage, as with picky
+    match contents.key_usage {
+        Some(key_usage) => {
+            let basic_constraints = Extension::new_basic_constraints(contents.ca, contents.pathlen);
+            extensions.push(match key_usage.digital_signature() {
+                true => basic_constraints.into_critical(),
+                false => basic_constraints.into_non_critical()
+            });
+            extensions.push(Extension::new_key_usage(key_usage));
+        }
+        None => extensions.push(Extension::new_basic_constraints(contents.ca, contents.pathlen).into_non_critical())
+    }
+
+    if let Some(extended_key_usage) = contents.extended_key_usage {
+        extensions.push(Extension::new_extended_key_usage(extended_key_usage));
+    }
+
+    if let Some(subject_alt_name) = contents.subject_alt_name {
+        extensions.push(Extension::new_subject_alt_name(picky_asn1_x509::GeneralNames::from(subject_alt_name)));
+    }
+
+    extensions.push(Extension::new_subject_key_identifier(subject_key_id));
+    extensions.push(Extension::new_authority_key_identifier(KeyIdentifier::from(issuer_key_id), None, None));
+
+    let tbs_certificate = TbsCertificate {
+        version: ExplicitContextTag0(Version::V3),
+        serial_number: generate_serial_number(),
+        signature: issuer_key.algorithm_identifier(),
+        issuer: Name::from(issuer_name),
+        validity: Validity {
+            not_before: contents.not_before.into(),
+            not_after: contents.not_after.into()
+        },
+        subject: Name::from(contents.subject),
+        subject_public_key_info: SubjectPublicKeyInfo::from(contents.subject_key),
+        extensions: ExplicitContextTag3(Extensions(extensions))
+    };
 
-    CertificateBuilder::new()
-        .validity(
-            UtcDate::ymd(
-                current_date.year() as u16,
-                current_date.month() as u8,
-                current_date.day() as u8
-            ).unwrap(),
-            UtcDate::ymd(
-                (current_date.year() + 20) as u16,
-                current_date.month() as u8,
-                current_date.day() as u8
-            ).unwrap()
-        )
-        .self_signed(DirectoryName::new_common_name("Driptorch"), key)
-        .ca(true)
-        .signature_hash_type(SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_512))
-        .key_id_gen_method(KeyIdGenMethod::SPKFullDER(HashAlgorithm::SHA2_512))
-        .build()
-}
-
-pub async fn generate_inter_cert(key: &PrivateKey, target: InterTarget, root: (&Cert, &PrivateKey)) -> Result<Cert, CertError> {
+    let tbs_der = picky_asn1_der::to_vec(&tbs_certificate)
+        .map_err(|source| CertError::Asn1Serialization { element: "tbs certificate", source })?;
+    let signature = issuer_key.sign(&tbs_der)
+        .map_err(|source| CertError::Signature { source })?;
+
+    let certificate = Certificate {
+        tbs_certificate,
+        signature_algorithm: issuer_key.algorithm_identifier(),
+        signature_value: BitString::with_bytes(signature).into()
+    };
+
+    let der = picky_asn1_der::to_vec(&certificate)
+        .map_err(|source| CertError::Asn1Serialization { element: "certificate", source })?;
+
+    Cert::from_der(&der)
+}
+
+/// The whole days a CA certificate is valid for, from today until the same day a number of years later
+///
+/// Certificates issued on 29 February expire on the 28th, since most years don't have the day.
+fn ca_validity(current_date: DateTime<Utc>, years: u32) -> Result<(UtcDate, UtcDate), CertError> {
+    let today = current_date.naive_utc().date();
+    let expiry = today.checked_add_months(Months::new(years * 12))
+        .ok_or(CertError::MissingBuilderArgument { arg: "valid_to" })?;
+
+    let not_before = UtcDate::ymd(today.year() as u16, today.month() as u8, today.day() as u8)
+        .ok_or(CertError::MissingBuilderArgument { arg: "valid_from" })?;
+    let not_after = UtcDate::ymd(expiry.year() as u16, expiry.month() as u8, expiry.day() as u8)
+        .ok_or(CertError::MissingBuilderArgument { arg: "valid_to" })?;
+
+    Ok((not_before, not_after))
+}
+
+pub async fn generate_root_cert(key: &SigningKey) -> Result<Cert, CertError> {
+    let (not_before, not_after) = ca_validity(Utc::now(), 20)?;
+    let name = DirectoryName::new_common_name("Driptorch");
+    let public_key = key.public_key();
+    let key_id = KeyIdGenMethod::SPKFullDER(HashAlgorithm::SHA2_512)
+        .generate_from(&public_key)
+        .map_err(|source| CertError::KeyIdGen { source })?;
+
+    let mut contents = CertContents::new(
+        name.clone(),
+        public_key,
+        not_before,
+        not_after
+    );
+    contents.ca = true;
+
+    build_signed_cert(name, key_id, key, contents)
+}
+
+pub async fn generate_inter_cert(key: &SigningKey, target: InterTarget, root: (&Cert, &SigningKey)) -> Result<Cert, CertError> {
+    let (not_before, not_after) = ca_validity(Utc::now(), 5)?;
+
+    let mut contents = CertContents::new(
+        DirectoryName::new_common_name(format!("Driptorch {}", target)),
+        key.public_key(),
+        not_before,
+        not_after
+    );
+    contents.ca = true;
+    contents.pathlen = Some(0);
+
+    build_signed_cert(root.0.subject_name(), root.0.subject_key_identifier()?.to_vec(), root.1, contents)
+}
+
+/// Signs a new root with the root it replaces, so anything trusting the old root trusts the new one
+///
+/// The cross-signed copy can't outlive the old root, so it expires alongside it.
+pub async fn generate_cross_cert(new_root: &Cert, old_root: (&Cert, &SigningKey)) -> Result<Cert, CertError> {
+    let mut contents = CertContents::new(
+        new_root.subject_name(),
+        new_root.public_key().clone(),
+        utc_date(Utc::now()),
+        old_root.0.valid_not_after()
+    );
+    contents.ca = true;
+
+    build_signed_cert(old_root.0.subject_name(), old_root.0.subject_key_identifier()?.to_vec(), old_root.1, contents)
+}
+
+/// Who a leaf certificate is for, which decides its intermediate and extended key usage
+pub enum LeafTarget {
+    CLIENT,
+    PROXY
+}
+
+impl LeafTarget {
+    /// The intermediate leaves of this kind are signed by
+    pub fn inter_type(&self) -> Types {
+        match self {
+            LeafTarget::CLIENT => Types::CLIENTINTER,
+            LeafTarget::PROXY => Types::PROXYINTER
+        }
+    }
+
+    pub fn leaf_type(&self) -> Types {
+        match self {
+            LeafTarget::CLIENT => Types::CLIENTLEAF,
+            LeafTarget::PROXY => Types::PROXYLEAF
+        }
+    }
+}
+
+impl fmt::Display for LeafTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LeafTarget::CLIENT => write!(f, "CLIENT"),
+            LeafTarget::PROXY => write!(f, "PROXY")
+        }
+    }
+}
+
+/// The subject, alternative names and lifetime of a leaf certificate
+pub struct LeafOptions {
+    pub common_name: String,
+    pub dns_names: Vec<String>,
+    pub ip_addresses: Vec<IpAddr>,
+    pub validity: Duration
+}
+
+pub fn utc_date(date: DateTime<Utc>) -> UtcDate {
+    UtcDate::new(
+        date.year() as u16,
+        date.month() as u8,
+        date.day() as u8,
+        date.hour() as u8,
+        date.minute() as u8,
+        date.second() as u8
+    ).unwrap()
+}
+
+pub fn from_utc_date(date: &UtcDate) -> DateTime<Utc> {
+    let naive_date = NaiveDate::from_ymd(date.year() as i32, date.month() as u32, date.day() as u32)
+        .and_hms(date.hour() as u32, date.minute() as u32, date.second() as u32);
+
+    DateTime::<Utc>::from_utc(naive_date, Utc)
+}
+
+/// DNS names must already be ASCII, see `is_valid_dns_name`
+fn subject_alt_names(options: &LeafOptions) -> Option<GeneralNames> {
+    let mut names: Vec<GeneralName> = options.dns_names.iter()
+        .map(|dns_name| GeneralName::new_dns_name(dns_name.clone()).expect("DNS names must be ASCII!"))
+        .collect();
+
+    for ip_address in &options.ip_addresses {
+        names.push(match ip_address {
+            IpAddr::V4(ip_address) => GeneralName::new_ip_address(ip_address.octets().to_vec()),
+            IpAddr::V6(ip_address) => GeneralName::new_ip_address(ip_address.octets().to_vec())
+        });
+    }
+
+    match names.is_empty() {
+        true => None,
+        false => Some(GeneralNames::from(names))
+    }
+}
+
+/// Whether a name can go in a certificate's subject alternative names
+pub fn is_valid_dns_name(dns_name: &str) -> bool {
+    !dns_name.is_empty() && dns_name.is_ascii()
+}
+
+fn build_leaf_cert(subject: DirectoryName, subject_key: PublicKey, names: Option<GeneralNames>, target: &LeafTarget, validity: Duration, inter: (&Cert, &SigningKey)) -> Result<Cert, CertError> {
     let current_date: DateTime<Utc> = Utc::now();
 
-    CertificateBuilder::new()
-        .validity(
-            UtcDate::ymd(
-                current_date.year() as u16,
-                current_date.month() as u8,
-                current_date.day() as u8
-            ).unwrap(),
-            UtcDate::ymd(
-                (current_date.year() + 5) as u16,
-                current_date.month() as u8,
-                current_date.day() as u8
-            ).unwrap()
-        )
-        .issuer_cert(&root.0, &root.1)
-        .ca(true)
-        .signature_hash_type(SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_512))
-        .key_id_gen_method(KeyIdGenMethod::SPKFullDER(HashAlgorithm::SHA2_512))
-        .pathlen(0)
-        .subject(
-            DirectoryName::new_common_name(format!("Driptorch {}", target.to_string())),
-            key.to_public_key()
-        )
-        .build()
-}
\ No newline at end of file
+    // Only RSA keys can encipher, EC keys agree on them instead
+    let mut key_usage = KeyUsage::new(3);
+    key_usage.set_digital_signature(true);
+    key_usage.set_key_encipherment(is_rsa_key(&subject_key));
+
+    let extended_key_usage = match target {
+        LeafTarget::CLIENT => ExtendedKeyUsage::new(vec![oids::kp_client_auth()]),
+        LeafTarget::PROXY => ExtendedKeyUsage::new(vec![oids::kp_server_auth()])
+    };
+
+    let mut contents = CertContents::new(subject, subject_key, utc_date(current_date), utc_date(current_date + validity));
+    contents.key_usage = Some(key_usage);
+    contents.extended_key_usage = Some(extended_key_usage);
+    contents.subject_alt_name = names;
+
+    build_signed_cert(inter.0.subject_name(), inter.0.subject_key_identifier()?.to_vec(), inter.1, contents)
+}
+
+/// Issues a leaf certificate for a public key, signed by the target's intermediate
+///
+/// Client leaves can only authenticate clients, and proxy leaves can only authenticate servers.
+pub async fn generate_leaf_cert(subject_key: PublicKey, target: &LeafTarget, options: &LeafOptions, inter: (&Cert, &SigningKey)) -> Result<Cert, CertError> {
+    build_leaf_cert(
+        DirectoryName::new_common_name(options.common_name.clone()),
+        subject_key,
+        subject_alt_names(options),
+        target,
+        options.validity,
+        inter
+    )
+}
+
+/// Re-signs a leaf certificate with a new validity period, keeping its subject, key and names
+///
+/// Holders keep their private key, so clients enrolled with a CSR can be renewed without one.
+pub async fn renew_leaf_cert(leaf: &Cert, target: &LeafTarget, validity: Duration, inter: (&Cert, &SigningKey)) -> Result<Cert, CertError> {
+    let names = leaf.extensions().iter().find_map(|extension| match extension.extn_value() {
+        ExtensionView::SubjectAltName(names) => Some(GeneralNames::from(names)),
+        _ => None
+    });
+
+    build_leaf_cert(leaf.subject_name(), leaf.public_key().clone(), names, target, validity, inter)
+}
+
+/// The DNS names in a certificate's subject alternative names
+pub fn dns_names(cert: &Cert) -> Vec<String> {
+    cert.extensions().iter().find_map(|extension| match extension.extn_value() {
+        ExtensionView::SubjectAltName(names) => Some(
+            names.0.iter()
+                .cloned()
+                .filter_map(|name| match GeneralName::from(name) {
+                    GeneralName::DNSName(dns_name) => Some(dns_name.to_string()),
+                    _ => None
+                })
+                .collect()
+        ),
+        _ => None
+    }).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ca_validity_spans_whole_years() {
+        let (not_before, not_after) = ca_validity(Utc.ymd(2022, 10, 9).and_hms(17, 12, 4), 5).unwrap();
+
+        assert_eq!((not_before.year(), not_before.month(), not_before.day()), (2022, 10, 9));
+        assert_eq!((not_after.year(), not_after.month(), not_after.day()), (2027, 10, 9));
+    }
+
+    #[test]
+    fn ca_validity_from_leap_day_ends_on_the_28th() {
+        let (not_before, not_after) = ca_validity(Utc.ymd(2024, 2, 29).and_hms(0, 0, 0), 5).unwrap();
+
+        assert_eq!((not_before.year(), not_before.month(), not_before.day()), (2024, 2, 29));
+        assert_eq!((not_after.year(), not_after.month(), not_after.day()), (2029, 2, 28));
+    }
+
+    #[test]
+    fn ca_validity_from_leap_day_to_leap_year_keeps_the_day() {
+        let (_, not_after) = ca_validity(Utc.ymd(2024, 2, 29).and_hms(0, 0, 0), 20).unwrap();
+
+        assert_eq!((not_after.year(), not_after.month(), not_after.day()), (2044, 2, 29));
+    }
+}
@@ -1,13 +1,33 @@
-use std::{env, fmt, fs};
+use std::{env, fmt};
 use std::fmt::Formatter;
-use std::path::Path;
-use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305};
-use chacha20poly1305::aead::{Aead, OsRng};
+use chrono::Duration;
+use picky::key::{KeyError, PublicKey};
+use picky::x509::Cert;
+use picky::x509::certificate::CertError;
+use sea_orm::*;
+use ulid::Ulid;
+use zeroize::Zeroizing;
 
+use crate::cert::algorithm::{KeyAlgorithm, SigningKey};
+use crate::cert::generate::{generate_leaf_cert, is_valid_dns_name, LeafOptions, LeafTarget};
+use crate::cert::keystore::{KeyStore, NONCE_LENGTH};
+use crate::entities::certificate;
+use crate::entities::prelude::Certificate;
+
+pub mod acme;
+pub mod algorithm;
+pub mod der;
+pub mod dnssec;
 pub mod generate;
+pub mod keystore;
+pub mod renewal;
+pub mod revocation;
+pub mod rotation;
 
 pub enum Types {
     ROOT,
+    /// A rotated root signed by the root it replaced, so leaves chain to either
+    CROSSROOT,
     CLIENTINTER,
     PROXYINTER,
     CLIENTLEAF,
@@ -18,6 +38,7 @@ impl fmt::Display for Types {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Types::ROOT => write!(f, "ROOT"),
+            Types::CROSSROOT => write!(f, "CROSSROOT"),
             Types::CLIENTINTER => write!(f, "CLIENTINTER"),
             Types::PROXYINTER => write!(f, "PROXYINTER"),
             Types::CLIENTLEAF => write!(f, "CLIENTLEAF"),
@@ -26,14 +47,270 @@ impl fmt::Display for Types {
     }
 }
 
+/// Encrypts a private key with XCC20_KEY, wiping the plaintext afterwards
 pub async fn encrypt_priv_key(key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
-    let cipher = XChaCha20Poly1305::new_from_slice(
-        fs::read(
-            Path::new(&env::var("XCC20_KEY").expect("XCC20_KEY must be set!"))
-        ).expect("Failed to load the XCC20 key!").as_slice()
-    ).expect("Error creating chacha20 cipher!");
+    let key = Zeroizing::new(key);
+    let encrypted = KeyStore::from_env("XCC20_KEY")
+        .expect("Failed to load the XCC20 key!")
+        .encrypt(&key);
+
+    (encrypted.nonce, encrypted.key)
+}
+
+pub async fn decrypt_priv_key(nonce: &[u8], key: &[u8]) -> Zeroizing<Vec<u8>> {
+    KeyStore::from_env("XCC20_KEY")
+        .expect("Failed to load the XCC20 key!")
+        .decrypt(nonce, key)
+        .expect("Failed to decrypt private key!")
+}
+
+/// Loads the root certificate alongside its private key, skipping roots that are being rotated out
+///
+/// The first root's key is RSA_KEY and its row only holds placeholder bytes, while rotated roots
+/// keep their key encrypted in the database like intermediates.
+pub async fn load_root<C: ConnectionTrait>(rsa_key: &SigningKey, connection: &C) -> Result<Option<(Cert, SigningKey)>, DbErr> {
+    let root_model = Certificate::find()
+        .filter(certificate::Column::CertType.eq(Types::ROOT.to_string()))
+        .filter(certificate::Column::Retiring.is_null())
+        .one(connection)
+        .await?;
+
+    let root_model = match root_model {
+        None => return Ok(None),
+        Some(root_model) => root_model
+    };
+
+    let root_cert = Cert::from_der(&root_model.data)
+        .expect("Failed to decode root cert!");
+
+    let root_key = match (&root_model.nonce, &root_model.key) {
+        (Some(nonce), Some(key)) if nonce.len() == NONCE_LENGTH => {
+            SigningKey::from_pkcs8(&decrypt_priv_key(nonce, key).await)
+                .expect("Failed to decode root private key!")
+        }
+        _ => rsa_key.clone()
+    };
+
+    Ok(Some((root_cert, root_key)))
+}
+
+/// Loads the certificates above the intermediates: the root, preceded by its cross-signed copy while
+/// the previous root is retiring
+pub async fn load_root_chain<C: ConnectionTrait>(connection: &C) -> Result<Vec<Cert>, DbErr> {
+    let mut chain = vec![];
+
+    for cert_type in [Types::CROSSROOT, Types::ROOT] {
+        let chain_model = Certificate::find()
+            .filter(certificate::Column::CertType.eq(cert_type.to_string()))
+            .filter(certificate::Column::Retiring.is_null())
+            .one(connection)
+            .await?;
+
+        if let Some(chain_model) = chain_model {
+            chain.push(Cert::from_der(&chain_model.data).expect("Failed to decode root cert!"));
+        }
+    }
+
+    Ok(chain)
+}
 
-    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+/// Loads an intermediate certificate alongside its decrypted private key, skipping intermediates
+/// that are being rotated out
+pub async fn load_intermediate<C: ConnectionTrait>(cert_type: Types, connection: &C) -> Result<Option<(Cert, SigningKey)>, DbErr> {
+    let inter_model = Certificate::find()
+        .filter(certificate::Column::CertType.eq(cert_type.to_string()))
+        .filter(certificate::Column::Retiring.is_null())
+        .one(connection)
+        .await?;
+
+    let inter_model = match inter_model {
+        None => return Ok(None),
+        Some(inter_model) => inter_model
+    };
+
+    let inter_cert = Cert::from_der(&inter_model.data)
+        .expect("Failed to decode intermediate cert!");
+
+    let pkcs8 = match (&inter_model.nonce, &inter_model.key) {
+        (Some(nonce), Some(key)) => decrypt_priv_key(nonce, key).await,
+        _ => panic!("Intermediate cert {} has no private key!", inter_model.id)
+    };
+
+    let priv_key = SigningKey::from_pkcs8(&pkcs8)
+        .expect("Failed to decode intermediate private key!");
+
+    Ok(Some((inter_cert, priv_key)))
+}
+
+/// Loads every intermediate of a type that hasn't been revoked, retiring ones included, so leaves
+/// issued before a rotation can still be matched to theirs
+pub async fn load_intermediates<C: ConnectionTrait>(cert_type: Types, connection: &C) -> Result<Vec<(certificate::Model, Cert)>, DbErr> {
+    Ok(Certificate::find()
+        .filter(certificate::Column::CertType.eq(cert_type.to_string()))
+        .filter(certificate::Column::Revoked.is_null())
+        .order_by_asc(certificate::Column::Id)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|inter_model| {
+            let inter_cert = Cert::from_der(&inter_model.data)
+                .expect("Failed to decode intermediate cert!");
+
+            (inter_model, inter_cert)
+        })
+        .collect())
+}
+
+/// Finds the intermediate that signed a leaf among those from `load_intermediates`
+pub fn find_issuer<'a>(leaf_cert: &Cert, intermediates: &'a [(certificate::Model, Cert)]) -> Option<&'a (certificate::Model, Cert)> {
+    intermediates.iter().find(|(_, inter_cert)| inter_cert.is_parent_of(leaf_cert).is_ok())
+}
+
+/// Loads the chain above a leaf, starting from the intermediate that actually signed it
+///
+/// Until a leaf is re-issued after a rotation that's the retiring intermediate, which only chains to
+/// the retiring root.
+pub async fn load_issuer_chain<C: ConnectionTrait>(leaf_cert: &Cert, inter_type: Types, connection: &C) -> Result<Option<Vec<Cert>>, DbErr> {
+    let intermediates = load_intermediates(inter_type, connection).await?;
+
+    let (inter_model, inter_cert) = match find_issuer(leaf_cert, &intermediates) {
+        None => return Ok(None),
+        Some(issuer) => issuer
+    };
+
+    let mut chain = vec![inter_cert.clone()];
+
+    if inter_model.retiring.is_none() {
+        chain.extend(load_root_chain(connection).await?);
+        return Ok(Some(chain));
+    }
+
+    let retiring_root_model = Certificate::find()
+        .filter(certificate::Column::CertType.eq(Types::ROOT.to_string()))
+        .filter(certificate::Column::Retiring.is_not_null())
+        .one(connection)
+        .await?;
+
+    if let Some(retiring_root_model) = retiring_root_model {
+        chain.push(Cert::from_der(&retiring_root_model.data).expect("Failed to decode root cert!"));
+    }
 
-    (nonce.to_vec(), cipher.encrypt(&nonce, key.as_slice()).expect("Failed to encrypt private key!"))
-}
\ No newline at end of file
+    Ok(Some(chain))
+}
+
+/// How long new leaves are valid for, set in days as CLIENT_LEAF_VALIDITY_DAYS or PROXY_LEAF_VALIDITY_DAYS
+pub fn leaf_validity(target: &LeafTarget) -> Duration {
+    let (variable, default_days) = match target {
+        LeafTarget::CLIENT => ("CLIENT_LEAF_VALIDITY_DAYS", 365),
+        LeafTarget::PROXY => ("PROXY_LEAF_VALIDITY_DAYS", 90)
+    };
+
+    let days = env::var(variable)
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(default_days);
+
+    Duration::days(days)
+}
+
+#[derive(Debug)]
+pub enum IssueError {
+    Database(DbErr),
+    MissingIntermediate(String),
+    InvalidName(String),
+    Key(KeyError),
+    Cert(CertError)
+}
+
+impl fmt::Display for IssueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueError::Database(err) => write!(f, "Database error: {}", err),
+            IssueError::MissingIntermediate(cert_type) => write!(f, "No {} cert to sign with", cert_type),
+            IssueError::InvalidName(name) => write!(f, "{} can't be used as a certificate name", name),
+            IssueError::Key(err) => write!(f, "Key error: {}", err),
+            IssueError::Cert(err) => write!(f, "Certificate error: {}", err)
+        }
+    }
+}
+
+impl From<DbErr> for IssueError {
+    fn from(err: DbErr) -> Self {
+        IssueError::Database(err)
+    }
+}
+
+impl From<KeyError> for IssueError {
+    fn from(err: KeyError) -> Self {
+        IssueError::Key(err)
+    }
+}
+
+impl From<CertError> for IssueError {
+    fn from(err: CertError) -> Self {
+        IssueError::Cert(err)
+    }
+}
+
+/// A stored leaf, with the intermediate that signed it
+pub struct IssuedLeaf {
+    pub model: certificate::Model,
+    pub cert: Cert,
+    pub issuer: Cert
+}
+
+/// Issues a leaf signed by the target's intermediate and stores it in the certificate table
+///
+/// Without a subject key one is generated and stored encrypted alongside the certificate, otherwise
+/// the key stays with whoever sent the CSR and only the certificate is stored.
+pub async fn issue_leaf<C: ConnectionTrait>(target: LeafTarget, subject_key: Option<PublicKey>, options: &LeafOptions, connection: &C) -> Result<IssuedLeaf, IssueError> {
+    if let Some(dns_name) = options.dns_names.iter().find(|dns_name| !is_valid_dns_name(dns_name)) {
+        return Err(IssueError::InvalidName(dns_name.clone()));
+    }
+
+    let (inter_cert, inter_key) = load_intermediate(target.inter_type(), connection)
+        .await?
+        .ok_or_else(|| IssueError::MissingIntermediate(target.inter_type().to_string()))?;
+
+    let (subject_key, leaf_key) = match subject_key {
+        Some(subject_key) => (subject_key, None),
+        None => {
+            let leaf_key = SigningKey::generate(KeyAlgorithm::for_type(&target.leaf_type()))?;
+            (leaf_key.public_key(), Some(leaf_key))
+        }
+    };
+
+    let leaf_cert = generate_leaf_cert(subject_key, &target, options, (&inter_cert, &inter_key)).await?;
+
+    let (nonce, key) = match &leaf_key {
+        Some(leaf_key) => {
+            let (nonce, key) = encrypt_priv_key(leaf_key.to_pkcs8()?).await;
+            (Some(nonce), Some(key))
+        }
+        None => (None, None)
+    };
+
+    let leaf_model = certificate::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        data: ActiveValue::Set(leaf_cert.to_der()?),
+        key: ActiveValue::Set(key),
+        nonce: ActiveValue::Set(nonce),
+        cert_type: ActiveValue::Set(target.leaf_type().to_string()),
+        revoked: ActiveValue::Set(None),
+        revocation_reason: ActiveValue::Set(None),
+        retiring: ActiveValue::Set(None),
+        chain: ActiveValue::Set(None),
+        fetched: ActiveValue::Set(None)
+    }
+        .insert(connection)
+        .await?;
+
+    info!("Issued {} leaf cert {} for {}", target, leaf_model.id, options.common_name);
+
+    Ok(IssuedLeaf {
+        model: leaf_model,
+        cert: leaf_cert,
+        issuer: inter_cert
+    })
+}
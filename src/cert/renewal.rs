@@ -0,0 +1,350 @@
+use std::env;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use lapin::{BasicProperties, Channel, ExchangeKind};
+use lapin::options::{BasicPublishOptions, ExchangeDeclareOptions};
+use lapin::types::FieldTable;
+use picky::x509::Cert;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use ulid::Ulid;
+
+use crate::cert::algorithm::SigningKey;
+use crate::cert::{decrypt_priv_key, IssueError, leaf_validity, load_intermediate, load_root, Types};
+use crate::cert::generate::{from_utc_date, generate_inter_cert, InterTarget, LeafTarget, renew_leaf_cert};
+use crate::cert::rotation::reissue_retiring_leaves;
+use crate::entities::{certificate, certificate_history, client};
+use crate::entities::prelude::{Certificate, CertificateHistory, Client};
+
+const RENEWAL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Bumped whenever the layout of renewal events changes in a way clients need to know about
+pub const CERTIFICATE_EVENT_VERSION: u8 = 1;
+/// Fanout exchange renewal events are published to, routed by certificate type
+pub const CERTIFICATE_EXCHANGE: &str = "driptorch.certificates";
+
+/// Published whenever a certificate is re-signed, so its holder can fetch the new one
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RenewalEvent {
+    pub version: u8,
+    pub certificate_id: String,
+    pub cert_type: String,
+    pub not_after: i64,
+    /// The client holding the certificate, for client leaves
+    pub client_id: Option<String>
+}
+
+/// A certificate that expires within the renewal threshold, or already has
+#[derive(Serialize, Clone, Debug)]
+pub struct ExpiringCertificate {
+    pub id: String,
+    pub cert_type: String,
+    pub not_after: i64,
+    pub expired: bool
+}
+
+/// The certificates found expiring by the last renewal run, shared with the status endpoint
+pub type ExpiryReport = Arc<RwLock<Vec<ExpiringCertificate>>>;
+
+/// How long before expiry certificates are renewed, set in days as CERT_RENEWAL_DAYS
+pub fn renewal_threshold() -> Duration {
+    let days = env::var("CERT_RENEWAL_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(30);
+
+    Duration::days(days)
+}
+
+pub fn not_after(model: &certificate::Model) -> Option<DateTime<Utc>> {
+    Cert::from_der(&model.data).ok().map(|cert| from_utc_date(&cert.valid_not_after()))
+}
+
+fn leaf_target(cert_type: &str) -> Option<LeafTarget> {
+    if cert_type == Types::CLIENTLEAF.to_string() {
+        Some(LeafTarget::CLIENT)
+    } else if cert_type == Types::PROXYLEAF.to_string() {
+        Some(LeafTarget::PROXY)
+    } else {
+        None
+    }
+}
+
+fn inter_target(cert_type: &str) -> Option<(InterTarget, LeafTarget)> {
+    if cert_type == Types::CLIENTINTER.to_string() {
+        Some((InterTarget::CLIENT, LeafTarget::CLIENT))
+    } else if cert_type == Types::PROXYINTER.to_string() {
+        Some((InterTarget::PROXY, LeafTarget::PROXY))
+    } else {
+        None
+    }
+}
+
+/// Finds every certificate expiring within a threshold, soonest first
+pub async fn find_expiring<C: ConnectionTrait>(threshold: Duration, connection: &C) -> Result<Vec<ExpiringCertificate>, DbErr> {
+    let now = Utc::now();
+
+    let mut expiring: Vec<ExpiringCertificate> = Certificate::find()
+        .filter(certificate::Column::Revoked.is_null())
+        .all(connection)
+        .await?
+        .iter()
+        .filter_map(|model| not_after(model).map(|not_after| (model, not_after)))
+        .filter(|(_, not_after)| *not_after < now + threshold)
+        .map(|(model, not_after)| ExpiringCertificate {
+            id: model.id.clone(),
+            cert_type: model.cert_type.clone(),
+            not_after: not_after.timestamp(),
+            expired: not_after < now
+        })
+        .collect();
+
+    expiring.sort_by_key(|certificate| certificate.not_after);
+
+    Ok(expiring)
+}
+
+/// Replaces a certificate with its renewal
+///
+/// Holders keep using the replaced certificate until they fetch the new one, so it's kept in the
+/// history until it expires, where it can still be revoked.
+async fn store_renewal<C: ConnectionTrait>(model: certificate::Model, renewed: &Cert, connection: &C) -> Result<certificate::Model, IssueError> {
+    let superseded = Cert::from_der(&model.data)?;
+
+    certificate_history::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        certificate: ActiveValue::Set(model.id.clone()),
+        data: ActiveValue::Set(model.data.clone()),
+        not_after: ActiveValue::Set(from_utc_date(&superseded.valid_not_after()).naive_utc()),
+        superseded: ActiveValue::Set(Utc::now().naive_utc())
+    }
+        .insert(connection)
+        .await?;
+
+    let mut renewed_model: certificate::ActiveModel = model.into();
+    renewed_model.data = ActiveValue::Set(renewed.to_der()?);
+    renewed_model.fetched = ActiveValue::Set(None);
+
+    Ok(renewed_model.update(connection).await?)
+}
+
+/// Forgets replaced certificates once they've expired, as nobody can use them anymore
+pub async fn prune_history<C: ConnectionTrait>(connection: &C) -> Result<u64, DbErr> {
+    Ok(CertificateHistory::delete_many()
+        .filter(certificate_history::Column::NotAfter.lt(Utc::now().naive_utc()))
+        .exec(connection)
+        .await?
+        .rows_affected)
+}
+
+/// Re-signs a leaf with its intermediate, keeping the leaf's key
+pub async fn renew_leaf<C: ConnectionTrait>(leaf_model: certificate::Model, inter: (&Cert, &SigningKey), connection: &C) -> Result<certificate::Model, IssueError> {
+    let target = leaf_target(&leaf_model.cert_type)
+        .ok_or_else(|| IssueError::MissingIntermediate(leaf_model.cert_type.clone()))?;
+
+    let leaf_cert = Cert::from_der(&leaf_model.data)?;
+    let renewed = renew_leaf_cert(&leaf_cert, &target, leaf_validity(&target), inter).await?;
+
+    store_renewal(leaf_model, &renewed, connection).await
+}
+
+/// Re-signs an intermediate with the root, then re-signs every leaf it issued
+///
+/// The intermediate keeps its key, so existing chains and anything already trusting it carry on
+/// working while holders pick up their re-signed leaves.
+pub async fn renew_intermediate<C: ConnectionTrait>(inter_model: certificate::Model, root: (&Cert, &SigningKey), connection: &C) -> Result<Vec<certificate::Model>, IssueError> {
+    let (inter_target, leaf_target) = inter_target(&inter_model.cert_type)
+        .ok_or_else(|| IssueError::MissingIntermediate(inter_model.cert_type.clone()))?;
+
+    let pkcs8 = match (&inter_model.nonce, &inter_model.key) {
+        (Some(nonce), Some(key)) => decrypt_priv_key(nonce, key).await,
+        _ => return Err(IssueError::MissingIntermediate(inter_model.cert_type.clone()))
+    };
+    let inter_key = SigningKey::from_pkcs8(&pkcs8)?;
+
+    let renewed_inter = generate_inter_cert(&inter_key, inter_target, root).await?;
+    let mut renewed = vec![store_renewal(inter_model, &renewed_inter, connection).await?];
+
+    // Leaves from an ACME CA don't chain to the intermediate, so they're left alone
+    let leaves = Certificate::find()
+        .filter(certificate::Column::CertType.eq(leaf_target.leaf_type().to_string()))
+        .filter(certificate::Column::Revoked.is_null())
+        .filter(certificate::Column::Chain.is_null())
+        .all(connection)
+        .await?;
+
+    for leaf_model in leaves {
+        renewed.push(renew_leaf(leaf_model, (&renewed_inter, &inter_key), connection).await?);
+    }
+
+    Ok(renewed)
+}
+
+pub async fn declare_certificate_exchange(channel: &Channel) -> Result<(), lapin::Error> {
+    channel.exchange_declare(
+        CERTIFICATE_EXCHANGE,
+        ExchangeKind::Fanout,
+        ExchangeDeclareOptions { durable: true, ..ExchangeDeclareOptions::default() },
+        FieldTable::default()
+    ).await
+}
+
+/// Publishes that a certificate was renewed, to be called once the renewal has committed
+pub async fn publish_renewal<C: ConnectionTrait>(channel: &Channel, renewed: &certificate::Model, connection: &C) {
+    let client_id = match leaf_target(&renewed.cert_type) {
+        Some(LeafTarget::CLIENT) => Client::find()
+            .filter(client::Column::Certificate.eq(renewed.id.clone()))
+            .one(connection)
+            .await
+            .ok()
+            .flatten()
+            .map(|holder| holder.id),
+        _ => None
+    };
+
+    let event = RenewalEvent {
+        version: CERTIFICATE_EVENT_VERSION,
+        certificate_id: renewed.id.clone(),
+        cert_type: renewed.cert_type.clone(),
+        not_after: not_after(renewed).map_or(0, |not_after| not_after.timestamp()),
+        client_id
+    };
+
+    let payload = match rmp_serde::to_vec_named(&event) {
+        Ok(payload) => payload,
+        Err(err) => {
+            error!("Failed to encode renewal event for cert {}: {}", renewed.id, err);
+            return;
+        }
+    };
+
+    let published = channel.basic_publish(
+        CERTIFICATE_EXCHANGE,
+        &renewed.cert_type,
+        BasicPublishOptions::default(),
+        &payload,
+        BasicProperties::default()
+            .with_content_type("application/msgpack".into())
+            .with_delivery_mode(2)
+    ).await;
+
+    if let Err(err) = published {
+        error!("Failed to publish renewal event for cert {}: {}", renewed.id, err);
+    }
+}
+
+async fn renew_certificates(connection: &DatabaseConnection, amqp_channel: &Channel, rsa_key: &SigningKey) -> Result<(), DbErr> {
+    let threshold = Utc::now() + renewal_threshold();
+    let due = |model: &certificate::Model| matches!(not_after(model), Some(not_after) if not_after < threshold);
+
+    // A retiring chain is replaced by rotation rather than renewed
+    let certificates = Certificate::find()
+        .filter(certificate::Column::Revoked.is_null())
+        .filter(certificate::Column::Retiring.is_null())
+        .all(connection)
+        .await?;
+
+    // Intermediates are re-signed by the root, so they wait for a rotation when it's due, but
+    // leaves only need their intermediate and carry on being renewed
+    let root_due = match certificates.iter().find(|model| model.cert_type == Types::ROOT.to_string()) {
+        Some(root_model) if due(root_model) => {
+            warn!("Root cert {} is about to expire and can't be renewed automatically, rotate it! Only leaves are being renewed until then.", root_model.id);
+            true
+        }
+        Some(_) => false,
+        None => return Ok(())
+    };
+
+    let root = match root_due {
+        true => None,
+        false => load_root(rsa_key, connection).await?
+    };
+
+    let mut renewed = vec![];
+    // Leaves of a renewed intermediate are all re-signed along with it
+    let mut resigned_types = vec![];
+
+    if let Some((root_cert, root_key)) = &root {
+        for inter_model in certificates.iter().filter(|model| inter_target(&model.cert_type).is_some() && due(model)) {
+            let renewal = async {
+                let txn = connection.begin().await?;
+                let renewed_models = renew_intermediate(inter_model.clone(), (root_cert, root_key), &txn).await?;
+                txn.commit().await?;
+
+                Ok::<Vec<certificate::Model>, IssueError>(renewed_models)
+            };
+
+            match renewal.await {
+                Ok(renewed_models) => {
+                    info!("Renewed {} cert {} and {} leaves", inter_model.cert_type, inter_model.id, renewed_models.len() - 1);
+                    resigned_types.push(inter_target(&inter_model.cert_type).unwrap().1.leaf_type().to_string());
+                    renewed.extend(renewed_models);
+                }
+                Err(err) => error!("Failed to renew {} cert {}: {}", inter_model.cert_type, inter_model.id, err)
+            }
+        }
+    }
+
+    // Leaves from an ACME CA are renewed by ordering them again, see `run_acme_renewal`
+    for leaf_model in certificates.iter().filter(|model| leaf_target(&model.cert_type).is_some() && model.chain.is_none() && due(model)) {
+        if resigned_types.contains(&leaf_model.cert_type) {
+            continue;
+        }
+
+        let renewal = async {
+            let target = leaf_target(&leaf_model.cert_type).unwrap();
+            let (inter_cert, inter_key) = load_intermediate(target.inter_type(), connection)
+                .await?
+                .ok_or_else(|| IssueError::MissingIntermediate(target.inter_type().to_string()))?;
+
+            renew_leaf(leaf_model.clone(), (&inter_cert, &inter_key), connection).await
+        };
+
+        match renewal.await {
+            Ok(renewed_model) => {
+                info!("Renewed {} cert {}", leaf_model.cert_type, leaf_model.id);
+                renewed.push(renewed_model);
+            }
+            Err(err) => error!("Failed to renew {} cert {}: {}", leaf_model.cert_type, leaf_model.id, err)
+        }
+    }
+
+    for renewed_model in &renewed {
+        publish_renewal(amqp_channel, renewed_model, connection).await;
+    }
+
+    Ok(())
+}
+
+/// Renews certificates before they expire, finishes re-issuing leaves after a rotation, forgets
+/// expired certificates and keeps the expiry report up to date, for the lifetime of the controller
+///
+/// RSA_KEY is only used while the first root is still in use, rotated roots keep their key in the
+/// database.
+pub async fn run_renewal(connection: DatabaseConnection, amqp_channel: Channel, rsa_key: SigningKey, expiry_report: ExpiryReport) {
+    let mut interval = tokio::time::interval(RENEWAL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = reissue_retiring_leaves(&connection, &amqp_channel).await {
+            error!("Failed to re-issue leaves of the retiring chain: {}", err);
+        }
+
+        if let Err(err) = renew_certificates(&connection, &amqp_channel, &rsa_key).await {
+            error!("Failed to renew certificates: {}", err);
+        }
+
+        if let Err(err) = prune_history(&connection).await {
+            error!("Failed to prune expired certificates from the history: {}", err);
+        }
+
+        match find_expiring(renewal_threshold(), &connection).await {
+            Ok(expiring) => *expiry_report.write().await = expiring,
+            Err(err) => error!("Failed to check certificate expiry: {}", err)
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use picky::hash::HashAlgorithm;
+use picky::signature::SignatureError;
+use picky::x509::Cert;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::algorithm::SigningKey;
+use crate::cert::der::*;
+use crate::cert::generate::LeafTarget;
+use crate::cert::{decrypt_priv_key, load_intermediate, load_intermediates};
+use crate::entities::{certificate, certificate_history};
+use crate::entities::prelude::{Certificate, CertificateHistory};
+
+const OID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_CRL_NUMBER: &[u8] = &[0x55, 0x1d, 0x14];
+const OID_CRL_REASON: &[u8] = &[0x55, 0x1d, 0x15];
+const OID_AUTHORITY_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x23];
+const OID_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+const OID_OCSP_NONCE: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x02];
+
+/// How long CRLs and OCSP responses can be relied on before fetching new ones
+fn update_interval() -> Duration {
+    Duration::hours(24)
+}
+
+/// The RFC 5280 section 5.3.1 reason codes that apply to leaves
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RevocationReason {
+    UNSPECIFIED,
+    KEYCOMPROMISE,
+    AFFILIATIONCHANGED,
+    SUPERSEDED,
+    CESSATIONOFOPERATION
+}
+
+impl RevocationReason {
+    pub fn code(&self) -> u8 {
+        match self {
+            RevocationReason::UNSPECIFIED => 0,
+            RevocationReason::KEYCOMPROMISE => 1,
+            RevocationReason::AFFILIATIONCHANGED => 3,
+            RevocationReason::SUPERSEDED => 4,
+            RevocationReason::CESSATIONOFOPERATION => 5
+        }
+    }
+
+    pub fn from_name(name: &str) -> RevocationReason {
+        match name {
+            "KEYCOMPROMISE" => RevocationReason::KEYCOMPROMISE,
+            "AFFILIATIONCHANGED" => RevocationReason::AFFILIATIONCHANGED,
+            "SUPERSEDED" => RevocationReason::SUPERSEDED,
+            "CESSATIONOFOPERATION" => RevocationReason::CESSATIONOFOPERATION,
+            _ => RevocationReason::UNSPECIFIED
+        }
+    }
+}
+
+impl fmt::Display for RevocationReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RevocationReason::UNSPECIFIED => write!(f, "UNSPECIFIED"),
+            RevocationReason::KEYCOMPROMISE => write!(f, "KEYCOMPROMISE"),
+            RevocationReason::AFFILIATIONCHANGED => write!(f, "AFFILIATIONCHANGED"),
+            RevocationReason::SUPERSEDED => write!(f, "SUPERSEDED"),
+            RevocationReason::CESSATIONOFOPERATION => write!(f, "CESSATIONOFOPERATION")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RevocationError {
+    Database(DbErr),
+    MissingIntermediate(String),
+    Malformed(DerError),
+    Signing(SignatureError)
+}
+
+impl fmt::Display for RevocationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RevocationError::Database(err) => write!(f, "Database error: {}", err),
+            RevocationError::MissingIntermediate(cert_type) => write!(f, "No {} cert to sign with", cert_type),
+            RevocationError::Malformed(err) => write!(f, "Malformed certificate: {}", err),
+            RevocationError::Signing(err) => write!(f, "Failed to sign: {}", err)
+        }
+    }
+}
+
+impl From<DbErr> for RevocationError {
+    fn from(err: DbErr) -> Self {
+        RevocationError::Database(err)
+    }
+}
+
+impl From<DerError> for RevocationError {
+    fn from(err: DerError) -> Self {
+        RevocationError::Malformed(err)
+    }
+}
+
+impl From<SignatureError> for RevocationError {
+    fn from(err: SignatureError) -> Self {
+        RevocationError::Signing(err)
+    }
+}
+
+fn revocation_time(revoked: NaiveDateTime) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(revoked, Utc)
+}
+
+fn revocation_reason(revoked_model: &certificate::Model) -> RevocationReason {
+    revoked_model.revocation_reason.as_deref().map_or(RevocationReason::UNSPECIFIED, RevocationReason::from_name)
+}
+
+/// Signs a to-be-signed structure, giving the complete signed structure
+fn sign(tbs: Vec<u8>, key: &SigningKey) -> Result<Vec<u8>, RevocationError> {
+    let signature = key.sign(&tbs)?;
+
+    Ok(sequence(&[tbs, key.signature_algorithm(), bit_string(&signature)]))
+}
+
+fn extension(extension_oid: &[u8], value: Vec<u8>) -> Vec<u8> {
+    sequence(&[oid(extension_oid), octet_string(&value)])
+}
+
+/// An intermediate signing CRLs and OCSP responses for the leaves it issued
+struct Issuer {
+    cert: Cert,
+    der: Vec<u8>,
+    key_identifier: Option<Vec<u8>>,
+    key: SigningKey
+}
+
+impl Issuer {
+    fn new(cert: Cert, key: SigningKey) -> Issuer {
+        Issuer {
+            der: cert.to_der().expect("Failed to convert cert into der!"),
+            key_identifier: cert.subject_key_identifier().ok().map(|key_identifier| key_identifier.to_vec()),
+            cert,
+            key
+        }
+    }
+}
+
+async fn load_issuer(inter_model: &certificate::Model, inter_cert: Cert) -> Result<Issuer, RevocationError> {
+    let pkcs8 = match (&inter_model.nonce, &inter_model.key) {
+        (Some(nonce), Some(key)) => decrypt_priv_key(nonce, key).await,
+        _ => return Err(RevocationError::MissingIntermediate(inter_model.cert_type.clone()))
+    };
+
+    let inter_key = SigningKey::from_pkcs8(&pkcs8)
+        .expect("Failed to decode intermediate private key!");
+
+    Ok(Issuer::new(inter_cert, inter_key))
+}
+
+fn leaf_target_of(inter_model: &certificate::Model) -> Option<LeafTarget> {
+    [LeafTarget::CLIENT, LeafTarget::PROXY]
+        .into_iter()
+        .find(|target| inter_model.cert_type == target.inter_type().to_string())
+}
+
+/// A leaf as it was issued, either the current certificate of a row or one a renewal replaced,
+/// which share the row's revocation
+struct IssuedCert<'a> {
+    der: &'a [u8],
+    model: &'a certificate::Model
+}
+
+/// Loads the leaves of a kind our intermediates issued, alongside the certificates renewals
+/// replaced which haven't expired yet
+async fn load_leaves<C: ConnectionTrait>(target: &LeafTarget, revoked_only: bool, connection: &C) -> Result<(Vec<certificate::Model>, Vec<certificate_history::Model>), DbErr> {
+    let mut condition = Condition::all()
+        .add(certificate::Column::CertType.eq(target.leaf_type().to_string()))
+        .add(certificate::Column::Chain.is_null());
+
+    if revoked_only {
+        condition = condition.add(certificate::Column::Revoked.is_not_null());
+    }
+
+    let leaf_models = Certificate::find()
+        .filter(condition.clone())
+        .order_by_asc(certificate::Column::Revoked)
+        .all(connection)
+        .await?;
+
+    let history_models = CertificateHistory::find()
+        .inner_join(Certificate)
+        .filter(condition)
+        .order_by_asc(certificate_history::Column::Superseded)
+        .all(connection)
+        .await?;
+
+    Ok((leaf_models, history_models))
+}
+
+fn issued_certs<'a>(leaf_models: &'a [certificate::Model], history_models: &'a [certificate_history::Model]) -> Vec<IssuedCert<'a>> {
+    let mut issued: Vec<IssuedCert> = leaf_models.iter()
+        .map(|leaf_model| IssuedCert { der: &leaf_model.data, model: leaf_model })
+        .collect();
+
+    for history_model in history_models {
+        if let Some(leaf_model) = leaf_models.iter().find(|leaf_model| leaf_model.id == history_model.certificate) {
+            issued.push(IssuedCert { der: &history_model.data, model: leaf_model });
+        }
+    }
+
+    issued
+}
+
+fn encode_crl(issuer: &Issuer, issued: &[IssuedCert], now: DateTime<Utc>) -> Result<Vec<u8>, RevocationError> {
+    let issuer_parts = certificate_parts(&issuer.der)?;
+    let mut revoked_entries = vec![];
+
+    for issued_cert in issued {
+        let revoked = match issued_cert.model.revoked {
+            Some(revoked) => revoked,
+            None => continue
+        };
+
+        let parts = match Cert::from_der(issued_cert.der) {
+            // Each intermediate only lists the leaves it signed
+            Ok(leaf_cert) if issuer.cert.is_parent_of(&leaf_cert).is_err() => continue,
+            Ok(_) => certificate_parts(issued_cert.der).ok(),
+            Err(_) => None
+        };
+
+        let parts = match parts {
+            Some(parts) => parts,
+            None => {
+                warn!("Revoked cert {} can't be decoded, it is missing from the CRL!", issued_cert.model.id);
+                continue;
+            }
+        };
+
+        let mut entry = vec![encode(TAG_INTEGER, parts.serial), utc_time(revocation_time(revoked))];
+
+        // RFC 5280 section 5.3.1, unspecified is left out rather than given as a reason
+        let reason = revocation_reason(issued_cert.model);
+        if reason != RevocationReason::UNSPECIFIED {
+            entry.push(sequence(&[extension(OID_CRL_REASON, enumerated(reason.code()))]));
+        }
+
+        revoked_entries.push(sequence(&entry));
+    }
+
+    let mut crl_extensions = vec![extension(OID_CRL_NUMBER, integer(now.timestamp() as u64))];
+    if let Some(key_identifier) = &issuer.key_identifier {
+        crl_extensions.insert(0, extension(
+            OID_AUTHORITY_KEY_IDENTIFIER,
+            sequence(&[encode(context_primitive(0), key_identifier)])
+        ));
+    }
+
+    let mut tbs_cert_list = vec![
+        integer(1),
+        issuer.key.signature_algorithm(),
+        issuer_parts.subject.to_vec(),
+        utc_time(now),
+        utc_time(now + update_interval())
+    ];
+
+    if !revoked_entries.is_empty() {
+        tbs_cert_list.push(sequence(&revoked_entries));
+    }
+
+    tbs_cert_list.push(encode(context_constructed(0), &sequence(&crl_extensions)));
+
+    sign(sequence(&tbs_cert_list), &issuer.key)
+}
+
+async fn crl_of<C: ConnectionTrait>(issuer: &Issuer, target: &LeafTarget, connection: &C) -> Result<Vec<u8>, RevocationError> {
+    let (leaf_models, history_models) = load_leaves(target, true, connection).await?;
+
+    encode_crl(issuer, &issued_certs(&leaf_models, &history_models), Utc::now())
+}
+
+/// Builds the DER encoded CRL of a kind of leaf, signed by its current intermediate
+///
+/// Leaves issued before a rotation are listed by the CRL of their own intermediate, see
+/// `build_intermediate_crl`.
+pub async fn build_crl<C: ConnectionTrait>(target: &LeafTarget, connection: &C) -> Result<Vec<u8>, RevocationError> {
+    let (inter_cert, inter_key) = load_intermediate(target.inter_type(), connection)
+        .await?
+        .ok_or_else(|| RevocationError::MissingIntermediate(target.inter_type().to_string()))?;
+
+    crl_of(&Issuer::new(inter_cert, inter_key), target, connection).await
+}
+
+/// Builds the DER encoded CRL of any intermediate which hasn't been revoked, retiring ones included
+pub async fn build_intermediate_crl<C: ConnectionTrait>(inter_id: &str, connection: &C) -> Result<Vec<u8>, RevocationError> {
+    let inter_model = Certificate::find_by_id(inter_id.to_string())
+        .filter(certificate::Column::Revoked.is_null())
+        .one(connection)
+        .await?;
+
+    let (inter_model, target) = match inter_model {
+        Some(inter_model) => match leaf_target_of(&inter_model) {
+            Some(target) => (inter_model, target),
+            None => return Err(RevocationError::MissingIntermediate(inter_id.to_string()))
+        },
+        None => return Err(RevocationError::MissingIntermediate(inter_id.to_string()))
+    };
+
+    let inter_cert = Cert::from_der(&inter_model.data)
+        .expect("Failed to decode intermediate cert!");
+    let issuer = load_issuer(&inter_model, inter_cert).await?;
+
+    crl_of(&issuer, &target, connection).await
+}
+
+/// RFC 6960 section 4.2.1 response statuses
+#[derive(Clone, Copy)]
+enum OcspStatus {
+    SUCCESSFUL = 0,
+    MALFORMEDREQUEST = 1,
+    INTERNALERROR = 2,
+    UNAUTHORIZED = 6
+}
+
+fn ocsp_error(status: OcspStatus) -> Vec<u8> {
+    sequence(&[enumerated(status as u8)])
+}
+
+/// A certificate asked about in an OCSP request
+struct CertId<'a> {
+    /// Echoed back in the response
+    encoded: &'a [u8],
+    hash_algorithm: Option<HashAlgorithm>,
+    issuer_name_hash: &'a [u8],
+    issuer_key_hash: &'a [u8],
+    serial: &'a [u8]
+}
+
+struct OcspRequest<'a> {
+    cert_ids: Vec<CertId<'a>>,
+    /// Echoed back in the response to prove it is fresh
+    nonce: Option<&'a [u8]>
+}
+
+fn parse_cert_id(encoded: &[u8]) -> Result<CertId<'_>, DerError> {
+    let mut cert_id_reader = DerReader::new(DerReader::new(encoded).read(TAG_SEQUENCE)?);
+
+    let hash_algorithm = match DerReader::new(cert_id_reader.read(TAG_SEQUENCE)?).read(TAG_OID)? {
+        OID_SHA1 => Some(HashAlgorithm::SHA1),
+        OID_SHA256 => Some(HashAlgorithm::SHA2_256),
+        _ => None
+    };
+
+    Ok(CertId {
+        encoded,
+        hash_algorithm,
+        issuer_name_hash: cert_id_reader.read(TAG_OCTET_STRING)?,
+        issuer_key_hash: cert_id_reader.read(TAG_OCTET_STRING)?,
+        serial: cert_id_reader.read(TAG_INTEGER)?
+    })
+}
+
+fn parse_ocsp_request(request: &[u8]) -> Result<OcspRequest<'_>, DerError> {
+    let mut request_reader = DerReader::new(DerReader::new(request).read(TAG_SEQUENCE)?);
+    let mut tbs_reader = DerReader::new(request_reader.read(TAG_SEQUENCE)?);
+
+    tbs_reader.read_optional(context_constructed(0))?;
+    tbs_reader.read_optional(context_constructed(1))?;
+
+    let mut cert_ids = vec![];
+    let mut request_list_reader = DerReader::new(tbs_reader.read(TAG_SEQUENCE)?);
+
+    while !request_list_reader.is_empty() {
+        let mut single_request_reader = DerReader::new(request_list_reader.read(TAG_SEQUENCE)?);
+        let (_, encoded) = single_request_reader.read_element(TAG_SEQUENCE)?;
+        cert_ids.push(parse_cert_id(encoded)?);
+    }
+
+    let mut nonce = None;
+
+    if let Some(request_extensions) = tbs_reader.read_optional(context_constructed(2))? {
+        let mut extensions_reader = DerReader::new(DerReader::new(request_extensions).read(TAG_SEQUENCE)?);
+
+        while !extensions_reader.is_empty() {
+            let (content, encoded) = extensions_reader.read_element(TAG_SEQUENCE)?;
+
+            if DerReader::new(content).read(TAG_OID)? == OID_OCSP_NONCE {
+                nonce = Some(encoded);
+            }
+        }
+    }
+
+    Ok(OcspRequest { cert_ids, nonce })
+}
+
+fn issued_by(issuer_parts: &CertificateParts, cert_id: &CertId) -> bool {
+    match cert_id.hash_algorithm {
+        Some(hash_algorithm) => hash_algorithm.digest(issuer_parts.subject) == cert_id.issuer_name_hash
+            && hash_algorithm.digest(issuer_parts.public_key) == cert_id.issuer_key_hash,
+        None => false
+    }
+}
+
+fn encode_ocsp_response(issuer: &Issuer, issued: &[IssuedCert], request: &OcspRequest, now: DateTime<Utc>) -> Result<Vec<u8>, RevocationError> {
+    let issuer_parts = certificate_parts(&issuer.der)?;
+    let mut single_responses = vec![];
+
+    for cert_id in &request.cert_ids {
+        let issued_cert = issued.iter().find(|issued_cert| {
+            issued_by(&issuer_parts, cert_id)
+                && matches!(certificate_parts(issued_cert.der), Ok(parts) if parts.serial == cert_id.serial)
+        });
+
+        let cert_status = match issued_cert {
+            None => encode(context_primitive(2), &[]),
+            Some(issued_cert) => match issued_cert.model.revoked {
+                None => encode(context_primitive(0), &[]),
+                Some(revoked) => encode(context_constructed(1), &[
+                    generalized_time(revocation_time(revoked)),
+                    encode(context_constructed(0), &enumerated(revocation_reason(issued_cert.model).code()))
+                ].concat())
+            }
+        };
+
+        single_responses.push(sequence(&[
+            cert_id.encoded.to_vec(),
+            cert_status,
+            generalized_time(now),
+            encode(context_constructed(0), &generalized_time(now + update_interval()))
+        ]));
+    }
+
+    // Responses are signed by the intermediate itself, identified by the hash of its key
+    let mut response_data = vec![
+        encode(context_constructed(2), &octet_string(&HashAlgorithm::SHA1.digest(issuer_parts.public_key))),
+        generalized_time(now),
+        sequence(&single_responses)
+    ];
+
+    if let Some(nonce) = request.nonce {
+        response_data.push(encode(context_constructed(1), &sequence(&[nonce.to_vec()])));
+    }
+
+    let basic_response = sign(sequence(&response_data), &issuer.key)?;
+
+    Ok(sequence(&[
+        enumerated(OcspStatus::SUCCESSFUL as u8),
+        encode(context_constructed(0), &sequence(&[oid(OID_OCSP_BASIC), octet_string(&basic_response)]))
+    ]))
+}
+
+/// Answers as the first intermediate of a kind of leaf that issued any of the certificates asked
+/// about, or None if none of them did
+///
+/// Retiring intermediates answer for the leaves they issued until the old chain is retired.
+async fn respond_as<C: ConnectionTrait>(target: &LeafTarget, request: &OcspRequest<'_>, connection: &C) -> Result<Option<Vec<u8>>, RevocationError> {
+    for (inter_model, inter_cert) in load_intermediates(target.inter_type(), connection).await? {
+        let inter_parts = certificate_parts(&inter_model.data)?;
+
+        if !request.cert_ids.iter().any(|cert_id| issued_by(&inter_parts, cert_id)) {
+            continue;
+        }
+
+        let issuer = load_issuer(&inter_model, inter_cert).await?;
+        let (leaf_models, history_models) = load_leaves(target, false, connection).await?;
+
+        return encode_ocsp_response(&issuer, &issued_certs(&leaf_models, &history_models), request, Utc::now()).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Answers a DER encoded OCSP request for client or proxy leaves
+///
+/// Every certificate in a request must come from the same intermediate, the others are answered
+/// as unknown. Certificates replaced by a renewal are answered for until they expire.
+pub async fn ocsp_response<C: ConnectionTrait>(request: &[u8], connection: &C) -> Vec<u8> {
+    let request = match parse_ocsp_request(request) {
+        Ok(request) if !request.cert_ids.is_empty() => request,
+        _ => return ocsp_error(OcspStatus::MALFORMEDREQUEST)
+    };
+
+    for target in [LeafTarget::CLIENT, LeafTarget::PROXY] {
+        match respond_as(&target, &request, connection).await {
+            Ok(Some(response)) => return response,
+            Ok(None) => continue,
+            Err(err) => {
+                error!("Failed to answer OCSP request for {} leaves: {}", target, err);
+                return ocsp_error(OcspStatus::INTERNALERROR);
+            }
+        }
+    }
+
+    ocsp_error(OcspStatus::UNAUTHORIZED)
+}
+
+#[cfg(test)]
+mod tests {
+    use base64ct::{Base64, Encoding};
+    use chrono::{NaiveDate, TimeZone};
+
+    use crate::cert::algorithm::KeyAlgorithm;
+    use crate::cert::generate::{generate_inter_cert, generate_leaf_cert, generate_root_cert, InterTarget, LeafOptions};
+
+    use super::*;
+
+    // An Ed25519 intermediate and two leaves it signed, generated with openssl, where the leaf with
+    // serial 0x0c0d0e0f was replaced by the one with serial 0x2a2b2c2d when it was renewed
+    const ISSUER_KEY: &str = "MC4CAQAwBQYDK2VwBCIEIN6cEDcTr4QOMDQC7lGb18HyKZffHA+6yUf02JVePLLE";
+    const ISSUER_CERT: &str = "MIIBSTCB/KADAgECAgIQATAFBgMrZXAwGjEYMBYGA1UEAwwPRHJpcHRvcmNoIFBST1hZMB4XDTI2MTAxODEwMzA0OFoXDTM2MTAxNTEwMzA0OFowGjEYMBYGA1UEAwwPRHJpcHRvcmNoIFBST1hZMCowBQYDK2VwAyEANUU7xxHS+FkEFutbtxBBtXgsKCUpb6U6lv/kDghh0WmjZjBkMB0GA1UdDgQWBBTzpzxEgYh6w701kyGdwIq6oAgkVDAfBgNVHSMEGDAWgBTzpzxEgYh6w701kyGdwIq6oAgkVDASBgNVHRMBAf8ECDAGAQH/AgEAMA4GA1UdDwEB/wQEAwIBBjAFBgMrZXADQQC0b7Wp47gdNojwsmGxUrJICkdCVvZgx3E9IWwzB3I7Xez8yKF3BdwuPtD8+eAkhoCXo8QyKVZ/VwztUsPF4uUG";
+    const LEAF_CERT: &str = "MIIBITCB1KADAgECAgQqKywtMAUGAytlcDAaMRgwFgYDVQQDDA9EcmlwdG9yY2ggUFJPWFkwHhcNMjYxMDE4MTAzMDQ4WhcNMzYxMDE1MTAzMDQ4WjAUMRIwEAYDVQQDDAlhLmV4YW1wbGUwKjAFBgMrZXADIQCv7nAq9UNTvAOh7bE+FAvaxdibUSuhFDzf87V0HgHiy6NCMEAwHQYDVR0OBBYEFP0LGWl8S1Juqxgbhrioo5+//1pzMB8GA1UdIwQYMBaAFPOnPESBiHrDvTWTIZ3AirqgCCRUMAUGAytlcANBAA8WHM/BelBtitEdx5ALWSFnDF2UehvNXdfREwsH+keL3WU29nmEMdyd/RC29Tu9AUDxBQ90MPoE11yx7nPiiwA=";
+    const REPLACED_CERT: &str = "MIIBITCB1KADAgECAgQMDQ4PMAUGAytlcDAaMRgwFgYDVQQDDA9EcmlwdG9yY2ggUFJPWFkwHhcNMjYxMDE4MTAzMDQ4WhcNMzYxMDE1MTAzMDQ4WjAUMRIwEAYDVQQDDAlhLmV4YW1wbGUwKjAFBgMrZXADIQCv7nAq9UNTvAOh7bE+FAvaxdibUSuhFDzf87V0HgHiy6NCMEAwHQYDVR0OBBYEFP0LGWl8S1Juqxgbhrioo5+//1pzMB8GA1UdIwQYMBaAFPOnPESBiHrDvTWTIZ3AirqgCCRUMAUGAytlcANBAM9bD7RjJeJNMn+RzwZAGB9tzAu6r3w+Smt5X8WW4F2obpjNx7cF+m5s4rQFi1ftJ3pQUYxf/DUaszGWLQLHzAQ=";
+
+    /// openssl ocsp -issuer issuer.pem -cert leaf.pem -cert replaced.pem -no_nonce -reqout request.der
+    const OCSP_REQUEST: &str = "MIGIMIGFMIGCMD8wPTAJBgUrDgMCGgUABBQfzITWBn2poGu11R81NToam30rJAQU86c8RIGIesO9NZMhncCKuqAIJFQCBCorLC0wPzA9MAkGBSsOAwIaBQAEFB/MhNYGfamga7XVHzU1OhqbfSskBBTzpzxEgYh6w701kyGdwIq6oAgkVAIEDA0ODw==";
+    /// openssl ocsp -issuer issuer.pem -cert leaf.pem -reqout request.der
+    const OCSP_REQUEST_WITH_NONCE: &str = "MGowaDBBMD8wPTAJBgUrDgMCGgUABBQfzITWBn2poGu11R81NToam30rJAQU86c8RIGIesO9NZMhncCKuqAIJFQCBCorLC2iIzAhMB8GCSsGAQUFBzABAgQSBBCuplPmcdMruUWZspBhet+D";
+
+    fn decode(encoded: &str) -> Vec<u8> {
+        Base64::decode_vec(encoded).unwrap()
+    }
+
+    fn issuer() -> Issuer {
+        Issuer::new(
+            Cert::from_der(&decode(ISSUER_CERT)).unwrap(),
+            SigningKey::from_pkcs8(&decode(ISSUER_KEY)).unwrap()
+        )
+    }
+
+    fn revoked_leaf() -> certificate::Model {
+        certificate::Model {
+            id: "leaf".to_string(),
+            data: decode(LEAF_CERT),
+            key: None,
+            nonce: None,
+            cert_type: LeafTarget::PROXY.leaf_type().to_string(),
+            revoked: Some(NaiveDate::from_ymd(2026, 10, 18).and_hms(12, 0, 0)),
+            revocation_reason: Some(RevocationReason::KEYCOMPROMISE.to_string()),
+            retiring: None,
+            chain: None,
+            fetched: None
+        }
+    }
+
+    fn replaced_leaf() -> certificate_history::Model {
+        certificate_history::Model {
+            id: "history".to_string(),
+            certificate: "leaf".to_string(),
+            data: decode(REPLACED_CERT),
+            not_after: NaiveDate::from_ymd(2036, 10, 15).and_hms(10, 30, 48),
+            superseded: NaiveDate::from_ymd(2026, 10, 18).and_hms(11, 0, 0)
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2026, 10, 18).and_hms(13, 0, 0)
+    }
+
+    #[test]
+    fn parses_an_openssl_request() {
+        let request_der = decode(OCSP_REQUEST);
+        let request = parse_ocsp_request(&request_der).unwrap();
+        let issuer_der = decode(ISSUER_CERT);
+        let issuer_parts = certificate_parts(&issuer_der).unwrap();
+
+        assert_eq!(request.cert_ids.len(), 2);
+        assert!(request.nonce.is_none());
+        assert!(request.cert_ids.iter().all(|cert_id| issued_by(&issuer_parts, cert_id)));
+        assert_eq!(request.cert_ids[0].serial, &[0x2a, 0x2b, 0x2c, 0x2d]);
+        assert_eq!(request.cert_ids[1].serial, &[0x0c, 0x0d, 0x0e, 0x0f]);
+    }
+
+    #[test]
+    fn keeps_the_nonce() {
+        let request_der = decode(OCSP_REQUEST_WITH_NONCE);
+        let request = parse_ocsp_request(&request_der).unwrap();
+
+        assert_eq!(request.cert_ids.len(), 1);
+        assert_eq!(DerReader::new(DerReader::new(request.nonce.unwrap()).read(TAG_SEQUENCE).unwrap()).read(TAG_OID).unwrap(), OID_OCSP_NONCE);
+    }
+
+    #[test]
+    fn rejects_a_truncated_request() {
+        let request_der = decode(OCSP_REQUEST);
+
+        assert!(parse_ocsp_request(&request_der[..request_der.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn answers_for_replaced_certificates() {
+        // Checked with openssl ocsp -respin response.der -issuer issuer.pem -CAfile issuer.pem
+        const OCSP_RESPONSE: &str = "MIIBkgoBAKCCAYswggGHBgkrBgEFBQcwAQEEggF4MIIBdDCCASaiFgQU86c8RIGIesO9NZMhncCKuqAIJFQYDzIwMjYxMDE4MTMwMDAwWjCB+jB7MD0wCQYFKw4DAhoFAAQUH8yE1gZ9qaBrtdUfNTU6Gpt9KyQEFPOnPESBiHrDvTWTIZ3AirqgCCRUAgQqKywtoRYYDzIwMjYxMDE4MTIwMDAwWqADCgEBGA8yMDI2MTAxODEzMDAwMFqgERgPMjAyNjEwMTkxMzAwMDBaMHswPTAJBgUrDgMCGgUABBQfzITWBn2poGu11R81NToam30rJAQU86c8RIGIesO9NZMhncCKuqAIJFQCBAwNDg+hFhgPMjAyNjEwMTgxMjAwMDBaoAMKAQEYDzIwMjYxMDE4MTMwMDAwWqARGA8yMDI2MTAxOTEzMDAwMFowBQYDK2VwA0EAd/1J1E839ZCbF9apG4gqCbwzvEi/bWDlyDH/4/ZhBwsdjsE5UaHg6aAduIFVTa/seBF2Jc4IucRKmMTE9T8PBQ==";
+
+        let (leaf_models, history_models) = ([revoked_leaf()], [replaced_leaf()]);
+        let request_der = decode(OCSP_REQUEST);
+        let request = parse_ocsp_request(&request_der).unwrap();
+
+        let response = encode_ocsp_response(&issuer(), &issued_certs(&leaf_models, &history_models), &request, now()).unwrap();
+
+        assert_eq!(response, decode(OCSP_RESPONSE));
+    }
+
+    #[test]
+    fn lists_replaced_certificates() {
+        // Checked with openssl crl -inform DER -in crl.der -CAfile issuer.pem
+        const CRL: &str = "MIIBETCBxAIBATAFBgMrZXAwGjEYMBYGA1UEAwwPRHJpcHRvcmNoIFBST1hZFw0yNjEwMTgxMzAwMDBaFw0yNjEwMTkxMzAwMDBaMEowIwIEKissLRcNMjYxMDE4MTIwMDAwWjAMMAoGA1UdFQQDCgEBMCMCBAwNDg8XDTI2MTAxODEyMDAwMFowDDAKBgNVHRUEAwoBAaAyMDAwHwYDVR0jBBgwFoAU86c8RIGIesO9NZMhncCKuqAIJFQwDQYDVR0UBAYCBGrUwtAwBQYDK2VwA0EAxloQyscUpy73zemUJVpsbXt740aoQa9Ni9bwOYwF8nyyLYApkSNHMVoSy7cZRR6k8BYOVp6s4UPFlqHRewMRAQ==";
+
+        let (leaf_models, history_models) = ([revoked_leaf()], [replaced_leaf()]);
+
+        let crl = encode_crl(&issuer(), &issued_certs(&leaf_models, &history_models), now()).unwrap();
+
+        assert_eq!(crl, decode(CRL));
+    }
+
+    #[tokio::test]
+    async fn leaves_out_leaves_it_did_not_sign() {
+        // Another intermediate with the same name, but its own key
+        let other_key = SigningKey::generate(KeyAlgorithm::ED25519).unwrap();
+        let other_root = generate_root_cert(&other_key).await.unwrap();
+        let other_inter = generate_inter_cert(&other_key, InterTarget::PROXY, (&other_root, &other_key)).await.unwrap();
+
+        let options = LeafOptions {
+            common_name: "a.example".to_string(),
+            dns_names: vec!["a.example".to_string()],
+            ip_addresses: vec![],
+            validity: Duration::days(1)
+        };
+        let other_leaf_cert = generate_leaf_cert(other_key.public_key(), &LeafTarget::PROXY, &options, (&other_inter, &other_key)).await.unwrap();
+
+        let mut other_leaf = revoked_leaf();
+        other_leaf.data = other_leaf_cert.to_der().unwrap();
+
+        let crl = encode_crl(&issuer(), &issued_certs(&[other_leaf], &[]), now()).unwrap();
+
+        assert_eq!(crl, encode_crl(&issuer(), &[], now()).unwrap());
+    }
+}
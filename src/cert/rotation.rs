@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Formatter;
+
+use chrono::Utc;
+use lapin::Channel;
+use picky::key::KeyError;
+use picky::x509::Cert;
+use picky::x509::certificate::CertError;
+use sea_orm::*;
+use sea_orm::sea_query::Expr;
+use ulid::Ulid;
+
+use crate::cert::algorithm::{KeyAlgorithm, SigningKey};
+use crate::cert::{encrypt_priv_key, find_issuer, IssueError, load_intermediate, load_intermediates, load_root, Types};
+use crate::cert::generate::{generate_cross_cert, generate_inter_cert, generate_root_cert, InterTarget, LeafTarget};
+use crate::cert::renewal::{publish_renewal, renew_leaf};
+use crate::entities::{certificate, certificate_history};
+use crate::entities::prelude::{Certificate, CertificateHistory};
+
+#[derive(Debug)]
+pub enum RotationError {
+    InProgress,
+    NotInProgress,
+    /// Leaves still signed by a retiring intermediate
+    Reissuing(usize),
+    MissingRoot,
+    Issue(IssueError)
+}
+
+impl fmt::Display for RotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RotationError::InProgress => write!(f, "A rotation is already in progress"),
+            RotationError::NotInProgress => write!(f, "No rotation is in progress"),
+            RotationError::Reissuing(remaining) => write!(f, "{} leaves still need re-issuing", remaining),
+            RotationError::MissingRoot => write!(f, "No root cert to rotate"),
+            RotationError::Issue(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl From<IssueError> for RotationError {
+    fn from(err: IssueError) -> Self {
+        RotationError::Issue(err)
+    }
+}
+
+impl From<DbErr> for RotationError {
+    fn from(err: DbErr) -> Self {
+        RotationError::Issue(IssueError::Database(err))
+    }
+}
+
+impl From<KeyError> for RotationError {
+    fn from(err: KeyError) -> Self {
+        RotationError::Issue(IssueError::Key(err))
+    }
+}
+
+impl From<CertError> for RotationError {
+    fn from(err: CertError) -> Self {
+        RotationError::Issue(IssueError::Cert(err))
+    }
+}
+
+/// The chain created by a rotation
+pub struct Rotation {
+    pub root: certificate::Model,
+    pub cross: certificate::Model,
+    pub intermediates: Vec<certificate::Model>
+}
+
+async fn store_ca_cert<C: ConnectionTrait>(cert: &Cert, key: Option<&SigningKey>, cert_type: Types, connection: &C) -> Result<certificate::Model, RotationError> {
+    let (nonce, key) = match key {
+        Some(key) => {
+            let (nonce, key) = encrypt_priv_key(key.to_pkcs8()?).await;
+            (Some(nonce), Some(key))
+        }
+        None => (None, None)
+    };
+
+    Ok(certificate::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        data: ActiveValue::Set(cert.to_der()?),
+        key: ActiveValue::Set(key),
+        nonce: ActiveValue::Set(nonce),
+        cert_type: ActiveValue::Set(cert_type.to_string()),
+        revoked: ActiveValue::Set(None),
+        revocation_reason: ActiveValue::Set(None),
+        retiring: ActiveValue::Set(None),
+        chain: ActiveValue::Set(None),
+        fetched: ActiveValue::Set(None)
+    }
+        .insert(connection)
+        .await?)
+}
+
+/// Replaces the root and intermediates, marking the current chain as retiring
+///
+/// The new root is cross-signed by the old one, so anything that only trusts the old root can still
+/// verify leaves issued by the new intermediates. The retiring chain stays trusted until
+/// `reissue_retiring_leaves` or `finish_rotation` retires it, and only one rotation can run at a time.
+pub async fn rotate_ca<C: ConnectionTrait>(rsa_key: &SigningKey, connection: &C) -> Result<Rotation, RotationError> {
+    // Concurrent rotations queue up on the root rows, so the next one sees this one's retiring chain
+    Certificate::find()
+        .filter(certificate::Column::CertType.eq(Types::ROOT.to_string()))
+        .lock_exclusive()
+        .all(connection)
+        .await?;
+
+    let retiring = Certificate::find()
+        .filter(certificate::Column::Retiring.is_not_null())
+        .count(connection)
+        .await?;
+
+    if retiring > 0 {
+        return Err(RotationError::InProgress);
+    }
+
+    let (old_root_cert, old_root_key) = load_root(rsa_key, connection)
+        .await?
+        .ok_or(RotationError::MissingRoot)?;
+
+    let root_key = SigningKey::generate(KeyAlgorithm::for_type(&Types::ROOT))?;
+    let root_cert = generate_root_cert(&root_key).await?;
+    let cross_cert = generate_cross_cert(&root_cert, (&old_root_cert, &old_root_key)).await?;
+
+    Certificate::update_many()
+        .col_expr(certificate::Column::Retiring, Expr::value(Utc::now().naive_utc()))
+        .filter(certificate::Column::CertType.is_in([
+            Types::ROOT.to_string(),
+            Types::CLIENTINTER.to_string(),
+            Types::PROXYINTER.to_string()
+        ]))
+        .exec(connection)
+        .await?;
+
+    let root = store_ca_cert(&root_cert, Some(&root_key), Types::ROOT, connection).await?;
+    // Its key is the new root's, which is already stored
+    let cross = store_ca_cert(&cross_cert, None, Types::CROSSROOT, connection).await?;
+
+    let mut intermediates = vec![];
+
+    for target in [InterTarget::CLIENT, InterTarget::PROXY] {
+        let cert_type = target.cert_type();
+        let inter_key = SigningKey::generate(KeyAlgorithm::for_type(&cert_type))?;
+        let inter_cert = generate_inter_cert(&inter_key, target, (&root_cert, &root_key)).await?;
+
+        intermediates.push(store_ca_cert(&inter_cert, Some(&inter_key), cert_type, connection).await?);
+    }
+
+    Ok(Rotation { root, cross, intermediates })
+}
+
+/// Records that the holder of a certificate has fetched it
+pub async fn mark_fetched<C: ConnectionTrait>(certificate_id: &str, connection: &C) -> Result<(), DbErr> {
+    Certificate::update_many()
+        .col_expr(certificate::Column::Fetched, Expr::value(Utc::now().naive_utc()))
+        .filter(certificate::Column::Id.eq(certificate_id))
+        .exec(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Loads the leaves of a type that weren't signed by the current intermediate
+async fn leaves_to_reissue<C: ConnectionTrait>(target: &LeafTarget, inter_cert: &Cert, connection: &C) -> Result<Vec<certificate::Model>, DbErr> {
+    Ok(Certificate::find()
+        .filter(certificate::Column::CertType.eq(target.leaf_type().to_string()))
+        .filter(certificate::Column::Revoked.is_null())
+        .filter(certificate::Column::Chain.is_null())
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter(|leaf_model| !matches!(Cert::from_der(&leaf_model.data), Ok(leaf_cert) if inter_cert.is_parent_of(&leaf_cert).is_ok()))
+        .collect())
+}
+
+/// Counts holders that haven't fetched their re-issued leaf yet while the one it replaced, signed by
+/// a retiring intermediate, is still valid
+async fn count_awaiting_fetch<C: ConnectionTrait>(connection: &C) -> Result<usize, DbErr> {
+    let mut retiring_intermediates = vec![];
+
+    for cert_type in [Types::CLIENTINTER, Types::PROXYINTER] {
+        retiring_intermediates.extend(
+            load_intermediates(cert_type, connection)
+                .await?
+                .into_iter()
+                .filter(|(inter_model, _)| inter_model.retiring.is_some())
+        );
+    }
+
+    // Renewals clear `fetched`, so it's only set once the current leaf has been picked up
+    let replaced = CertificateHistory::find()
+        .find_also_related(Certificate)
+        .filter(certificate_history::Column::NotAfter.gt(Utc::now().naive_utc()))
+        .filter(certificate::Column::Revoked.is_null())
+        .filter(certificate::Column::Fetched.is_null())
+        .all(connection)
+        .await?;
+
+    let awaiting: HashSet<String> = replaced
+        .into_iter()
+        .filter(|(history_model, _)| {
+            matches!(Cert::from_der(&history_model.data), Ok(replaced_cert) if find_issuer(&replaced_cert, &retiring_intermediates).is_some())
+        })
+        .map(|(history_model, _)| history_model.certificate)
+        .collect();
+
+    Ok(awaiting.len())
+}
+
+/// Deletes the retiring chain and the cross-signed root, which nothing needs to chain to anymore
+async fn retire_old_chain<C: ConnectionTrait>(connection: &C) -> Result<u64, DbErr> {
+    let retired = Certificate::delete_many()
+        .filter(
+            Condition::any()
+                .add(certificate::Column::Retiring.is_not_null())
+                .add(certificate::Column::CertType.eq(Types::CROSSROOT.to_string()))
+        )
+        .exec(connection)
+        .await?;
+
+    info!("Retired {} certs of the old chain", retired.rows_affected);
+
+    Ok(retired.rows_affected)
+}
+
+/// Re-issues every leaf still signed by a retiring intermediate, then retires the old chain once
+/// every holder has fetched its re-issued leaf, or the leaf it replaced has expired
+///
+/// Holders are told about their re-issued leaf like any renewal. Leaves that fail are retried on the
+/// next run, and the old chain is kept until they succeed. Returns whether the old chain was retired.
+pub async fn reissue_retiring_leaves(connection: &DatabaseConnection, amqp_channel: &Channel) -> Result<bool, DbErr> {
+    let retiring = Certificate::find()
+        .filter(certificate::Column::Retiring.is_not_null())
+        .count(connection)
+        .await?;
+
+    if retiring == 0 {
+        return Ok(false);
+    }
+
+    let mut remaining = 0;
+
+    for target in [LeafTarget::CLIENT, LeafTarget::PROXY] {
+        let (inter_cert, inter_key) = match load_intermediate(target.inter_type(), connection).await? {
+            Some(inter) => inter,
+            None => {
+                error!("No {} cert to re-issue {} leaves with!", target.inter_type(), target);
+                remaining += 1;
+                continue;
+            }
+        };
+
+        for leaf_model in leaves_to_reissue(&target, &inter_cert, connection).await? {
+            match renew_leaf(leaf_model.clone(), (&inter_cert, &inter_key), connection).await {
+                Ok(reissued_model) => {
+                    info!("Re-issued {} cert {} with the new chain", reissued_model.cert_type, reissued_model.id);
+                    publish_renewal(amqp_channel, &reissued_model, connection).await;
+                }
+                Err(err) => {
+                    error!("Failed to re-issue {} cert {}: {}", leaf_model.cert_type, leaf_model.id, err);
+                    remaining += 1;
+                }
+            }
+        }
+    }
+
+    if remaining > 0 {
+        warn!("{} leaves still need re-issuing before the old chain can be retired", remaining);
+        return Ok(false);
+    }
+
+    let awaiting = count_awaiting_fetch(connection).await?;
+
+    if awaiting > 0 {
+        info!("{} holders haven't fetched their re-issued leaf yet, keeping the old chain", awaiting);
+        return Ok(false);
+    }
+
+    retire_old_chain(connection).await?;
+
+    Ok(true)
+}
+
+/// Retires the old chain without waiting for holders to fetch their re-issued leaves
+///
+/// Holders that haven't fetched theirs yet can't be verified until they do. Every leaf still has to
+/// be re-issued first, as nothing would be left to verify them with otherwise.
+pub async fn finish_rotation<C: ConnectionTrait>(connection: &C) -> Result<u64, RotationError> {
+    Certificate::find()
+        .filter(certificate::Column::CertType.eq(Types::ROOT.to_string()))
+        .lock_exclusive()
+        .all(connection)
+        .await?;
+
+    let retiring = Certificate::find()
+        .filter(certificate::Column::Retiring.is_not_null())
+        .count(connection)
+        .await?;
+
+    if retiring == 0 {
+        return Err(RotationError::NotInProgress);
+    }
+
+    let mut remaining = 0;
+
+    for target in [LeafTarget::CLIENT, LeafTarget::PROXY] {
+        let (inter_cert, _) = load_intermediate(target.inter_type(), connection)
+            .await?
+            .ok_or_else(|| IssueError::MissingIntermediate(target.inter_type().to_string()))?;
+
+        remaining += leaves_to_reissue(&target, &inter_cert, connection).await?.len();
+    }
+
+    if remaining > 0 {
+        return Err(RotationError::Reissuing(remaining));
+    }
+
+    Ok(retire_old_chain(connection).await?)
+}
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use lapin::Channel;
+use sea_orm::*;
+use ulid::Ulid;
+
+use crate::cert::acme::ChallengeSolver;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::bump_serial;
+use crate::dns::validation::normalise_name;
+use crate::entities::{record, zone};
+use crate::entities::prelude::{Record, Zone};
+use crate::rpc::changes::{Change, ChangeEvent, publish_change, record_changes, SyncedRecord};
+
+/// Kept short so a stale challenge doesn't linger in resolvers' caches
+const CHALLENGE_TTL: i32 = 60;
+
+/// Answers DNS-01 challenges with TXT records in whichever of our zones holds the name
+///
+/// The records go through the journal like any other change, so clients serving the zone get them
+/// as well as the embedded DNS server.
+pub struct RecordSolver {
+    connection: DatabaseConnection,
+    amqp_channel: Channel
+}
+
+impl RecordSolver {
+    pub fn new(connection: DatabaseConnection, amqp_channel: Channel) -> RecordSolver {
+        RecordSolver { connection, amqp_channel }
+    }
+
+    /// Finds the zone closest to a name, which is the one with the longest matching origin
+    async fn find_zone(&self, name: &str) -> Result<zone::Model, String> {
+        let zones = Zone::find()
+            .all(&self.connection)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        zones.into_iter()
+            .filter(|candidate| name == candidate.origin || name.ends_with(&format!(".{}", candidate.origin)))
+            .max_by_key(|candidate| candidate.origin.len())
+            .ok_or_else(|| format!("No zone holds {}", name))
+    }
+
+    /// Bumps the zone's serial and journals the change, then commits and publishes it
+    async fn commit_change(&self, txn: DatabaseTransaction, changed_zone: &zone::Model, change: Change) -> Result<(), DbErr> {
+        bump_serial(changed_zone, &txn).await?;
+        let change_event: ChangeEvent = record_changes(changed_zone, vec![change], &txn).await?;
+
+        txn.commit().await?;
+
+        publish_change(&self.amqp_channel, &change_event).await;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChallengeSolver for RecordSolver {
+    async fn present(&self, name: &str, value: &str) -> Result<String, String> {
+        let hostname = format!("_acme-challenge.{}", normalise_name(name));
+        let challenge_zone = self.find_zone(&hostname).await?;
+
+        let record_id = Ulid::new().to_string();
+        let challenge_record = RecordTypes::TXT { hostname, ttl: CHALLENGE_TTL, txt_data: value.to_string() };
+
+        let presented = async {
+            let txn = self.connection.begin().await?;
+
+            Record::insert(record::ActiveModel {
+                id: ActiveValue::Set(record_id.clone()),
+                zone: ActiveValue::Set(challenge_zone.id.clone()),
+                value: ActiveValue::Set(challenge_record.to_msgpack().expect("Failed to encode record!")),
+                active: ActiveValue::Set(true)
+            })
+                .exec(&txn)
+                .await?;
+
+            let created = Change::RecordUpserted {
+                record: SyncedRecord { id: record_id.clone(), active: true, record: challenge_record }
+            };
+
+            self.commit_change(txn, &challenge_zone, created).await
+        };
+
+        presented.await.map_err(|err| err.to_string())?;
+
+        Ok(record_id)
+    }
+
+    async fn clean_up(&self, id: &str) -> Result<(), String> {
+        let cleaned_up = async {
+            let (challenge_record, challenge_zone) = match Record::find_by_id(id.to_string())
+                .find_also_related(Zone)
+                .one(&self.connection)
+                .await?
+            {
+                Some((challenge_record, Some(challenge_zone))) => (challenge_record, challenge_zone),
+                // The record went with its zone
+                _ => return Ok(())
+            };
+
+            let txn = self.connection.begin().await?;
+
+            Record::delete_by_id(challenge_record.id.clone())
+                .exec(&txn)
+                .await?;
+
+            self.commit_change(txn, &challenge_zone, Change::RecordDeleted { record_id: challenge_record.id }).await
+        };
+
+        cleaned_up.await.map_err(|err: DbErr| err.to_string())
+    }
+}
@@ -0,0 +1,434 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sea_orm::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+use crate::dns::nameservers;
+use crate::dns::records::RecordTypes;
+use crate::dns::validation::normalise_name;
+use crate::dns::wire::{CLASS_IN, decode_name, decode_rdata, encode_name};
+use crate::entities::prelude::Zone;
+use crate::entities::zone;
+
+/// How often every zone's delegation is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How long to wait for the resolver to answer
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const TYPE_NS: u16 = 2;
+const TYPE_OPT: u16 = 41;
+const UDP_PAYLOAD: u16 = 4096;
+
+/// Looks up which nameservers a zone is delegated to
+///
+/// This is a trait so the verifier can be pointed at a stub instead of the real DNS.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Returns the NS set for `origin`, which is empty when the zone isn't delegated at all
+    ///
+    /// Errors are reserved for failures to get an answer, such as timeouts or SERVFAIL.
+    async fn lookup_ns(&self, origin: &str) -> Result<Vec<String>, String>;
+}
+
+pub type SharedResolver = Arc<dyn Resolver>;
+
+/// Asks a recursive resolver over UDP, falling back to TCP for truncated answers
+pub struct UdpResolver {
+    addr: SocketAddr
+}
+
+impl UdpResolver {
+    pub fn new(addr: SocketAddr) -> UdpResolver {
+        UdpResolver { addr }
+    }
+
+    fn build_query(id: u16, origin: &str) -> Result<Vec<u8>, String> {
+        let mut query: Vec<u8> = vec![];
+        query.extend_from_slice(&id.to_be_bytes());
+        // Standard query with recursion desired, one question and an OPT record
+        query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+
+        encode_name(origin, &mut query, None).map_err(|err| err.to_string())?;
+        query.extend_from_slice(&TYPE_NS.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        query.push(0);
+        query.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        query.extend_from_slice(&UDP_PAYLOAD.to_be_bytes());
+        query.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        Ok(query)
+    }
+
+    async fn query_udp(&self, query: &[u8]) -> Result<Vec<u8>, String> {
+        let bind_addr = if self.addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+
+        let socket = UdpSocket::bind(bind_addr).await.map_err(|err| err.to_string())?;
+        socket.connect(self.addr).await.map_err(|err| err.to_string())?;
+        socket.send(query).await.map_err(|err| err.to_string())?;
+
+        let mut buf = vec![0u8; UDP_PAYLOAD as usize];
+
+        // Anything that isn't an answer to this query is ignored
+        loop {
+            let length = timeout(QUERY_TIMEOUT, socket.recv(&mut buf))
+                .await
+                .map_err(|_| "The resolver didn't answer in time".to_string())?
+                .map_err(|err| err.to_string())?;
+
+            if length >= 12 && buf[..2] == query[..2] {
+                buf.truncate(length);
+                return Ok(buf);
+            }
+        }
+    }
+
+    async fn query_tcp(&self, query: &[u8]) -> Result<Vec<u8>, String> {
+        let exchange = async {
+            let mut stream = TcpStream::connect(self.addr).await?;
+            stream.write_u16(query.len() as u16).await?;
+            stream.write_all(query).await?;
+
+            let length = stream.read_u16().await? as usize;
+            let mut response = vec![0u8; length];
+            stream.read_exact(&mut response).await?;
+
+            Ok::<Vec<u8>, std::io::Error>(response)
+        };
+
+        timeout(QUERY_TIMEOUT, exchange)
+            .await
+            .map_err(|_| "The resolver didn't answer in time".to_string())?
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[async_trait]
+impl Resolver for UdpResolver {
+    async fn lookup_ns(&self, origin: &str) -> Result<Vec<String>, String> {
+        let query = UdpResolver::build_query(rand::random(), origin)?;
+
+        let mut response = self.query_udp(&query).await?;
+        if response[2] & 0x02 != 0 {
+            response = self.query_tcp(&query).await?;
+        }
+
+        if response.len() < 12 {
+            return Err("The resolver sent a malformed response".to_string());
+        }
+
+        match response[3] & 0x0F {
+            0 => {}
+            3 => return Ok(vec![]),
+            2 => return Err("The resolver failed to look up the zone (SERVFAIL)".to_string()),
+            code => return Err(format!("The resolver refused to look up the zone (rcode {})", code))
+        }
+
+        let count = |position: usize| u16::from_be_bytes([response[position], response[position + 1]]) as usize;
+        let records = count(6) + count(8);
+        let malformed = |_| "The resolver sent a malformed response".to_string();
+
+        let (_, mut position) = decode_name(&response, 12).map_err(malformed)?;
+        position += 4;
+
+        // NS records can come back as an answer or, from a parent, as a referral
+        let mut found: Vec<String> = vec![];
+        for _ in 0..records {
+            let (owner, next) = decode_name(&response, position).map_err(malformed)?;
+            let fixed = response.get(next..next + 10).ok_or("The resolver sent a truncated response")?;
+            let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+            let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+            if record_type == TYPE_NS && owner == origin {
+                if let Ok(RecordTypes::NS { nsdame, .. }) = decode_rdata(TYPE_NS, owner, 0, &response, next + 10, length) {
+                    found.push(nsdame);
+                }
+            }
+
+            position = next + 10 + length;
+        }
+
+        Ok(found)
+    }
+}
+
+/// Compares the nameservers a zone is delegated to against `expected`, returning whether they
+/// match and why not
+///
+/// Failing to get an answer keeps `delegated` as it was, so a flaky resolver doesn't flip zones.
+async fn assess_delegation(origin: &str, delegated: bool, expected: &[String], resolver: &dyn Resolver) -> (bool, Option<String>) {
+    if expected.is_empty() {
+        return (false, Some("No nameservers are configured to compare against.".to_string()));
+    }
+
+    let found = match resolver.lookup_ns(origin).await {
+        Ok(found) => found,
+        Err(reason) => return (delegated, Some(reason))
+    };
+
+    let mut found: Vec<String> = found.iter().map(|nameserver| normalise_name(nameserver)).collect();
+    found.sort();
+    found.dedup();
+
+    let missing: Vec<&str> = expected.iter().filter(|nameserver| !found.contains(nameserver)).map(|nameserver| nameserver.as_str()).collect();
+    let unexpected: Vec<&str> = found.iter().filter(|nameserver| !expected.contains(nameserver)).map(|nameserver| nameserver.as_str()).collect();
+
+    if found.is_empty() {
+        return (false, Some("The zone isn't delegated to any nameservers.".to_string()));
+    }
+
+    if missing.is_empty() && unexpected.is_empty() {
+        return (true, None);
+    }
+
+    let mut reasons: Vec<String> = vec![];
+    if !missing.is_empty() {
+        reasons.push(format!("Missing nameservers: {}.", missing.join(", ")));
+    }
+    if !unexpected.is_empty() {
+        reasons.push(format!("Unexpected nameservers: {}.", unexpected.join(", ")));
+    }
+
+    (false, Some(reasons.join(" ")))
+}
+
+/// Compares the nameservers a zone is delegated to against ours, saving the result on the zone
+///
+/// Failing to get an answer leaves `delegated` as it was, so a flaky resolver doesn't flip zones.
+pub async fn check_delegation(checked_zone: zone::Model, resolver: &dyn Resolver, connection: &DatabaseConnection) -> Result<zone::Model, DbErr> {
+    let mut expected = nameservers();
+    expected.sort();
+    expected.dedup();
+
+    let (delegated, error) = assess_delegation(&checked_zone.origin, checked_zone.delegated, &expected, resolver).await;
+
+    let mut updated_zone: zone::ActiveModel = checked_zone.into();
+    updated_zone.delegated = ActiveValue::Set(delegated);
+    updated_zone.delegation_checked = ActiveValue::Set(Some(chrono::offset::Utc::now().naive_utc()));
+    updated_zone.delegation_error = ActiveValue::Set(error);
+
+    updated_zone.update(connection).await
+}
+
+/// Checks every zone's delegation on an interval, for the lifetime of the controller
+pub async fn run_verifier(connection: DatabaseConnection, resolver: SharedResolver) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let zones = match Zone::find().all(&connection).await {
+            Ok(zones) => zones,
+            Err(err) => {
+                error!("Failed to retrieve zones for delegation checks: {}", err);
+                continue;
+            }
+        };
+
+        for checked_zone in zones {
+            let zone_id = checked_zone.id.clone();
+
+            match check_delegation(checked_zone, resolver.as_ref(), &connection).await {
+                Ok(checked_zone) if checked_zone.delegation_error.is_some() => {
+                    debug!("Zone {} failed its delegation check: {}", zone_id, checked_zone.delegation_error.unwrap());
+                }
+                Ok(_) => {}
+                Err(err) => error!("Failed to save delegation check for zone {}: {}", zone_id, err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tokio::net::TcpListener;
+
+    use crate::dns::wire::{Compressor, encode_record};
+
+    use super::*;
+
+    /// Answers every lookup the same way
+    struct StubResolver {
+        answer: Result<Vec<String>, String>
+    }
+
+    #[async_trait]
+    impl Resolver for StubResolver {
+        async fn lookup_ns(&self, _origin: &str) -> Result<Vec<String>, String> {
+            self.answer.clone()
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    async fn assess(answer: Result<Vec<String>, String>, delegated: bool) -> (bool, Option<String>) {
+        let expected = names(&["ns1.driptorch.net", "ns2.driptorch.net"]);
+        assess_delegation("example.com", delegated, &expected, &StubResolver { answer }).await
+    }
+
+    #[tokio::test]
+    async fn accepts_matching_delegations() {
+        // Case, trailing dots and duplicates don't matter
+        let found = names(&["NS2.driptorch.net.", "ns1.driptorch.net", "ns1.driptorch.net"]);
+        assert_eq!(assess(Ok(found), false).await, (true, None));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_nameservers() {
+        assert_eq!(
+            assess(Ok(names(&["ns1.driptorch.net"])), true).await,
+            (false, Some("Missing nameservers: ns2.driptorch.net.".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_unexpected_nameservers() {
+        assert_eq!(
+            assess(Ok(names(&["ns1.driptorch.net", "ns2.driptorch.net", "ns.elsewhere.com"])), true).await,
+            (false, Some("Unexpected nameservers: ns.elsewhere.com.".to_string()))
+        );
+        assert_eq!(
+            assess(Ok(names(&["ns1.driptorch.net", "ns.elsewhere.com"])), true).await,
+            (false, Some("Missing nameservers: ns2.driptorch.net. Unexpected nameservers: ns.elsewhere.com.".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_undelegated_zones() {
+        assert_eq!(assess(Ok(vec![]), true).await, (false, Some("The zone isn't delegated to any nameservers.".to_string())));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_previous_result_on_errors() {
+        let error = "The resolver didn't answer in time".to_string();
+
+        assert_eq!(assess(Err(error.clone()), true).await, (true, Some(error.clone())));
+        assert_eq!(assess(Err(error.clone()), false).await, (false, Some(error)));
+    }
+
+    #[tokio::test]
+    async fn requires_configured_nameservers() {
+        let resolver = StubResolver { answer: Ok(names(&["ns1.driptorch.net"])) };
+
+        assert_eq!(
+            assess_delegation("example.com", true, &[], &resolver).await,
+            (false, Some("No nameservers are configured to compare against.".to_string()))
+        );
+    }
+
+    fn ns(owner: &str, nameserver: &str) -> RecordTypes {
+        RecordTypes::NS { hostname: owner.to_string(), ttl: 3600, nsdame: nameserver.to_string() }
+    }
+
+    /// Builds a response to `query` with the given flags and sections, the way a resolver would
+    fn build_response(query: &[u8], flags: [u8; 2], answers: &[RecordTypes], authority: &[RecordTypes], additional: &[RecordTypes]) -> Vec<u8> {
+        let (_, question_end) = decode_name(query, 12).unwrap();
+        let question_end = question_end + 4;
+
+        let mut response = query[..2].to_vec();
+        response.extend_from_slice(&flags);
+        response.extend_from_slice(&1u16.to_be_bytes());
+        for section in [answers, authority, additional] {
+            response.extend_from_slice(&(section.len() as u16).to_be_bytes());
+        }
+        response.extend_from_slice(&query[12..question_end]);
+
+        let mut compressor = Compressor::default();
+        compressor.remember("example.com", 12);
+
+        for record in answers.iter().chain(authority).chain(additional) {
+            encode_record(record, "example.com", &mut response, Some(&mut compressor)).unwrap();
+        }
+
+        response
+    }
+
+    /// Answers a single UDP query with a response built from it, returning the address to ask
+    async fn stub_udp(respond: impl Fn(&[u8]) -> Vec<u8> + Send + 'static) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (length, peer) = socket.recv_from(&mut buf).await.unwrap();
+            socket.send_to(&respond(&buf[..length]), peer).await.unwrap();
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn reads_answers() {
+        let addr = stub_udp(|query| {
+            let answers = [ns("example.com", "ns1.driptorch.net"), ns("example.com", "ns2.driptorch.net")];
+            build_response(query, [0x81, 0x80], &answers, &[], &[])
+        }).await;
+
+        let found = UdpResolver::new(addr).lookup_ns("example.com").await.unwrap();
+        assert_eq!(found, names(&["ns1.driptorch.net", "ns2.driptorch.net"]));
+    }
+
+    #[tokio::test]
+    async fn reads_referrals() {
+        let addr = stub_udp(|query| {
+            let authority = [
+                ns("example.com", "ns1.driptorch.net"),
+                ns("example.com", "ns2.driptorch.net"),
+                // Records for other owners are ignored
+                ns("com", "a.gtld-servers.net")
+            ];
+            let glue = [RecordTypes::A { hostname: "ns1.driptorch.net".to_string(), ttl: 3600, address: Ipv4Addr::new(192, 0, 2, 53) }];
+
+            build_response(query, [0x81, 0x80], &[], &authority, &glue)
+        }).await;
+
+        let found = UdpResolver::new(addr).lookup_ns("example.com").await.unwrap();
+        assert_eq!(found, names(&["ns1.driptorch.net", "ns2.driptorch.net"]));
+    }
+
+    #[tokio::test]
+    async fn reads_response_codes() {
+        let addr = stub_udp(|query| build_response(query, [0x81, 0x83], &[], &[], &[])).await;
+        assert_eq!(UdpResolver::new(addr).lookup_ns("example.com").await, Ok(vec![]));
+
+        let addr = stub_udp(|query| build_response(query, [0x81, 0x82], &[], &[], &[])).await;
+        assert_eq!(
+            UdpResolver::new(addr).lookup_ns("example.com").await,
+            Err("The resolver failed to look up the zone (SERVFAIL)".to_string())
+        );
+
+        let addr = stub_udp(|query| build_response(query, [0x81, 0x85], &[], &[], &[])).await;
+        assert_eq!(
+            UdpResolver::new(addr).lookup_ns("example.com").await,
+            Err("The resolver refused to look up the zone (rcode 5)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_truncated_answers_over_tcp() {
+        let addr = stub_udp(|query| build_response(query, [0x83, 0x80], &[], &[], &[])).await;
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let length = stream.read_u16().await.unwrap();
+            let mut query = vec![0u8; length as usize];
+            stream.read_exact(&mut query).await.unwrap();
+
+            let response = build_response(&query, [0x81, 0x80], &[ns("example.com", "ns1.driptorch.net")], &[], &[]);
+            stream.write_u16(response.len() as u16).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let found = UdpResolver::new(addr).lookup_ns("example.com").await.unwrap();
+        assert_eq!(found, names(&["ns1.driptorch.net"]));
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Formatter;
+
+use base64ct::{Base64, Encoding};
+use picky::hash::HashAlgorithm;
+use picky::key::{PrivateKey, PublicKey};
+use picky::signature::SignatureAlgorithm;
+
+use crate::dns::records::RecordTypes;
+use crate::dns::wire::{CLASS_IN, encode_name, encode_rdata, WireError};
+use crate::util::{encode_base32hex, encode_hex};
+
+/// RSA/SHA-256 (RFC 5702), the only algorithm zones are signed with for now
+pub const ALGORITHM_RSASHA256: u8 = 8;
+/// SHA-256 DS digests (RFC 4509)
+pub const DIGEST_SHA256: u8 = 2;
+pub const DNSKEY_TTL: i32 = 3600;
+/// Key signing keys have the SEP bit set on top of the zone key bit
+pub const FLAGS_KSK: u16 = 257;
+pub const FLAGS_ZSK: u16 = 256;
+
+const NSEC3_SHA1: u8 = 1;
+/// Extra iterations add nothing but cost (RFC 9276 section 3.1)
+const NSEC3_ITERATIONS: u16 = 0;
+/// How long signatures are valid for
+const SIGNATURE_VALIDITY: u32 = 14 * 24 * 60 * 60;
+/// Signatures start a little in the past to allow for clock skew
+const SIGNATURE_BACKDATE: u32 = 60 * 60;
+
+const TYPE_NS: u16 = 2;
+const TYPE_SOA: u16 = 6;
+const TYPE_DS: u16 = 43;
+const TYPE_RRSIG: u16 = 46;
+const TYPE_DNSKEY: u16 = 48;
+
+#[derive(Debug)]
+pub enum DnssecError {
+    /// The zone has no SOA to sign
+    MissingSoa,
+    /// A record couldn't be put on the wire to be signed
+    Wire(WireError),
+    /// A key couldn't produce a signature
+    Signature(String)
+}
+
+impl fmt::Display for DnssecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DnssecError::MissingSoa => write!(f, "Zone has no SOA"),
+            DnssecError::Wire(err) => write!(f, "{}", err),
+            DnssecError::Signature(reason) => write!(f, "Failed to sign: {}", reason)
+        }
+    }
+}
+
+impl From<WireError> for DnssecError {
+    fn from(err: WireError) -> Self {
+        DnssecError::Wire(err)
+    }
+}
+
+/// Records sharing an owner and type, each alongside its RDATA
+type RRset = Vec<(Vec<u8>, RecordTypes)>;
+
+/// A zone's key, alongside the DNSKEY record it's published as
+pub struct SigningKey {
+    pub dnskey: RecordTypes,
+    pub key_tag: u16,
+    pub private_key: PrivateKey,
+    /// Keys being pre-published or retired are published without signing anything
+    pub signing: bool
+}
+
+impl SigningKey {
+    fn is_ksk(&self) -> bool {
+        matches!(self.dnskey, RecordTypes::DNSKEY { flags: FLAGS_KSK, .. })
+    }
+}
+
+/// Reads a single DER element, returning its tag, its contents and whatever follows it
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_length = *data.get(1)? as usize;
+
+    let (length, header) = if first_length < 0x80 {
+        (first_length, 2)
+    } else {
+        let octets = first_length & 0x7F;
+        let length = data.get(2..2 + octets)?.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + octets)
+    };
+
+    let contents = data.get(header..header + length)?;
+    Some((tag, contents, &data[header + length..]))
+}
+
+fn strip_leading_zeros(integer: &[u8]) -> &[u8] {
+    let start = integer.iter().position(|byte| *byte != 0).unwrap_or(integer.len());
+    &integer[start..]
+}
+
+/// Converts an RSA public key to the DNSKEY public key format (RFC 3110 section 2)
+pub fn rsa_public_key(public_key: &PublicKey) -> Option<Vec<u8>> {
+    let der = public_key.to_der().ok()?;
+
+    // SubjectPublicKeyInfo ::= SEQUENCE { algorithm, BIT STRING { RSAPublicKey } }
+    let (_, spki, _) = der_element(&der)?;
+    let (_, _, spki) = der_element(spki)?;
+    let (_, bit_string, _) = der_element(spki)?;
+
+    // RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+    let (_, rsa_key, _) = der_element(bit_string.get(1..)?)?;
+    let (_, modulus, rsa_key) = der_element(rsa_key)?;
+    let (_, exponent, _) = der_element(rsa_key)?;
+
+    let modulus = strip_leading_zeros(modulus);
+    let exponent = strip_leading_zeros(exponent);
+
+    let mut encoded = vec![];
+    if exponent.len() <= 255 {
+        encoded.push(exponent.len() as u8);
+    } else {
+        encoded.push(0);
+        encoded.extend_from_slice(&(exponent.len() as u16).to_be_bytes());
+    }
+    encoded.extend_from_slice(exponent);
+    encoded.extend_from_slice(modulus);
+
+    Some(encoded)
+}
+
+/// Calculates the key tag of a DNSKEY from its RDATA (RFC 4034 appendix B)
+pub fn key_tag(dnskey_rdata: &[u8]) -> u16 {
+    let mut accumulator: u32 = 0;
+
+    for (i, byte) in dnskey_rdata.iter().enumerate() {
+        if i % 2 == 0 {
+            accumulator += (*byte as u32) << 8;
+        } else {
+            accumulator += *byte as u32;
+        }
+    }
+
+    accumulator += (accumulator >> 16) & 0xFFFF;
+    (accumulator & 0xFFFF) as u16
+}
+
+/// Builds the DNSKEY record a key is published as
+pub fn dnskey_record(origin: &str, flags: u16, public_key: &[u8]) -> RecordTypes {
+    RecordTypes::DNSKEY {
+        hostname: origin.to_string(),
+        ttl: DNSKEY_TTL,
+        flags,
+        protocol: 3,
+        algorithm: ALGORITHM_RSASHA256,
+        public_key: Base64::encode_string(public_key)
+    }
+}
+
+/// Builds the DS record the parent zone should publish for a key signing key (RFC 4034 section 5.1.4)
+pub fn ds_record(origin: &str, dnskey: &RecordTypes) -> Result<RecordTypes, WireError> {
+    let rdata = encode_rdata(dnskey)?;
+
+    let mut digest_input = vec![];
+    encode_name(origin, &mut digest_input, None)?;
+    digest_input.extend_from_slice(&rdata);
+
+    let algorithm = match dnskey {
+        RecordTypes::DNSKEY { algorithm, .. } => *algorithm,
+        _ => return Err(WireError::InvalidField("dnskey"))
+    };
+
+    Ok(RecordTypes::DS {
+        hostname: origin.to_string(),
+        ttl: DNSKEY_TTL,
+        key_tag: key_tag(&rdata),
+        algorithm,
+        digest_type: DIGEST_SHA256,
+        digest: encode_hex(&HashAlgorithm::SHA2_256.digest(&digest_input))
+    })
+}
+
+/// Hashes an owner name for NSEC3 (RFC 5155 section 5)
+fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> Result<Vec<u8>, WireError> {
+    let mut input = vec![];
+    encode_name(name, &mut input, None)?;
+    input.extend_from_slice(salt);
+
+    let mut hash = HashAlgorithm::SHA1.digest(&input);
+    for _ in 0..iterations {
+        hash.extend_from_slice(salt);
+        hash = HashAlgorithm::SHA1.digest(&hash);
+    }
+
+    Ok(hash)
+}
+
+fn owner<'a>(record: &'a RecordTypes, origin: &'a str) -> &'a str {
+    record.hostname().unwrap_or(origin)
+}
+
+fn within(name: &str, origin: &str) -> bool {
+    name == origin || name.ends_with(&format!(".{}", origin))
+}
+
+/// Counts the labels an RRSIG covers, which leaves out the root and any wildcard
+fn label_count(name: &str) -> u8 {
+    if name == "." {
+        return 0;
+    }
+
+    let labels = name.split('.').count();
+    if name.starts_with("*.") {
+        (labels - 1) as u8
+    } else {
+        labels as u8
+    }
+}
+
+/// Signs a single RRset whose records are already in canonical order (RFC 4034 section 3.1.8.1)
+fn sign_rrset(origin: &str, name: &str, rrset: &[(Vec<u8>, RecordTypes)], key: &SigningKey, inception: u32, expiration: u32) -> Result<RecordTypes, DnssecError> {
+    let type_covered = rrset[0].1.type_code();
+    let original_ttl = rrset[0].1.ttl();
+
+    let mut signed_data = vec![];
+    signed_data.extend_from_slice(&type_covered.to_be_bytes());
+    signed_data.push(ALGORITHM_RSASHA256);
+    signed_data.push(label_count(name));
+    signed_data.extend_from_slice(&(original_ttl as u32).to_be_bytes());
+    signed_data.extend_from_slice(&expiration.to_be_bytes());
+    signed_data.extend_from_slice(&inception.to_be_bytes());
+    signed_data.extend_from_slice(&key.key_tag.to_be_bytes());
+    encode_name(origin, &mut signed_data, None)?;
+
+    let mut owner_name = vec![];
+    encode_name(name, &mut owner_name, None)?;
+
+    for (rdata, _) in rrset {
+        signed_data.extend_from_slice(&owner_name);
+        signed_data.extend_from_slice(&type_covered.to_be_bytes());
+        signed_data.extend_from_slice(&CLASS_IN.to_be_bytes());
+        signed_data.extend_from_slice(&(original_ttl as u32).to_be_bytes());
+        signed_data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        signed_data.extend_from_slice(rdata);
+    }
+
+    let signature = SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_256)
+        .sign(&signed_data, &key.private_key)
+        .map_err(|err| DnssecError::Signature(err.to_string()))?;
+
+    Ok(RecordTypes::RRSIG {
+        hostname: name.to_string(),
+        ttl: original_ttl,
+        type_covered,
+        algorithm: ALGORITHM_RSASHA256,
+        labels: label_count(name),
+        original_ttl: original_ttl as u32,
+        expiration,
+        inception,
+        key_tag: key.key_tag,
+        signer_name: origin.to_string(),
+        signature: Base64::encode_string(&signature)
+    })
+}
+
+/// Signs a zone's records, returning them alongside its DNSKEY, NSEC3 and RRSIG records
+///
+/// The DNSKEY RRset is signed by the key signing keys and everything else by the zone signing
+/// keys. Delegations and glue are left unsigned apart from DS records, as the child zone is
+/// authoritative for them. `now` is in seconds since the epoch.
+pub fn sign_zone(origin: &str, records: &[RecordTypes], keys: &[SigningKey], now: i64) -> Result<Vec<RecordTypes>, DnssecError> {
+    let (soa_ttl, soa_minimum) = match records.iter().find(|record| matches!(record, RecordTypes::SOA { .. })) {
+        Some(RecordTypes::SOA { ttl, minimum, .. }) => (*ttl, *minimum),
+        _ => return Err(DnssecError::MissingSoa)
+    };
+
+    let inception = (now as u32).wrapping_sub(SIGNATURE_BACKDATE);
+    let expiration = (now as u32).wrapping_add(SIGNATURE_VALIDITY);
+
+    let mut zone: Vec<RecordTypes> = records.iter()
+        .filter(|record| !record.is_dnssec_generated())
+        .cloned()
+        .collect();
+
+    zone.extend(keys.iter().map(|key| key.dnskey.clone()));
+    zone.push(RecordTypes::NSEC3PARAM {
+        hostname: origin.to_string(),
+        ttl: 0,
+        hash_algorithm: NSEC3_SHA1,
+        flags: 0,
+        iterations: NSEC3_ITERATIONS,
+        salt: String::new()
+    });
+
+    // Records are grouped into RRsets of canonically ordered RDATA (RFC 4034 section 6.3)
+    let mut rrsets: BTreeMap<(String, u16), RRset> = BTreeMap::new();
+    for record in zone {
+        let rdata = encode_rdata(&record)?;
+        rrsets.entry((owner(&record, origin).to_string(), record.type_code()))
+            .or_default()
+            .push((rdata, record));
+    }
+
+    for rrset in rrsets.values_mut() {
+        rrset.sort_by(|(a, _), (b, _)| a.cmp(b));
+        rrset.dedup_by(|(a, _), (b, _)| a == b);
+
+        // Every record in an RRset has to share a TTL (RFC 2181 section 5.2)
+        let ttl = rrset.iter().map(|(_, record)| record.ttl()).min().unwrap();
+        for (_, record) in rrset.iter_mut() {
+            record.set_ttl(ttl);
+        }
+    }
+
+    let cuts: BTreeSet<String> = rrsets.keys()
+        .filter(|(name, record_type)| *record_type == TYPE_NS && name != origin)
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let below_cut = |name: &str| cuts.iter().any(|cut| name != cut && within(name, cut));
+
+    let mut signed: Vec<RecordTypes> = vec![];
+    let mut types_at: BTreeMap<String, BTreeSet<u16>> = BTreeMap::new();
+
+    for ((name, record_type), rrset) in &rrsets {
+        signed.extend(rrset.iter().map(|(_, record)| record.clone()));
+
+        // Glue isn't part of the zone's authoritative data
+        if below_cut(name) {
+            continue;
+        }
+
+        let types = types_at.entry(name.clone()).or_default();
+        types.insert(*record_type);
+
+        if cuts.contains(name) && *record_type != TYPE_DS {
+            continue;
+        }
+
+        let key_signing = *record_type == TYPE_DNSKEY && name == origin;
+        for key in keys.iter().filter(|key| key.signing && key.is_ksk() == key_signing) {
+            signed.push(sign_rrset(origin, name, rrset, key, inception, expiration)?);
+            types.insert(TYPE_RRSIG);
+        }
+    }
+
+    // Empty non-terminals need NSEC3 records too, so their existence can be proven
+    let names: Vec<String> = types_at.keys().cloned().collect();
+    for name in names {
+        let mut parent = name.split_once('.').map(|(_, parent)| parent.to_string());
+
+        while let Some(current) = parent {
+            if !within(&current, origin) || current == origin {
+                break;
+            }
+
+            types_at.entry(current.clone()).or_default();
+            parent = current.split_once('.').map(|(_, parent)| parent.to_string());
+        }
+    }
+
+    let mut chain: Vec<(Vec<u8>, &BTreeSet<u16>)> = types_at.iter()
+        .map(|(name, types)| Ok((nsec3_hash(name, &[], NSEC3_ITERATIONS)?, types)))
+        .collect::<Result<_, WireError>>()?;
+    chain.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // Negative answers are cached for the lower of the SOA's TTL and minimum (RFC 9077)
+    let nsec3_ttl = soa_ttl.min(i32::try_from(soa_minimum).unwrap_or(i32::MAX));
+
+    for (i, (hash, types)) in chain.iter().enumerate() {
+        let next_hash = &chain[(i + 1) % chain.len()].0;
+        let name = format!("{}.{}", encode_base32hex(hash), origin);
+
+        let nsec3 = RecordTypes::NSEC3 {
+            hostname: name.clone(),
+            ttl: nsec3_ttl,
+            hash_algorithm: NSEC3_SHA1,
+            flags: 0,
+            iterations: NSEC3_ITERATIONS,
+            salt: String::new(),
+            next_hashed_owner: encode_base32hex(next_hash),
+            types: types.iter().cloned().collect()
+        };
+
+        let rrset = vec![(encode_rdata(&nsec3)?, nsec3.clone())];
+        signed.push(nsec3);
+
+        for key in keys.iter().filter(|key| key.signing && !key.is_ksk()) {
+            signed.push(sign_rrset(origin, &name, &rrset, key, inception, expiration)?);
+        }
+    }
+
+    // The SOA is kept first, as it is in transfers
+    signed.sort_by_key(|record| record.type_code() != TYPE_SOA);
+
+    Ok(signed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use picky_asn1::wrapper::IntegerAsn1;
+    use picky_asn1_x509::SubjectPublicKeyInfo;
+
+    use super::*;
+
+    const ORIGIN: &str = "example.com";
+    /// Signing time used throughout, 2022-10-09T17:12:04Z
+    const NOW: i64 = 1665335524;
+
+    /// The DNSKEY from the examples in RFC 4034 section 5.4 and RFC 4509 section 2.3
+    fn rfc_dnskey() -> RecordTypes {
+        RecordTypes::DNSKEY {
+            hostname: "dskey.example.com".to_string(),
+            ttl: 86400,
+            flags: FLAGS_ZSK,
+            protocol: 3,
+            algorithm: 5,
+            public_key: "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw==".to_string()
+        }
+    }
+
+    fn signing_key(flags: u16) -> SigningKey {
+        let private_key = PrivateKey::generate_rsa(1024).unwrap();
+        let dnskey = dnskey_record(ORIGIN, flags, &rsa_public_key(&private_key.to_public_key()).unwrap());
+
+        SigningKey {
+            key_tag: key_tag(&encode_rdata(&dnskey).unwrap()),
+            dnskey,
+            private_key,
+            signing: true
+        }
+    }
+
+    /// Reads an RSA public key back out of the DNSKEY format
+    fn dnskey_public_key(dnskey: &RecordTypes) -> PublicKey {
+        let public_key = match dnskey {
+            RecordTypes::DNSKEY { public_key, .. } => Base64::decode_vec(public_key).unwrap(),
+            _ => panic!("Not a DNSKEY")
+        };
+
+        let exponent_length = public_key[0] as usize;
+        let exponent = &public_key[1..1 + exponent_length];
+        let modulus = &public_key[1 + exponent_length..];
+
+        PublicKey::from(SubjectPublicKeyInfo::new_rsa_key(
+            IntegerAsn1::from_bytes_be_unsigned(modulus.to_vec()),
+            IntegerAsn1::from_bytes_be_unsigned(exponent.to_vec())
+        ))
+    }
+
+    /// Rebuilds the data an RRSIG signs from its own fields and the RRset it covers
+    fn rrsig_signed_data(rrsig: &RecordTypes, rrset: &[&RecordTypes]) -> Vec<u8> {
+        let (hostname, type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name) = match rrsig {
+            RecordTypes::RRSIG { hostname, type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, .. } => {
+                (hostname, *type_covered, *algorithm, *labels, *original_ttl, *expiration, *inception, *key_tag, signer_name)
+            }
+            _ => panic!("Not an RRSIG")
+        };
+
+        let mut signed_data = vec![];
+        signed_data.extend_from_slice(&type_covered.to_be_bytes());
+        signed_data.push(algorithm);
+        signed_data.push(labels);
+        signed_data.extend_from_slice(&original_ttl.to_be_bytes());
+        signed_data.extend_from_slice(&expiration.to_be_bytes());
+        signed_data.extend_from_slice(&inception.to_be_bytes());
+        signed_data.extend_from_slice(&key_tag.to_be_bytes());
+        encode_name(signer_name, &mut signed_data, None).unwrap();
+
+        let mut rdatas: Vec<Vec<u8>> = rrset.iter().map(|record| encode_rdata(record).unwrap()).collect();
+        rdatas.sort();
+
+        for rdata in rdatas {
+            encode_name(hostname, &mut signed_data, None).unwrap();
+            signed_data.extend_from_slice(&type_covered.to_be_bytes());
+            signed_data.extend_from_slice(&CLASS_IN.to_be_bytes());
+            signed_data.extend_from_slice(&original_ttl.to_be_bytes());
+            signed_data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            signed_data.extend_from_slice(&rdata);
+        }
+
+        signed_data
+    }
+
+    fn test_zone() -> Vec<RecordTypes> {
+        vec![
+            RecordTypes::SOA {
+                ttl: 3600,
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2022100901,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300
+            },
+            RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 3600, address: Ipv4Addr::new(192, 0, 2, 1) },
+            RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 2) },
+            // a.b.example.com leaves b.example.com as an empty non-terminal
+            RecordTypes::A { hostname: "a.b.example.com".to_string(), ttl: 3600, address: Ipv4Addr::new(192, 0, 2, 3) },
+            RecordTypes::NS { hostname: "sub.example.com".to_string(), ttl: 3600, nsdame: "ns.sub.example.com".to_string() },
+            RecordTypes::A { hostname: "ns.sub.example.com".to_string(), ttl: 3600, address: Ipv4Addr::new(192, 0, 2, 53) },
+            RecordTypes::DS {
+                hostname: "sub.example.com".to_string(),
+                ttl: 3600,
+                key_tag: 60485,
+                algorithm: 8,
+                digest_type: DIGEST_SHA256,
+                digest: "d4b7d520e7bb5f0f67674a0cceb1e3e0614b93c4f9e99b8383f6a1e4469da50a".to_string()
+            }
+        ]
+    }
+
+    fn rrsigs_covering<'a>(signed: &'a [RecordTypes], name: &str, type_code: u16) -> Vec<&'a RecordTypes> {
+        signed.iter()
+            .filter(|record| matches!(record, RecordTypes::RRSIG { hostname, type_covered, .. } if hostname == name && *type_covered == type_code))
+            .collect()
+    }
+
+    fn nsec3_owner(name: &str) -> String {
+        format!("{}.{}", encode_base32hex(&nsec3_hash(name, &[], NSEC3_ITERATIONS).unwrap()), ORIGIN)
+    }
+
+    #[test]
+    fn key_tag_matches_rfc_4034_example() {
+        assert_eq!(key_tag(&encode_rdata(&rfc_dnskey()).unwrap()), 60485);
+    }
+
+    #[test]
+    fn ds_record_matches_rfc_4509_example() {
+        let ds = ds_record("dskey.example.com", &rfc_dnskey()).unwrap();
+
+        assert_eq!(ds, RecordTypes::DS {
+            hostname: "dskey.example.com".to_string(),
+            ttl: DNSKEY_TTL,
+            key_tag: 60485,
+            algorithm: 5,
+            digest_type: DIGEST_SHA256,
+            digest: "d4b7d520e7bb5f0f67674a0cceb1e3e0614b93c4f9e99b8383f6a1e4469da50a".to_string()
+        });
+    }
+
+    #[test]
+    fn nsec3_hash_matches_rfc_5155_vectors() {
+        // RFC 5155 appendix A, hashed with salt aabbccdd and 12 iterations
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        let vectors = [
+            ("example", "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom"),
+            ("a.example", "35mthgpgcu1qg68fab165klnsnk3dpvl"),
+            ("ai.example", "gjeqe526plbf1g8mklp59enfd789njgi"),
+            ("ns1.example", "2t7b4g4vsa5smi47k61mv5bv1a22bojr"),
+            ("ns2.example", "q04jkcevqvmu85r014c7dkba38o0ji5r"),
+            ("w.example", "k8udemvp1j2f7eg6jebps17vp3n8i58h"),
+            ("*.w.example", "r53bq7cc2uvmubfu5ocmm6pers9tk9en"),
+            ("x.w.example", "b4um86eghhds6nea196smvmlo4ors995"),
+            ("y.w.example", "ji6neoaepv8b5o6k4ev33abha8ht9fgc"),
+            ("x.y.w.example", "2vptu5timamqttgl4luu9kg21e0aor3s"),
+            ("xx.example", "t644ebqk9bibcna874givr6joj62mlhv")
+        ];
+
+        for (name, expected) in vectors {
+            let hash = nsec3_hash(name, &salt, 12).unwrap();
+            assert_eq!(encode_base32hex(&hash).to_lowercase(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn sign_zone_signatures_verify() {
+        let keys = vec![signing_key(FLAGS_KSK), signing_key(FLAGS_ZSK)];
+        let signed = sign_zone(ORIGIN, &test_zone(), &keys, NOW).unwrap();
+
+        let rrsigs: Vec<&RecordTypes> = signed.iter().filter(|record| matches!(record, RecordTypes::RRSIG { .. })).collect();
+        assert!(!rrsigs.is_empty());
+
+        for rrsig in rrsigs {
+            let (hostname, type_covered, key_tag, signature) = match rrsig {
+                RecordTypes::RRSIG { hostname, type_covered, key_tag, signature, .. } => (hostname, *type_covered, *key_tag, signature),
+                _ => unreachable!()
+            };
+
+            let rrset: Vec<&RecordTypes> = signed.iter()
+                .filter(|record| record.type_code() == type_covered && owner(record, ORIGIN) == hostname)
+                .collect();
+            let key = keys.iter().find(|key| key.key_tag == key_tag).unwrap();
+
+            // Only the DNSKEY RRset is signed by the KSK
+            assert_eq!(key.is_ksk(), type_covered == TYPE_DNSKEY, "{:?}", rrsig);
+
+            SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_256)
+                .verify(&dnskey_public_key(&key.dnskey), &rrsig_signed_data(rrsig, &rrset), &Base64::decode_vec(signature).unwrap())
+                .unwrap_or_else(|err| panic!("{:?} did not verify: {}", rrsig, err));
+        }
+    }
+
+    #[test]
+    fn sign_zone_evens_out_rrset_ttls() {
+        let keys = vec![signing_key(FLAGS_ZSK)];
+        let signed = sign_zone(ORIGIN, &test_zone(), &keys, NOW).unwrap();
+
+        let ttls: Vec<i32> = signed.iter()
+            .filter(|record| matches!(record, RecordTypes::A { hostname, .. } if hostname == "www.example.com"))
+            .map(|record| record.ttl())
+            .collect();
+
+        assert_eq!(ttls, vec![300, 300]);
+    }
+
+    #[test]
+    fn sign_zone_leaves_delegations_and_glue_unsigned() {
+        let keys = vec![signing_key(FLAGS_KSK), signing_key(FLAGS_ZSK)];
+        let signed = sign_zone(ORIGIN, &test_zone(), &keys, NOW).unwrap();
+
+        assert!(rrsigs_covering(&signed, "sub.example.com", TYPE_NS).is_empty());
+        assert!(rrsigs_covering(&signed, "ns.sub.example.com", 1).is_empty());
+        assert_eq!(rrsigs_covering(&signed, "sub.example.com", TYPE_DS).len(), 1);
+
+        // Glue is in the zone but out of the NSEC3 chain
+        assert!(signed.iter().any(|record| matches!(record, RecordTypes::A { hostname, .. } if hostname == "ns.sub.example.com")));
+        assert!(!signed.iter().any(|record| record.hostname() == Some(nsec3_owner("ns.sub.example.com").as_str())));
+    }
+
+    #[test]
+    fn sign_zone_covers_empty_non_terminals() {
+        let keys = vec![signing_key(FLAGS_ZSK)];
+        let signed = sign_zone(ORIGIN, &test_zone(), &keys, NOW).unwrap();
+
+        let owner_name = nsec3_owner("b.example.com");
+        let nsec3 = signed.iter()
+            .find(|record| matches!(record, RecordTypes::NSEC3 { hostname, .. } if *hostname == owner_name))
+            .expect("No NSEC3 for the empty non-terminal");
+
+        assert!(matches!(nsec3, RecordTypes::NSEC3 { types, .. } if types.is_empty()));
+        assert_eq!(rrsigs_covering(&signed, &owner_name, nsec3.type_code()).len(), 1);
+    }
+
+    #[test]
+    fn sign_zone_requires_an_soa() {
+        let records: Vec<RecordTypes> = test_zone().into_iter().skip(1).collect();
+
+        assert!(matches!(sign_zone(ORIGIN, &records, &[], NOW), Err(DnssecError::MissingSoa)));
+    }
+}
@@ -1 +1,23 @@
-pub mod records;
\ No newline at end of file
+use std::env;
+
+use crate::dns::validation::normalise_name;
+
+pub mod challenge;
+pub mod delegation;
+pub mod dnssec;
+pub mod records;
+pub mod server;
+pub mod soa;
+pub mod validation;
+pub mod wire;
+pub mod zonefile;
+
+/// Gets the nameservers Driptorch serves zones from, set as a comma separated NAMESERVERS
+pub fn nameservers() -> Vec<String> {
+    env::var("NAMESERVERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(normalise_name)
+        .filter(|nameserver| !nameserver.is_empty())
+        .collect()
+}
@@ -1,7 +1,7 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum RecordTypes {
     SOA {
         /// Time-to-live
@@ -65,7 +65,12 @@ pub enum RecordTypes {
     CAA {
         hostname: String,
         ttl: i32,
-        property: String
+        /// Flags, only the issuer critical bit (128) is defined
+        flags: u8,
+        /// Property tag such as `issue`, `issuewild` or `iodef`
+        tag: String,
+        /// Property value, interpreted according to the tag
+        value: String
     },
     SRV {
         hostname: String,
@@ -74,5 +79,337 @@ pub enum RecordTypes {
         weight: u16,
         port: u16,
         target: String
+    },
+    SVCB {
+        hostname: String,
+        ttl: i32,
+        /// 0 for alias mode, otherwise the service's priority
+        priority: u16,
+        target: String,
+        params: SvcParams
+    },
+    HTTPS {
+        hostname: String,
+        ttl: i32,
+        /// 0 for alias mode, otherwise the service's priority
+        priority: u16,
+        target: String,
+        params: SvcParams
+    },
+    TLSA {
+        hostname: String,
+        ttl: i32,
+        /// How the certificate association is used (PKIX-TA, PKIX-EE, DANE-TA, DANE-EE)
+        usage: u8,
+        /// Whether the full certificate (0) or its public key (1) is matched
+        selector: u8,
+        /// Whether the data is exact (0), SHA-256 (1) or SHA-512 (2)
+        matching_type: u8,
+        /// Hex encoded certificate association data
+        certificate_data: String
+    },
+    SSHFP {
+        hostname: String,
+        ttl: i32,
+        /// Host key algorithm (RSA, DSA, ECDSA, Ed25519, Ed448)
+        algorithm: u8,
+        /// Whether the fingerprint is SHA-1 (1) or SHA-256 (2)
+        fingerprint_type: u8,
+        /// Hex encoded fingerprint
+        fingerprint: String
+    },
+    NAPTR {
+        hostname: String,
+        ttl: i32,
+        order: u16,
+        preference: u16,
+        flags: String,
+        services: String,
+        regexp: String,
+        replacement: String
+    },
+    DS {
+        hostname: String,
+        ttl: i32,
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        /// Hex encoded digest of the child's DNSKEY
+        digest: String
+    },
+    DNSKEY {
+        hostname: String,
+        ttl: i32,
+        /// 256 for a zone signing key, 257 for a key signing key
+        flags: u16,
+        /// Always 3
+        protocol: u8,
+        algorithm: u8,
+        /// Base64 encoded public key
+        public_key: String
+    },
+    RRSIG {
+        hostname: String,
+        ttl: i32,
+        /// TYPE value of the RRset this signature covers
+        type_covered: u16,
+        algorithm: u8,
+        /// Number of labels in the owner name, not counting a wildcard
+        labels: u8,
+        original_ttl: u32,
+        /// Seconds since the epoch, modulo 2^32
+        expiration: u32,
+        /// Seconds since the epoch, modulo 2^32
+        inception: u32,
+        key_tag: u16,
+        signer_name: String,
+        /// Base64 encoded signature
+        signature: String
+    },
+    NSEC3 {
+        hostname: String,
+        ttl: i32,
+        hash_algorithm: u8,
+        /// 1 when the opt-out flag is set
+        flags: u8,
+        iterations: u16,
+        /// Hex encoded salt, empty for no salt
+        salt: String,
+        /// Base32hex encoded hash of the next owner name in the chain
+        next_hashed_owner: String,
+        /// TYPE values present at the original owner name
+        types: Vec<u16>
+    },
+    NSEC3PARAM {
+        hostname: String,
+        ttl: i32,
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        /// Hex encoded salt, empty for no salt
+        salt: String
+    }
+}
+
+/// Service parameters for SVCB and HTTPS records (RFC 9460)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct SvcParams {
+    /// Keys a client must understand to use the record
+    pub mandatory: Vec<String>,
+    /// Supported ALPN protocol ids, such as h2 or h3
+    pub alpn: Vec<String>,
+    pub no_default_alpn: bool,
+    pub port: Option<u16>,
+    pub ipv4hint: Vec<Ipv4Addr>,
+    /// Base64 encoded ECHConfigList
+    pub ech: Option<String>,
+    pub ipv6hint: Vec<Ipv6Addr>
+}
+
+impl RecordTypes {
+    /// Gets the record's type as it appears in DNS
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RecordTypes::SOA { .. } => "SOA",
+            RecordTypes::A { .. } => "A",
+            RecordTypes::AAAA { .. } => "AAAA",
+            RecordTypes::CNAME { .. } => "CNAME",
+            RecordTypes::DNAME { .. } => "DNAME",
+            RecordTypes::MX { .. } => "MX",
+            RecordTypes::NS { .. } => "NS",
+            RecordTypes::PTR { .. } => "PTR",
+            RecordTypes::TXT { .. } => "TXT",
+            RecordTypes::CAA { .. } => "CAA",
+            RecordTypes::SRV { .. } => "SRV",
+            RecordTypes::SVCB { .. } => "SVCB",
+            RecordTypes::HTTPS { .. } => "HTTPS",
+            RecordTypes::TLSA { .. } => "TLSA",
+            RecordTypes::SSHFP { .. } => "SSHFP",
+            RecordTypes::NAPTR { .. } => "NAPTR",
+            RecordTypes::DS { .. } => "DS",
+            RecordTypes::DNSKEY { .. } => "DNSKEY",
+            RecordTypes::RRSIG { .. } => "RRSIG",
+            RecordTypes::NSEC3 { .. } => "NSEC3",
+            RecordTypes::NSEC3PARAM { .. } => "NSEC3PARAM"
+        }
+    }
+
+    /// Gets the record's TYPE value from the IANA DNS parameters registry
+    pub fn type_code(&self) -> u16 {
+        match self {
+            RecordTypes::SOA { .. } => 6,
+            RecordTypes::A { .. } => 1,
+            RecordTypes::AAAA { .. } => 28,
+            RecordTypes::CNAME { .. } => 5,
+            RecordTypes::DNAME { .. } => 39,
+            RecordTypes::MX { .. } => 15,
+            RecordTypes::NS { .. } => 2,
+            RecordTypes::PTR { .. } => 12,
+            RecordTypes::TXT { .. } => 16,
+            RecordTypes::CAA { .. } => 257,
+            RecordTypes::SRV { .. } => 33,
+            RecordTypes::SVCB { .. } => 64,
+            RecordTypes::HTTPS { .. } => 65,
+            RecordTypes::TLSA { .. } => 52,
+            RecordTypes::SSHFP { .. } => 44,
+            RecordTypes::NAPTR { .. } => 35,
+            RecordTypes::DS { .. } => 43,
+            RecordTypes::DNSKEY { .. } => 48,
+            RecordTypes::RRSIG { .. } => 46,
+            RecordTypes::NSEC3 { .. } => 50,
+            RecordTypes::NSEC3PARAM { .. } => 51
+        }
+    }
+
+    /// Whether the record is generated when a zone is signed, rather than managed by users
+    pub fn is_dnssec_generated(&self) -> bool {
+        matches!(self, RecordTypes::RRSIG { .. } | RecordTypes::NSEC3 { .. } | RecordTypes::NSEC3PARAM { .. })
+    }
+
+    /// Gets the record's owner name, SOA records always live at the zone's origin
+    pub fn hostname(&self) -> Option<&str> {
+        match self {
+            RecordTypes::SOA { .. } => None,
+            RecordTypes::A { hostname, .. }
+            | RecordTypes::AAAA { hostname, .. }
+            | RecordTypes::CNAME { hostname, .. }
+            | RecordTypes::DNAME { hostname, .. }
+            | RecordTypes::MX { hostname, .. }
+            | RecordTypes::NS { hostname, .. }
+            | RecordTypes::PTR { hostname, .. }
+            | RecordTypes::TXT { hostname, .. }
+            | RecordTypes::CAA { hostname, .. }
+            | RecordTypes::SRV { hostname, .. }
+            | RecordTypes::SVCB { hostname, .. }
+            | RecordTypes::HTTPS { hostname, .. }
+            | RecordTypes::TLSA { hostname, .. }
+            | RecordTypes::SSHFP { hostname, .. }
+            | RecordTypes::NAPTR { hostname, .. }
+            | RecordTypes::DS { hostname, .. }
+            | RecordTypes::DNSKEY { hostname, .. }
+            | RecordTypes::RRSIG { hostname, .. }
+            | RecordTypes::NSEC3 { hostname, .. }
+            | RecordTypes::NSEC3PARAM { hostname, .. } => Some(hostname)
+        }
+    }
+
+    /// Replaces the record's owner name, does nothing for SOA records
+    pub fn set_hostname(&mut self, new_hostname: String) {
+        match self {
+            RecordTypes::SOA { .. } => {}
+            RecordTypes::A { hostname, .. }
+            | RecordTypes::AAAA { hostname, .. }
+            | RecordTypes::CNAME { hostname, .. }
+            | RecordTypes::DNAME { hostname, .. }
+            | RecordTypes::MX { hostname, .. }
+            | RecordTypes::NS { hostname, .. }
+            | RecordTypes::PTR { hostname, .. }
+            | RecordTypes::TXT { hostname, .. }
+            | RecordTypes::CAA { hostname, .. }
+            | RecordTypes::SRV { hostname, .. }
+            | RecordTypes::SVCB { hostname, .. }
+            | RecordTypes::HTTPS { hostname, .. }
+            | RecordTypes::TLSA { hostname, .. }
+            | RecordTypes::SSHFP { hostname, .. }
+            | RecordTypes::NAPTR { hostname, .. }
+            | RecordTypes::DS { hostname, .. }
+            | RecordTypes::DNSKEY { hostname, .. }
+            | RecordTypes::RRSIG { hostname, .. }
+            | RecordTypes::NSEC3 { hostname, .. }
+            | RecordTypes::NSEC3PARAM { hostname, .. } => *hostname = new_hostname
+        }
+    }
+
+    pub fn ttl(&self) -> i32 {
+        match self {
+            RecordTypes::SOA { ttl, .. }
+            | RecordTypes::A { ttl, .. }
+            | RecordTypes::AAAA { ttl, .. }
+            | RecordTypes::CNAME { ttl, .. }
+            | RecordTypes::DNAME { ttl, .. }
+            | RecordTypes::MX { ttl, .. }
+            | RecordTypes::NS { ttl, .. }
+            | RecordTypes::PTR { ttl, .. }
+            | RecordTypes::TXT { ttl, .. }
+            | RecordTypes::CAA { ttl, .. }
+            | RecordTypes::SRV { ttl, .. }
+            | RecordTypes::SVCB { ttl, .. }
+            | RecordTypes::HTTPS { ttl, .. }
+            | RecordTypes::TLSA { ttl, .. }
+            | RecordTypes::SSHFP { ttl, .. }
+            | RecordTypes::NAPTR { ttl, .. }
+            | RecordTypes::DS { ttl, .. }
+            | RecordTypes::DNSKEY { ttl, .. }
+            | RecordTypes::RRSIG { ttl, .. }
+            | RecordTypes::NSEC3 { ttl, .. }
+            | RecordTypes::NSEC3PARAM { ttl, .. } => *ttl
+        }
+    }
+
+    pub fn set_ttl(&mut self, new_ttl: i32) {
+        match self {
+            RecordTypes::SOA { ttl, .. }
+            | RecordTypes::A { ttl, .. }
+            | RecordTypes::AAAA { ttl, .. }
+            | RecordTypes::CNAME { ttl, .. }
+            | RecordTypes::DNAME { ttl, .. }
+            | RecordTypes::MX { ttl, .. }
+            | RecordTypes::NS { ttl, .. }
+            | RecordTypes::PTR { ttl, .. }
+            | RecordTypes::TXT { ttl, .. }
+            | RecordTypes::CAA { ttl, .. }
+            | RecordTypes::SRV { ttl, .. }
+            | RecordTypes::SVCB { ttl, .. }
+            | RecordTypes::HTTPS { ttl, .. }
+            | RecordTypes::TLSA { ttl, .. }
+            | RecordTypes::SSHFP { ttl, .. }
+            | RecordTypes::NAPTR { ttl, .. }
+            | RecordTypes::DS { ttl, .. }
+            | RecordTypes::DNSKEY { ttl, .. }
+            | RecordTypes::RRSIG { ttl, .. }
+            | RecordTypes::NSEC3 { ttl, .. }
+            | RecordTypes::NSEC3PARAM { ttl, .. } => *ttl = new_ttl
+        }
     }
-}
\ No newline at end of file
+
+    /// Encodes the record as MessagePack for storage in `record.value`
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    /// Decodes a record from its stored MessagePack form
+    pub fn from_msgpack(value: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(value)
+    }
+}
+
+/// Gets the mnemonic for a TYPE value, using the RFC 3597 form for types Driptorch doesn't know
+pub fn type_mnemonic(code: u16) -> String {
+    let mnemonic = match code {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        15 => "MX",
+        16 => "TXT",
+        28 => "AAAA",
+        33 => "SRV",
+        35 => "NAPTR",
+        39 => "DNAME",
+        43 => "DS",
+        44 => "SSHFP",
+        46 => "RRSIG",
+        48 => "DNSKEY",
+        50 => "NSEC3",
+        51 => "NSEC3PARAM",
+        52 => "TLSA",
+        64 => "SVCB",
+        65 => "HTTPS",
+        257 => "CAA",
+        _ => return format!("TYPE{}", code)
+    };
+
+    mnemonic.to_string()
+}
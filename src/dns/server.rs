@@ -0,0 +1,811 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sea_orm::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+use crate::dns::records::RecordTypes;
+use crate::dns::wire::{CLASS_IN, Compressor, decode_name, encode_record, WireError};
+use crate::entities::{record, zone};
+use crate::entities::prelude::{Record, Zone};
+
+const HEADER_LENGTH: usize = 12;
+/// Longest an encoded name can be in octets
+const MAX_NAME_LENGTH: usize = 255;
+/// Largest UDP response for clients that don't advertise EDNS (RFC 1035 section 4.2.1)
+const MIN_UDP_PAYLOAD: usize = 512;
+/// Largest UDP payload Driptorch will send or advertise
+const MAX_UDP_PAYLOAD: usize = 4096;
+/// How many CNAMEs and DNAMEs are followed within a zone before giving up
+const MAX_CNAME_CHAIN: usize = 8;
+/// How long an idle TCP connection is kept open
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const TYPE_CNAME: u16 = 5;
+const TYPE_OPT: u16 = 41;
+const TYPE_DS: u16 = 43;
+const TYPE_ANY: u16 = 255;
+const CLASS_ANY: u16 = 255;
+
+const OPCODE_QUERY: u8 = 0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ResponseCode {
+    NOERROR = 0,
+    FORMERR = 1,
+    SERVFAIL = 2,
+    NXDOMAIN = 3,
+    NOTIMP = 4,
+    REFUSED = 5,
+    /// A DNAME substitution produced a name that is too long (RFC 6672 section 2.2)
+    YXDOMAIN = 6
+}
+
+struct Query {
+    id: u16,
+    opcode: u8,
+    recursion_desired: bool,
+    /// The question as it was sent, so the name's case is echoed back
+    question: Vec<u8>,
+    qname: String,
+    qtype: u16,
+    qclass: u16,
+    /// The UDP payload size the client advertised through EDNS, if it used EDNS
+    udp_payload: Option<usize>
+}
+
+#[derive(Default)]
+struct Answer {
+    authoritative: bool,
+    origin: String,
+    answers: Vec<RecordTypes>,
+    authority: Vec<RecordTypes>,
+    additional: Vec<RecordTypes>
+}
+
+/// Finds the zone a name falls within
+///
+/// This is a trait so the server can be pointed at zones held in memory instead of the database.
+#[async_trait]
+pub trait ZoneStore: Send + Sync {
+    /// Returns the origin and active records of the closest zone enclosing `name`, if there is one
+    async fn find_zone(&self, name: &str) -> Result<Option<(String, Vec<RecordTypes>)>, DbErr>;
+}
+
+pub type SharedZoneStore = Arc<dyn ZoneStore>;
+
+#[async_trait]
+impl ZoneStore for DatabaseConnection {
+    async fn find_zone(&self, name: &str) -> Result<Option<(String, Vec<RecordTypes>)>, DbErr> {
+        let mut candidates = vec![name.to_string()];
+        let mut name = name;
+        while let Some(parent) = parent(name) {
+            candidates.push(parent.to_string());
+            name = parent;
+        }
+
+        let zone = Zone::find()
+            .filter(zone::Column::Origin.is_in(candidates))
+            .all(self)
+            .await?
+            .into_iter()
+            .max_by_key(|zone| zone.origin.len());
+
+        let zone = match zone {
+            Some(zone) => zone,
+            None => return Ok(None)
+        };
+
+        let records: Vec<RecordTypes> = Record::find()
+            .filter(record::Column::Zone.eq(zone.id.clone()))
+            .filter(record::Column::Active.eq(true))
+            .all(self)
+            .await?
+            .into_iter()
+            .filter_map(|model| RecordTypes::from_msgpack(&model.value).ok())
+            .collect();
+
+        Ok(Some((zone.origin, records)))
+    }
+}
+
+/// Answers authoritative queries straight from the zone and record tables
+///
+/// This is meant for testing records without deploying a client, it doesn't do zone transfers.
+pub struct DnsServer {
+    udp_socket: Arc<UdpSocket>,
+    tcp_listener: TcpListener
+}
+
+impl DnsServer {
+    pub async fn bind(addr: SocketAddr) -> io::Result<DnsServer> {
+        let udp_socket = UdpSocket::bind(addr).await?;
+        // TCP shares the UDP socket's port, even when the OS picked it
+        let tcp_listener = TcpListener::bind(udp_socket.local_addr()?).await?;
+
+        Ok(DnsServer { udp_socket: Arc::new(udp_socket), tcp_listener })
+    }
+
+    pub async fn run(self, zones: SharedZoneStore) {
+        let tcp_zones = zones.clone();
+        let tcp_listener = self.tcp_listener;
+
+        tokio::spawn(async move {
+            loop {
+                match tcp_listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle_tcp(stream, tcp_zones.clone()));
+                    }
+                    Err(err) => error!("Failed to accept DNS connection: {}", err)
+                }
+            }
+        });
+
+        let mut buf = [0u8; MAX_UDP_PAYLOAD];
+
+        loop {
+            let (length, peer) = match self.udp_socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(err) => {
+                    error!("Failed to receive DNS query: {}", err);
+                    continue;
+                }
+            };
+
+            let message = buf[..length].to_vec();
+            let udp_socket = self.udp_socket.clone();
+            let zones = zones.clone();
+
+            tokio::spawn(async move {
+                if let Some(response) = handle_message(&message, false, zones.as_ref()).await {
+                    if let Err(err) = udp_socket.send_to(&response, peer).await {
+                        warn!("Failed to send DNS response to {}: {}", peer, err);
+                    }
+                }
+            });
+        }
+    }
+}
+
+async fn handle_tcp(mut stream: TcpStream, zones: SharedZoneStore) {
+    // Each message is prefixed by its length (RFC 1035 section 4.2.2)
+    loop {
+        let length = match timeout(TCP_IDLE_TIMEOUT, stream.read_u16()).await {
+            Ok(Ok(length)) => length as usize,
+            _ => return
+        };
+
+        let mut message = vec![0u8; length];
+        match timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut message)).await {
+            Ok(Ok(_)) => {}
+            _ => return
+        }
+
+        let response = match handle_message(&message, true, zones.as_ref()).await {
+            Some(response) => response,
+            None => return
+        };
+
+        if stream.write_u16(response.len() as u16).await.is_err() || stream.write_all(&response).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Builds the response to a message, or nothing if it should be dropped
+async fn handle_message(message: &[u8], tcp: bool, zones: &dyn ZoneStore) -> Option<Vec<u8>> {
+    // Responses are never answered, to avoid loops
+    if message.len() < HEADER_LENGTH || message[2] & 0x80 != 0 {
+        return None;
+    }
+
+    let query = match parse_query(message) {
+        Ok(query) => query,
+        Err(_) => {
+            let id = u16::from_be_bytes([message[0], message[1]]);
+            let mut response = id.to_be_bytes().to_vec();
+            response.extend_from_slice(&[0x80 | (message[2] & 0x78), ResponseCode::FORMERR as u8, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+            return Some(response);
+        }
+    };
+
+    let (code, answer) = if query.opcode != OPCODE_QUERY {
+        (ResponseCode::NOTIMP, Answer::default())
+    } else if query.qclass != CLASS_IN && query.qclass != CLASS_ANY {
+        (ResponseCode::REFUSED, Answer::default())
+    } else {
+        match answer_query(&query, zones).await {
+            Ok(answered) => answered,
+            Err(err) => {
+                error!("Failed to answer DNS query for {}: {}", query.qname, err);
+                (ResponseCode::SERVFAIL, Answer::default())
+            }
+        }
+    };
+
+    let max_length = if tcp {
+        u16::MAX as usize
+    } else {
+        query.udp_payload.unwrap_or(MIN_UDP_PAYLOAD)
+    };
+
+    match encode_response(&query, code, &answer, false) {
+        Ok(response) if response.len() <= max_length => Some(response),
+        Ok(_) => encode_response(&query, code, &Answer { authoritative: answer.authoritative, ..Answer::default() }, true).ok(),
+        Err(err) => {
+            error!("Failed to encode DNS response for {}: {}", query.qname, err);
+            encode_response(&query, ResponseCode::SERVFAIL, &Answer::default(), false).ok()
+        }
+    }
+}
+
+fn parse_query(message: &[u8]) -> Result<Query, WireError> {
+    let count = |position: usize| u16::from_be_bytes([message[position], message[position + 1]]);
+
+    if count(4) != 1 {
+        return Err(WireError::InvalidField("qdcount"));
+    }
+
+    let (qname, position) = decode_name(message, HEADER_LENGTH)?;
+    let fixed = message.get(position..position + 4).ok_or(WireError::Truncated)?;
+    let question_end = position + 4;
+
+    // The only record a query is expected to carry is an OPT record for EDNS (RFC 6891)
+    let mut udp_payload = None;
+    if count(6) == 0 && count(8) == 0 && count(10) == 1 {
+        let (_, position) = decode_name(message, question_end)?;
+        let fixed = message.get(position..position + 4).ok_or(WireError::Truncated)?;
+
+        if u16::from_be_bytes([fixed[0], fixed[1]]) == TYPE_OPT {
+            let payload = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+            udp_payload = Some(payload.clamp(MIN_UDP_PAYLOAD, MAX_UDP_PAYLOAD));
+        }
+    }
+
+    Ok(Query {
+        id: count(0),
+        opcode: (message[2] >> 3) & 0x0F,
+        recursion_desired: message[2] & 0x01 != 0,
+        question: message[HEADER_LENGTH..question_end].to_vec(),
+        qname,
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        udp_payload
+    })
+}
+
+fn encode_response(query: &Query, code: ResponseCode, answer: &Answer, truncated: bool) -> Result<Vec<u8>, WireError> {
+    let mut flags: u16 = 0x8000 | ((query.opcode as u16) << 11) | code as u16;
+    if answer.authoritative {
+        flags |= 0x0400;
+    }
+    if truncated {
+        flags |= 0x0200;
+    }
+    if query.recursion_desired {
+        flags |= 0x0100;
+    }
+
+    let mut buf: Vec<u8> = Vec::with_capacity(MIN_UDP_PAYLOAD);
+    buf.extend_from_slice(&query.id.to_be_bytes());
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&1u16.to_be_bytes());
+    buf.extend_from_slice(&(answer.answers.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&(answer.authority.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&((answer.additional.len() + query.udp_payload.is_some() as usize) as u16).to_be_bytes());
+
+    let mut compressor = Compressor::default();
+
+    // Only point at the question when it was sent uncompressed
+    if query.question.len() - 4 == encoded_name_length(&query.qname) {
+        compressor.remember(&query.qname, buf.len());
+    }
+    buf.extend_from_slice(&query.question);
+
+    for record in answer.answers.iter().chain(&answer.authority).chain(&answer.additional) {
+        encode_record(record, &answer.origin, &mut buf, Some(&mut compressor))?;
+    }
+
+    if query.udp_payload.is_some() {
+        // Root owner, OPT, payload size as the class, no extended flags and no options
+        buf.push(0);
+        buf.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        buf.extend_from_slice(&(MAX_UDP_PAYLOAD as u16).to_be_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    }
+
+    Ok(buf)
+}
+
+fn encoded_name_length(name: &str) -> usize {
+    if name == "." {
+        1
+    } else {
+        name.split('.').map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+}
+
+fn within(name: &str, origin: &str) -> bool {
+    name == origin || origin == "." || name.ends_with(&format!(".{}", origin))
+}
+
+fn owner<'a>(record: &'a RecordTypes, origin: &'a str) -> &'a str {
+    record.hostname().unwrap_or(origin)
+}
+
+fn parent(name: &str) -> Option<&str> {
+    name.split_once('.').map(|(_, parent)| parent)
+}
+
+/// Looks up the zone a query falls within and answers it from that zone's active records
+async fn answer_query(query: &Query, zones: &dyn ZoneStore) -> Result<(ResponseCode, Answer), DbErr> {
+    Ok(match zones.find_zone(&query.qname).await? {
+        Some((origin, records)) => resolve(&origin, &records, &query.qname, query.qtype),
+        None => (ResponseCode::REFUSED, Answer::default())
+    })
+}
+
+/// Answers a query from a single zone's records, following RFC 1034 section 4.3.2 and RFC 6672
+/// section 3.2 for DNAMEs
+fn resolve(origin: &str, records: &[RecordTypes], qname: &str, qtype: u16) -> (ResponseCode, Answer) {
+    let mut answer = Answer { authoritative: true, origin: origin.to_string(), ..Answer::default() };
+    let mut name = qname.to_string();
+
+    let exists = |name: &str| records.iter().any(|record| {
+        let record_owner = owner(record, origin);
+        record_owner == name || record_owner.ends_with(&format!(".{}", name))
+    });
+
+    let negative = |answer: &mut Answer| {
+        // Negative answers are cached for the lower of the SOA's TTL and minimum (RFC 2308 section 5)
+        let soa = records.iter().find(|record| matches!(record, RecordTypes::SOA { .. })).cloned();
+
+        if let Some(mut soa) = soa {
+            if let RecordTypes::SOA { ttl, minimum, .. } = &mut soa {
+                *ttl = (*ttl).min(i32::try_from(*minimum).unwrap_or(i32::MAX));
+            }
+
+            answer.authority.push(soa);
+        }
+    };
+
+    for _ in 0..MAX_CNAME_CHAIN {
+        // Names at or below a delegation are answered with a referral, apart from DS records
+        // which live on the parent side of the cut
+        if let Some(cut) = find_cut(origin, records, &name, qtype == TYPE_DS) {
+            if answer.answers.is_empty() {
+                answer.authoritative = false;
+                referral(origin, records, &cut, &mut answer);
+            }
+
+            return (ResponseCode::NOERROR, answer);
+        }
+
+        // A DNAME redirects everything below its owner, but not the owner itself
+        if let Some(dname) = find_dname(origin, records, &name) {
+            let (target, ttl) = match dname {
+                RecordTypes::DNAME { dname: target, ttl, .. } => (target, *ttl),
+                _ => unreachable!()
+            };
+
+            let substituted = substitute(&name, owner(dname, origin), target);
+            if encoded_name_length(&substituted) > MAX_NAME_LENGTH {
+                return (ResponseCode::YXDOMAIN, answer);
+            }
+
+            answer.answers.push(dname.clone());
+            answer.answers.push(RecordTypes::CNAME { hostname: name.clone(), ttl, cname: substituted.clone() });
+
+            if qtype == TYPE_CNAME || !within(&substituted, origin) {
+                return (ResponseCode::NOERROR, answer);
+            }
+
+            name = substituted;
+            continue;
+        }
+
+        let mut at_name: Vec<RecordTypes> = records.iter()
+            .filter(|record| owner(record, origin) == name)
+            .cloned()
+            .collect();
+
+        if at_name.is_empty() && !exists(&name) {
+            at_name = synthesise_wildcard(origin, records, &name, &exists);
+        }
+
+        if at_name.is_empty() && !exists(&name) {
+            negative(&mut answer);
+            return (ResponseCode::NXDOMAIN, answer);
+        }
+
+        let cname = at_name.iter().find(|record| matches!(record, RecordTypes::CNAME { .. })).cloned();
+
+        match cname {
+            Some(cname @ RecordTypes::CNAME { .. }) if qtype != TYPE_CNAME && qtype != TYPE_ANY => {
+                let target = match &cname {
+                    RecordTypes::CNAME { cname: target, .. } => target.clone(),
+                    _ => unreachable!()
+                };
+
+                answer.answers.push(cname);
+
+                if !within(&target, origin) {
+                    return (ResponseCode::NOERROR, answer);
+                }
+
+                name = target;
+            }
+            _ => {
+                let matching: Vec<RecordTypes> = at_name.into_iter()
+                    .filter(|record| qtype == TYPE_ANY || record.type_code() == qtype)
+                    .collect();
+
+                if matching.is_empty() {
+                    negative(&mut answer);
+                } else {
+                    answer.answers.extend(matching);
+                }
+
+                return (ResponseCode::NOERROR, answer);
+            }
+        }
+    }
+
+    (ResponseCode::NOERROR, answer)
+}
+
+/// Finds the closest delegation point between the zone's origin and `name`
+fn find_cut(origin: &str, records: &[RecordTypes], name: &str, exclude_name: bool) -> Option<String> {
+    let mut cut: Option<String> = None;
+    let mut candidate = Some(name);
+
+    while let Some(current) = candidate {
+        if current == origin || !within(current, origin) {
+            break;
+        }
+
+        let delegated = records.iter()
+            .any(|record| matches!(record, RecordTypes::NS { .. }) && owner(record, origin) == current);
+
+        if delegated && !(exclude_name && current == name) {
+            cut = Some(current.to_string());
+        }
+
+        candidate = parent(current);
+    }
+
+    cut
+}
+
+/// Finds the DNAME closest to the zone's origin that `name` falls below
+fn find_dname<'a>(origin: &str, records: &'a [RecordTypes], name: &str) -> Option<&'a RecordTypes> {
+    let mut dname: Option<&RecordTypes> = None;
+    let mut candidate = parent(name);
+
+    while let Some(current) = candidate {
+        if !within(current, origin) {
+            break;
+        }
+
+        if let Some(record) = records.iter().find(|record| matches!(record, RecordTypes::DNAME { .. }) && owner(record, origin) == current) {
+            dname = Some(record);
+        }
+
+        candidate = parent(current);
+    }
+
+    dname
+}
+
+/// Replaces the DNAME owner `suffix` at the end of `name` with the DNAME's target
+fn substitute(name: &str, suffix: &str, target: &str) -> String {
+    let prefix = match suffix {
+        "." => name,
+        _ => name.strip_suffix(suffix).and_then(|prefix| prefix.strip_suffix('.')).unwrap_or(name)
+    };
+
+    match target {
+        "." => prefix.to_string(),
+        _ => format!("{}.{}", prefix, target)
+    }
+}
+
+fn referral(origin: &str, records: &[RecordTypes], cut: &str, answer: &mut Answer) {
+    for record in records {
+        if let RecordTypes::NS { nsdame, .. } = record {
+            if owner(record, origin) != cut {
+                continue;
+            }
+
+            answer.authority.push(record.clone());
+
+            // Glue is only needed for nameservers inside the delegated zone
+            if within(nsdame, cut) {
+                answer.additional.extend(records.iter()
+                    .filter(|glue| matches!(glue, RecordTypes::A { .. } | RecordTypes::AAAA { .. }))
+                    .filter(|glue| owner(glue, origin) == nsdame)
+                    .cloned());
+            }
+        }
+    }
+}
+
+/// Builds records for `name` from the wildcard at its closest encloser (RFC 4592 section 3.3.1)
+fn synthesise_wildcard(origin: &str, records: &[RecordTypes], name: &str, exists: &dyn Fn(&str) -> bool) -> Vec<RecordTypes> {
+    let mut candidate = parent(name);
+
+    while let Some(encloser) = candidate {
+        if !within(encloser, origin) {
+            break;
+        }
+
+        if exists(encloser) {
+            let wildcard = format!("*.{}", encloser);
+
+            return records.iter()
+                .filter(|record| owner(record, origin) == wildcard)
+                .map(|record| {
+                    let mut synthesised = record.clone();
+                    synthesised.set_hostname(name.to_string());
+                    synthesised
+                })
+                .collect();
+        }
+
+        candidate = parent(encloser);
+    }
+
+    vec![]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::dns::wire::{decode_rdata, encode_name};
+
+    use super::*;
+
+    const TYPE_A: u16 = 1;
+
+    /// Serves a fixed set of zones instead of the database
+    struct MemoryZones {
+        zones: Vec<(String, Vec<RecordTypes>)>
+    }
+
+    #[async_trait]
+    impl ZoneStore for MemoryZones {
+        async fn find_zone(&self, name: &str) -> Result<Option<(String, Vec<RecordTypes>)>, DbErr> {
+            Ok(self.zones.iter()
+                .filter(|(origin, _)| within(name, origin))
+                .max_by_key(|(origin, _)| origin.len())
+                .cloned())
+        }
+    }
+
+    fn a(hostname: &str, address: [u8; 4]) -> RecordTypes {
+        RecordTypes::A { hostname: hostname.to_string(), ttl: 300, address: Ipv4Addr::from(address) }
+    }
+
+    fn ns(hostname: &str, nameserver: &str) -> RecordTypes {
+        RecordTypes::NS { hostname: hostname.to_string(), ttl: 3600, nsdame: nameserver.to_string() }
+    }
+
+    fn example_zone() -> (String, Vec<RecordTypes>) {
+        let records = vec![
+            RecordTypes::SOA {
+                ttl: 3600,
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 1,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300
+            },
+            ns("example.com", "ns1.example.com"),
+            a("ns1.example.com", [192, 0, 2, 53]),
+            a("www.example.com", [192, 0, 2, 1]),
+            ns("sub.example.com", "ns1.sub.example.com"),
+            a("ns1.sub.example.com", [192, 0, 2, 54]),
+            RecordTypes::DNAME { hostname: "old.example.com".to_string(), ttl: 600, dname: "new.example.com".to_string() },
+            a("www.new.example.com", [192, 0, 2, 2])
+        ];
+
+        ("example.com".to_string(), records)
+    }
+
+    fn build_query(id: u16, qname: &str, qtype: u16) -> Vec<u8> {
+        let mut query = id.to_be_bytes().to_vec();
+        query.extend_from_slice(&[0x00, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        encode_name(qname, &mut query, None).unwrap();
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        query
+    }
+
+    struct Response {
+        id: u16,
+        flags: u16,
+        answers: Vec<RecordTypes>,
+        authority: Vec<RecordTypes>,
+        additional: Vec<RecordTypes>
+    }
+
+    impl Response {
+        fn code(&self) -> u16 {
+            self.flags & 0x000F
+        }
+
+        fn authoritative(&self) -> bool {
+            self.flags & 0x0400 != 0
+        }
+    }
+
+    fn parse_response(message: &[u8]) -> Response {
+        let count = |position: usize| u16::from_be_bytes([message[position], message[position + 1]]) as usize;
+        let (_, mut position) = decode_name(message, HEADER_LENGTH).unwrap();
+        position += 4;
+
+        let mut sections: Vec<Vec<RecordTypes>> = vec![];
+        for section_count in [count(6), count(8), count(10)] {
+            let mut section = vec![];
+
+            for _ in 0..section_count {
+                let (owner, next) = decode_name(message, position).unwrap();
+                let fixed = &message[next..next + 10];
+                let type_code = u16::from_be_bytes([fixed[0], fixed[1]]);
+                let ttl = i32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+                let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+                section.push(decode_rdata(type_code, owner, ttl, message, next + 10, length).unwrap());
+                position = next + 10 + length;
+            }
+
+            sections.push(section);
+        }
+
+        assert_eq!(position, message.len());
+
+        let additional = sections.pop().unwrap();
+        let authority = sections.pop().unwrap();
+        let answers = sections.pop().unwrap();
+
+        Response { id: count(0) as u16, flags: count(2) as u16, answers, authority, additional }
+    }
+
+    /// Starts a server on a free loopback port, returning the address it listens on
+    async fn start_server() -> SocketAddr {
+        let server = DnsServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.udp_socket.local_addr().unwrap();
+
+        tokio::spawn(server.run(Arc::new(MemoryZones { zones: vec![example_zone()] })));
+
+        addr
+    }
+
+    async fn query_udp(addr: SocketAddr, query: &[u8]) -> Response {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(query, addr).await.unwrap();
+
+        let mut buf = [0u8; MAX_UDP_PAYLOAD];
+        let (length, _) = timeout(Duration::from_secs(5), socket.recv_from(&mut buf)).await.unwrap().unwrap();
+
+        parse_response(&buf[..length])
+    }
+
+    async fn query_tcp(stream: &mut TcpStream, query: &[u8]) -> Response {
+        stream.write_u16(query.len() as u16).await.unwrap();
+        stream.write_all(query).await.unwrap();
+
+        let length = timeout(Duration::from_secs(5), stream.read_u16()).await.unwrap().unwrap();
+        let mut buf = vec![0u8; length as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        parse_response(&buf)
+    }
+
+    #[tokio::test]
+    async fn answers_over_udp() {
+        let addr = start_server().await;
+
+        let response = query_udp(addr, &build_query(1, "www.example.com", TYPE_A)).await;
+        assert_eq!(response.id, 1);
+        assert_eq!(response.code(), ResponseCode::NOERROR as u16);
+        assert!(response.authoritative());
+        assert_eq!(response.answers, vec![a("www.example.com", [192, 0, 2, 1])]);
+        assert!(response.authority.is_empty());
+
+        let response = query_udp(addr, &build_query(2, "missing.example.com", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::NXDOMAIN as u16);
+        assert!(response.authoritative());
+        assert!(response.answers.is_empty());
+        assert!(matches!(response.authority.as_slice(), [RecordTypes::SOA { ttl: 300, .. }]));
+
+        let response = query_udp(addr, &build_query(3, "www.example.org", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::REFUSED as u16);
+    }
+
+    #[tokio::test]
+    async fn answers_over_tcp() {
+        let addr = start_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Delegated names are referred to the child zone's nameservers, with glue
+        let response = query_tcp(&mut stream, &build_query(4, "host.sub.example.com", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::NOERROR as u16);
+        assert!(!response.authoritative());
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authority, vec![ns("sub.example.com", "ns1.sub.example.com")]);
+        assert_eq!(response.additional, vec![a("ns1.sub.example.com", [192, 0, 2, 54])]);
+
+        // The connection stays open for further queries
+        let response = query_tcp(&mut stream, &build_query(5, "www.example.com", TYPE_A)).await;
+        assert_eq!(response.id, 5);
+        assert!(response.authoritative());
+        assert_eq!(response.answers, vec![a("www.example.com", [192, 0, 2, 1])]);
+
+        let response = query_tcp(&mut stream, &build_query(6, "missing.example.com", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::NXDOMAIN as u16);
+    }
+
+    #[tokio::test]
+    async fn follows_dnames() {
+        let addr = start_server().await;
+
+        let response = query_udp(addr, &build_query(7, "www.old.example.com", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::NOERROR as u16);
+        assert!(response.authoritative());
+        assert_eq!(response.answers, vec![
+            RecordTypes::DNAME { hostname: "old.example.com".to_string(), ttl: 600, dname: "new.example.com".to_string() },
+            RecordTypes::CNAME { hostname: "www.old.example.com".to_string(), ttl: 600, cname: "www.new.example.com".to_string() },
+            a("www.new.example.com", [192, 0, 2, 2])
+        ]);
+
+        // The owner itself isn't redirected
+        let response = query_udp(addr, &build_query(8, "old.example.com", TYPE_A)).await;
+        assert_eq!(response.code(), ResponseCode::NOERROR as u16);
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn substitutes_dnames() {
+        let (origin, mut records) = example_zone();
+        records.push(RecordTypes::DNAME { hostname: "away.example.com".to_string(), ttl: 600, dname: "example.net".to_string() });
+
+        // Targets outside the zone are left for the client to follow
+        let (code, answer) = resolve(&origin, &records, "a.b.away.example.com", TYPE_A);
+        assert!(code == ResponseCode::NOERROR);
+        assert_eq!(answer.answers.len(), 2);
+        assert_eq!(answer.answers[1], RecordTypes::CNAME { hostname: "a.b.away.example.com".to_string(), ttl: 600, cname: "a.b.example.net".to_string() });
+
+        // Names under a DNAME that doesn't lead anywhere are NXDOMAIN at the target
+        let (code, answer) = resolve(&origin, &records, "gone.old.example.com", TYPE_A);
+        assert!(code == ResponseCode::NXDOMAIN);
+        assert_eq!(answer.answers.len(), 2);
+
+        assert_eq!(substitute("www.example.com", ".", "example.net"), "www.example.com.example.net");
+        assert_eq!(substitute("www.example.com", "example.com", "."), "www");
+    }
+
+    #[test]
+    fn rejects_overlong_substitutions() {
+        let (origin, mut records) = example_zone();
+        let long_target = ["a".repeat(63), "a".repeat(63), "a".repeat(63), "example".to_string()].join(".");
+        records.push(RecordTypes::DNAME { hostname: "long.example.com".to_string(), ttl: 600, dname: long_target });
+
+        let (code, _) = resolve(&origin, &records, "www.long.example.com", TYPE_A);
+        assert!(code == ResponseCode::NOERROR);
+
+        let (code, _) = resolve(&origin, &records, &format!("{}.long.example.com", "b".repeat(60)), TYPE_A);
+        assert!(code == ResponseCode::YXDOMAIN);
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use sea_orm::*;
+use ulid::Ulid;
+
+use crate::dns::nameservers;
+use crate::dns::records::RecordTypes;
+use crate::entities::{record, zone};
+use crate::entities::prelude::{Record, Zone};
+
+const DEFAULT_TTL: i32 = 3600;
+const DEFAULT_REFRESH: i32 = 7200;
+const DEFAULT_RETRY: i32 = 3600;
+const DEFAULT_EXPIRE: i32 = 1209600;
+const DEFAULT_MINIMUM: u32 = 300;
+
+/// Gets the first serial of a day in the YYYYMMDDnn scheme
+fn serial_base(date: NaiveDate) -> u32 {
+    (date.year() as u32 * 10000 + date.month() * 100 + date.day()) * 100
+}
+
+/// Gets the serial following the current one
+///
+/// Serials move to the current day when they're behind it, otherwise they count up within the
+/// day. More than 99 changes in a day borrow from the following day, keeping serials increasing.
+/// Serials compare as in RFC 1982, so the largest one wraps around to 1, skipping 0 which some
+/// secondaries take as unset.
+pub fn next_serial(current: u32, today: NaiveDate) -> u32 {
+    let base = serial_base(today);
+
+    if current < base {
+        return base + 1;
+    }
+
+    match current.wrapping_add(1) {
+        0 => {
+            warn!("SOA serial {} wrapped around", current);
+            1
+        }
+        next => next
+    }
+}
+
+/// Builds the SOA a zone starts out with
+pub fn default_soa(origin: &str) -> RecordTypes {
+    let mname = nameservers()
+        .into_iter()
+        .next()
+        .unwrap_or(format!("ns1.{}", origin));
+
+    RecordTypes::SOA {
+        ttl: DEFAULT_TTL,
+        mname,
+        rname: format!("hostmaster.{}", origin),
+        serial: next_serial(0, Utc::now().naive_utc().date()),
+        refresh: DEFAULT_REFRESH,
+        retry: DEFAULT_RETRY,
+        expire: DEFAULT_EXPIRE,
+        minimum: DEFAULT_MINIMUM
+    }
+}
+
+/// Inserts a zone's default SOA
+pub async fn create_soa<C: ConnectionTrait>(zone: &zone::Model, connection: &C) -> Result<(), DbErr> {
+    Record::insert(record::ActiveModel {
+        id: ActiveValue::Set(Ulid::new().to_string()),
+        zone: ActiveValue::Set(zone.id.clone()),
+        value: ActiveValue::Set(default_soa(&zone.origin).to_msgpack().expect("Failed to encode SOA!")),
+        active: ActiveValue::Set(true)
+    })
+        .exec(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Finds a zone's SOA record alongside its decoded value
+pub async fn find_soa<C: ConnectionTrait>(zone_id: &str, connection: &C) -> Result<Option<(record::Model, RecordTypes)>, DbErr> {
+    let records: Vec<record::Model> = Record::find()
+        .filter(record::Column::Zone.eq(zone_id))
+        .all(connection)
+        .await?;
+
+    Ok(records.into_iter()
+        .filter_map(|model| {
+            RecordTypes::from_msgpack(&model.value).ok().map(|decoded| (model, decoded))
+        })
+        .find(|(_, decoded)| matches!(decoded, RecordTypes::SOA { .. })))
+}
+
+/// Locks a zone until the transaction ends, so changes checked against the zone's other records
+/// are checked and written one at a time
+pub async fn lock_zone<C: ConnectionTrait>(zone: &zone::Model, connection: &C) -> Result<(), DbErr> {
+    Zone::find_by_id(zone.id.clone())
+        .lock_exclusive()
+        .one(connection)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("Zone {}", zone.id)))?;
+
+    Ok(())
+}
+
+/// Moves a zone's SOA serial on, to be called within the transaction that changed the zone
+///
+/// Zones which somehow lost their SOA are given a fresh one. The SOA row stays locked until the
+/// transaction ends.
+pub async fn bump_serial<C: ConnectionTrait>(zone: &zone::Model, connection: &C) -> Result<u32, DbErr> {
+    match find_soa(&zone.id, connection).await? {
+        None => {
+            warn!("Zone {} had no SOA, creating a new one", zone.id);
+            create_soa(zone, connection).await?;
+
+            match default_soa(&zone.origin) {
+                RecordTypes::SOA { serial, .. } => Ok(serial),
+                _ => unreachable!()
+            }
+        }
+        Some((model, _)) => {
+            // Read again under a row lock, so concurrent changes to the zone each move the serial
+            // on rather than writing the same one
+            let model = Record::find_by_id(model.id)
+                .lock_exclusive()
+                .one(connection)
+                .await?
+                .ok_or_else(|| DbErr::RecordNotFound(format!("SOA of zone {}", zone.id)))?;
+            let mut soa = RecordTypes::from_msgpack(&model.value)
+                .map_err(|_| DbErr::Custom(format!("SOA of zone {} could not be decoded", zone.id)))?;
+
+            let new_serial = match &mut soa {
+                RecordTypes::SOA { serial, .. } => {
+                    *serial = next_serial(*serial, Utc::now().naive_utc().date());
+                    *serial
+                }
+                _ => unreachable!()
+            };
+
+            let mut bumped_soa: record::ActiveModel = model.into();
+            bumped_soa.value = ActiveValue::Set(soa.to_msgpack().expect("Failed to encode SOA!"));
+            bumped_soa.update(connection).await?;
+
+            Ok(new_serial)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_serial_behind_today_jumps_to_today() {
+        let today = NaiveDate::from_ymd(2022, 10, 9);
+
+        assert_eq!(next_serial(0, today), 2022100901);
+        assert_eq!(next_serial(2022100842, today), 2022100901);
+    }
+
+    #[test]
+    fn next_serial_counts_up_within_the_day() {
+        let today = NaiveDate::from_ymd(2022, 10, 9);
+
+        assert_eq!(next_serial(2022100900, today), 2022100901);
+        assert_eq!(next_serial(2022100901, today), 2022100902);
+    }
+
+    #[test]
+    fn next_serial_borrows_from_the_next_day() {
+        let today = NaiveDate::from_ymd(2022, 10, 9);
+
+        assert_eq!(next_serial(2022100999, today), 2022101000);
+        assert_eq!(next_serial(next_serial(2022100999, today), NaiveDate::from_ymd(2022, 10, 10)), 2022101001);
+    }
+
+    #[test]
+    fn next_serial_wraps_past_zero() {
+        let today = NaiveDate::from_ymd(2022, 10, 9);
+
+        assert_eq!(next_serial(u32::MAX - 1, today), u32::MAX);
+        assert_eq!(next_serial(u32::MAX, today), 1);
+        assert_eq!(next_serial(1, today), 2022100901);
+    }
+}
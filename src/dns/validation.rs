@@ -0,0 +1,857 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use base64ct::{Base64, Encoding};
+use serde::Serialize;
+
+use crate::dns::records::{RecordTypes, SvcParams};
+use crate::dns::wire::{encode_rdata, svc_param_key};
+use crate::util::decode_hex;
+
+pub const MIN_TTL: i32 = 30;
+pub const MAX_TTL: i32 = 604800;
+
+/// Longest a single label can be in octets
+const MAX_LABEL_LENGTH: usize = 63;
+/// Longest a name can be in its presentation form, without the trailing root label
+const MAX_NAME_LENGTH: usize = 253;
+
+/// Issuer critical flag for CAA records
+const CAA_ISSUER_CRITICAL: u8 = 128;
+/// CAA property tags registered with IANA
+const CAA_TAGS: [&str; 7] = ["issue", "issuewild", "iodef", "issuemail", "issuevmc", "contactemail", "contactphone"];
+
+/// Issues found with a record, keyed by the field they relate to
+///
+/// The form structs elsewhere have a field per input, but every record type has its own fields, so
+/// these are keyed by name instead. It serialises to the same object of issue lists, leaving out
+/// fields without issues.
+#[derive(Serialize, Default, Debug)]
+#[serde(transparent)]
+pub struct RecordIssues(BTreeMap<&'static str, Vec<String>>);
+
+impl RecordIssues {
+    pub fn push(&mut self, field: &'static str, issue: String) {
+        self.0.entry(field).or_default().push(issue);
+    }
+
+    pub fn extend(&mut self, field: &'static str, issues: Vec<String>) {
+        for issue in issues {
+            self.push(field, issue);
+        }
+    }
+
+    pub fn merge(&mut self, other: RecordIssues) {
+        for (field, issues) in other.0 {
+            self.extend(field, issues);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.values().all(|issues| issues.is_empty())
+    }
+}
+
+/// Lowercases a name and strips its trailing root label, the root itself is left as `.`
+pub fn normalise_name(name: &str) -> String {
+    match name.trim() {
+        "." => ".".to_string(),
+        name => name.trim_end_matches('.').to_lowercase()
+    }
+}
+
+/// Normalises a record's owner name and the names it points at
+///
+/// `@` is expanded to the zone's origin.
+pub fn normalise_record(record: &mut RecordTypes, origin: &str) {
+    if let Some(hostname) = record.hostname() {
+        let hostname = match hostname.trim() {
+            "@" => origin.to_string(),
+            hostname => normalise_name(hostname)
+        };
+
+        record.set_hostname(hostname);
+    }
+
+    match record {
+        RecordTypes::SOA { mname, rname, .. } => {
+            *mname = normalise_name(mname);
+            *rname = normalise_name(rname);
+        }
+        RecordTypes::CNAME { cname: target, .. }
+        | RecordTypes::DNAME { dname: target, .. }
+        | RecordTypes::MX { exchange: target, .. }
+        | RecordTypes::NS { nsdame: target, .. }
+        | RecordTypes::PTR { nsdame: target, .. }
+        | RecordTypes::SRV { target, .. }
+        | RecordTypes::SVCB { target, .. }
+        | RecordTypes::HTTPS { target, .. }
+        | RecordTypes::NAPTR { replacement: target, .. } => {
+            *target = normalise_name(target);
+        }
+        RecordTypes::TLSA { certificate_data: hex, .. }
+        | RecordTypes::SSHFP { fingerprint: hex, .. }
+        | RecordTypes::DS { digest: hex, .. } => {
+            *hex = hex.split_whitespace().collect::<String>().to_lowercase();
+        }
+        RecordTypes::DNSKEY { public_key, .. } => {
+            *public_key = public_key.split_whitespace().collect();
+        }
+        RecordTypes::CAA { tag, .. } => {
+            *tag = tag.trim().to_lowercase();
+        }
+        _ => {}
+    }
+}
+
+/// Checks the syntax of a normalised domain name, returning any issues found
+///
+/// Underscores are only accepted when `allow_underscore` is set, as they're only meaningful
+/// in owner names such as `_sip._tcp` or `_dmarc`.
+pub fn validate_name(name: &str, allow_underscore: bool) -> Vec<String> {
+    let mut issues: Vec<String> = vec![];
+
+    if name.is_empty() {
+        issues.push("Name cannot be empty.".to_string());
+        return issues;
+    }
+
+    if name.len() > MAX_NAME_LENGTH {
+        issues.push(format!("Name cannot be longer than {} characters.", MAX_NAME_LENGTH));
+    }
+
+    for label in name.split('.') {
+        if label.is_empty() {
+            issues.push("Name cannot contain empty labels.".to_string());
+            break;
+        }
+
+        if label.len() > MAX_LABEL_LENGTH {
+            issues.push(format!("Label {} is longer than {} characters.", label, MAX_LABEL_LENGTH));
+        }
+
+        // The wildcard label is only valid on its own
+        if label == "*" {
+            continue;
+        }
+
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || (allow_underscore && c == '_')) {
+            issues.push(format!("Label {} contains invalid characters.", label));
+        } else if label.starts_with('-') || label.ends_with('-') {
+            issues.push(format!("Label {} cannot start or end with a hyphen.", label));
+        }
+    }
+
+    if name.split('.').skip(1).any(|label| label == "*") {
+        issues.push("A wildcard can only be the leftmost label.".to_string());
+    }
+
+    issues
+}
+
+/// Checks a name that a record points at, which must be a host rather than an address
+fn validate_target(target: &str) -> Vec<String> {
+    if target.parse::<IpAddr>().is_ok() {
+        return vec!["Target must be a hostname, not an IP address.".to_string()];
+    }
+
+    let mut issues = validate_name(target, false);
+
+    if target.contains('*') {
+        issues.push("Target cannot contain a wildcard.".to_string());
+    }
+
+    issues
+}
+
+pub fn validate_ttl(ttl: i32) -> Vec<String> {
+    let mut issues: Vec<String> = vec![];
+
+    if ttl < 0 {
+        issues.push("TTL cannot be negative.".to_string());
+    } else if ttl < MIN_TTL {
+        issues.push(format!("TTL cannot be lower than {}.", MIN_TTL));
+    } else if ttl > MAX_TTL {
+        issues.push(format!("TTL cannot be higher than {}.", MAX_TTL));
+    }
+
+    issues
+}
+
+/// Checks a CAA property (RFC 8659) and the value its tag expects
+fn validate_caa(flags: u8, tag: &str, value: &str) -> RecordIssues {
+    let mut issues = RecordIssues::default();
+
+    // Every flag other than issuer critical is reserved
+    if flags & !CAA_ISSUER_CRITICAL != 0 {
+        issues.push("flags", format!("Flags can only be 0 or {}.", CAA_ISSUER_CRITICAL));
+    }
+
+    if !CAA_TAGS.contains(&tag) {
+        issues.push("tag", format!("Tag must be one of {}.", CAA_TAGS.join(", ")));
+        return issues;
+    }
+
+    if !value.is_ascii() || value.chars().any(|c| c.is_ascii_control()) {
+        issues.push("value", "Value can only contain printable ASCII characters.".to_string());
+        return issues;
+    }
+
+    match tag {
+        "issue" | "issuewild" => {
+            // issuer-domain-name *(";" parameter), an empty issuer forbids issuance entirely
+            let mut parts = value.split(';');
+            let issuer = parts.next().unwrap_or("").trim();
+
+            if !issuer.is_empty() {
+                for issue in validate_name(&normalise_name(issuer), false) {
+                    issues.push("value", format!("Issuer {} is invalid: {}", issuer, issue));
+                }
+            }
+
+            for parameter in parts.map(|parameter| parameter.trim()).filter(|parameter| !parameter.is_empty()) {
+                match parameter.split_once('=') {
+                    Some((key, parameter_value))
+                    if !key.trim().is_empty()
+                        && key.trim().chars().all(|c| c.is_ascii_alphanumeric())
+                        && !parameter_value.trim().is_empty() => {}
+                    _ => issues.push("value", format!("Parameter {} must be in the form key=value.", parameter))
+                }
+            }
+        }
+        "iodef" => {
+            if !value.starts_with("mailto:") && !value.starts_with("https://") && !value.starts_with("http://") {
+                issues.push("value", "iodef must be a mailto:, http:// or https:// URL.".to_string());
+            }
+        }
+        _ => {
+            if value.is_empty() {
+                issues.push("value", "Value cannot be empty.".to_string());
+            }
+        }
+    }
+
+    issues
+}
+
+/// Checks a normalised record on its own merits, without regard for the rest of its zone
+pub fn validate_record(record: &RecordTypes, origin: &str) -> RecordIssues {
+    let mut issues = RecordIssues::default();
+
+    if let Some(hostname) = record.hostname() {
+        let allow_underscore = !matches!(record, RecordTypes::A { .. } | RecordTypes::AAAA { .. } | RecordTypes::MX { .. });
+        issues.extend("hostname", validate_name(hostname, allow_underscore));
+
+        if hostname != origin && !hostname.ends_with(&format!(".{}", origin)) {
+            issues.push("hostname", format!("Hostname must be within {}.", origin));
+        }
+    }
+
+    issues.extend("ttl", validate_ttl(record.ttl()));
+
+    match record {
+        RecordTypes::SOA { mname, rname, refresh, retry, expire, .. } => {
+            issues.extend("mname", validate_target(mname));
+            issues.extend("rname", validate_name(rname, false));
+
+            for (field, value) in [("refresh", refresh), ("retry", retry), ("expire", expire)] {
+                if *value < 0 {
+                    issues.push(field, format!("{} cannot be negative.", field));
+                }
+            }
+
+            if retry >= refresh {
+                issues.push("retry", "Retry must be shorter than refresh.".to_string());
+            }
+
+            if expire <= refresh {
+                issues.push("expire", "Expire must be longer than refresh.".to_string());
+            }
+        }
+        RecordTypes::A { .. } | RecordTypes::AAAA { .. } => {}
+        RecordTypes::CNAME { cname, .. } => {
+            issues.extend("cname", validate_target(cname));
+        }
+        RecordTypes::DNAME { hostname, dname, .. } => {
+            issues.extend("dname", validate_target(dname));
+
+            if dname == hostname || dname.ends_with(&format!(".{}", hostname)) {
+                issues.push("dname", "DNAME cannot point within its own subtree.".to_string());
+            }
+        }
+        RecordTypes::MX { preference, exchange, .. } => {
+            if *preference < 0 {
+                issues.push("preference", "Preference cannot be negative.".to_string());
+            }
+
+            // A null MX (RFC 7505) states that the domain accepts no mail
+            if exchange == "." {
+                if *preference != 0 {
+                    issues.push("preference", "A null MX must have a preference of 0.".to_string());
+                }
+            } else {
+                issues.extend("exchange", validate_target(exchange));
+            }
+        }
+        RecordTypes::NS { nsdame, .. } => {
+            issues.extend("nsdame", validate_target(nsdame));
+        }
+        RecordTypes::PTR { nsdame, .. } => {
+            issues.extend("nsdame", validate_target(nsdame));
+        }
+        RecordTypes::TXT { txt_data, .. } => {
+            if txt_data.is_empty() {
+                issues.push("txt_data", "Text cannot be empty.".to_string());
+            } else if !txt_data.is_ascii() {
+                issues.push("txt_data", "Text can only contain ASCII characters.".to_string());
+            }
+        }
+        RecordTypes::CAA { flags, tag, value, .. } => {
+            issues.merge(validate_caa(*flags, tag, value));
+        }
+        RecordTypes::SRV { hostname, target, .. } => {
+            // Owner names are _service._proto.name (RFC 2782)
+            let labels: Vec<&str> = hostname.split('.').collect();
+            if labels.len() < 3 || !labels[0].starts_with('_') || !labels[1].starts_with('_') {
+                issues.push("hostname", "SRV hostnames must start with _service._proto.".to_string());
+            }
+
+            // A target of . states that the service isn't available
+            if target != "." {
+                issues.extend("target", validate_target(target));
+            }
+        }
+        RecordTypes::SVCB { priority, target, params, .. }
+        | RecordTypes::HTTPS { priority, target, params, .. } => {
+            // A target of . refers to the owner name itself
+            if target != "." {
+                issues.extend("target", validate_target(target));
+            }
+
+            if *priority == 0 {
+                if *params != SvcParams::default() {
+                    issues.push("params", "Alias mode records (priority 0) cannot have parameters.".to_string());
+                }
+            } else {
+                issues.extend("params", validate_svc_params(params));
+            }
+        }
+        RecordTypes::TLSA { hostname, usage, selector, matching_type, certificate_data, .. } => {
+            // Owner names are _port._proto.name (RFC 6698)
+            let labels: Vec<&str> = hostname.split('.').collect();
+            if labels.len() < 3 || !labels[0].starts_with('_') || !labels[1].starts_with('_') {
+                issues.push("hostname", "TLSA hostnames must start with _port._proto.".to_string());
+            }
+
+            if *usage > 3 {
+                issues.push("usage", "Usage must be between 0 and 3.".to_string());
+            }
+
+            if *selector > 1 {
+                issues.push("selector", "Selector must be 0 or 1.".to_string());
+            }
+
+            let expected_length = match matching_type {
+                0 => None,
+                1 => Some(32),
+                2 => Some(64),
+                _ => {
+                    issues.push("matching_type", "Matching type must be between 0 and 2.".to_string());
+                    None
+                }
+            };
+
+            issues.extend("certificate_data", validate_hex(certificate_data, expected_length));
+        }
+        RecordTypes::SSHFP { algorithm, fingerprint_type, fingerprint, .. } => {
+            if !matches!(algorithm, 1..=4 | 6) {
+                issues.push("algorithm", "Algorithm must be RSA (1), DSA (2), ECDSA (3), Ed25519 (4) or Ed448 (6).".to_string());
+            }
+
+            let expected_length = match fingerprint_type {
+                1 => Some(20),
+                2 => Some(32),
+                _ => {
+                    issues.push("fingerprint_type", "Fingerprint type must be SHA-1 (1) or SHA-256 (2).".to_string());
+                    None
+                }
+            };
+
+            issues.extend("fingerprint", validate_hex(fingerprint, expected_length));
+        }
+        RecordTypes::NAPTR { flags, services, regexp, replacement, .. } => {
+            if !flags.chars().all(|c| c.is_ascii_alphanumeric()) {
+                issues.push("flags", "Flags can only contain letters and numbers.".to_string());
+            }
+
+            if !services.is_ascii() {
+                issues.push("services", "Services can only contain ASCII characters.".to_string());
+            }
+
+            // Only one of regexp and replacement may be used (RFC 3403 section 4.1)
+            if !regexp.is_empty() && replacement != "." {
+                issues.push("replacement", "Replacement must be . when a regexp is set.".to_string());
+            } else if regexp.is_empty() && replacement == "." {
+                issues.push("regexp", "Either a regexp or a replacement is required.".to_string());
+            }
+
+            if replacement != "." {
+                issues.extend("replacement", validate_name(replacement, true));
+            }
+        }
+        RecordTypes::DS { algorithm, digest_type, digest, .. } => {
+            issues.extend("algorithm", validate_dnssec_algorithm(*algorithm));
+
+            let expected_length = match digest_type {
+                1 => Some(20),
+                2 => Some(32),
+                4 => Some(48),
+                _ => {
+                    issues.push("digest_type", "Digest type must be SHA-1 (1), SHA-256 (2) or SHA-384 (4).".to_string());
+                    None
+                }
+            };
+
+            issues.extend("digest", validate_hex(digest, expected_length));
+        }
+        RecordTypes::DNSKEY { flags, protocol, algorithm, public_key, .. } => {
+            if *flags != 256 && *flags != 257 {
+                issues.push("flags", "Flags must be 256 (ZSK) or 257 (KSK).".to_string());
+            }
+
+            if *protocol != 3 {
+                issues.push("protocol", "Protocol must be 3.".to_string());
+            }
+
+            issues.extend("algorithm", validate_dnssec_algorithm(*algorithm));
+
+            if Base64::decode_vec(public_key).map_or(true, |key| key.is_empty()) {
+                issues.push("public_key", "Public key must be valid base64.".to_string());
+            }
+        }
+        RecordTypes::RRSIG { .. } | RecordTypes::NSEC3 { .. } | RecordTypes::NSEC3PARAM { .. } => {
+            issues.push("value", format!("{} records are generated when the zone is signed.", record.type_name()));
+        }
+    }
+
+    // Anything left that can't be put on the wire is still a problem
+    if issues.is_empty() {
+        if let Err(err) = encode_rdata(record) {
+            issues.push("value", format!("Record cannot be encoded: {}.", err));
+        }
+    }
+
+    issues
+}
+
+fn validate_hex(hex: &str, expected_length: Option<usize>) -> Vec<String> {
+    match decode_hex(hex) {
+        None => vec!["Must be valid hex.".to_string()],
+        Some(bytes) if bytes.is_empty() => vec!["Cannot be empty.".to_string()],
+        Some(bytes) => match expected_length {
+            Some(expected_length) if bytes.len() != expected_length => {
+                vec![format!("Must be {} bytes long, found {}.", expected_length, bytes.len())]
+            }
+            _ => vec![]
+        }
+    }
+}
+
+/// Checks for DNSSEC algorithms that are still recommended for use (RFC 8624)
+fn validate_dnssec_algorithm(algorithm: u8) -> Vec<String> {
+    match algorithm {
+        5 | 7 | 8 | 10 | 13 | 14 | 15 | 16 => vec![],
+        _ => vec![format!("Algorithm {} is unsupported.", algorithm)]
+    }
+}
+
+fn validate_svc_params(params: &SvcParams) -> Vec<String> {
+    let mut issues: Vec<String> = vec![];
+
+    for key in &params.mandatory {
+        let present = match key.as_str() {
+            "alpn" => !params.alpn.is_empty(),
+            "no-default-alpn" => params.no_default_alpn,
+            "port" => params.port.is_some(),
+            "ipv4hint" => !params.ipv4hint.is_empty(),
+            "ech" => params.ech.is_some(),
+            "ipv6hint" => !params.ipv6hint.is_empty(),
+            _ => {
+                if svc_param_key(key).is_none() {
+                    issues.push(format!("Mandatory key {} is unknown.", key));
+                } else {
+                    issues.push("mandatory cannot list itself.".to_string());
+                }
+                continue;
+            }
+        };
+
+        if !present {
+            issues.push(format!("Mandatory key {} is missing.", key));
+        }
+    }
+
+    for alpn in &params.alpn {
+        if alpn.is_empty() || alpn.len() > 255 {
+            issues.push(format!("ALPN id {} must be between 1 and 255 characters.", alpn));
+        }
+    }
+
+    if params.no_default_alpn && params.alpn.is_empty() {
+        issues.push("no-default-alpn requires alpn to be set.".to_string());
+    }
+
+    if let Some(ech) = &params.ech {
+        if Base64::decode_vec(ech).is_err() {
+            issues.push("ech must be valid base64.".to_string());
+        }
+    }
+
+    issues
+}
+
+/// Checks a normalised record against the other records in its zone
+///
+/// `replacing` is the id of the record being updated, so it isn't compared against itself.
+pub fn validate_zone_conflicts(
+    record: &RecordTypes,
+    origin: &str,
+    existing: &[(String, RecordTypes)],
+    replacing: Option<&str>
+) -> RecordIssues {
+    let mut issues = RecordIssues::default();
+
+    let hostname = record.hostname().unwrap_or(origin);
+    let others = existing.iter()
+        .filter(|(id, _)| replacing != Some(id.as_str()))
+        .map(|(_, other)| other);
+
+    match record {
+        RecordTypes::SOA { .. } if others.clone().any(|other| matches!(other, RecordTypes::SOA { .. })) => {
+            issues.push("value", "A zone can only have one SOA record, update the existing one instead.".to_string());
+        }
+        RecordTypes::CNAME { .. } if hostname == origin => {
+            issues.push("hostname", "A CNAME cannot be placed at the zone's origin.".to_string());
+        }
+        RecordTypes::MX { exchange: target, .. } | RecordTypes::SRV { target, .. } => {
+            // MX and SRV targets must not be aliases (RFC 2181 section 10.3)
+            let aliased = others.clone().any(|other| {
+                matches!(other, RecordTypes::CNAME { .. }) && other.hostname() == Some(target.as_str())
+            });
+
+            if aliased {
+                let field = if matches!(record, RecordTypes::MX { .. }) { "exchange" } else { "target" };
+                issues.push(field, format!("{} is a CNAME, targets must be canonical names.", target));
+            }
+        }
+        _ => {}
+    }
+
+    let is_cname = matches!(record, RecordTypes::CNAME { .. });
+
+    for other in others {
+        if other.hostname().unwrap_or(origin) != hostname {
+            // Nothing may point at an alias once one is created either
+            if is_cname {
+                if let RecordTypes::MX { exchange: target, .. } | RecordTypes::SRV { target, .. } = other {
+                    if target == hostname {
+                        issues.push("hostname", format!("Hostname is the target of an existing {} record.", other.type_name()));
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        // A CNAME cannot coexist with any other data at the same name
+        if is_cname {
+            issues.push("hostname", format!("A CNAME cannot share its hostname with the existing {} record.", other.type_name()));
+            break;
+        } else if matches!(other, RecordTypes::CNAME { .. }) {
+            issues.push("hostname", "Hostname already has a CNAME record.".to_string());
+            break;
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn issues_for(issues: &RecordIssues, field: &str) -> Vec<String> {
+        issues.0.get(field).cloned().unwrap_or_default()
+    }
+
+    fn a_record(hostname: &str) -> RecordTypes {
+        RecordTypes::A { hostname: hostname.to_string(), ttl: 3600, address: Ipv4Addr::new(192, 0, 2, 1) }
+    }
+
+    fn cname_record(hostname: &str, cname: &str) -> RecordTypes {
+        RecordTypes::CNAME { hostname: hostname.to_string(), ttl: 3600, cname: cname.to_string() }
+    }
+
+    fn soa_record() -> RecordTypes {
+        RecordTypes::SOA {
+            ttl: 3600,
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 2022100901,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300
+        }
+    }
+
+    #[test]
+    fn validate_name_limits_label_length() {
+        assert!(validate_name(&format!("{}.example.com", "a".repeat(63)), false).is_empty());
+        assert_eq!(validate_name(&format!("{}.example.com", "a".repeat(64)), false).len(), 1);
+    }
+
+    #[test]
+    fn validate_name_limits_total_length() {
+        let name = format!("{0}.{0}.{0}.{1}", "a".repeat(63), "b".repeat(61));
+        assert_eq!(name.len(), MAX_NAME_LENGTH);
+        assert!(validate_name(&name, false).is_empty());
+
+        let name = format!("{0}.{0}.{0}.{1}", "a".repeat(63), "b".repeat(62));
+        assert_eq!(validate_name(&name, false), vec![format!("Name cannot be longer than {} characters.", MAX_NAME_LENGTH)]);
+    }
+
+    #[test]
+    fn validate_name_rejects_empty_labels() {
+        assert_eq!(validate_name("", false), vec!["Name cannot be empty.".to_string()]);
+        assert_eq!(validate_name("www..example.com", false), vec!["Name cannot contain empty labels.".to_string()]);
+    }
+
+    #[test]
+    fn validate_name_only_allows_leftmost_wildcards() {
+        assert!(validate_name("*.example.com", false).is_empty());
+        assert_eq!(validate_name("www.*.example.com", false), vec!["A wildcard can only be the leftmost label.".to_string()]);
+        assert_eq!(validate_name("w*w.example.com", false), vec!["Label w*w contains invalid characters.".to_string()]);
+    }
+
+    #[test]
+    fn validate_name_only_allows_underscores_when_asked() {
+        assert!(validate_name("_dmarc.example.com", true).is_empty());
+        assert_eq!(validate_name("_dmarc.example.com", false), vec!["Label _dmarc contains invalid characters.".to_string()]);
+    }
+
+    #[test]
+    fn validate_name_rejects_hyphens_at_label_edges() {
+        assert!(validate_name("my-host.example.com", false).is_empty());
+        assert_eq!(validate_name("-host.example.com", false), vec!["Label -host cannot start or end with a hyphen.".to_string()]);
+    }
+
+    #[test]
+    fn validate_ttl_bounds() {
+        assert_eq!(validate_ttl(-1), vec!["TTL cannot be negative.".to_string()]);
+        assert_eq!(validate_ttl(MIN_TTL - 1), vec![format!("TTL cannot be lower than {}.", MIN_TTL)]);
+        assert!(validate_ttl(MIN_TTL).is_empty());
+        assert!(validate_ttl(MAX_TTL).is_empty());
+        assert_eq!(validate_ttl(MAX_TTL + 1), vec![format!("TTL cannot be higher than {}.", MAX_TTL)]);
+    }
+
+    #[test]
+    fn normalise_record_expands_origin() {
+        let mut record = cname_record(" @ ", "Target.Example.COM.");
+        normalise_record(&mut record, "example.com");
+
+        assert_eq!(record, cname_record("example.com", "target.example.com"));
+    }
+
+    #[test]
+    fn normalise_record_lowercases_hostnames() {
+        let mut record = a_record("WWW.Example.com.");
+        normalise_record(&mut record, "example.com");
+
+        assert_eq!(record, a_record("www.example.com"));
+    }
+
+    #[test]
+    fn validate_zone_conflicts_keeps_cnames_alone() {
+        let existing = vec![("a".to_string(), a_record("www.example.com"))];
+        let issues = validate_zone_conflicts(&cname_record("www.example.com", "example.net"), "example.com", &existing, None);
+        assert_eq!(issues_for(&issues, "hostname"), vec!["A CNAME cannot share its hostname with the existing A record.".to_string()]);
+
+        let existing = vec![("cname".to_string(), cname_record("www.example.com", "example.net"))];
+        let issues = validate_zone_conflicts(&a_record("www.example.com"), "example.com", &existing, None);
+        assert_eq!(issues_for(&issues, "hostname"), vec!["Hostname already has a CNAME record.".to_string()]);
+    }
+
+    #[test]
+    fn validate_zone_conflicts_skips_the_record_being_replaced() {
+        let existing = vec![("cname".to_string(), cname_record("www.example.com", "example.net"))];
+        let issues = validate_zone_conflicts(&cname_record("www.example.com", "example.org"), "example.com", &existing, Some("cname"));
+
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn validate_zone_conflicts_allows_one_soa() {
+        let existing = vec![("soa".to_string(), soa_record())];
+
+        let issues = validate_zone_conflicts(&soa_record(), "example.com", &existing, None);
+        assert_eq!(issues_for(&issues, "value").len(), 1);
+
+        assert!(validate_zone_conflicts(&soa_record(), "example.com", &existing, Some("soa")).is_empty());
+    }
+
+    #[test]
+    fn validate_zone_conflicts_rejects_cnames_at_the_origin() {
+        let issues = validate_zone_conflicts(&cname_record("example.com", "example.net"), "example.com", &[], None);
+
+        assert_eq!(issues_for(&issues, "hostname"), vec!["A CNAME cannot be placed at the zone's origin.".to_string()]);
+    }
+
+    #[test]
+    fn validate_caa_accepts_issuers() {
+        assert!(validate_caa(0, "issue", "letsencrypt.org").is_empty());
+        assert!(validate_caa(0, "issue", "letsencrypt.org; accounturi=https://acme-v02.api.letsencrypt.org/acme/acct/1").is_empty());
+        assert!(validate_caa(CAA_ISSUER_CRITICAL, "issuewild", ";").is_empty());
+    }
+
+    #[test]
+    fn validate_caa_rejects_malformed_issuers() {
+        let issues = validate_caa(0, "issue", "letsencrypt..org");
+        assert_eq!(issues_for(&issues, "value"), vec!["Issuer letsencrypt..org is invalid: Name cannot contain empty labels.".to_string()]);
+
+        let issues = validate_caa(0, "issuewild", "letsencrypt.org; accounturi");
+        assert_eq!(issues_for(&issues, "value"), vec!["Parameter accounturi must be in the form key=value.".to_string()]);
+    }
+
+    #[test]
+    fn validate_caa_checks_iodef_urls() {
+        assert!(validate_caa(0, "iodef", "mailto:security@example.com").is_empty());
+        assert!(validate_caa(0, "iodef", "https://example.com/iodef").is_empty());
+
+        let issues = validate_caa(0, "iodef", "ftp://example.com/iodef");
+        assert_eq!(issues_for(&issues, "value"), vec!["iodef must be a mailto:, http:// or https:// URL.".to_string()]);
+    }
+
+    #[test]
+    fn validate_caa_rejects_unknown_tags() {
+        for flags in [0, CAA_ISSUER_CRITICAL] {
+            let issues = validate_caa(flags, "issuer", "letsencrypt.org");
+
+            assert_eq!(issues_for(&issues, "tag").len(), 1);
+            assert!(issues_for(&issues, "flags").is_empty());
+            assert!(issues_for(&issues, "value").is_empty());
+        }
+    }
+
+    #[test]
+    fn validate_caa_rejects_reserved_flags() {
+        let issues = validate_caa(1, "issue", "letsencrypt.org");
+
+        assert_eq!(issues_for(&issues, "flags"), vec![format!("Flags can only be 0 or {}.", CAA_ISSUER_CRITICAL)]);
+    }
+
+    fn tlsa_record(usage: u8, selector: u8, matching_type: u8, bytes: usize) -> RecordTypes {
+        RecordTypes::TLSA {
+            hostname: "_443._tcp.www.example.com".to_string(),
+            ttl: 3600,
+            usage,
+            selector,
+            matching_type,
+            certificate_data: "ab".repeat(bytes)
+        }
+    }
+
+    fn sshfp_record(algorithm: u8, fingerprint_type: u8, bytes: usize) -> RecordTypes {
+        RecordTypes::SSHFP {
+            hostname: "www.example.com".to_string(),
+            ttl: 3600,
+            algorithm,
+            fingerprint_type,
+            fingerprint: "ab".repeat(bytes)
+        }
+    }
+
+    fn https_record(priority: u16, params: SvcParams) -> RecordTypes {
+        RecordTypes::HTTPS {
+            hostname: "example.com".to_string(),
+            ttl: 3600,
+            priority,
+            target: ".".to_string(),
+            params
+        }
+    }
+
+    /// Fields with issues, or none when the record is valid
+    fn invalid_fields(record: &RecordTypes) -> Vec<&'static str> {
+        validate_record(record, "example.com").0.into_keys().collect()
+    }
+
+    #[test]
+    fn validate_tlsa() {
+        let cases: Vec<(RecordTypes, Vec<&str>)> = vec![
+            (tlsa_record(3, 1, 1, 32), vec![]),
+            (tlsa_record(0, 0, 2, 64), vec![]),
+            (tlsa_record(2, 0, 0, 300), vec![]),
+            (tlsa_record(4, 1, 1, 32), vec!["usage"]),
+            (tlsa_record(3, 2, 1, 32), vec!["selector"]),
+            (tlsa_record(3, 1, 3, 32), vec!["matching_type"]),
+            (tlsa_record(3, 1, 1, 31), vec!["certificate_data"]),
+            (tlsa_record(3, 1, 2, 32), vec!["certificate_data"]),
+            (tlsa_record(3, 1, 0, 0), vec!["certificate_data"]),
+            (RecordTypes::TLSA {
+                hostname: "www.example.com".to_string(),
+                ttl: 3600,
+                usage: 3,
+                selector: 1,
+                matching_type: 1,
+                certificate_data: "ab".repeat(32)
+            }, vec!["hostname"])
+        ];
+
+        for (record, fields) in cases {
+            assert_eq!(invalid_fields(&record), fields, "{:?}", record);
+        }
+    }
+
+    #[test]
+    fn validate_sshfp() {
+        let cases: Vec<(RecordTypes, Vec<&str>)> = vec![
+            (sshfp_record(4, 1, 20), vec![]),
+            (sshfp_record(4, 2, 32), vec![]),
+            (sshfp_record(6, 2, 32), vec![]),
+            (sshfp_record(4, 1, 32), vec!["fingerprint"]),
+            (sshfp_record(4, 2, 20), vec!["fingerprint"]),
+            (sshfp_record(4, 3, 32), vec!["fingerprint_type"]),
+            (sshfp_record(5, 2, 32), vec!["algorithm"])
+        ];
+
+        for (record, fields) in cases {
+            assert_eq!(invalid_fields(&record), fields, "{:?}", record);
+        }
+    }
+
+    #[test]
+    fn validate_svcb_params() {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>();
+
+        let cases: Vec<(RecordTypes, Vec<&str>)> = vec![
+            (https_record(0, SvcParams::default()), vec![]),
+            (https_record(0, SvcParams { alpn: strings(&["h2"]), ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams::default()), vec![]),
+            (https_record(1, SvcParams { alpn: strings(&["h2", "h3"]), port: Some(8443), ..SvcParams::default() }), vec![]),
+            (https_record(1, SvcParams { mandatory: strings(&["alpn"]), alpn: strings(&["h2"]), ..SvcParams::default() }), vec![]),
+            (https_record(1, SvcParams { mandatory: strings(&["port"]), alpn: strings(&["h2"]), ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams { mandatory: strings(&["mandatory"]), ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams { mandatory: strings(&["unknown"]), ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams { alpn: strings(&[""]), ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams { no_default_alpn: true, ..SvcParams::default() }), vec!["params"]),
+            (https_record(1, SvcParams { ech: Some("not base64!".to_string()), ..SvcParams::default() }), vec!["params"])
+        ];
+
+        for (record, fields) in cases {
+            assert_eq!(invalid_fields(&record), fields, "{:?}", record);
+        }
+    }
+}
@@ -0,0 +1,1066 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use base64ct::{Base64, Encoding};
+
+use crate::dns::records::{RecordTypes, SvcParams};
+use crate::util::{decode_base32hex, decode_hex, encode_base32hex, encode_hex};
+
+/// Longest a single label can be in octets
+const MAX_LABEL_LENGTH: usize = 63;
+/// Longest an encoded name can be in octets
+const MAX_NAME_LENGTH: usize = 255;
+/// Longest a single character-string can be in octets
+const MAX_CHARACTER_STRING: usize = 255;
+/// Compression pointers can only address the first 16KiB of a message
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+/// The IN class, the only one Driptorch serves
+pub const CLASS_IN: u16 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum WireError {
+    /// A name couldn't be represented on the wire
+    InvalidName(String),
+    /// A field held data that couldn't be represented on the wire
+    InvalidField(&'static str),
+    /// The message ended before the data did
+    Truncated,
+    /// A compression pointer pointed forwards or formed a loop
+    InvalidPointer,
+    /// RDATA was longer than its type allows
+    TrailingData,
+    /// The record type has no equivalent in RecordTypes
+    UnsupportedType(u16)
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::InvalidName(name) => write!(f, "Invalid name {}", name),
+            WireError::InvalidField(field) => write!(f, "Invalid {}", field),
+            WireError::Truncated => write!(f, "Message is truncated"),
+            WireError::InvalidPointer => write!(f, "Invalid compression pointer"),
+            WireError::TrailingData => write!(f, "RDATA has trailing data"),
+            WireError::UnsupportedType(code) => write!(f, "Unsupported record type {}", code)
+        }
+    }
+}
+
+/// SvcParamKey values (RFC 9460 section 14.3.2)
+pub const SVC_PARAM_KEYS: [(&str, u16); 7] = [
+    ("mandatory", 0),
+    ("alpn", 1),
+    ("no-default-alpn", 2),
+    ("port", 3),
+    ("ipv4hint", 4),
+    ("ech", 5),
+    ("ipv6hint", 6)
+];
+
+pub fn svc_param_key(name: &str) -> Option<u16> {
+    SVC_PARAM_KEYS.iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+fn svc_param_name(key: u16) -> Option<&'static str> {
+    SVC_PARAM_KEYS.iter()
+        .find(|(_, param_key)| *param_key == key)
+        .map(|(name, _)| *name)
+}
+
+/// Remembers where names were written within a message so later ones can point back at them
+#[derive(Default)]
+pub struct Compressor {
+    offsets: HashMap<String, u16>
+}
+
+impl Compressor {
+    /// Remembers an uncompressed name that was copied into the message at `offset`
+    pub fn remember(&mut self, name: &str, offset: usize) {
+        if name == "." {
+            return;
+        }
+
+        let labels: Vec<&str> = name.split('.').collect();
+        let mut offset = offset;
+
+        for i in 0..labels.len() {
+            if offset > MAX_POINTER_OFFSET {
+                break;
+            }
+
+            self.offsets.entry(labels[i..].join(".")).or_insert(offset as u16);
+            offset += labels[i].len() + 1;
+        }
+    }
+
+    /// Forgets names written at or after `offset`, once the message has been truncated back to it
+    fn forget_from(&mut self, offset: usize) {
+        self.offsets.retain(|_, name_offset| (*name_offset as usize) < offset);
+    }
+}
+
+/// Encodes a normalised name, compressing it against earlier names when a compressor is given
+///
+/// `buf` has to be the whole message for compression offsets to be correct.
+pub fn encode_name(name: &str, buf: &mut Vec<u8>, mut compressor: Option<&mut Compressor>) -> Result<(), WireError> {
+    let labels: Vec<&str> = if name.is_empty() || name == "." {
+        vec![]
+    } else {
+        name.trim_end_matches('.').split('.').collect()
+    };
+
+    if labels.iter().any(|label| label.is_empty() || label.len() > MAX_LABEL_LENGTH)
+        || labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1 > MAX_NAME_LENGTH {
+        return Err(WireError::InvalidName(name.to_string()));
+    }
+
+    for i in 0..labels.len() {
+        let suffix = labels[i..].join(".").to_lowercase();
+
+        if let Some(compressor) = compressor.as_deref_mut() {
+            if let Some(offset) = compressor.offsets.get(&suffix) {
+                buf.extend_from_slice(&(0xC000 | offset).to_be_bytes());
+                return Ok(());
+            }
+
+            if buf.len() <= MAX_POINTER_OFFSET {
+                compressor.offsets.insert(suffix, buf.len() as u16);
+            }
+        }
+
+        buf.push(labels[i].len() as u8);
+        buf.extend_from_slice(labels[i].as_bytes());
+    }
+
+    buf.push(0);
+
+    Ok(())
+}
+
+/// Decodes a possibly compressed name starting at `offset`, returning it and the offset after it
+pub fn decode_name(message: &[u8], offset: usize) -> Result<(String, usize), WireError> {
+    let mut labels: Vec<String> = vec![];
+    let mut position = offset;
+    let mut end: Option<usize> = None;
+    let mut length = 1;
+
+    loop {
+        let label_length = *message.get(position).ok_or(WireError::Truncated)? as usize;
+
+        match label_length & 0xC0 {
+            0x00 => {
+                if label_length == 0 {
+                    position += 1;
+                    break;
+                }
+
+                let label = message.get(position + 1..position + 1 + label_length)
+                    .ok_or(WireError::Truncated)?;
+
+                length += label_length + 1;
+                if length > MAX_NAME_LENGTH {
+                    return Err(WireError::InvalidName(labels.join(".")));
+                }
+
+                if label.contains(&b'.') || !label.is_ascii() {
+                    return Err(WireError::InvalidName(String::from_utf8_lossy(label).to_string()));
+                }
+
+                labels.push(String::from_utf8_lossy(label).to_lowercase());
+                position += 1 + label_length;
+            }
+            0xC0 => {
+                let low = *message.get(position + 1).ok_or(WireError::Truncated)? as usize;
+                let target = ((label_length & 0x3F) << 8) | low;
+
+                // Pointers may only point backwards, which also rules out loops
+                if target >= position {
+                    return Err(WireError::InvalidPointer);
+                }
+
+                if end.is_none() {
+                    end = Some(position + 2);
+                }
+
+                position = target;
+            }
+            _ => return Err(WireError::InvalidName("Unsupported label type".to_string()))
+        }
+    }
+
+    let name = if labels.is_empty() {
+        ".".to_string()
+    } else {
+        labels.join(".")
+    };
+
+    Ok((name, end.unwrap_or(position)))
+}
+
+fn encode_character_string(text: &[u8], field: &'static str, buf: &mut Vec<u8>) -> Result<(), WireError> {
+    if text.len() > MAX_CHARACTER_STRING {
+        return Err(WireError::InvalidField(field));
+    }
+
+    buf.push(text.len() as u8);
+    buf.extend_from_slice(text);
+
+    Ok(())
+}
+
+fn encode_hex_field(hex: &str, field: &'static str, buf: &mut Vec<u8>) -> Result<(), WireError> {
+    buf.extend(decode_hex(hex).ok_or(WireError::InvalidField(field))?);
+
+    Ok(())
+}
+
+fn encode_svc_params(params: &SvcParams, buf: &mut Vec<u8>) -> Result<(), WireError> {
+    // Parameters must be in strictly increasing key order
+    let mut encoded: Vec<(u16, Vec<u8>)> = vec![];
+
+    if !params.mandatory.is_empty() {
+        let mut keys: Vec<u16> = params.mandatory.iter()
+            .map(|key| svc_param_key(key).ok_or(WireError::InvalidField("mandatory")))
+            .collect::<Result<_, _>>()?;
+        keys.sort_unstable();
+
+        encoded.push((0, keys.iter().flat_map(|key| key.to_be_bytes()).collect()));
+    }
+
+    if !params.alpn.is_empty() {
+        let mut value = vec![];
+        for alpn in &params.alpn {
+            encode_character_string(alpn.as_bytes(), "alpn", &mut value)?;
+        }
+        encoded.push((1, value));
+    }
+
+    if params.no_default_alpn {
+        encoded.push((2, vec![]));
+    }
+
+    if let Some(port) = params.port {
+        encoded.push((3, port.to_be_bytes().to_vec()));
+    }
+
+    if !params.ipv4hint.is_empty() {
+        encoded.push((4, params.ipv4hint.iter().flat_map(|address| address.octets()).collect()));
+    }
+
+    if let Some(ech) = &params.ech {
+        encoded.push((5, Base64::decode_vec(ech).map_err(|_| WireError::InvalidField("ech"))?));
+    }
+
+    if !params.ipv6hint.is_empty() {
+        encoded.push((6, params.ipv6hint.iter().flat_map(|address| address.octets()).collect()));
+    }
+
+    for (key, value) in encoded {
+        let length = u16::try_from(value.len()).map_err(|_| WireError::InvalidField("params"))?;
+
+        buf.extend_from_slice(&key.to_be_bytes());
+        buf.extend_from_slice(&length.to_be_bytes());
+        buf.extend(value);
+    }
+
+    Ok(())
+}
+
+/// Writes the windowed type bitmap used by NSEC3 (RFC 4034 section 4.1.2)
+fn encode_type_bitmap(types: &[u16], buf: &mut Vec<u8>) {
+    let mut types = types.to_vec();
+    types.sort_unstable();
+    types.dedup();
+
+    for window in 0..=255u8 {
+        let in_window: Vec<u8> = types.iter()
+            .filter(|record_type| (*record_type >> 8) as u8 == window)
+            .map(|record_type| (*record_type & 0xFF) as u8)
+            .collect();
+
+        if in_window.is_empty() {
+            continue;
+        }
+
+        let length = *in_window.last().unwrap() as usize / 8 + 1;
+        let mut bitmap = vec![0u8; length];
+        for low in in_window {
+            bitmap[low as usize / 8] |= 0x80 >> (low % 8);
+        }
+
+        buf.push(window);
+        buf.push(length as u8);
+        buf.extend(bitmap);
+    }
+}
+
+fn encode_salt(salt: &str, buf: &mut Vec<u8>) -> Result<(), WireError> {
+    let salt = decode_hex(salt).ok_or(WireError::InvalidField("salt"))?;
+    encode_character_string(&salt, "salt", buf)
+}
+
+/// Writes a record's RDATA (RFC 1035 section 3.3 and each type's own RFC) onto `buf`
+///
+/// Only the types defined in RFC 1035 have their names compressed, as RFC 3597 forbids it for
+/// anything newer.
+fn write_rdata(record: &RecordTypes, buf: &mut Vec<u8>, mut compressor: Option<&mut Compressor>) -> Result<(), WireError> {
+    match record {
+        RecordTypes::SOA { mname, rname, serial, refresh, retry, expire, minimum, .. } => {
+            encode_name(mname, buf, compressor.as_deref_mut())?;
+            encode_name(rname, buf, compressor)?;
+            buf.extend_from_slice(&serial.to_be_bytes());
+            buf.extend_from_slice(&refresh.to_be_bytes());
+            buf.extend_from_slice(&retry.to_be_bytes());
+            buf.extend_from_slice(&expire.to_be_bytes());
+            buf.extend_from_slice(&minimum.to_be_bytes());
+        }
+        RecordTypes::A { address, .. } => buf.extend_from_slice(&address.octets()),
+        RecordTypes::AAAA { address, .. } => buf.extend_from_slice(&address.octets()),
+        RecordTypes::CNAME { cname: target, .. }
+        | RecordTypes::NS { nsdame: target, .. }
+        | RecordTypes::PTR { nsdame: target, .. } => encode_name(target, buf, compressor)?,
+        RecordTypes::DNAME { dname, .. } => encode_name(dname, buf, None)?,
+        RecordTypes::MX { preference, exchange, .. } => {
+            buf.extend_from_slice(&preference.to_be_bytes());
+            encode_name(exchange, buf, compressor)?;
+        }
+        RecordTypes::TXT { txt_data, .. } => {
+            // Long values are split over several character-strings
+            for chunk in txt_data.as_bytes().chunks(MAX_CHARACTER_STRING) {
+                encode_character_string(chunk, "txt_data", buf)?;
+            }
+        }
+        RecordTypes::CAA { flags, tag, value, .. } => {
+            buf.push(*flags);
+            encode_character_string(tag.as_bytes(), "tag", buf)?;
+            buf.extend_from_slice(value.as_bytes());
+        }
+        RecordTypes::SRV { priority, weight, port, target, .. } => {
+            buf.extend_from_slice(&priority.to_be_bytes());
+            buf.extend_from_slice(&weight.to_be_bytes());
+            buf.extend_from_slice(&port.to_be_bytes());
+            encode_name(target, buf, None)?;
+        }
+        RecordTypes::SVCB { priority, target, params, .. }
+        | RecordTypes::HTTPS { priority, target, params, .. } => {
+            buf.extend_from_slice(&priority.to_be_bytes());
+            encode_name(target, buf, None)?;
+            encode_svc_params(params, buf)?;
+        }
+        RecordTypes::TLSA { usage, selector, matching_type, certificate_data, .. } => {
+            buf.push(*usage);
+            buf.push(*selector);
+            buf.push(*matching_type);
+            encode_hex_field(certificate_data, "certificate_data", buf)?;
+        }
+        RecordTypes::SSHFP { algorithm, fingerprint_type, fingerprint, .. } => {
+            buf.push(*algorithm);
+            buf.push(*fingerprint_type);
+            encode_hex_field(fingerprint, "fingerprint", buf)?;
+        }
+        RecordTypes::NAPTR { order, preference, flags, services, regexp, replacement, .. } => {
+            buf.extend_from_slice(&order.to_be_bytes());
+            buf.extend_from_slice(&preference.to_be_bytes());
+            encode_character_string(flags.as_bytes(), "flags", buf)?;
+            encode_character_string(services.as_bytes(), "services", buf)?;
+            encode_character_string(regexp.as_bytes(), "regexp", buf)?;
+            encode_name(replacement, buf, None)?;
+        }
+        RecordTypes::DS { key_tag, algorithm, digest_type, digest, .. } => {
+            buf.extend_from_slice(&key_tag.to_be_bytes());
+            buf.push(*algorithm);
+            buf.push(*digest_type);
+            encode_hex_field(digest, "digest", buf)?;
+        }
+        RecordTypes::DNSKEY { flags, protocol, algorithm, public_key, .. } => {
+            buf.extend_from_slice(&flags.to_be_bytes());
+            buf.push(*protocol);
+            buf.push(*algorithm);
+            buf.extend(Base64::decode_vec(public_key).map_err(|_| WireError::InvalidField("public_key"))?);
+        }
+        RecordTypes::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature, .. } => {
+            buf.extend_from_slice(&type_covered.to_be_bytes());
+            buf.push(*algorithm);
+            buf.push(*labels);
+            buf.extend_from_slice(&original_ttl.to_be_bytes());
+            buf.extend_from_slice(&expiration.to_be_bytes());
+            buf.extend_from_slice(&inception.to_be_bytes());
+            buf.extend_from_slice(&key_tag.to_be_bytes());
+            encode_name(signer_name, buf, None)?;
+            buf.extend(Base64::decode_vec(signature).map_err(|_| WireError::InvalidField("signature"))?);
+        }
+        RecordTypes::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types, .. } => {
+            buf.push(*hash_algorithm);
+            buf.push(*flags);
+            buf.extend_from_slice(&iterations.to_be_bytes());
+            encode_salt(salt, buf)?;
+            let next_hashed_owner = decode_base32hex(next_hashed_owner).ok_or(WireError::InvalidField("next_hashed_owner"))?;
+            encode_character_string(&next_hashed_owner, "next_hashed_owner", buf)?;
+            encode_type_bitmap(types, buf);
+        }
+        RecordTypes::NSEC3PARAM { hash_algorithm, flags, iterations, salt, .. } => {
+            buf.push(*hash_algorithm);
+            buf.push(*flags);
+            buf.extend_from_slice(&iterations.to_be_bytes());
+            encode_salt(salt, buf)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encodes a record's RDATA on its own, without name compression
+pub fn encode_rdata(record: &RecordTypes) -> Result<Vec<u8>, WireError> {
+    let mut buf: Vec<u8> = vec![];
+    write_rdata(record, &mut buf, None)?;
+
+    if buf.len() > u16::MAX as usize {
+        return Err(WireError::InvalidField("rdata"));
+    }
+
+    Ok(buf)
+}
+
+/// Writes a whole resource record onto a message
+///
+/// SOA records are owned by `origin`, everything else by its own hostname.
+pub fn encode_record(record: &RecordTypes, origin: &str, buf: &mut Vec<u8>, mut compressor: Option<&mut Compressor>) -> Result<(), WireError> {
+    let start = buf.len();
+    let ttl = u32::try_from(record.ttl()).map_err(|_| WireError::InvalidField("ttl"))?;
+
+    let result = (|| {
+        encode_name(record.hostname().unwrap_or(origin), buf, compressor.as_deref_mut())?;
+        buf.extend_from_slice(&record.type_code().to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&ttl.to_be_bytes());
+
+        // RDLENGTH is filled in once the RDATA has been written
+        let length_position = buf.len();
+        buf.extend_from_slice(&[0, 0]);
+
+        write_rdata(record, buf, compressor.as_deref_mut())?;
+
+        let length = u16::try_from(buf.len() - length_position - 2)
+            .map_err(|_| WireError::InvalidField("rdata"))?;
+        buf[length_position..length_position + 2].copy_from_slice(&length.to_be_bytes());
+
+        Ok(())
+    })();
+
+    // Leave the message and compressor as they were if the record couldn't be written
+    if result.is_err() {
+        buf.truncate(start);
+
+        if let Some(compressor) = compressor {
+            compressor.forget_from(start);
+        }
+    }
+
+    result
+}
+
+/// Reads fixed size fields from RDATA, keeping track of the position within the message
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+    end: usize
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], WireError> {
+        if self.position + count > self.end {
+            return Err(WireError::Truncated);
+        }
+
+        let bytes = &self.message[self.position..self.position + count];
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, WireError> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.message[self.position..self.end];
+        self.position = self.end;
+
+        bytes
+    }
+
+    fn name(&mut self) -> Result<String, WireError> {
+        let (name, next) = decode_name(&self.message[..self.end], self.position)?;
+        self.position = next;
+
+        Ok(name)
+    }
+
+    fn character_string(&mut self, field: &'static str) -> Result<String, WireError> {
+        let length = self.u8()? as usize;
+        let bytes = self.bytes(length)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| WireError::InvalidField(field))
+    }
+
+    fn finished(&self) -> bool {
+        self.position >= self.end
+    }
+}
+
+fn decode_type_bitmap(reader: &mut Reader) -> Result<Vec<u16>, WireError> {
+    let mut types = vec![];
+
+    while !reader.finished() {
+        let window = reader.u8()? as u16;
+        let length = reader.u8()? as usize;
+
+        if length == 0 || length > 32 {
+            return Err(WireError::InvalidField("types"));
+        }
+
+        for (i, byte) in reader.bytes(length)?.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push((window << 8) | (i * 8 + bit) as u16);
+                }
+            }
+        }
+    }
+
+    Ok(types)
+}
+
+fn decode_salt(reader: &mut Reader) -> Result<String, WireError> {
+    let length = reader.u8()? as usize;
+    Ok(encode_hex(reader.bytes(length)?))
+}
+
+fn decode_svc_params(reader: &mut Reader) -> Result<SvcParams, WireError> {
+    let mut params = SvcParams::default();
+    let mut last_key: Option<u16> = None;
+
+    while !reader.finished() {
+        let key = reader.u16()?;
+        let length = reader.u16()? as usize;
+        let value = reader.bytes(length)?;
+
+        if matches!(last_key, Some(last_key) if key <= last_key) {
+            return Err(WireError::InvalidField("params"));
+        }
+        last_key = Some(key);
+
+        let mut value_reader = Reader { message: value, position: 0, end: value.len() };
+
+        match key {
+            0 => {
+                while !value_reader.finished() {
+                    let mandatory_key = value_reader.u16()?;
+                    params.mandatory.push(
+                        svc_param_name(mandatory_key).ok_or(WireError::InvalidField("mandatory"))?.to_string()
+                    );
+                }
+            }
+            1 => {
+                while !value_reader.finished() {
+                    params.alpn.push(value_reader.character_string("alpn")?);
+                }
+            }
+            2 => params.no_default_alpn = true,
+            3 => params.port = Some(value_reader.u16()?),
+            4 => {
+                while !value_reader.finished() {
+                    let octets = value_reader.bytes(4)?;
+                    params.ipv4hint.push(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]));
+                }
+            }
+            5 => params.ech = Some(Base64::encode_string(value)),
+            6 => {
+                while !value_reader.finished() {
+                    let octets: [u8; 16] = value_reader.bytes(16)?.try_into().unwrap();
+                    params.ipv6hint.push(Ipv6Addr::from(octets));
+                }
+            }
+            _ => return Err(WireError::InvalidField("params"))
+        }
+
+        if key != 5 && !value_reader.finished() {
+            return Err(WireError::TrailingData);
+        }
+    }
+
+    Ok(params)
+}
+
+/// Decodes RDATA of the given type found at `offset` within a message
+///
+/// The whole message is required as names may be compressed against earlier parts of it.
+pub fn decode_rdata(type_code: u16, hostname: String, ttl: i32, message: &[u8], offset: usize, length: usize) -> Result<RecordTypes, WireError> {
+    if offset + length > message.len() {
+        return Err(WireError::Truncated);
+    }
+
+    let mut reader = Reader { message, position: offset, end: offset + length };
+
+    let record = match type_code {
+        6 => RecordTypes::SOA {
+            ttl,
+            mname: reader.name()?,
+            rname: reader.name()?,
+            serial: reader.u32()?,
+            refresh: reader.i32()?,
+            retry: reader.i32()?,
+            expire: reader.i32()?,
+            minimum: reader.u32()?
+        },
+        1 => {
+            let octets = reader.bytes(4)?;
+            RecordTypes::A { hostname, ttl, address: Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]) }
+        }
+        28 => {
+            let octets: [u8; 16] = reader.bytes(16)?.try_into().unwrap();
+            RecordTypes::AAAA { hostname, ttl, address: Ipv6Addr::from(octets) }
+        }
+        5 => RecordTypes::CNAME { hostname, ttl, cname: reader.name()? },
+        39 => RecordTypes::DNAME { hostname, ttl, dname: reader.name()? },
+        15 => RecordTypes::MX { hostname, ttl, preference: reader.u16()? as i16, exchange: reader.name()? },
+        2 => RecordTypes::NS { hostname, ttl, nsdame: reader.name()? },
+        12 => RecordTypes::PTR { hostname, ttl, nsdame: reader.name()? },
+        16 => {
+            let mut txt_data = String::new();
+            while !reader.finished() {
+                txt_data.push_str(&reader.character_string("txt_data")?);
+            }
+
+            RecordTypes::TXT { hostname, ttl, txt_data }
+        }
+        257 => RecordTypes::CAA {
+            hostname,
+            ttl,
+            flags: reader.u8()?,
+            tag: reader.character_string("tag")?,
+            value: String::from_utf8(reader.rest().to_vec()).map_err(|_| WireError::InvalidField("value"))?
+        },
+        33 => RecordTypes::SRV {
+            hostname,
+            ttl,
+            priority: reader.u16()?,
+            weight: reader.u16()?,
+            port: reader.u16()?,
+            target: reader.name()?
+        },
+        64 | 65 => {
+            let priority = reader.u16()?;
+            let target = reader.name()?;
+            let params = decode_svc_params(&mut reader)?;
+
+            if type_code == 64 {
+                RecordTypes::SVCB { hostname, ttl, priority, target, params }
+            } else {
+                RecordTypes::HTTPS { hostname, ttl, priority, target, params }
+            }
+        }
+        52 => RecordTypes::TLSA {
+            hostname,
+            ttl,
+            usage: reader.u8()?,
+            selector: reader.u8()?,
+            matching_type: reader.u8()?,
+            certificate_data: encode_hex(reader.rest())
+        },
+        44 => RecordTypes::SSHFP {
+            hostname,
+            ttl,
+            algorithm: reader.u8()?,
+            fingerprint_type: reader.u8()?,
+            fingerprint: encode_hex(reader.rest())
+        },
+        35 => RecordTypes::NAPTR {
+            hostname,
+            ttl,
+            order: reader.u16()?,
+            preference: reader.u16()?,
+            flags: reader.character_string("flags")?,
+            services: reader.character_string("services")?,
+            regexp: reader.character_string("regexp")?,
+            replacement: reader.name()?
+        },
+        43 => RecordTypes::DS {
+            hostname,
+            ttl,
+            key_tag: reader.u16()?,
+            algorithm: reader.u8()?,
+            digest_type: reader.u8()?,
+            digest: encode_hex(reader.rest())
+        },
+        48 => RecordTypes::DNSKEY {
+            hostname,
+            ttl,
+            flags: reader.u16()?,
+            protocol: reader.u8()?,
+            algorithm: reader.u8()?,
+            public_key: Base64::encode_string(reader.rest())
+        },
+        46 => RecordTypes::RRSIG {
+            hostname,
+            ttl,
+            type_covered: reader.u16()?,
+            algorithm: reader.u8()?,
+            labels: reader.u8()?,
+            original_ttl: reader.u32()?,
+            expiration: reader.u32()?,
+            inception: reader.u32()?,
+            key_tag: reader.u16()?,
+            signer_name: reader.name()?,
+            signature: Base64::encode_string(reader.rest())
+        },
+        50 => {
+            let hash_algorithm = reader.u8()?;
+            let flags = reader.u8()?;
+            let iterations = reader.u16()?;
+            let salt = decode_salt(&mut reader)?;
+            let hash_length = reader.u8()? as usize;
+            let next_hashed_owner = encode_base32hex(reader.bytes(hash_length)?);
+
+            RecordTypes::NSEC3 {
+                hostname,
+                ttl,
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed_owner,
+                types: decode_type_bitmap(&mut reader)?
+            }
+        }
+        51 => RecordTypes::NSEC3PARAM {
+            hostname,
+            ttl,
+            hash_algorithm: reader.u8()?,
+            flags: reader.u8()?,
+            iterations: reader.u16()?,
+            salt: decode_salt(&mut reader)?
+        },
+        _ => return Err(WireError::UnsupportedType(type_code))
+    };
+
+    if !reader.finished() {
+        return Err(WireError::TrailingData);
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: &str = "example.com";
+
+    fn every_type() -> Vec<RecordTypes> {
+        vec![
+            RecordTypes::SOA {
+                ttl: 3600,
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2022100901,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300
+            },
+            RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) },
+            RecordTypes::AAAA { hostname: "www.example.com".to_string(), ttl: 300, address: "2001:db8::1".parse().unwrap() },
+            RecordTypes::CNAME { hostname: "blog.example.com".to_string(), ttl: 300, cname: "www.example.com".to_string() },
+            RecordTypes::DNAME { hostname: "old.example.com".to_string(), ttl: 300, dname: "new.example.com".to_string() },
+            RecordTypes::MX { hostname: "example.com".to_string(), ttl: 300, preference: 10, exchange: "mail.example.com".to_string() },
+            RecordTypes::NS { hostname: "sub.example.com".to_string(), ttl: 300, nsdame: "ns1.example.com".to_string() },
+            RecordTypes::PTR { hostname: "1.2.0.192.in-addr.arpa".to_string(), ttl: 300, nsdame: "www.example.com".to_string() },
+            // Longer than a single character-string
+            RecordTypes::TXT { hostname: "example.com".to_string(), ttl: 300, txt_data: "v=spf1 -all ".repeat(30) },
+            RecordTypes::CAA { hostname: "example.com".to_string(), ttl: 300, flags: 128, tag: "issue".to_string(), value: "letsencrypt.org".to_string() },
+            RecordTypes::SRV {
+                hostname: "_sip._tcp.example.com".to_string(),
+                ttl: 300,
+                priority: 10,
+                weight: 60,
+                port: 5060,
+                target: "sip.example.com".to_string()
+            },
+            RecordTypes::SVCB {
+                hostname: "_dns.example.com".to_string(),
+                ttl: 300,
+                priority: 1,
+                target: "dns.example.com".to_string(),
+                params: SvcParams { alpn: vec!["dot".to_string()], port: Some(853), ..SvcParams::default() }
+            },
+            RecordTypes::HTTPS {
+                hostname: "example.com".to_string(),
+                ttl: 300,
+                priority: 1,
+                target: ".".to_string(),
+                params: SvcParams {
+                    mandatory: vec!["alpn".to_string(), "port".to_string()],
+                    alpn: vec!["h2".to_string(), "h3".to_string()],
+                    no_default_alpn: true,
+                    port: Some(8443),
+                    ipv4hint: vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)],
+                    ech: Some(Base64::encode_string(b"ech config list")),
+                    ipv6hint: vec!["2001:db8::1".parse().unwrap()]
+                }
+            },
+            RecordTypes::TLSA {
+                hostname: "_443._tcp.www.example.com".to_string(),
+                ttl: 300,
+                usage: 3,
+                selector: 1,
+                matching_type: 1,
+                certificate_data: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string()
+            },
+            RecordTypes::SSHFP {
+                hostname: "www.example.com".to_string(),
+                ttl: 300,
+                algorithm: 4,
+                fingerprint_type: 2,
+                fingerprint: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string()
+            },
+            RecordTypes::NAPTR {
+                hostname: "example.com".to_string(),
+                ttl: 300,
+                order: 100,
+                preference: 10,
+                flags: "S".to_string(),
+                services: "SIP+D2U".to_string(),
+                regexp: String::new(),
+                replacement: "_sip._udp.example.com".to_string()
+            },
+            RecordTypes::DS {
+                hostname: "sub.example.com".to_string(),
+                ttl: 300,
+                key_tag: 12345,
+                algorithm: 13,
+                digest_type: 2,
+                digest: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string()
+            },
+            RecordTypes::DNSKEY {
+                hostname: "example.com".to_string(),
+                ttl: 3600,
+                flags: 257,
+                protocol: 3,
+                algorithm: 13,
+                public_key: Base64::encode_string(&[7; 64])
+            },
+            RecordTypes::RRSIG {
+                hostname: "www.example.com".to_string(),
+                ttl: 300,
+                type_covered: 1,
+                algorithm: 13,
+                labels: 3,
+                original_ttl: 300,
+                expiration: 1667952000,
+                inception: 1665360000,
+                key_tag: 12345,
+                signer_name: "example.com".to_string(),
+                signature: Base64::encode_string(&[9; 64])
+            },
+            RecordTypes::NSEC3 {
+                hostname: "2vptu5timamqttgl4luu9kg21e0aor3s.example.com".to_string(),
+                ttl: 300,
+                hash_algorithm: 1,
+                flags: 0,
+                iterations: 0,
+                salt: "aabbccdd".to_string(),
+                next_hashed_owner: encode_base32hex(&[0x5a; 20]),
+                types: vec![1, 28, 46, 257]
+            },
+            RecordTypes::NSEC3PARAM { hostname: "example.com".to_string(), ttl: 0, hash_algorithm: 1, flags: 0, iterations: 0, salt: String::new() }
+        ]
+    }
+
+    /// Reads back a record written by `encode_record`, returning its owner and the offset after it
+    fn decode(message: &[u8], offset: usize) -> (String, RecordTypes, usize) {
+        let (owner, position) = decode_name(message, offset).unwrap();
+        let fixed = &message[position..position + 10];
+
+        let type_code = u16::from_be_bytes([fixed[0], fixed[1]]);
+        assert_eq!(u16::from_be_bytes([fixed[2], fixed[3]]), CLASS_IN);
+        let ttl = i32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+        let record = decode_rdata(type_code, owner.clone(), ttl, message, position + 10, length).unwrap();
+
+        (owner, record, position + 10 + length)
+    }
+
+    /// Encodes records one after another behind a blank header, the way answers are built
+    fn encode_message(records: &[RecordTypes], compressor: Option<&mut Compressor>) -> Vec<u8> {
+        let mut buf = vec![0; 12];
+        let mut compressor = compressor;
+
+        for record in records {
+            encode_record(record, ORIGIN, &mut buf, compressor.as_deref_mut()).unwrap();
+        }
+
+        buf
+    }
+
+    fn assert_round_trips(message: &[u8], records: &[RecordTypes]) {
+        let mut position = 12;
+
+        for record in records {
+            let (owner, decoded, next) = decode(message, position);
+
+            assert_eq!(owner, record.hostname().unwrap_or(ORIGIN));
+            assert_eq!(&decoded, record);
+            position = next;
+        }
+
+        assert_eq!(position, message.len());
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let records = every_type();
+        assert_round_trips(&encode_message(&records, None), &records);
+
+        for record in &records {
+            let rdata = encode_rdata(record).unwrap();
+            let hostname = record.hostname().unwrap_or(ORIGIN).to_string();
+
+            assert_eq!(&decode_rdata(record.type_code(), hostname, record.ttl(), &rdata, 0, rdata.len()).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn round_trips_every_type_compressed() {
+        let records = every_type();
+        let compressed = encode_message(&records, Some(&mut Compressor::default()));
+
+        assert!(compressed.len() < encode_message(&records, None).len());
+        assert_round_trips(&compressed, &records);
+    }
+
+    #[test]
+    fn compresses_names_against_earlier_ones() {
+        let records = vec![
+            RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) },
+            RecordTypes::CNAME { hostname: "blog.example.com".to_string(), ttl: 300, cname: "www.example.com".to_string() }
+        ];
+        let message = encode_message(&records, Some(&mut Compressor::default()));
+
+        // www.example.com is written out once at the start, the CNAME's owner points at example.com
+        // and its target at the whole name
+        let cname_start = 12 + 17 + 10 + 4;
+        assert_eq!(&message[cname_start..cname_start + 7], b"\x04blog\xC0\x10");
+        assert_eq!(&message[message.len() - 2..], b"\xC0\x0C");
+        assert_round_trips(&message, &records);
+    }
+
+    #[test]
+    fn leaves_newer_types_uncompressed() {
+        let records = vec![
+            RecordTypes::A { hostname: "sip.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) },
+            RecordTypes::SRV {
+                hostname: "_sip._tcp.example.com".to_string(),
+                ttl: 300,
+                priority: 10,
+                weight: 60,
+                port: 5060,
+                target: "sip.example.com".to_string()
+            }
+        ];
+        let message = encode_message(&records, Some(&mut Compressor::default()));
+
+        assert!(message.ends_with(b"\x03sip\x07example\x03com\x00"));
+        assert_round_trips(&message, &records);
+    }
+
+    #[test]
+    fn remembers_the_question() {
+        let mut compressor = Compressor::default();
+        let mut buf = vec![0; 12];
+        encode_name("www.example.com", &mut buf, None).unwrap();
+        compressor.remember("www.example.com", 12);
+
+        let record = RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) };
+        encode_record(&record, ORIGIN, &mut buf, Some(&mut compressor)).unwrap();
+
+        assert_eq!(&buf[29..31], b"\xC0\x0C");
+        assert_eq!(decode(&buf, 29).1, record);
+    }
+
+    #[test]
+    fn rolls_back_failed_records() {
+        let mut compressor = Compressor::default();
+        let mut buf = vec![0; 12];
+
+        let first = RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) };
+        encode_record(&first, ORIGIN, &mut buf, Some(&mut compressor)).unwrap();
+        let written = buf.clone();
+
+        // The owner is written, and remembered, before the exchange turns out to be invalid
+        let invalid = RecordTypes::MX {
+            hostname: "mail.other.example.net".to_string(),
+            ttl: 300,
+            preference: 10,
+            exchange: format!("{}.example.net", "a".repeat(64))
+        };
+        assert_eq!(encode_record(&invalid, ORIGIN, &mut buf, Some(&mut compressor)), Err(WireError::InvalidName(format!("{}.example.net", "a".repeat(64)))));
+        assert_eq!(buf, written);
+
+        // Nothing may point into what was truncated
+        let records = vec![
+            first,
+            RecordTypes::A { hostname: "mail.other.example.net".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 2) }
+        ];
+        encode_record(&records[1], ORIGIN, &mut buf, Some(&mut compressor)).unwrap();
+
+        assert_round_trips(&buf, &records);
+    }
+
+    #[test]
+    fn rejects_invalid_records() {
+        let long_label = RecordTypes::CNAME { hostname: format!("{}.example.com", "a".repeat(64)), ttl: 300, cname: "www.example.com".to_string() };
+        assert_eq!(encode_record(&long_label, ORIGIN, &mut vec![], None), Err(WireError::InvalidName(format!("{}.example.com", "a".repeat(64)))));
+
+        let negative_ttl = RecordTypes::A { hostname: "www.example.com".to_string(), ttl: -1, address: Ipv4Addr::new(192, 0, 2, 1) };
+        assert_eq!(encode_record(&negative_ttl, ORIGIN, &mut vec![], None), Err(WireError::InvalidField("ttl")));
+
+        let invalid_hex = RecordTypes::DS { hostname: "sub.example.com".to_string(), ttl: 300, key_tag: 1, algorithm: 13, digest_type: 2, digest: "xyz".to_string() };
+        assert_eq!(encode_rdata(&invalid_hex), Err(WireError::InvalidField("digest")));
+    }
+
+    #[test]
+    fn rejects_invalid_pointers() {
+        // A pointer to itself, and one pointing forwards
+        assert_eq!(decode_name(b"\x00\x00\xC0\x02", 2), Err(WireError::InvalidPointer));
+        assert_eq!(decode_name(b"\xC0\x02\x00", 0), Err(WireError::InvalidPointer));
+        assert_eq!(decode_name(b"\x03www", 0), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn rejects_trailing_rdata() {
+        let rdata = [192, 0, 2, 1, 0];
+        assert_eq!(decode_rdata(1, "www.example.com".to_string(), 300, &rdata, 0, rdata.len()), Err(WireError::TrailingData));
+        assert_eq!(decode_rdata(1, "www.example.com".to_string(), 300, &rdata, 0, 3), Err(WireError::Truncated));
+        assert_eq!(decode_rdata(99, "www.example.com".to_string(), 300, &rdata, 0, 4), Err(WireError::UnsupportedType(99)));
+    }
+}
@@ -0,0 +1,908 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use chrono::NaiveDateTime;
+
+use crate::dns::records::{RecordTypes, SvcParams, type_mnemonic};
+use crate::dns::validation::normalise_name;
+
+/// Longest a single character-string can be within TXT RDATA
+const MAX_CHARACTER_STRING: usize = 255;
+
+#[derive(Debug)]
+pub struct ZoneFileError {
+    pub line: usize,
+    pub reason: String
+}
+
+impl fmt::Display for ZoneFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.reason)
+    }
+}
+
+fn error<T>(line: usize, reason: impl Into<String>) -> Result<T, ZoneFileError> {
+    Err(ZoneFileError { line, reason: reason.into() })
+}
+
+struct Token {
+    text: String,
+    quoted: bool
+}
+
+/// A record or directive, which may have spanned several physical lines
+struct Entry {
+    line: usize,
+    /// Entries starting with whitespace inherit the previous owner name
+    inherits_owner: bool,
+    tokens: Vec<Token>
+}
+
+/// Splits a master file into entries, removing comments and joining parenthesised lines
+fn tokenise(input: &str) -> Result<Vec<Entry>, ZoneFileError> {
+    let mut entries: Vec<Entry> = vec![];
+    let mut current: Option<Entry> = None;
+    let mut depth = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let mut chars = line.chars().peekable();
+
+        if current.is_none() {
+            current = Some(Entry {
+                line: line_number,
+                inherits_owner: line.starts_with(' ') || line.starts_with('\t'),
+                tokens: vec![]
+            });
+        }
+
+        let entry = current.as_mut().unwrap();
+
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '(' => depth += 1,
+                ')' => {
+                    if depth == 0 {
+                        return error(line_number, "Unbalanced closing parenthesis.");
+                    }
+                    depth -= 1;
+                }
+                '"' => {
+                    let mut text = String::new();
+                    let mut closed = false;
+
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => {
+                                if let Some(escaped) = chars.next() {
+                                    text.push('\\');
+                                    text.push(escaped);
+                                }
+                            }
+                            '"' => {
+                                closed = true;
+                                break;
+                            }
+                            c => text.push(c)
+                        }
+                    }
+
+                    if !closed {
+                        return error(line_number, "Unterminated quoted string.");
+                    }
+
+                    entry.tokens.push(Token { text: unescape(&text, line_number)?, quoted: true });
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut text = String::from(c);
+
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || next == ';' || next == '(' || next == ')' || next == '"' {
+                            break;
+                        }
+                        text.push(next);
+                        chars.next();
+                    }
+
+                    entry.tokens.push(Token { text, quoted: false });
+                }
+            }
+        }
+
+        if depth == 0 {
+            let entry = current.take().unwrap();
+            if !entry.tokens.is_empty() {
+                entries.push(entry);
+            }
+        }
+    }
+
+    if let Some(entry) = current {
+        return error(entry.line, "Unbalanced opening parenthesis.");
+    }
+
+    Ok(entries)
+}
+
+/// Resolves `\X` and `\DDD` escapes within quoted text
+fn unescape(text: &str, line: usize) -> Result<String, ZoneFileError> {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some(d) if d.is_ascii_digit() => {
+                let digits: String = std::iter::once(d).chain(chars.by_ref().take(2)).collect();
+                match digits.parse::<u8>() {
+                    Ok(value) if digits.len() == 3 => unescaped.push(value as char),
+                    _ => return error(line, format!("Invalid escape \\{}.", digits))
+                }
+            }
+            Some(c) => unescaped.push(c),
+            None => return error(line, "Dangling escape.")
+        }
+    }
+
+    Ok(unescaped)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => escaped.push_str(&format!("\\{:03}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+
+    escaped
+}
+
+/// Parses a TTL or other time value, allowing BIND-style units such as `1h30m`
+pub fn parse_time(text: &str) -> Option<u32> {
+    if text.is_empty() {
+        return None;
+    }
+
+    if let Ok(seconds) = text.parse::<u32>() {
+        return Some(seconds);
+    }
+
+    let mut total: u32 = 0;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return None
+        };
+
+        let value: u32 = number.parse().ok()?;
+        total = total.checked_add(value.checked_mul(multiplier)?)?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    Some(total)
+}
+
+/// Makes a name from a master file absolute against the current origin
+fn absolute_name(name: &str, origin: &str) -> String {
+    if name == "@" {
+        origin.to_string()
+    } else if name.ends_with('.') || origin == "." {
+        normalise_name(name)
+    } else {
+        normalise_name(&format!("{}.{}", name, origin))
+    }
+}
+
+/// Parses a BIND-style master file (RFC 1035 section 5) into records
+///
+/// Each record is returned alongside the line it started on.
+pub fn parse_zone_file(input: &str, origin: &str) -> Result<Vec<(usize, RecordTypes)>, ZoneFileError> {
+    let mut records: Vec<(usize, RecordTypes)> = vec![];
+
+    let mut origin = normalise_name(origin);
+    let mut default_ttl: Option<u32> = None;
+    let mut last_ttl: Option<u32> = None;
+    let mut last_owner: Option<String> = None;
+
+    for entry in tokenise(input)? {
+        let line = entry.line;
+        let mut tokens = entry.tokens.into_iter().peekable();
+
+        // Directives
+        let first = tokens.peek().unwrap();
+        if !first.quoted && first.text.starts_with('$') && !entry.inherits_owner {
+            let directive = tokens.next().unwrap().text.to_uppercase();
+            let argument = match tokens.next() {
+                None => return error(line, format!("{} requires an argument.", directive)),
+                Some(argument) => argument.text
+            };
+
+            match directive.as_str() {
+                "$ORIGIN" => origin = absolute_name(&argument, &origin),
+                "$TTL" => match parse_time(&argument) {
+                    None => return error(line, format!("Invalid TTL {}.", argument)),
+                    Some(ttl) => default_ttl = Some(ttl)
+                },
+                _ => return error(line, format!("Unsupported directive {}.", directive))
+            }
+
+            continue;
+        }
+
+        let owner = if entry.inherits_owner {
+            match &last_owner {
+                None => return error(line, "Record has no owner name."),
+                Some(owner) => owner.clone()
+            }
+        } else {
+            absolute_name(&tokens.next().unwrap().text, &origin)
+        };
+        last_owner = Some(owner.clone());
+
+        // TTL and class may appear in either order
+        let mut ttl: Option<u32> = None;
+        for _ in 0..2 {
+            let token = match tokens.peek() {
+                None => break,
+                Some(token) => token.text.to_uppercase()
+            };
+
+            match token.as_str() {
+                "IN" => {}
+                "CH" | "HS" | "CS" => return error(line, format!("Unsupported class {}.", token)),
+                _ => match parse_time(&token) {
+                    Some(parsed) if ttl.is_none() => ttl = Some(parsed),
+                    _ => break
+                }
+            }
+
+            tokens.next();
+        }
+
+        let record_type = match tokens.next() {
+            None => return error(line, "Record has no type."),
+            Some(token) => token.text.to_uppercase()
+        };
+
+        // Signatures and denial of existence records are regenerated whenever the zone is signed
+        if matches!(record_type.as_str(), "RRSIG" | "NSEC" | "NSEC3" | "NSEC3PARAM") {
+            continue;
+        }
+
+        let rdata: Vec<Token> = tokens.collect();
+
+        // SOA records provide their own TTL even without a $TTL
+        let ttl = match ttl.or(default_ttl).or(last_ttl) {
+            Some(ttl) => ttl,
+            None if record_type == "SOA" && rdata.len() == 7 => {
+                parse_time(&rdata[6].text).unwrap_or(0)
+            }
+            None => return error(line, "Record has no TTL and no $TTL was set.")
+        };
+        last_ttl = Some(ttl);
+
+        let ttl = match i32::try_from(ttl) {
+            Ok(ttl) => ttl,
+            Err(_) => return error(line, "TTL is too large.")
+        };
+
+        let record = parse_rdata(&record_type, owner, ttl, &rdata, &origin, line)?;
+        records.push((line, record));
+    }
+
+    Ok(records)
+}
+
+fn expect_fields(rdata: &[Token], count: usize, record_type: &str, line: usize) -> Result<(), ZoneFileError> {
+    if rdata.len() != count {
+        return error(line, format!("{} records take {} fields, found {}.", record_type, count, rdata.len()));
+    }
+
+    Ok(())
+}
+
+fn parse_number<T: std::str::FromStr>(token: &Token, field: &str, line: usize) -> Result<T, ZoneFileError> {
+    match token.text.parse::<T>() {
+        Ok(value) => Ok(value),
+        Err(_) => error(line, format!("Invalid {} {}.", field, token.text))
+    }
+}
+
+fn parse_time_field(token: &Token, field: &str, line: usize) -> Result<i32, ZoneFileError> {
+    match parse_time(&token.text).and_then(|time| i32::try_from(time).ok()) {
+        Some(time) => Ok(time),
+        None => error(line, format!("Invalid {} {}.", field, token.text))
+    }
+}
+
+fn parse_rdata(record_type: &str, hostname: String, ttl: i32, rdata: &[Token], origin: &str, line: usize) -> Result<RecordTypes, ZoneFileError> {
+    let record = match record_type {
+        "SOA" => {
+            expect_fields(rdata, 7, record_type, line)?;
+
+            RecordTypes::SOA {
+                ttl,
+                mname: absolute_name(&rdata[0].text, origin),
+                rname: absolute_name(&rdata[1].text, origin),
+                serial: parse_number(&rdata[2], "serial", line)?,
+                refresh: parse_time_field(&rdata[3], "refresh", line)?,
+                retry: parse_time_field(&rdata[4], "retry", line)?,
+                expire: parse_time_field(&rdata[5], "expire", line)?,
+                minimum: parse_time(&rdata[6].text)
+                    .map_or_else(|| error(line, format!("Invalid minimum {}.", rdata[6].text)), Ok)?
+            }
+        }
+        "A" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::A { hostname, ttl, address: parse_number::<Ipv4Addr>(&rdata[0], "address", line)? }
+        }
+        "AAAA" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::AAAA { hostname, ttl, address: parse_number::<Ipv6Addr>(&rdata[0], "address", line)? }
+        }
+        "CNAME" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::CNAME { hostname, ttl, cname: absolute_name(&rdata[0].text, origin) }
+        }
+        "DNAME" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::DNAME { hostname, ttl, dname: absolute_name(&rdata[0].text, origin) }
+        }
+        "MX" => {
+            expect_fields(rdata, 2, record_type, line)?;
+            RecordTypes::MX {
+                hostname,
+                ttl,
+                preference: parse_number(&rdata[0], "preference", line)?,
+                exchange: absolute_name(&rdata[1].text, origin)
+            }
+        }
+        "NS" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::NS { hostname, ttl, nsdame: absolute_name(&rdata[0].text, origin) }
+        }
+        "PTR" => {
+            expect_fields(rdata, 1, record_type, line)?;
+            RecordTypes::PTR { hostname, ttl, nsdame: absolute_name(&rdata[0].text, origin) }
+        }
+        "TXT" => {
+            if rdata.is_empty() {
+                return error(line, "TXT records need at least one string.");
+            }
+
+            // Multiple character-strings are joined back into one value
+            RecordTypes::TXT { hostname, ttl, txt_data: rdata.iter().map(|token| token.text.as_str()).collect() }
+        }
+        "CAA" => {
+            expect_fields(rdata, 3, record_type, line)?;
+            RecordTypes::CAA {
+                hostname,
+                ttl,
+                flags: parse_number(&rdata[0], "flags", line)?,
+                tag: rdata[1].text.to_lowercase(),
+                value: rdata[2].text.clone()
+            }
+        }
+        "SRV" => {
+            expect_fields(rdata, 4, record_type, line)?;
+            RecordTypes::SRV {
+                hostname,
+                ttl,
+                priority: parse_number(&rdata[0], "priority", line)?,
+                weight: parse_number(&rdata[1], "weight", line)?,
+                port: parse_number(&rdata[2], "port", line)?,
+                target: absolute_name(&rdata[3].text, origin)
+            }
+        }
+        "SVCB" | "HTTPS" => {
+            if rdata.len() < 2 {
+                return error(line, format!("{} records take at least 2 fields, found {}.", record_type, rdata.len()));
+            }
+
+            let priority = parse_number(&rdata[0], "priority", line)?;
+            let target = absolute_name(&rdata[1].text, origin);
+            let params = parse_svc_params(&rdata[2..], line)?;
+
+            if record_type == "SVCB" {
+                RecordTypes::SVCB { hostname, ttl, priority, target, params }
+            } else {
+                RecordTypes::HTTPS { hostname, ttl, priority, target, params }
+            }
+        }
+        "TLSA" => {
+            if rdata.len() < 4 {
+                return error(line, format!("TLSA records take at least 4 fields, found {}.", rdata.len()));
+            }
+
+            RecordTypes::TLSA {
+                hostname,
+                ttl,
+                usage: parse_number(&rdata[0], "usage", line)?,
+                selector: parse_number(&rdata[1], "selector", line)?,
+                matching_type: parse_number(&rdata[2], "matching type", line)?,
+                certificate_data: join_tokens(&rdata[3..])
+            }
+        }
+        "SSHFP" => {
+            if rdata.len() < 3 {
+                return error(line, format!("SSHFP records take at least 3 fields, found {}.", rdata.len()));
+            }
+
+            RecordTypes::SSHFP {
+                hostname,
+                ttl,
+                algorithm: parse_number(&rdata[0], "algorithm", line)?,
+                fingerprint_type: parse_number(&rdata[1], "fingerprint type", line)?,
+                fingerprint: join_tokens(&rdata[2..])
+            }
+        }
+        "NAPTR" => {
+            expect_fields(rdata, 6, record_type, line)?;
+            RecordTypes::NAPTR {
+                hostname,
+                ttl,
+                order: parse_number(&rdata[0], "order", line)?,
+                preference: parse_number(&rdata[1], "preference", line)?,
+                flags: rdata[2].text.clone(),
+                services: rdata[3].text.clone(),
+                regexp: rdata[4].text.clone(),
+                replacement: absolute_name(&rdata[5].text, origin)
+            }
+        }
+        "DS" => {
+            if rdata.len() < 4 {
+                return error(line, format!("DS records take at least 4 fields, found {}.", rdata.len()));
+            }
+
+            RecordTypes::DS {
+                hostname,
+                ttl,
+                key_tag: parse_number(&rdata[0], "key tag", line)?,
+                algorithm: parse_number(&rdata[1], "algorithm", line)?,
+                digest_type: parse_number(&rdata[2], "digest type", line)?,
+                digest: join_tokens(&rdata[3..])
+            }
+        }
+        "DNSKEY" => {
+            if rdata.len() < 4 {
+                return error(line, format!("DNSKEY records take at least 4 fields, found {}.", rdata.len()));
+            }
+
+            RecordTypes::DNSKEY {
+                hostname,
+                ttl,
+                flags: parse_number(&rdata[0], "flags", line)?,
+                protocol: parse_number(&rdata[1], "protocol", line)?,
+                algorithm: parse_number(&rdata[2], "algorithm", line)?,
+                public_key: join_tokens(&rdata[3..])
+            }
+        }
+        _ => return error(line, format!("Unsupported record type {}.", record_type))
+    };
+
+    Ok(record)
+}
+
+/// Joins hex or base64 data which is allowed to be split by whitespace
+fn join_tokens(tokens: &[Token]) -> String {
+    tokens.iter().map(|token| token.text.as_str()).collect()
+}
+
+/// Parses SVCB/HTTPS parameters in their presentation form (RFC 9460 section 2.1)
+fn parse_svc_params(tokens: &[Token], line: usize) -> Result<SvcParams, ZoneFileError> {
+    let mut params = SvcParams::default();
+    let mut tokens = tokens.iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let (key, mut value) = match token.text.split_once('=') {
+            None => (token.text.to_lowercase(), None),
+            Some((key, value)) => (key.to_lowercase(), Some(value.to_string()))
+        };
+
+        // Quoted values are tokenised separately from their key
+        if value.as_deref() == Some("") {
+            if let Some(next) = tokens.peek() {
+                if next.quoted {
+                    value = Some(next.text.clone());
+                    tokens.next();
+                }
+            }
+        }
+
+        let list = |value: &Option<String>| -> Vec<String> {
+            value.as_deref()
+                .unwrap_or("")
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .map(|item| item.to_string())
+                .collect()
+        };
+
+        match key.as_str() {
+            "mandatory" => params.mandatory = list(&value),
+            "alpn" => params.alpn = list(&value),
+            "no-default-alpn" => params.no_default_alpn = true,
+            "port" => params.port = match value.as_deref().map(str::parse::<u16>) {
+                Some(Ok(port)) => Some(port),
+                _ => return error(line, "Invalid port parameter.")
+            },
+            "ipv4hint" => params.ipv4hint = list(&value).iter()
+                .map(|address| address.parse::<Ipv4Addr>())
+                .collect::<Result<_, _>>()
+                .map_or_else(|_| error(line, "Invalid ipv4hint parameter."), Ok)?,
+            "ech" => params.ech = value,
+            "ipv6hint" => params.ipv6hint = list(&value).iter()
+                .map(|address| address.parse::<Ipv6Addr>())
+                .collect::<Result<_, _>>()
+                .map_or_else(|_| error(line, "Invalid ipv6hint parameter."), Ok)?,
+            _ => return error(line, format!("Unsupported service parameter {}.", key))
+        }
+    }
+
+    Ok(params)
+}
+
+fn render_svc_params(params: &SvcParams) -> String {
+    let mut rendered: Vec<String> = vec![];
+
+    if !params.mandatory.is_empty() {
+        rendered.push(format!("mandatory={}", params.mandatory.join(",")));
+    }
+    if !params.alpn.is_empty() {
+        rendered.push(format!("alpn={}", params.alpn.join(",")));
+    }
+    if params.no_default_alpn {
+        rendered.push("no-default-alpn".to_string());
+    }
+    if let Some(port) = params.port {
+        rendered.push(format!("port={}", port));
+    }
+    if !params.ipv4hint.is_empty() {
+        rendered.push(format!("ipv4hint={}", params.ipv4hint.iter().map(|address| address.to_string()).collect::<Vec<String>>().join(",")));
+    }
+    if let Some(ech) = &params.ech {
+        rendered.push(format!("ech={}", ech));
+    }
+    if !params.ipv6hint.is_empty() {
+        rendered.push(format!("ipv6hint={}", params.ipv6hint.iter().map(|address| address.to_string()).collect::<Vec<String>>().join(",")));
+    }
+
+    rendered.join(" ")
+}
+
+/// Presents an RRSIG timestamp as YYYYMMDDHHmmSS (RFC 4034 section 3.2)
+fn render_timestamp(timestamp: u32) -> String {
+    match NaiveDateTime::from_timestamp_opt(timestamp as i64, 0) {
+        Some(timestamp) => timestamp.format("%Y%m%d%H%M%S").to_string(),
+        None => timestamp.to_string()
+    }
+}
+
+/// Presents an NSEC3 salt, which is written as - when empty (RFC 5155 section 3.3)
+fn render_salt(salt: &str) -> &str {
+    if salt.is_empty() {
+        "-"
+    } else {
+        salt
+    }
+}
+
+/// Presents a stored name as an absolute name
+fn fqdn(name: &str) -> String {
+    if name == "." {
+        name.to_string()
+    } else {
+        format!("{}.", name)
+    }
+}
+
+/// Renders a zone's records as a master file
+///
+/// The SOA is placed first, followed by the apex NS records and then everything else by name.
+pub fn render_zone_file(origin: &str, records: &[RecordTypes]) -> String {
+    let origin = normalise_name(origin);
+    let mut output = format!("$ORIGIN {}\n", fqdn(&origin));
+
+    let mut sorted: Vec<&RecordTypes> = records.iter().collect();
+    sorted.sort_by_key(|record| {
+        let rank = match record {
+            RecordTypes::SOA { .. } => 0,
+            RecordTypes::NS { hostname, .. } if *hostname == origin => 1,
+            _ => 2
+        };
+        let hostname = record.hostname().unwrap_or(&origin);
+        // Sort by labels from the root down so related names stay together
+        let labels: Vec<String> = hostname.split('.').rev().map(|label| label.to_string()).collect();
+
+        (rank, labels, record.type_name())
+    });
+
+    for record in sorted {
+        let owner = fqdn(record.hostname().unwrap_or(&origin));
+        let ttl = record.ttl();
+        let record_type = record.type_name();
+
+        let rdata = match record {
+            RecordTypes::SOA { mname, rname, serial, refresh, retry, expire, minimum, .. } => {
+                format!(
+                    "{} {} (\n\t\t{} ; serial\n\t\t{} ; refresh\n\t\t{} ; retry\n\t\t{} ; expire\n\t\t{} ; minimum\n\t\t)",
+                    fqdn(mname), fqdn(rname), serial, refresh, retry, expire, minimum
+                )
+            }
+            RecordTypes::A { address, .. } => address.to_string(),
+            RecordTypes::AAAA { address, .. } => address.to_string(),
+            RecordTypes::CNAME { cname, .. } => fqdn(cname),
+            RecordTypes::DNAME { dname, .. } => fqdn(dname),
+            RecordTypes::MX { preference, exchange, .. } => format!("{} {}", preference, fqdn(exchange)),
+            RecordTypes::NS { nsdame, .. } => fqdn(nsdame),
+            RecordTypes::PTR { nsdame, .. } => fqdn(nsdame),
+            RecordTypes::TXT { txt_data, .. } => {
+                // Long values have to be split into several character-strings
+                let bytes = txt_data.as_bytes();
+                bytes.chunks(MAX_CHARACTER_STRING)
+                    .map(|chunk| format!("\"{}\"", escape(&String::from_utf8_lossy(chunk))))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            }
+            RecordTypes::CAA { flags, tag, value, .. } => format!("{} {} \"{}\"", flags, tag, escape(value)),
+            RecordTypes::SRV { priority, weight, port, target, .. } => {
+                format!("{} {} {} {}", priority, weight, port, fqdn(target))
+            }
+            RecordTypes::SVCB { priority, target, params, .. }
+            | RecordTypes::HTTPS { priority, target, params, .. } => {
+                format!("{} {} {}", priority, fqdn(target), render_svc_params(params)).trim_end().to_string()
+            }
+            RecordTypes::TLSA { usage, selector, matching_type, certificate_data, .. } => {
+                format!("{} {} {} {}", usage, selector, matching_type, certificate_data)
+            }
+            RecordTypes::SSHFP { algorithm, fingerprint_type, fingerprint, .. } => {
+                format!("{} {} {}", algorithm, fingerprint_type, fingerprint)
+            }
+            RecordTypes::NAPTR { order, preference, flags, services, regexp, replacement, .. } => {
+                format!("{} {} \"{}\" \"{}\" \"{}\" {}", order, preference, escape(flags), escape(services), escape(regexp), fqdn(replacement))
+            }
+            RecordTypes::DS { key_tag, algorithm, digest_type, digest, .. } => {
+                format!("{} {} {} {}", key_tag, algorithm, digest_type, digest)
+            }
+            RecordTypes::DNSKEY { flags, protocol, algorithm, public_key, .. } => {
+                format!("{} {} {} {}", flags, protocol, algorithm, public_key)
+            }
+            RecordTypes::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature, .. } => {
+                format!(
+                    "{} {} {} {} {} {} {} {} {}",
+                    type_mnemonic(*type_covered), algorithm, labels, original_ttl,
+                    render_timestamp(*expiration), render_timestamp(*inception), key_tag, fqdn(signer_name), signature
+                )
+            }
+            RecordTypes::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types, .. } => {
+                let types: Vec<String> = types.iter().map(|record_type| type_mnemonic(*record_type)).collect();
+                format!("{} {} {} {} {} {}", hash_algorithm, flags, iterations, render_salt(salt), next_hashed_owner, types.join(" ")).trim_end().to_string()
+            }
+            RecordTypes::NSEC3PARAM { hash_algorithm, flags, iterations, salt, .. } => {
+                format!("{} {} {} {}", hash_algorithm, flags, iterations, render_salt(salt))
+            }
+        };
+
+        output.push_str(&format!("{}\t{}\tIN\t{}\t{}\n", owner, ttl, record_type, rdata));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<RecordTypes> {
+        parse_zone_file(input, "example.com")
+            .unwrap()
+            .into_iter()
+            .map(|(_, record)| record)
+            .collect()
+    }
+
+    fn a_record(hostname: &str, ttl: i32, address: [u8; 4]) -> RecordTypes {
+        RecordTypes::A { hostname: hostname.to_string(), ttl, address: Ipv4Addr::from(address) }
+    }
+
+    #[test]
+    fn parse_follows_origin_and_ttl_directives() {
+        let input = "$TTL 1h\n$ORIGIN example.net.\nwww A 192.0.2.1\n$ORIGIN sub\nhost 300 A 192.0.2.2\n@ A 192.0.2.3\n";
+
+        assert_eq!(parse(input), vec![
+            a_record("www.example.net", 3600, [192, 0, 2, 1]),
+            a_record("host.sub.example.net", 300, [192, 0, 2, 2]),
+            // An explicit TTL only applies to its own record once $TTL is set
+            a_record("sub.example.net", 3600, [192, 0, 2, 3])
+        ]);
+    }
+
+    #[test]
+    fn parse_joins_parenthesised_soa() {
+        let input = "\n@ IN SOA ns1 hostmaster (\n\t2022100901 ; serial\n\t2h ; refresh\n\t1h\n\t2w\n\t300 )\n";
+        let records = parse_zone_file(input, "example.com").unwrap();
+
+        assert_eq!(records, vec![(2, RecordTypes::SOA {
+            // Without a $TTL the SOA's minimum is used
+            ttl: 300,
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 2022100901,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300
+        })]);
+    }
+
+    #[test]
+    fn parse_inherits_owner() {
+        let input = "$TTL 300\nwww A 192.0.2.1\n\tAAAA 2001:db8::1\n  A 192.0.2.2\n";
+
+        assert_eq!(parse(input), vec![
+            a_record("www.example.com", 300, [192, 0, 2, 1]),
+            RecordTypes::AAAA { hostname: "www.example.com".to_string(), ttl: 300, address: "2001:db8::1".parse().unwrap() },
+            a_record("www.example.com", 300, [192, 0, 2, 2])
+        ]);
+    }
+
+    #[test]
+    fn parse_reads_ttl_and_class_in_either_order() {
+        let input = "a 300 IN A 192.0.2.1\nb IN 600 A 192.0.2.2\nc in 1m a 192.0.2.3\n";
+
+        assert_eq!(parse(input), vec![
+            a_record("a.example.com", 300, [192, 0, 2, 1]),
+            a_record("b.example.com", 600, [192, 0, 2, 2]),
+            a_record("c.example.com", 60, [192, 0, 2, 3])
+        ]);
+
+        assert_eq!(parse_zone_file("a 300 CH A 192.0.2.1\n", "example.com").unwrap_err().reason, "Unsupported class CH.");
+    }
+
+    #[test]
+    fn parse_unescapes_quoted_text() {
+        let input = "$TTL 300\ntxt TXT \"v=spf1 ; -all\" \"say \\\"hi\\\" \\\\ \\065\" ; comment\n";
+
+        assert_eq!(parse(input), vec![RecordTypes::TXT {
+            hostname: "txt.example.com".to_string(),
+            ttl: 300,
+            txt_data: "v=spf1 ; -allsay \"hi\" \\ A".to_string()
+        }]);
+    }
+
+    #[test]
+    fn parse_rejects_unbalanced_parentheses() {
+        let err = parse_zone_file("$TTL 300\n@ SOA ns1 hostmaster (\n\t1 2 3 4 5\n", "example.com").unwrap_err();
+        assert_eq!((err.line, err.reason.as_str()), (2, "Unbalanced opening parenthesis."));
+
+        let err = parse_zone_file("$TTL 300\nwww A 192.0.2.1 )\n", "example.com").unwrap_err();
+        assert_eq!((err.line, err.reason.as_str()), (2, "Unbalanced closing parenthesis."));
+
+        let err = parse_zone_file("$TTL 300\ntxt TXT \"unterminated\n", "example.com").unwrap_err();
+        assert_eq!((err.line, err.reason.as_str()), (2, "Unterminated quoted string."));
+    }
+
+    #[test]
+    fn parse_skips_generated_dnssec_records() {
+        let input = "$TTL 300\n@ NSEC3PARAM 1 0 0 -\nwww A 192.0.2.1\n";
+
+        assert_eq!(parse(input), vec![a_record("www.example.com", 300, [192, 0, 2, 1])]);
+    }
+
+    #[test]
+    fn render_then_parse_round_trips() {
+        let records = vec![
+            RecordTypes::SOA {
+                ttl: 3600,
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2022100901,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300
+            },
+            RecordTypes::NS { hostname: "example.com".to_string(), ttl: 3600, nsdame: "ns1.example.com".to_string() },
+            a_record("www.example.com", 300, [192, 0, 2, 1]),
+            RecordTypes::AAAA { hostname: "www.example.com".to_string(), ttl: 300, address: "2001:db8::1".parse().unwrap() },
+            RecordTypes::CNAME { hostname: "alias.example.com".to_string(), ttl: 300, cname: "www.example.com".to_string() },
+            RecordTypes::DNAME { hostname: "old.example.com".to_string(), ttl: 300, dname: "example.net".to_string() },
+            RecordTypes::MX { hostname: "example.com".to_string(), ttl: 300, preference: 10, exchange: "mail.example.com".to_string() },
+            RecordTypes::PTR { hostname: "1.2.0.192.in-addr.example.com".to_string(), ttl: 300, nsdame: "www.example.com".to_string() },
+            RecordTypes::TXT {
+                hostname: "txt.example.com".to_string(),
+                ttl: 300,
+                txt_data: format!("v=spf1 ; \"quoted\" \\ {}", "x".repeat(300))
+            },
+            RecordTypes::CAA {
+                hostname: "example.com".to_string(),
+                ttl: 300,
+                flags: 128,
+                tag: "issue".to_string(),
+                value: "letsencrypt.org; accounturi=https://example.com/acct/1".to_string()
+            },
+            RecordTypes::SRV {
+                hostname: "_sip._tcp.example.com".to_string(),
+                ttl: 300,
+                priority: 10,
+                weight: 5,
+                port: 5060,
+                target: "sip.example.com".to_string()
+            },
+            RecordTypes::HTTPS {
+                hostname: "example.com".to_string(),
+                ttl: 300,
+                priority: 1,
+                target: ".".to_string(),
+                params: SvcParams {
+                    mandatory: vec!["alpn".to_string()],
+                    alpn: vec!["h2".to_string(), "h3".to_string()],
+                    port: Some(8443),
+                    ipv4hint: vec![Ipv4Addr::new(192, 0, 2, 1)],
+                    ipv6hint: vec!["2001:db8::1".parse().unwrap()],
+                    ..SvcParams::default()
+                }
+            },
+            RecordTypes::SVCB { hostname: "_dns.example.com".to_string(), ttl: 300, priority: 0, target: "dns.example.com".to_string(), params: SvcParams::default() },
+            RecordTypes::TLSA {
+                hostname: "_443._tcp.www.example.com".to_string(),
+                ttl: 300,
+                usage: 3,
+                selector: 1,
+                matching_type: 1,
+                certificate_data: "ab".repeat(32)
+            },
+            RecordTypes::SSHFP { hostname: "www.example.com".to_string(), ttl: 300, algorithm: 4, fingerprint_type: 2, fingerprint: "cd".repeat(32) },
+            RecordTypes::NAPTR {
+                hostname: "example.com".to_string(),
+                ttl: 300,
+                order: 100,
+                preference: 10,
+                flags: "u".to_string(),
+                services: "E2U+sip".to_string(),
+                regexp: "!^.*$!sip:info@example.com!".to_string(),
+                replacement: ".".to_string()
+            },
+            RecordTypes::DS { hostname: "sub.example.com".to_string(), ttl: 300, key_tag: 60485, algorithm: 8, digest_type: 2, digest: "ef".repeat(32) },
+            RecordTypes::DNSKEY { hostname: "example.com".to_string(), ttl: 3600, flags: 257, protocol: 3, algorithm: 8, public_key: "AwEAAQ==".to_string() }
+        ];
+
+        let mut parsed = parse(&render_zone_file("example.com", &records));
+        assert_eq!(parsed.len(), records.len());
+
+        for record in &records {
+            let position = parsed.iter().position(|parsed| parsed == record)
+                .unwrap_or_else(|| panic!("{:?} did not round trip", record));
+            parsed.remove(position);
+        }
+    }
+}
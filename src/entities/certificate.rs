@@ -8,10 +8,15 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
     pub data: Vec<u8>,
-    pub key: Vec<u8>,
+    pub key: Option<Vec<u8>>,
     #[sea_orm(unique)]
-    pub nonce: Vec<u8>,
+    pub nonce: Option<Vec<u8>>,
     pub cert_type: String,
+    pub revoked: Option<DateTime>,
+    pub revocation_reason: Option<String>,
+    pub retiring: Option<DateTime>,
+    pub chain: Option<Vec<u8>>,
+    pub fetched: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -20,6 +25,8 @@ pub enum Relation {
     Proxy,
     #[sea_orm(has_many = "super::client::Entity")]
     Client,
+    #[sea_orm(has_many = "super::certificate_history::Entity")]
+    CertificateHistory,
 }
 
 impl Related<super::proxy::Entity> for Entity {
@@ -34,4 +41,10 @@ impl Related<super::client::Entity> for Entity {
     }
 }
 
+impl Related<super::certificate_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CertificateHistory.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
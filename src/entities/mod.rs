@@ -2,8 +2,14 @@
 
 pub mod prelude;
 
+pub mod acme_account;
 pub mod certificate;
+pub mod certificate_history;
+pub mod change_journal;
+pub mod change_sequence;
 pub mod client;
+pub mod dnssec_key;
+pub mod join_token;
 pub mod proxy;
 pub mod record;
 pub mod session;
@@ -1,7 +1,13 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
+pub use super::acme_account::Entity as AcmeAccount;
 pub use super::certificate::Entity as Certificate;
+pub use super::certificate_history::Entity as CertificateHistory;
+pub use super::change_journal::Entity as ChangeJournal;
+pub use super::change_sequence::Entity as ChangeSequence;
 pub use super::client::Entity as Client;
+pub use super::dnssec_key::Entity as DnssecKey;
+pub use super::join_token::Entity as JoinToken;
 pub use super::proxy::Entity as Proxy;
 pub use super::record::Entity as Record;
 pub use super::session::Entity as Session;
@@ -11,6 +11,7 @@ pub struct Model {
     pub port: i32,
     pub active: bool,
     pub certificate: String,
+    pub upstreams: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -17,12 +17,20 @@ pub struct Model {
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::join_token::Entity")]
+    JoinToken,
     #[sea_orm(has_many = "super::session::Entity")]
     Session,
     #[sea_orm(has_many = "super::team_member::Entity")]
     TeamMember,
 }
 
+impl Related<super::join_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::JoinToken.def()
+    }
+}
+
 impl Related<super::session::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Session.def()
@@ -11,6 +11,9 @@ pub struct Model {
     #[sea_orm(unique)]
     pub origin: String,
     pub delegated: bool,
+    pub delegation_checked: Option<DateTime>,
+    pub delegation_error: Option<String>,
+    pub dnssec: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -25,6 +28,8 @@ pub enum Relation {
     Team,
     #[sea_orm(has_many = "super::record::Entity")]
     Record,
+    #[sea_orm(has_many = "super::dnssec_key::Entity")]
+    DnssecKey,
 }
 
 impl Related<super::team::Entity> for Entity {
@@ -39,4 +44,10 @@ impl Related<super::record::Entity> for Entity {
     }
 }
 
+impl Related<super::dnssec_key::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DnssecKey.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,25 +2,38 @@
 
 use std::{env, fs};
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use axum::extract::Extension;
 use axum::Router;
 use axum::routing::{delete, get, post};
 use dotenv::dotenv;
 use lapin::ConnectionProperties;
-use picky::key::PrivateKey;
 use picky::x509::Cert;
-use sea_orm::{ActiveValue, ColumnTrait, ConnectOptions, Database, EntityTrait, QueryFilter};
+use sea_orm::{ActiveValue, ColumnTrait, ConnectOptions, Database, EntityTrait, QueryFilter, TransactionTrait};
 use sea_orm_migration::prelude::*;
+use tokio::sync::RwLock;
 use tower::ServiceBuilder;
 use ulid::Ulid;
-use crate::cert::encrypt_priv_key;
+use crate::cert::acme::{AcmeIssuer, AcmeSettings, run_acme_renewal, SharedAcmeIssuer, SharedSolver};
+use crate::cert::algorithm::{KeyAlgorithm, SigningKey};
+use crate::cert::dnssec::run_rollover;
+use crate::cert::{encrypt_priv_key, load_root};
+use crate::cert::keystore::{KeyStore, rewrap_keys};
+use crate::cert::renewal::{declare_certificate_exchange, ExpiryReport, run_renewal};
 use crate::cert::generate::{generate_inter_cert, generate_root_cert};
 use crate::cert::generate::InterTarget::{CLIENT, PROXY};
 use crate::cert::Types::{CLIENTINTER, PROXYINTER, ROOT};
 use crate::certificate::Model;
+use crate::dns::challenge::RecordSolver;
+use crate::dns::delegation::{run_verifier, SharedResolver, UdpResolver};
+use crate::dns::server::{DnsServer, SharedZoneStore};
 use crate::entities::certificate;
+use crate::rpc::changes::declare_exchange;
+use crate::rpc::proxies::declare_proxy_exchange;
+use crate::rpc::sync::run_journal_pruning;
+use crate::rpc::tls::RpcTlsServer;
 
 mod entities;
 mod dns;
@@ -71,8 +84,8 @@ async fn main() {
         error!("Please generate a 32 bits of randomness to encrypt private keys!")
     }
 
-    let root_rsa_key = PrivateKey::from_pem_str(
-        &*fs::read_to_string(
+    let root_rsa_key = SigningKey::from_pem_str(
+        &fs::read_to_string(
             Path::new(&env::var("RSA_KEY").expect("RSA_KEY must be set! Halting start-up."))
         ).expect("Failed to load the root RSA key! Halting start-up.")
     ).expect("Failed to load the root RSA key! Halting start-up.");
@@ -90,14 +103,38 @@ async fn main() {
         .await
         .expect("Failed to run migrations! Halting start-up.");
 
+    if env::var("XCC20_PREVIOUS_KEY").is_ok() {
+        info!("Re-encrypting private keys with the new XCC20 key...");
+        let previous_key_store = KeyStore::from_env("XCC20_PREVIOUS_KEY")
+            .expect("Failed to load the previous XCC20 key! Halting start-up.");
+        let key_store = KeyStore::from_env("XCC20_KEY")
+            .expect("Failed to load the XCC20 key! Halting start-up.");
+
+        let txn = connection.begin()
+            .await
+            .expect("Failed to begin key rotation transaction! Halting start-up.");
+
+        let rewrapped = rewrap_keys(&previous_key_store, &key_store, &txn)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to re-encrypt private keys: {}! Halting start-up.", err));
+
+        txn.commit()
+            .await
+            .expect("Failed to commit key rotation transaction! Halting start-up.");
+
+        info!("Re-encrypted {} private keys, XCC20_PREVIOUS_KEY can now be unset", rewrapped);
+    }
+
     info!("Checking for root cert...");
     let root_cert_model: Option<Model> = certificate::Entity::find()
         .filter(certificate::Column::CertType.eq(cert::Types::ROOT.to_string()))
+        .filter(certificate::Column::Retiring.is_null())
         .one(&connection)
         .await
         .expect("Failed to retrieve the root cert from the database! Halting start-up.");
 
     let root_cert: Cert;
+    let root_key: SigningKey;
 
     match root_cert_model {
         None => {
@@ -108,15 +145,21 @@ async fn main() {
                 .expect("Failed to generate the root cert from private key! Halting start-up.");
 
             root_cert = new_root_cert.clone();
+            root_key = root_rsa_key.clone();
 
             let root_insert = certificate::Entity::insert(certificate::ActiveModel {
                 id: ActiveValue::Set(Ulid::new().to_string()),
                 data: ActiveValue::Set(
                     new_root_cert.to_der().expect("Failed to convert cert into der!")
                 ),
-                key: ActiveValue::set(vec![145, 66, 62, 61, 56, 156, 145, 164]),
-                nonce: ActiveValue::set(vec![145, 71, 62, 66, 56, 156, 145, 164]),
-                cert_type: ActiveValue::Set(ROOT.to_string())
+                key: ActiveValue::set(Some(vec![145, 66, 62, 61, 56, 156, 145, 164])),
+                nonce: ActiveValue::set(Some(vec![145, 71, 62, 66, 56, 156, 145, 164])),
+                cert_type: ActiveValue::Set(ROOT.to_string()),
+                revoked: ActiveValue::Set(None),
+                revocation_reason: ActiveValue::Set(None),
+                retiring: ActiveValue::Set(None),
+                chain: ActiveValue::Set(None),
+                fetched: ActiveValue::Set(None)
             })
                 .exec(&connection)
                 .await
@@ -127,14 +170,17 @@ async fn main() {
         Some(root_cert_model) => {
             info!("Found root cert: {}", root_cert_model.id);
 
-            root_cert = Cert::from_der(&root_cert_model.data)
-                .expect("Failed to decode root cert!");
+            (root_cert, root_key) = load_root(&root_rsa_key, &connection)
+                .await
+                .expect("Failed to retrieve the root cert from the database! Halting start-up.")
+                .expect("Failed to load the root cert! Halting start-up.");
         }
     }
 
     info!("Checking for proxy intermediate cert...");
     let proxy_inter_model: Option<Model> = certificate::Entity::find()
         .filter(certificate::Column::CertType.eq(PROXYINTER.to_string()))
+        .filter(certificate::Column::Retiring.is_null())
         .one(&connection)
         .await
         .expect("Failed to retrieve the proxy intermediate cert from the database! Halting start-up.");
@@ -145,8 +191,8 @@ async fn main() {
         None => {
             info!("Generating proxy intermediate cert...");
 
-            // Generate 4096 bit RSA private key
-            let priv_key = PrivateKey::generate_rsa(4096)
+            // 4096 bit RSA unless PROXYINTER_KEY_ALGORITHM says otherwise
+            let priv_key = SigningKey::generate(KeyAlgorithm::for_type(&PROXYINTER))
                 .expect("Failed to generate a key");
 
             let encrypted_priv_key = encrypt_priv_key(priv_key
@@ -155,7 +201,7 @@ async fn main() {
                 .expect("Failed to convert generated private key to pkcs8!")
             ).await;
 
-            let new_proxy_inter_cert = generate_inter_cert(&priv_key, PROXY, (&root_cert, &root_rsa_key))
+            let new_proxy_inter_cert = generate_inter_cert(&priv_key, PROXY, (&root_cert, &root_key))
                 .await
                 .expect("Failed to generate new proxy intermediate cert");
 
@@ -166,9 +212,14 @@ async fn main() {
                 data: ActiveValue::Set(
                     new_proxy_inter_cert.to_der().expect("Failed to convert cert into der!")
                 ),
-                key: ActiveValue::set(encrypted_priv_key.1),
-                nonce: ActiveValue::set(encrypted_priv_key.0),
-                cert_type: ActiveValue::Set(PROXYINTER.to_string())
+                key: ActiveValue::set(Some(encrypted_priv_key.1)),
+                nonce: ActiveValue::set(Some(encrypted_priv_key.0)),
+                cert_type: ActiveValue::Set(PROXYINTER.to_string()),
+                revoked: ActiveValue::Set(None),
+                revocation_reason: ActiveValue::Set(None),
+                retiring: ActiveValue::Set(None),
+                chain: ActiveValue::Set(None),
+                fetched: ActiveValue::Set(None)
             })
                 .exec(&connection)
                 .await
@@ -187,6 +238,7 @@ async fn main() {
     info!("Checking for client intermediate cert...");
     let client_inter_model: Option<Model> = certificate::Entity::find()
         .filter(certificate::Column::CertType.eq(CLIENTINTER.to_string()))
+        .filter(certificate::Column::Retiring.is_null())
         .one(&connection)
         .await
         .expect("Failed to retrieve the client intermediate cert from the database! Halting start-up.");
@@ -197,8 +249,8 @@ async fn main() {
         None => {
             info!("Generating client intermediate cert...");
 
-            // Generate 4096 bit RSA private key
-            let priv_key = PrivateKey::generate_rsa(4096)
+            // 4096 bit RSA unless CLIENTINTER_KEY_ALGORITHM says otherwise
+            let priv_key = SigningKey::generate(KeyAlgorithm::for_type(&CLIENTINTER))
                 .expect("Failed to generate a key");
 
             let encrypted_priv_key = encrypt_priv_key(priv_key
@@ -207,7 +259,7 @@ async fn main() {
                 .expect("Failed to convert generated private key to pkcs8!")
             ).await;
 
-            let new_client_inter_cert = generate_inter_cert(&priv_key, CLIENT, (&root_cert, &root_rsa_key))
+            let new_client_inter_cert = generate_inter_cert(&priv_key, CLIENT, (&root_cert, &root_key))
                 .await
                 .expect("Failed to generate new client intermediate cert");
 
@@ -218,9 +270,14 @@ async fn main() {
                 data: ActiveValue::Set(
                     new_client_inter_cert.to_der().expect("Failed to convert cert into der!")
                 ),
-                key: ActiveValue::set(encrypted_priv_key.1),
-                nonce: ActiveValue::set(encrypted_priv_key.0),
-                cert_type: ActiveValue::Set(CLIENTINTER.to_string())
+                key: ActiveValue::set(Some(encrypted_priv_key.1)),
+                nonce: ActiveValue::set(Some(encrypted_priv_key.0)),
+                cert_type: ActiveValue::Set(CLIENTINTER.to_string()),
+                revoked: ActiveValue::Set(None),
+                revocation_reason: ActiveValue::Set(None),
+                retiring: ActiveValue::Set(None),
+                chain: ActiveValue::Set(None),
+                fetched: ActiveValue::Set(None)
             })
                 .exec(&connection)
                 .await
@@ -245,6 +302,86 @@ async fn main() {
     let amqp_channel = amqp_connection.create_channel()
         .await
         .expect("Failed to create a message broker channel! Halting start-up.");
+    declare_exchange(&amqp_channel)
+        .await
+        .expect("Failed to declare the change exchange! Halting start-up.");
+    declare_certificate_exchange(&amqp_channel)
+        .await
+        .expect("Failed to declare the certificate exchange! Halting start-up.");
+    declare_proxy_exchange(&amqp_channel)
+        .await
+        .expect("Failed to declare the proxy exchange! Halting start-up.");
+
+    // The embedded DNS server is only started when an address is given for it
+    if let Ok(dns_addr) = env::var("DNS_LISTEN_ADDR") {
+        info!("Starting DNS server...");
+
+        let dns_socket_addr: SocketAddr = dns_addr.parse()
+            .expect("Failed to parse DNS_LISTEN_ADDR! Halting start-up.");
+
+        let dns_server = DnsServer::bind(dns_socket_addr)
+            .await
+            .expect("Failed to bind the DNS server! Halting start-up.");
+
+        info!("DNS server is now listening on {}!", dns_socket_addr);
+
+        let zones: SharedZoneStore = Arc::new(connection.clone());
+        tokio::spawn(dns_server.run(zones));
+    }
+
+    info!("Starting delegation verifier...");
+    let resolver_addr: SocketAddr = env::var("DELEGATION_RESOLVER")
+        .unwrap_or("1.1.1.1:53".to_string())
+        .parse()
+        .expect("Failed to parse DELEGATION_RESOLVER! Halting start-up.");
+    let resolver: SharedResolver = Arc::new(UdpResolver::new(resolver_addr));
+
+    tokio::spawn(run_verifier(connection.clone(), resolver.clone()));
+
+    info!("Starting DNSSEC key rollover...");
+    tokio::spawn(run_rollover(connection.clone(), amqp_channel.clone()));
+
+    info!("Starting certificate renewal...");
+    let expiry_report: ExpiryReport = Arc::new(RwLock::new(vec![]));
+    tokio::spawn(run_renewal(connection.clone(), amqp_channel.clone(), root_rsa_key.clone(), expiry_report.clone()));
+
+    // Proxies can only get publicly trusted certificates when an ACME directory is given
+    let acme_issuer: SharedAcmeIssuer = AcmeSettings::from_env().map(|acme_settings| {
+        info!("Starting ACME renewal against {}...", acme_settings.directory_url);
+        let solver: SharedSolver = Arc::new(RecordSolver::new(connection.clone(), amqp_channel.clone()));
+
+        tokio::spawn(run_acme_renewal(connection.clone(), amqp_channel.clone(), acme_settings.clone(), solver.clone()));
+
+        Arc::new(AcmeIssuer { settings: acme_settings, solver })
+    });
+
+    info!("Starting change journal pruning...");
+    tokio::spawn(run_journal_pruning(connection.clone()));
+
+    // Clients can only authenticate with certificates when a TLS address is given
+    if let Ok(rpc_tls_addr) = env::var("RPC_TLS_LISTEN_ADDR") {
+        info!("Starting mutual TLS RPC listener...");
+
+        let rpc_tls_socket_addr: SocketAddr = rpc_tls_addr.parse()
+            .expect("Failed to parse RPC_TLS_LISTEN_ADDR! Halting start-up.");
+        let rpc_tls_cert = PathBuf::from(env::var("RPC_TLS_CERT")
+            .expect("RPC_TLS_CERT must be set when RPC_TLS_LISTEN_ADDR is! Halting start-up."));
+        let rpc_tls_key = PathBuf::from(env::var("RPC_TLS_KEY")
+            .expect("RPC_TLS_KEY must be set when RPC_TLS_LISTEN_ADDR is! Halting start-up."));
+
+        let rpc_tls_server = RpcTlsServer::bind(
+            rpc_tls_socket_addr,
+            &rpc_tls_cert,
+            &rpc_tls_key,
+            &connection
+        )
+            .await
+            .expect("Failed to start the mutual TLS RPC listener! Halting start-up.");
+
+        info!("Mutual TLS RPC listener is now listening on {}!", rpc_tls_socket_addr);
+
+        tokio::spawn(rpc_tls_server.run(connection.clone()));
+    }
 
     info!("Starting web server...");
     let app = Router::new()
@@ -259,14 +396,55 @@ async fn main() {
         //-- Settings
 
         // Teams
+        .route("/team/create", post(routes::teams::create::create))
+        .route("/team/rename", post(routes::teams::rename::rename))
+        .route("/team/deactivate", post(routes::teams::deactivate::deactivate))
+        .route("/team/list", get(routes::teams::list::list))
+        //-- Members
+        .route("/team/members", get(routes::teams::list_members::list_members))
+        .route("/team/invite", post(routes::teams::invite::invite))
+        .route("/team/remove_member", post(routes::teams::remove_member::remove_member))
+        .route("/team/change_role", post(routes::teams::change_role::change_role))
 
         // Zones
+        .route("/zone/create", post(routes::zones::create::create))
+        .route("/zone/list", get(routes::zones::list::list))
+        .route("/zone/get", get(routes::zones::get::get))
+        .route("/zone/transfer", post(routes::zones::transfer::transfer))
+        .route("/zone/delete", delete(routes::zones::delete::delete))
+        .route("/zone/import", post(routes::zones::import::import))
+        .route("/zone/export", get(routes::zones::export::export))
+        .route("/zone/check_delegation", post(routes::zones::check_delegation::check_delegation))
+        .route("/zone/dnssec", get(routes::zones::dnssec::dnssec))
+        .route("/zone/set_dnssec", post(routes::zones::set_dnssec::set_dnssec))
 
         // Records
+        .route("/record/create", post(routes::records::create::create))
+        .route("/record/update", post(routes::records::update::update))
+        .route("/record/list", get(routes::records::list::list))
+        .route("/record/delete", delete(routes::records::delete::delete))
+
+        // Clients
+        .route("/client/create_join_token", post(routes::clients::create_join_token::create_join_token))
+        .route("/client/join_tokens", get(routes::clients::list_join_tokens::list_join_tokens))
+        .route("/client/enroll", post(routes::clients::enroll::enroll))
 
         // Proxies
+        .route("/proxy/create", post(routes::proxies::create::create))
+        .route("/proxy/update", post(routes::proxies::update::update))
+        .route("/proxy/list", get(routes::proxies::list::list))
+        .route("/proxy/delete", delete(routes::proxies::delete::delete))
+
+        // PKI
+        .route("/pki/crl/client", get(routes::pki::crl::client_crl))
+        .route("/pki/crl/proxy", get(routes::pki::crl::proxy_crl))
+        .route("/pki/crl/intermediate", get(routes::pki::crl::intermediate_crl))
+        .route("/pki/ocsp", post(routes::pki::ocsp::ocsp))
 
         // Admin
+        .route("/admin/revoke_certificate", post(routes::admin::revoke_certificate::revoke_certificate))
+        .route("/admin/rotate_ca", post(routes::admin::rotate_ca::rotate_ca))
+        .route("/admin/finish_rotation", post(routes::admin::finish_rotation::finish_rotation))
 
         // RPC
         .route("/rpc", post(rpc::rpc))
@@ -278,6 +456,10 @@ async fn main() {
         	ServiceBuilder::new()
         		.layer(Extension(connection))
                 .layer(Extension(amqp_channel))
+                .layer(Extension(resolver))
+                .layer(Extension(expiry_report))
+                .layer(Extension(acme_issuer))
+                .layer(Extension(root_rsa_key))
         );
     
     let addr = env::var("LISTEN_ADDR")
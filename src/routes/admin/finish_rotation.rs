@@ -0,0 +1,45 @@
+use axum::Extension;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+
+use crate::cert::rotation::{finish_rotation as finish, RotationError};
+use crate::util::auth::{authorise_admin, UserFromBearer};
+
+/// Retires the old chain of a rotation without waiting for every holder to fetch its re-issued leaf
+pub async fn finish_rotation(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer
+) -> impl IntoResponse {
+    let user = user.0;
+
+    if let Err(err) = authorise_admin(&user) {
+        return err;
+    }
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin rotation transaction!");
+
+    let retired = match finish(&txn).await {
+        Ok(retired) => retired,
+        Err(RotationError::NotInProgress) => {
+            return (StatusCode::CONFLICT, "No rotation is in progress".to_string());
+        }
+        Err(RotationError::Reissuing(remaining)) => {
+            return (StatusCode::CONFLICT, format!("{} leaves still need re-issuing by the new chain", remaining));
+        }
+        Err(err) => {
+            error!("Failed to finish the CA rotation: {}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit rotation transaction!");
+
+    warn!("CA rotation was finished by {}, retiring {} certs of the old chain", user.id, retired);
+
+    (StatusCode::OK, format!("Retired {} certs of the old chain", retired))
+}
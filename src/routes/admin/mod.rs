@@ -0,0 +1,3 @@
+pub mod finish_rotation;
+pub mod revoke_certificate;
+pub mod rotate_ca;
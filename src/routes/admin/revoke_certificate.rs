@@ -0,0 +1,106 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::Utc;
+use sea_orm::*;
+use sea_orm::sea_query::Expr;
+use serde::Deserialize;
+
+use crate::cert::revocation::RevocationReason;
+use crate::cert::Types::{CLIENTLEAF, PROXYLEAF};
+use crate::entities::{certificate, client};
+use crate::entities::prelude::{Certificate, Client};
+use crate::util::auth::{authorise_admin, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct RevokeCertificateInput {
+    /// Either the certificate, or the client holding it
+    certificate_id: Option<String>,
+    client_id: Option<String>,
+    reason: Option<RevocationReason>
+}
+
+/// Revokes a client or proxy certificate
+///
+/// A client whose certificate is revoked is deactivated too, as a compromised client's key is
+/// just as compromised as its certificate.
+pub async fn revoke_certificate(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<RevokeCertificateInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    if let Err(err) = authorise_admin(&user) {
+        return err;
+    }
+
+    let certificate_id = match (payload.certificate_id, payload.client_id) {
+        (Some(certificate_id), None) => certificate_id,
+        (None, Some(client_id)) => {
+            let holding_client = Client::find_by_id(client_id)
+                .one(connection)
+                .await
+                .expect("Failed to retrieve client from the database.");
+
+            match holding_client {
+                Some(holding_client) => holding_client.certificate,
+                None => return (StatusCode::NOT_FOUND, "Requested client doesn't exist".to_string())
+            }
+        }
+        _ => return (StatusCode::BAD_REQUEST, "Either a certificate_id or a client_id is required".to_string())
+    };
+
+    let requested_certificate = Certificate::find_by_id(certificate_id.clone())
+        .one(connection)
+        .await
+        .expect("Failed to retrieve certificate from the database.");
+
+    let requested_certificate = match requested_certificate {
+        None => return (StatusCode::NOT_FOUND, "Requested certificate doesn't exist".to_string()),
+        Some(requested_certificate) => requested_certificate
+    };
+
+    // Intermediates are replaced by rotating the CA rather than revoked
+    if requested_certificate.cert_type != CLIENTLEAF.to_string() && requested_certificate.cert_type != PROXYLEAF.to_string() {
+        return (StatusCode::BAD_REQUEST, "Only client and proxy certificates can be revoked".to_string());
+    }
+
+    if requested_certificate.revoked.is_some() {
+        return (StatusCode::CONFLICT, "Certificate has already been revoked".to_string());
+    }
+
+    let reason = payload.reason.unwrap_or(RevocationReason::UNSPECIFIED);
+    let cert_type = requested_certificate.cert_type.clone();
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin revocation transaction!");
+
+    let mut revoked_certificate: certificate::ActiveModel = requested_certificate.into();
+    revoked_certificate.revoked = ActiveValue::Set(Some(Utc::now().naive_utc()));
+    revoked_certificate.revocation_reason = ActiveValue::Set(Some(reason.to_string()));
+
+    if revoked_certificate.update(&txn).await.is_err() {
+        error!("Failed to revoke certificate {}!", certificate_id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    let deactivated_clients = Client::update_many()
+        .col_expr(client::Column::Active, Expr::value(false))
+        .filter(client::Column::Certificate.eq(certificate_id.clone()))
+        .exec(&txn)
+        .await
+        .expect("Failed to deactivate the certificate's client!");
+
+    txn.commit()
+        .await
+        .expect("Failed to commit revocation transaction!");
+
+    warn!("{} cert {} was revoked by {} ({})", cert_type, certificate_id, user.id, reason);
+
+    match deactivated_clients.rows_affected {
+        0 => (StatusCode::OK, format!("Revoked {}", certificate_id)),
+        _ => (StatusCode::OK, format!("Revoked {} and deactivated its client", certificate_id))
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use lapin::Channel;
+use sea_orm::*;
+use serde::Serialize;
+
+use crate::cert::algorithm::SigningKey;
+use crate::cert::rotation::{reissue_retiring_leaves, rotate_ca as rotate, RotationError};
+use crate::util::auth::{authorise_admin, UserFromBearer};
+
+#[derive(Serialize)]
+pub struct RotateCaResponse {
+    root_id: String,
+    /// The new root signed by the old one, served in chains until the old root is retired
+    cross_signed_root_id: String,
+    intermediate_ids: Vec<String>
+}
+
+/// Replaces the root and intermediates, cross-signing the new root with the old one
+///
+/// Leaves are re-issued by the new intermediates in the background, and the old chain stays trusted
+/// until every holder has fetched its re-issued leaf, the leaves it replaced have expired, or the
+/// rotation is finished with `finish_rotation`.
+pub async fn rotate_ca(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    Extension(ref root_rsa_key): Extension<SigningKey>,
+    UserFromBearer(user): UserFromBearer
+) -> Response {
+    let user = user.0;
+
+    if let Err(err) = authorise_admin(&user) {
+        return err.into_response();
+    }
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin rotation transaction!");
+
+    let rotation = match rotate(root_rsa_key, &txn).await {
+        Ok(rotation) => rotation,
+        Err(RotationError::InProgress) => {
+            return (StatusCode::CONFLICT, "The previous rotation hasn't finished yet".to_string()).into_response();
+        }
+        Err(err) => {
+            error!("Failed to rotate the CA: {}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response();
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit rotation transaction!");
+
+    warn!("CA was rotated by {}, new root cert is {}", user.id, rotation.root.id);
+
+    let (connection, amqp_channel) = (connection.clone(), amqp_channel.clone());
+    tokio::spawn(async move {
+        if let Err(err) = reissue_retiring_leaves(&connection, &amqp_channel).await {
+            error!("Failed to re-issue leaves of the retiring chain: {}", err);
+        }
+    });
+
+    (StatusCode::CREATED, Json(RotateCaResponse {
+        root_id: rotation.root.id,
+        cross_signed_root_id: rotation.cross.id,
+        intermediate_ids: rotation.intermediates.into_iter().map(|inter_model| inter_model.id).collect()
+    })).into_response()
+}
@@ -0,0 +1,124 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{Duration, Utc};
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::entities::{client, join_token};
+use crate::entities::prelude::{Client, JoinToken};
+use crate::routes::clients::hash_join_token;
+use crate::util::auth::{authorise_admin, UserFromBearer};
+use crate::util::generate_session_token;
+
+/// How long a join token lasts when no expiry is asked for
+const DEFAULT_EXPIRY_MINUTES: i64 = 60;
+const MAX_EXPIRY_MINUTES: i64 = 24 * 60;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct CreateJoinTokenInput {
+    /// Name the enrolled client is given
+    name: String,
+    #[serde(default = "default_true")]
+    dns: bool,
+    #[serde(default = "default_true")]
+    proxy: bool,
+    /// Minutes until the token expires
+    expires_in: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct CreateJoinTokenIssues {
+    name: Vec<String>,
+    expires_in: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct CreateJoinTokenResponse {
+    id: Option<String>,
+    /// Only ever shown here, the controller keeps a hash of it
+    token: Option<String>,
+    expiry: Option<i64>,
+    issues: Option<CreateJoinTokenIssues>
+}
+
+pub async fn create_join_token(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<CreateJoinTokenInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let mut validation_issues = CreateJoinTokenIssues {
+        name: vec![],
+        expires_in: vec![]
+    };
+
+    if let Err((status, reason)) = authorise_admin(&user) {
+        validation_issues.name.push(reason);
+        return (status, Json(CreateJoinTokenResponse { id: None, token: None, expiry: None, issues: Some(validation_issues) }));
+    }
+
+    let name = payload.name.trim().to_string();
+    if name.is_empty() || name.len() > 64 {
+        validation_issues.name.push("Name must be between 1 and 64 characters long.".to_string());
+    }
+
+    let existing_client = Client::find()
+        .filter(client::Column::Name.eq(name.clone()))
+        .one(connection)
+        .await
+        .expect("Failed to check database.");
+
+    if existing_client.is_some() {
+        validation_issues.name.push("A client with this name already exists.".to_string());
+    }
+
+    let expires_in = payload.expires_in.unwrap_or(DEFAULT_EXPIRY_MINUTES);
+    if !(1..=MAX_EXPIRY_MINUTES).contains(&expires_in) {
+        validation_issues.expires_in.push(format!("Tokens must expire within 1 to {} minutes.", MAX_EXPIRY_MINUTES));
+    }
+
+    if !validation_issues.name.is_empty() || !validation_issues.expires_in.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(CreateJoinTokenResponse { id: None, token: None, expiry: None, issues: Some(validation_issues) }));
+    }
+
+    let token = generate_session_token();
+    let now = Utc::now().naive_utc();
+
+    let new_join_token = join_token::Model {
+        id: Ulid::new().to_string(),
+        token_hash: hash_join_token(&token),
+        name,
+        dns: payload.dns,
+        proxy: payload.proxy,
+        created_by: user.id,
+        created: now,
+        expiry: now + Duration::minutes(expires_in),
+        used: None,
+        used_ip: None,
+        client: None
+    };
+
+    match JoinToken::insert(join_token::ActiveModel::from(new_join_token.clone())).exec(connection).await {
+        Ok(_) => {
+            info!("Join token {} created for client {}", new_join_token.id, new_join_token.name);
+
+            (StatusCode::CREATED, Json(CreateJoinTokenResponse {
+                id: Some(new_join_token.id),
+                token: Some(token),
+                expiry: Some(new_join_token.expiry.timestamp()),
+                issues: None
+            }))
+        }
+        Err(_) => {
+            error!("Failed to create join token for client {}!", new_join_token.name);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(CreateJoinTokenResponse { id: None, token: None, expiry: None, issues: None }))
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::net::SocketAddr;
+
+use axum::{Extension, extract, Json};
+use axum::extract::ConnectInfo;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use picky::x509::{Cert, Csr};
+use sea_orm::*;
+use sea_orm::sea_query::Expr;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::cert::{issue_leaf, leaf_validity, load_root_chain};
+use crate::cert::generate::{LeafOptions, LeafTarget};
+use crate::entities::{client, join_token};
+use crate::entities::prelude::{Client, JoinToken};
+use crate::routes::clients::hash_join_token;
+use crate::routes::status::Health;
+use crate::util::{generate_session_token, hash_password};
+
+#[derive(Deserialize)]
+pub struct EnrollInput {
+    token: String,
+    /// PEM encoded certificate signing request for the client's own key
+    csr: String
+}
+
+#[derive(Serialize)]
+pub struct EnrollResponse {
+    client_id: String,
+    /// Key for calling /rpc without the client certificate, only ever shown here
+    key: String,
+    /// PEM encoded client certificate
+    certificate: String,
+    /// PEM encoded intermediate and root, in that order, with the cross-signed root in between while
+    /// the previous root is retiring
+    chain: Vec<String>
+}
+
+fn internal_error() -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response()
+}
+
+/// Enrolls a new client with a join token, issuing a certificate for the key in its CSR
+pub async fn enroll(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    extract::Json(payload): extract::Json<EnrollInput>
+) -> Response {
+    let requested_token: Option<join_token::Model> = JoinToken::find()
+        .filter(join_token::Column::TokenHash.eq(hash_join_token(&payload.token)))
+        .one(connection)
+        .await
+        .expect("Failed to retrieve join token from the database.");
+
+    let requested_token = match requested_token {
+        None => return (StatusCode::UNAUTHORIZED, "Join token is invalid".to_string()).into_response(),
+        Some(requested_token) => requested_token
+    };
+
+    if requested_token.used.is_some() {
+        warn!("Join token {} was reused from {}", requested_token.id, addr.ip());
+        return (StatusCode::UNAUTHORIZED, "Join token has already been used".to_string()).into_response();
+    }
+
+    if requested_token.expiry < Utc::now().naive_utc() {
+        return (StatusCode::UNAUTHORIZED, "Join token has expired".to_string()).into_response();
+    }
+
+    let csr = match Csr::from_pem_str(&payload.csr) {
+        Ok(csr) if csr.verify().is_ok() => csr,
+        _ => return (StatusCode::BAD_REQUEST, "CSR is malformed or its signature is invalid".to_string()).into_response()
+    };
+
+    let existing_client = Client::find()
+        .filter(client::Column::Name.eq(requested_token.name.clone()))
+        .one(connection)
+        .await
+        .expect("Failed to check database.");
+
+    if existing_client.is_some() {
+        return (StatusCode::CONFLICT, "A client with this name already exists".to_string()).into_response();
+    }
+
+    let root_chain = load_root_chain(connection)
+        .await
+        .expect("Failed to retrieve the root cert from the database.");
+
+    if root_chain.is_empty() {
+        error!("Failed to load the root cert for enrollment!");
+        return internal_error();
+    }
+
+    let client_id = Ulid::new().to_string();
+    let key = generate_session_token();
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin enrollment transaction!");
+
+    // Claiming the token only succeeds once, however many enrollments race for it
+    let claimed = JoinToken::update_many()
+        .col_expr(join_token::Column::Used, Expr::value(Utc::now().naive_utc()))
+        .col_expr(join_token::Column::UsedIp, Expr::value(addr.ip().to_string()))
+        .col_expr(join_token::Column::Client, Expr::value(client_id.clone()))
+        .filter(join_token::Column::Id.eq(requested_token.id.clone()))
+        .filter(join_token::Column::Used.is_null())
+        .exec(&txn)
+        .await
+        .expect("Failed to claim join token!");
+
+    if claimed.rows_affected == 0 {
+        return (StatusCode::UNAUTHORIZED, "Join token has already been used".to_string()).into_response();
+    }
+
+    let (_, subject_key) = csr.into_subject_infos();
+    let leaf_options = LeafOptions {
+        common_name: requested_token.name.clone(),
+        dns_names: Vec::new(),
+        ip_addresses: Vec::new(),
+        validity: leaf_validity(&LeafTarget::CLIENT)
+    };
+
+    let leaf = match issue_leaf(LeafTarget::CLIENT, Some(subject_key), &leaf_options, &txn).await {
+        Ok(leaf) => leaf,
+        Err(err) => {
+            error!("Failed to issue client cert for {}: {}", requested_token.name, err);
+            return internal_error();
+        }
+    };
+
+    let client_insert = Client::insert(client::ActiveModel {
+        id: ActiveValue::Set(client_id.clone()),
+        name: ActiveValue::Set(requested_token.name.clone()),
+        ip: ActiveValue::Set(addr.ip().to_string()),
+        key: ActiveValue::Set(hash_password(key.clone())),
+        active: ActiveValue::Set(true),
+        dns: ActiveValue::Set(requested_token.dns),
+        proxy: ActiveValue::Set(requested_token.proxy),
+        health: ActiveValue::Set(Health::DEAD.to_string()),
+        certificate: ActiveValue::Set(leaf.model.id.clone())
+    })
+        .exec(&txn)
+        .await;
+
+    if client_insert.is_err() {
+        error!("Failed to enroll client {}!", requested_token.name);
+        return internal_error();
+    }
+
+    txn.commit()
+        .await
+        .expect("Failed to commit enrollment transaction!");
+
+    info!("Enrolled client {} ({}) from {} with join token {}", requested_token.name, client_id, addr.ip(), requested_token.id);
+
+    let pem = |cert: &Cert| cert.to_pem().expect("Failed to convert cert into pem!").to_string();
+
+    (StatusCode::CREATED, Json(EnrollResponse {
+        client_id,
+        key,
+        certificate: pem(&leaf.cert),
+        chain: [&leaf.issuer].into_iter().chain(&root_chain).map(pem).collect()
+    })).into_response()
+}
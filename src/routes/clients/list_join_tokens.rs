@@ -0,0 +1,37 @@
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use sea_orm::*;
+use serde::Serialize;
+
+use crate::entities::join_token;
+use crate::entities::prelude::JoinToken;
+use crate::routes::clients::JoinTokenResponse;
+use crate::util::auth::{authorise_admin, UserFromBearer};
+
+#[derive(Serialize)]
+pub struct ListJoinTokensResponse {
+    join_tokens: Vec<JoinTokenResponse>
+}
+
+/// Lists every join token, used or not, as the record of who enrolled which client
+pub async fn list_join_tokens(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+) -> Response {
+    let user = user.0;
+
+    if let Err(err) = authorise_admin(&user) {
+        return err.into_response();
+    }
+
+    let join_tokens: Vec<join_token::Model> = JoinToken::find()
+        .order_by_desc(join_token::Column::Created)
+        .all(connection)
+        .await
+        .expect("Failed to access database");
+
+    (StatusCode::OK, Json(ListJoinTokensResponse {
+        join_tokens: join_tokens.into_iter().map(JoinTokenResponse::from).collect()
+    })).into_response()
+}
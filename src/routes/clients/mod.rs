@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+use crate::entities::join_token;
+
+pub mod create_join_token;
+pub mod list_join_tokens;
+pub mod enroll;
+
+/// Join tokens are looked up by hash so a leaked database doesn't leak usable tokens
+pub fn hash_join_token(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+#[derive(Serialize)]
+pub struct JoinTokenResponse {
+    id: String,
+    name: String,
+    dns: bool,
+    proxy: bool,
+    created_by: String,
+    created: i64,
+    expiry: i64,
+    used: Option<i64>,
+    used_ip: Option<String>,
+    client: Option<String>
+}
+
+impl From<join_token::Model> for JoinTokenResponse {
+    fn from(join_token: join_token::Model) -> Self {
+        JoinTokenResponse {
+            id: join_token.id,
+            name: join_token.name,
+            dns: join_token.dns,
+            proxy: join_token.proxy,
+            created_by: join_token.created_by,
+            created: join_token.created.timestamp(),
+            expiry: join_token.expiry.timestamp(),
+            used: join_token.used.map(|used| used.timestamp()),
+            used_ip: join_token.used_ip,
+            client: join_token.client
+        }
+    }
+}
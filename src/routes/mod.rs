@@ -1,2 +1,9 @@
 pub mod status;
-pub mod users;
\ No newline at end of file
+pub mod users;
+pub mod teams;
+pub mod zones;
+pub mod records;
+pub mod proxies;
+pub mod clients;
+pub mod admin;
+pub mod pki;
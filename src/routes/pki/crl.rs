@@ -0,0 +1,54 @@
+use axum::{Extension, extract};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::generate::LeafTarget;
+use crate::cert::revocation::{build_crl, build_intermediate_crl, RevocationError};
+
+async fn crl(target: LeafTarget, connection: &DatabaseConnection) -> Response {
+    match build_crl(&target, connection).await {
+        Ok(crl) => (StatusCode::OK, [(header::CONTENT_TYPE, "application/pkix-crl")], crl).into_response(),
+        Err(err) => {
+            error!("Failed to build the {} CRL: {}", target, err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response()
+        }
+    }
+}
+
+/// Serves the CRL of client certificates, signed by the current client intermediate
+pub async fn client_crl(
+    Extension(ref connection): Extension<DatabaseConnection>
+) -> Response {
+    crl(LeafTarget::CLIENT, connection).await
+}
+
+/// Serves the CRL of proxy certificates, signed by the current proxy intermediate
+pub async fn proxy_crl(
+    Extension(ref connection): Extension<DatabaseConnection>
+) -> Response {
+    crl(LeafTarget::PROXY, connection).await
+}
+
+#[derive(Deserialize)]
+pub struct IntermediateCrlInput {
+    certificate_id: String
+}
+
+/// Serves the CRL of a specific intermediate, for leaves issued by one that's retiring
+pub async fn intermediate_crl(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    extract::Query(query): extract::Query<IntermediateCrlInput>
+) -> Response {
+    match build_intermediate_crl(&query.certificate_id, connection).await {
+        Ok(crl) => (StatusCode::OK, [(header::CONTENT_TYPE, "application/pkix-crl")], crl).into_response(),
+        Err(RevocationError::MissingIntermediate(_)) => {
+            (StatusCode::NOT_FOUND, "No such intermediate".to_string()).into_response()
+        }
+        Err(err) => {
+            error!("Failed to build the CRL of intermediate {}: {}", query.certificate_id, err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod crl;
+pub mod ocsp;
@@ -0,0 +1,17 @@
+use axum::body::Bytes;
+use axum::Extension;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use sea_orm::*;
+
+use crate::cert::revocation::ocsp_response;
+
+/// Answers OCSP requests POSTed as application/ocsp-request (RFC 6960 appendix A.1)
+pub async fn ocsp(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    body: Bytes
+) -> impl IntoResponse {
+    let response = ocsp_response(&body, connection).await;
+
+    (StatusCode::OK, [(header::CONTENT_TYPE, "application/ocsp-response")], response)
+}
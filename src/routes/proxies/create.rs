@@ -0,0 +1,122 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::cert::acme::SharedAcmeIssuer;
+use crate::cert::IssueError;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::lock_zone;
+use crate::dns::validation::RecordIssues;
+use crate::entities::proxy;
+use crate::routes::proxies::{check_proxy, get_zone_proxies, issue_proxy_certificate, join_upstreams, order_public_certificate, proxied_hostname, ProxyFormResponse};
+use crate::rpc::proxies::{proxy_upserts, publish_proxy_changes};
+use crate::util::auth::{authorise_record, TeamPermissions, UserFromBearer};
+
+fn default_port() -> i32 {
+    443
+}
+
+#[derive(Deserialize)]
+pub struct NewProxyInput {
+    record_id: String,
+    #[serde(default = "default_port")]
+    port: i32,
+    upstreams: Vec<String>
+}
+
+/// Attaches a proxy to an A, AAAA or CNAME record
+///
+/// The proxy starts out with a certificate from our intermediate, which is swapped for a publicly
+/// trusted one in the background when ACME is configured.
+pub async fn create(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    Extension(ref acme_issuer): Extension<SharedAcmeIssuer>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<NewProxyInput>
+) -> impl IntoResponse {
+    let user = user.0;
+    let mut upstreams = payload.upstreams;
+
+    let (requested_record, requested_zone) = match authorise_record(&user.id, &payload.record_id, TeamPermissions::EDITOR, connection).await {
+        Ok(requested) => requested,
+        Err((status, reason)) => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("record_id", reason);
+            return (status, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+    };
+
+    let hostname = match RecordTypes::from_msgpack(&requested_record.value).ok().and_then(|record| proxied_hostname(&record)) {
+        Some(hostname) => hostname,
+        None => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("record_id", "Proxies can only be attached to A, AAAA or CNAME records.".to_string());
+            return (StatusCode::BAD_REQUEST, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+    };
+
+    let proxy_id = Ulid::new().to_string();
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin proxy creation transaction!");
+
+    // Checked under the zone's lock, so concurrent requests can't both claim the same port
+    if lock_zone(&requested_zone, &txn).await.is_err() {
+        error!("Failed to lock zone {} for proxy creation!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+    }
+
+    let existing_proxies = get_zone_proxies(&requested_zone.id, &txn).await;
+    let validation_issues = check_proxy(&hostname, payload.port, &mut upstreams, &existing_proxies, None);
+
+    if !validation_issues.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    let proxy_certificate = match issue_proxy_certificate(&hostname, &txn).await {
+        Ok(proxy_certificate) => proxy_certificate,
+        Err(IssueError::InvalidName(_)) => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("record_id", format!("{} can't be used as a certificate name.", hostname));
+            return (StatusCode::BAD_REQUEST, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+        Err(err) => {
+            error!("Failed to issue a cert for proxy {}: {}", proxy_id, err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+        }
+    };
+
+    let proxy_creation = proxy::ActiveModel {
+        id: ActiveValue::Set(proxy_id.clone()),
+        record: ActiveValue::Set(requested_record.id.clone()),
+        port: ActiveValue::Set(payload.port),
+        active: ActiveValue::Set(true),
+        certificate: ActiveValue::Set(proxy_certificate.id),
+        upstreams: ActiveValue::Set(join_upstreams(&upstreams))
+    }
+        .insert(&txn)
+        .await;
+
+    let created_proxy = match proxy_creation {
+        Ok(created_proxy) => created_proxy,
+        Err(_) => {
+            error!("Failed to create proxy {}!", proxy_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit proxy creation transaction!");
+
+    publish_proxy_changes(amqp_channel, proxy_upserts(&[created_proxy], &requested_record)).await;
+    order_public_certificate(acme_issuer, connection, amqp_channel, proxy_id.clone(), hostname);
+
+    (StatusCode::CREATED, Json(ProxyFormResponse { id: Some(proxy_id), issues: None }))
+}
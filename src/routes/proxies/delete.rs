@@ -0,0 +1,59 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::revocation::RevocationReason;
+use crate::entities::proxy;
+use crate::routes::proxies::retire_certificate;
+use crate::rpc::proxies::{ProxyChange, publish_proxy_changes};
+use crate::util::auth::{authorise_proxy, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct DeleteProxyInput {
+    proxy_id: String
+}
+
+/// Deletes a proxy, retiring its certificate along with it
+pub async fn delete(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<DeleteProxyInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let (requested_proxy, _, _) = match authorise_proxy(&user.id, &payload.proxy_id, TeamPermissions::EDITOR, connection).await {
+        Ok(requested) => requested,
+        Err(err) => return err
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin proxy deletion transaction!");
+
+    let proxy_delete = proxy::Entity::delete_by_id(requested_proxy.id.clone())
+        .exec(&txn)
+        .await
+        .expect("Failed to delete proxy!");
+
+    if proxy_delete.rows_affected.eq(&0) {
+        error!("Could not delete proxy {}!", requested_proxy.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    if retire_certificate(&requested_proxy.certificate, RevocationReason::CESSATIONOFOPERATION, &txn).await.is_err() {
+        error!("Could not retire the cert of proxy {}!", requested_proxy.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    txn.commit()
+        .await
+        .expect("Failed to commit proxy deletion transaction!");
+
+    publish_proxy_changes(amqp_channel, vec![ProxyChange::ProxyDeleted { proxy_id: requested_proxy.id }]).await;
+
+    (StatusCode::OK, "Deleted proxy".to_string())
+}
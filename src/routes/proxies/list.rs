@@ -0,0 +1,39 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::routes::proxies::{get_zone_proxies, ProxyResponse};
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ListProxiesInput {
+    zone_id: String
+}
+
+#[derive(Serialize)]
+pub struct ListProxiesResponse {
+    proxies: Vec<ProxyResponse>
+}
+
+pub async fn list(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<ListProxiesInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &query.zone_id, TeamPermissions::VIEWER, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    let proxies = get_zone_proxies(&requested_zone.id, connection)
+        .await
+        .into_iter()
+        .map(|(model, _, hostname)| ProxyResponse::new(model, hostname))
+        .collect();
+
+    (StatusCode::OK, Json(ListProxiesResponse { proxies })).into_response()
+}
@@ -0,0 +1,448 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use chrono::Utc;
+use lapin::Channel;
+use picky::x509::Cert;
+use sea_orm::*;
+use sea_orm::sea_query::Expr;
+use serde::Serialize;
+
+use crate::cert::{IssueError, issue_leaf, leaf_validity};
+use crate::cert::acme::{AcmeError, AcmeIssuer, connect_account, issue_acme_leaf, SharedAcmeIssuer};
+use crate::cert::generate::{dns_names, LeafOptions, LeafTarget};
+use crate::cert::revocation::RevocationReason;
+use crate::dns::records::RecordTypes;
+use crate::dns::validation::{RecordIssues, validate_name};
+use crate::entities::{certificate, proxy, record};
+use crate::entities::prelude::{Certificate, Proxy, Record};
+use crate::rpc::proxies::{get_record_proxies, proxy_upserts, publish_proxy_changes};
+
+pub mod create;
+pub mod update;
+pub mod list;
+pub mod delete;
+
+/// Most upstreams a single proxy can forward to
+const MAX_UPSTREAMS: usize = 16;
+
+#[derive(Serialize)]
+pub struct ProxyFormResponse {
+    pub id: Option<String>,
+    pub issues: Option<RecordIssues>
+}
+
+#[derive(Serialize)]
+pub struct ProxyResponse {
+    id: String,
+    record: String,
+    hostname: String,
+    port: i32,
+    upstreams: Vec<String>,
+    active: bool,
+    certificate: String
+}
+
+impl ProxyResponse {
+    pub fn new(model: proxy::Model, hostname: String) -> Self {
+        ProxyResponse {
+            id: model.id,
+            record: model.record,
+            hostname,
+            port: model.port,
+            upstreams: split_upstreams(&model.upstreams),
+            active: model.active,
+            certificate: model.certificate
+        }
+    }
+}
+
+/// The name a record's proxies serve, only A, AAAA and CNAME records can have proxies
+pub fn proxied_hostname(record: &RecordTypes) -> Option<String> {
+    match record {
+        RecordTypes::A { hostname, .. } | RecordTypes::AAAA { hostname, .. } | RecordTypes::CNAME { hostname, .. } => Some(hostname.clone()),
+        _ => None
+    }
+}
+
+/// Upstreams are stored comma separated, which validated upstreams can't contain
+pub fn split_upstreams(upstreams: &str) -> Vec<String> {
+    upstreams.split(',')
+        .filter(|upstream| !upstream.is_empty())
+        .map(|upstream| upstream.to_string())
+        .collect()
+}
+
+pub fn join_upstreams(upstreams: &[String]) -> String {
+    upstreams.join(",")
+}
+
+/// Checks an upstream is an address or hostname followed by a port, with IPv6 addresses in brackets
+fn validate_upstream(upstream: &str) -> Vec<String> {
+    if let Ok(address) = upstream.parse::<SocketAddr>() {
+        return match address.port() {
+            0 => vec![format!("Upstream {} cannot use port 0.", upstream)],
+            _ => vec![]
+        };
+    }
+
+    let (host, port) = match upstream.rsplit_once(':') {
+        Some(host_and_port) if !upstream.ends_with(']') => host_and_port,
+        _ => return vec![format!("Upstream {} must be given as host:port.", upstream)]
+    };
+
+    let mut issues: Vec<String> = vec![];
+
+    if !matches!(port.parse::<u16>(), Ok(port) if port > 0) {
+        issues.push(format!("Upstream {} has an invalid port.", upstream));
+    }
+
+    let bracketed = host.strip_prefix('[').and_then(|host| host.strip_suffix(']'));
+
+    if host.parse::<Ipv4Addr>().is_ok() || matches!(bracketed, Some(address) if address.parse::<Ipv6Addr>().is_ok()) {
+        return issues;
+    }
+
+    if bracketed.is_some() {
+        issues.push(format!("Upstream {} has an invalid IPv6 address.", upstream));
+    } else if host.contains(':') {
+        issues.push(format!("Upstream {} must put its IPv6 address in brackets.", upstream));
+    } else if host.contains('*') {
+        issues.push(format!("Upstream {} cannot contain a wildcard.", upstream));
+    } else {
+        issues.extend(validate_name(host, false).into_iter().map(|issue| format!("Upstream {}: {}", upstream, issue)));
+    }
+
+    issues
+}
+
+/// Normalises a proxy's upstreams and checks them and its port against the other proxies in the zone
+///
+/// `replacing` is the id of the proxy being updated, so it isn't compared against itself.
+pub fn check_proxy(
+    hostname: &str,
+    port: i32,
+    upstreams: &mut [String],
+    existing: &[(proxy::Model, record::Model, String)],
+    replacing: Option<&str>
+) -> RecordIssues {
+    let mut issues = RecordIssues::default();
+
+    if !(1..=65535).contains(&port) {
+        issues.push("port", "Port must be between 1 and 65535.".to_string());
+    } else if existing.iter().any(|(other, _, other_hostname)| Some(other.id.as_str()) != replacing && other_hostname == hostname && other.port == port) {
+        issues.push("port", format!("Another proxy already serves {} on port {}.", hostname, port));
+    }
+
+    for upstream in upstreams.iter_mut() {
+        *upstream = upstream.trim().to_lowercase();
+    }
+
+    if upstreams.is_empty() {
+        issues.push("upstreams", "At least one upstream is required.".to_string());
+    } else if upstreams.len() > MAX_UPSTREAMS {
+        issues.push("upstreams", format!("A proxy cannot have more than {} upstreams.", MAX_UPSTREAMS));
+    }
+
+    for (index, upstream) in upstreams.iter().enumerate() {
+        issues.extend("upstreams", validate_upstream(upstream));
+
+        if upstreams[..index].contains(upstream) {
+            issues.push("upstreams", format!("Upstream {} is listed more than once.", upstream));
+        }
+    }
+
+    issues
+}
+
+/// Gets every proxy in a zone alongside its record and the hostname it serves
+pub async fn get_zone_proxies<C: ConnectionTrait>(zone_id: &str, connection: &C) -> Vec<(proxy::Model, record::Model, String)> {
+    let proxies = Proxy::find()
+        .find_also_related(Record)
+        .filter(record::Column::Zone.eq(zone_id))
+        .all(connection)
+        .await
+        .expect("Failed to retrieve proxies from the database.");
+
+    proxies.into_iter()
+        .filter_map(|(model, record_model)| {
+            let record_model = record_model?;
+
+            match RecordTypes::from_msgpack(&record_model.value).ok().and_then(|record| proxied_hostname(&record)) {
+                Some(hostname) => Some((model, record_model, hostname)),
+                None => {
+                    error!("Proxy {} is attached to record {}, which can't be proxied!", model.id, record_model.id);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Marks a certificate revoked once no proxy uses it, which also stops it being renewed
+pub async fn retire_certificate<C: ConnectionTrait>(certificate_id: &str, reason: RevocationReason, connection: &C) -> Result<(), DbErr> {
+    Certificate::update_many()
+        .col_expr(certificate::Column::Revoked, Expr::value(Utc::now().naive_utc()))
+        .col_expr(certificate::Column::RevocationReason, Expr::value(reason.to_string()))
+        .filter(certificate::Column::Id.eq(certificate_id))
+        .filter(certificate::Column::Revoked.is_null())
+        .exec(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Issues a certificate for a proxy from our intermediate, which it keeps unless ACME is configured
+pub async fn issue_proxy_certificate<C: ConnectionTrait>(hostname: &str, connection: &C) -> Result<certificate::Model, IssueError> {
+    let options = LeafOptions {
+        common_name: hostname.to_string(),
+        dns_names: vec![hostname.to_string()],
+        ip_addresses: vec![],
+        validity: leaf_validity(&LeafTarget::PROXY)
+    };
+
+    Ok(issue_leaf(LeafTarget::PROXY, None, &options, connection).await?.model)
+}
+
+/// Gives a proxy a new certificate when its current one doesn't cover its hostname, such as after
+/// its record was renamed, returning the proxy and whether it was reissued
+pub async fn reissue_if_renamed<C: ConnectionTrait>(proxy_model: proxy::Model, hostname: &str, connection: &C) -> Result<(proxy::Model, bool), IssueError> {
+    let current_model = Certificate::find_by_id(proxy_model.certificate.clone())
+        .one(connection)
+        .await?;
+
+    let current_cert = current_model.and_then(|current_model| Cert::from_der(&current_model.data).ok());
+    let covered = matches!(current_cert, Some(current_cert) if dns_names(&current_cert).iter().any(|dns_name| dns_name == hostname));
+
+    if covered {
+        return Ok((proxy_model, false));
+    }
+
+    let reissued_model = issue_proxy_certificate(hostname, connection).await?;
+    let previous_id = proxy_model.certificate.clone();
+
+    let mut changed_proxy: proxy::ActiveModel = proxy_model.into();
+    changed_proxy.certificate = ActiveValue::Set(reissued_model.id);
+    let changed_proxy = changed_proxy.update(connection).await?;
+
+    retire_certificate(&previous_id, RevocationReason::SUPERSEDED, connection).await?;
+
+    Ok((changed_proxy, true))
+}
+
+/// Orders a publicly trusted certificate for a proxy in the background when ACME is configured,
+/// swapping it in for the certificate the proxy started out with once it's issued
+pub fn order_public_certificate(acme_issuer: &SharedAcmeIssuer, connection: &DatabaseConnection, amqp_channel: &Channel, proxy_id: String, hostname: String) {
+    let acme_issuer = match acme_issuer {
+        Some(acme_issuer) => acme_issuer.clone(),
+        None => return
+    };
+
+    let connection = connection.clone();
+    let amqp_channel = amqp_channel.clone();
+
+    tokio::spawn(async move {
+        if let Err(err) = swap_in_public_certificate(&acme_issuer, &connection, &amqp_channel, &proxy_id, &hostname).await {
+            error!("Failed to order an ACME cert for proxy {}: {}", proxy_id, err);
+        }
+    });
+}
+
+async fn swap_in_public_certificate(acme_issuer: &AcmeIssuer, connection: &DatabaseConnection, amqp_channel: &Channel, proxy_id: &str, hostname: &str) -> Result<(), AcmeError> {
+    let client = connect_account(&acme_issuer.settings, connection).await?;
+    let acme_model = issue_acme_leaf(&client, acme_issuer.solver.as_ref(), &[hostname.to_string()], connection).await?;
+
+    let txn = connection.begin().await?;
+
+    let ordered_for = Proxy::find_by_id(proxy_id.to_string())
+        .find_also_related(Record)
+        .one(&txn)
+        .await?;
+
+    // The proxy may have been deleted, or its record renamed, while the order was in progress
+    let (proxy_model, record_model) = match ordered_for {
+        Some((proxy_model, Some(record_model)))
+            if RecordTypes::from_msgpack(&record_model.value).ok().and_then(|record| proxied_hostname(&record)).as_deref() == Some(hostname) =>
+            (proxy_model, record_model),
+        _ => {
+            retire_certificate(&acme_model.id, RevocationReason::CESSATIONOFOPERATION, &txn).await?;
+            txn.commit().await?;
+
+            info!("Proxy {} changed while ACME cert {} was ordered for it, so the cert was retired", proxy_id, acme_model.id);
+            return Ok(());
+        }
+    };
+
+    let previous_id = proxy_model.certificate.clone();
+
+    let mut changed_proxy: proxy::ActiveModel = proxy_model.into();
+    changed_proxy.certificate = ActiveValue::Set(acme_model.id.clone());
+    let changed_proxy = changed_proxy.update(&txn).await?;
+
+    retire_certificate(&previous_id, RevocationReason::SUPERSEDED, &txn).await?;
+
+    txn.commit().await?;
+
+    info!("Proxy {} now uses ACME cert {}", proxy_id, acme_model.id);
+
+    publish_proxy_changes(amqp_channel, proxy_upserts(&[changed_proxy], &record_model)).await;
+
+    Ok(())
+}
+
+/// Carries a record change through to its proxies, reissuing their certificates if it was renamed and
+/// pushing them to clients, to be called once the record change has committed
+pub async fn sync_record_proxies(record_model: &record::Model, connection: &DatabaseConnection, amqp_channel: &Channel, acme_issuer: &SharedAcmeIssuer) {
+    let hostname = match RecordTypes::from_msgpack(&record_model.value).ok().and_then(|record| proxied_hostname(&record)) {
+        Some(hostname) => hostname,
+        None => return
+    };
+
+    let synced = async {
+        let txn = connection.begin().await?;
+        let mut synced = vec![];
+        let mut reissued = vec![];
+
+        for proxy_model in get_record_proxies(&record_model.id, &txn).await? {
+            let (proxy_model, renamed) = reissue_if_renamed(proxy_model, &hostname, &txn).await?;
+
+            if renamed {
+                reissued.push(proxy_model.id.clone());
+            }
+
+            synced.push(proxy_model);
+        }
+
+        txn.commit().await?;
+
+        Ok::<(Vec<proxy::Model>, Vec<String>), IssueError>((synced, reissued))
+    };
+
+    let (synced, reissued) = match synced.await {
+        Ok(synced) => synced,
+        Err(err) => {
+            error!("Failed to sync the proxies of record {}: {}", record_model.id, err);
+            return;
+        }
+    };
+
+    publish_proxy_changes(amqp_channel, proxy_upserts(&synced, record_model)).await;
+
+    for proxy_id in reissued {
+        order_public_certificate(acme_issuer, connection, amqp_channel, proxy_id, hostname.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_issues(issues: &RecordIssues, field: &str) -> usize {
+        serde_json::to_value(issues).unwrap()[field].as_array().map_or(0, |issues| issues.len())
+    }
+
+    fn existing_proxy(id: &str, hostname: &str, port: i32) -> (proxy::Model, record::Model, String) {
+        let proxy_model = proxy::Model {
+            id: id.to_string(),
+            record: format!("{}-record", id),
+            port,
+            active: true,
+            certificate: format!("{}-certificate", id),
+            upstreams: "10.0.0.1:80".to_string()
+        };
+        let record_model = record::Model {
+            id: format!("{}-record", id),
+            zone: "zone".to_string(),
+            value: vec![],
+            active: true
+        };
+
+        (proxy_model, record_model, hostname.to_string())
+    }
+
+    fn check(port: i32, upstreams: &[&str], existing: &[(proxy::Model, record::Model, String)]) -> RecordIssues {
+        let mut upstreams: Vec<String> = upstreams.iter().map(|upstream| upstream.to_string()).collect();
+        check_proxy("www.example.com", port, &mut upstreams, existing, Some("updated"))
+    }
+
+    #[test]
+    fn accepts_upstreams() {
+        for upstream in ["10.0.0.1:8080", "[2001:db8::1]:443", "[::1]:80", "backend.example.com:80", "backend:65535"] {
+            assert!(validate_upstream(upstream).is_empty(), "{} was rejected", upstream);
+        }
+    }
+
+    #[test]
+    fn requires_brackets_around_ipv6() {
+        assert_eq!(validate_upstream("2001:db8::1:443"), vec!["Upstream 2001:db8::1:443 must put its IPv6 address in brackets."]);
+        assert_eq!(validate_upstream("[2001:db8::zz]:443"), vec!["Upstream [2001:db8::zz]:443 has an invalid IPv6 address."]);
+    }
+
+    #[test]
+    fn requires_a_port() {
+        assert_eq!(validate_upstream("backend.example.com"), vec!["Upstream backend.example.com must be given as host:port."]);
+        assert_eq!(validate_upstream("[2001:db8::1]"), vec!["Upstream [2001:db8::1] must be given as host:port."]);
+        assert_eq!(validate_upstream("10.0.0.1:"), vec!["Upstream 10.0.0.1: has an invalid port."]);
+        assert_eq!(validate_upstream("10.0.0.1:65536"), vec!["Upstream 10.0.0.1:65536 has an invalid port."]);
+    }
+
+    #[test]
+    fn rejects_port_zero() {
+        assert_eq!(validate_upstream("10.0.0.1:0"), vec!["Upstream 10.0.0.1:0 cannot use port 0."]);
+        assert_eq!(validate_upstream("[2001:db8::1]:0"), vec!["Upstream [2001:db8::1]:0 cannot use port 0."]);
+        assert_eq!(validate_upstream("backend.example.com:0"), vec!["Upstream backend.example.com:0 has an invalid port."]);
+    }
+
+    #[test]
+    fn rejects_wildcards_and_invalid_names() {
+        assert_eq!(validate_upstream("*.example.com:80"), vec!["Upstream *.example.com:80 cannot contain a wildcard."]);
+        assert_eq!(validate_upstream("back_end.example.com:80").len(), 1);
+        assert_eq!(validate_upstream(":80").len(), 1);
+    }
+
+    #[test]
+    fn normalises_upstreams() {
+        let mut upstreams = vec![" Backend.Example.com:80 ".to_string(), "[2001:DB8::1]:443".to_string()];
+
+        assert!(check_proxy("www.example.com", 443, &mut upstreams, &[], None).is_empty());
+        assert_eq!(upstreams, vec!["backend.example.com:80", "[2001:db8::1]:443"]);
+    }
+
+    #[test]
+    fn rejects_duplicate_upstreams() {
+        let issues = check(443, &["backend.example.com:80", "BACKEND.example.com:80", "10.0.0.1:80"], &[]);
+
+        assert_eq!(field_issues(&issues, "upstreams"), 1);
+        assert_eq!(field_issues(&issues, "port"), 0);
+    }
+
+    #[test]
+    fn checks_the_upstream_count() {
+        assert_eq!(field_issues(&check(443, &[], &[]), "upstreams"), 1);
+
+        let upstreams: Vec<String> = (1..=MAX_UPSTREAMS + 1).map(|port| format!("10.0.0.1:{}", port)).collect();
+        let upstreams: Vec<&str> = upstreams.iter().map(|upstream| upstream.as_str()).collect();
+
+        assert_eq!(field_issues(&check(443, &upstreams, &[]), "upstreams"), 1);
+    }
+
+    #[test]
+    fn checks_the_port() {
+        for port in [0, -1, 65536] {
+            assert_eq!(field_issues(&check(port, &["10.0.0.1:80"], &[]), "port"), 1, "{} was accepted", port);
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_proxies() {
+        let existing = vec![
+            existing_proxy("other", "www.example.com", 443),
+            existing_proxy("updated", "www.example.com", 8443),
+            existing_proxy("elsewhere", "api.example.com", 8080)
+        ];
+
+        assert_eq!(field_issues(&check(443, &["10.0.0.1:80"], &existing), "port"), 1);
+        // The proxy being updated and proxies of other names don't conflict
+        assert!(check(8443, &["10.0.0.1:80"], &existing).is_empty());
+        assert!(check(8080, &["10.0.0.1:80"], &existing).is_empty());
+    }
+}
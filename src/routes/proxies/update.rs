@@ -0,0 +1,109 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::acme::SharedAcmeIssuer;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::lock_zone;
+use crate::dns::validation::RecordIssues;
+use crate::entities::proxy;
+use crate::routes::proxies::{check_proxy, get_zone_proxies, join_upstreams, order_public_certificate, proxied_hostname, ProxyFormResponse, reissue_if_renamed, split_upstreams};
+use crate::rpc::proxies::{proxy_upserts, publish_proxy_changes};
+use crate::util::auth::{authorise_proxy, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct UpdateProxyInput {
+    proxy_id: String,
+    /// Each left as it is when missing
+    port: Option<i32>,
+    upstreams: Option<Vec<String>>,
+    active: Option<bool>
+}
+
+pub async fn update(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    Extension(ref acme_issuer): Extension<SharedAcmeIssuer>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<UpdateProxyInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let (requested_proxy, requested_record, requested_zone) = match authorise_proxy(&user.id, &payload.proxy_id, TeamPermissions::EDITOR, connection).await {
+        Ok(requested) => requested,
+        Err((status, reason)) => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("proxy_id", reason);
+            return (status, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+    };
+
+    let hostname = match RecordTypes::from_msgpack(&requested_record.value).ok().and_then(|record| proxied_hostname(&record)) {
+        Some(hostname) => hostname,
+        None => {
+            error!("Proxy {} is attached to record {}, which can't be proxied!", requested_proxy.id, requested_record.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+        }
+    };
+
+    let port = payload.port.unwrap_or(requested_proxy.port);
+    let mut upstreams = payload.upstreams.unwrap_or_else(|| split_upstreams(&requested_proxy.upstreams));
+
+    let proxy_id = requested_proxy.id.clone();
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin proxy update transaction!");
+
+    // Checked under the zone's lock, so concurrent requests can't both claim the same port
+    if lock_zone(&requested_zone, &txn).await.is_err() {
+        error!("Failed to lock zone {} for proxy update!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+    }
+
+    let existing_proxies = get_zone_proxies(&requested_zone.id, &txn).await;
+    let validation_issues = check_proxy(&hostname, port, &mut upstreams, &existing_proxies, Some(&requested_proxy.id));
+
+    if !validation_issues.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(ProxyFormResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    // A proxy that missed its record being renamed catches up here
+    let (requested_proxy, reissued) = match reissue_if_renamed(requested_proxy, &hostname, &txn).await {
+        Ok(reissued) => reissued,
+        Err(err) => {
+            error!("Failed to reissue the cert of proxy {}: {}", proxy_id, err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+        }
+    };
+
+    let mut changed_proxy: proxy::ActiveModel = requested_proxy.into();
+    changed_proxy.port = ActiveValue::Set(port);
+    changed_proxy.upstreams = ActiveValue::Set(join_upstreams(&upstreams));
+    if let Some(active) = payload.active {
+        changed_proxy.active = ActiveValue::Set(active);
+    }
+
+    let changed_proxy = match changed_proxy.update(&txn).await {
+        Ok(changed_proxy) => changed_proxy,
+        Err(_) => {
+            error!("Failed to update proxy {}!", proxy_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ProxyFormResponse { id: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit proxy update transaction!");
+
+    publish_proxy_changes(amqp_channel, proxy_upserts(&[changed_proxy], &requested_record)).await;
+
+    if reissued {
+        order_public_certificate(acme_issuer, connection, amqp_channel, proxy_id.clone(), hostname);
+    }
+
+    (StatusCode::OK, Json(ProxyFormResponse { id: Some(proxy_id), issues: None }))
+}
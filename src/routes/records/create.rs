@@ -0,0 +1,93 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::{bump_serial, lock_zone};
+use crate::dns::validation::RecordIssues;
+use crate::entities::record;
+use crate::entities::prelude::Record;
+use crate::rpc::changes::{Change, publish_change, record_changes, SyncedRecord};
+use crate::routes::records::{check_record, get_zone_records, RecordFormResponse};
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct NewRecordInput {
+    zone_id: String,
+    record: RecordTypes
+}
+
+pub async fn create(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<NewRecordInput>
+) -> impl IntoResponse {
+    let user = user.0;
+    let mut new_record = payload.record;
+
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::EDITOR, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err((status, reason)) => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("zone_id", reason);
+            return (status, Json(RecordFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin record creation transaction!");
+
+    // Checked under the zone's lock, so a concurrent change can't slip a conflicting record in
+    if lock_zone(&requested_zone, &txn).await.is_err() {
+        error!("Failed to lock zone {} for record creation!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+    }
+
+    let existing_records = get_zone_records(&requested_zone.id, &txn).await;
+    let validation_issues = check_record(&mut new_record, &requested_zone, &existing_records, None);
+
+    if !validation_issues.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(RecordFormResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    let record_id = Ulid::new().to_string();
+
+    let record_creation = Record::insert(record::ActiveModel {
+        id: ActiveValue::Set(record_id.clone()),
+        zone: ActiveValue::Set(requested_zone.id.clone()),
+        value: ActiveValue::Set(new_record.to_msgpack().expect("Failed to encode record!")),
+        active: ActiveValue::Set(true)
+    })
+        .exec(&txn)
+        .await;
+
+    if record_creation.is_err() || bump_serial(&requested_zone, &txn).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+    }
+
+    let created = Change::RecordUpserted {
+        record: SyncedRecord { id: record_id.clone(), active: true, record: new_record }
+    };
+
+    let change_event = match record_changes(&requested_zone, vec![created], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Failed to record the creation of record {}!", record_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit record creation transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    (StatusCode::CREATED, Json(RecordFormResponse { id: Some(record_id), issues: None }))
+}
@@ -0,0 +1,93 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::revocation::RevocationReason;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::bump_serial;
+use crate::entities::record;
+use crate::routes::proxies::retire_certificate;
+use crate::rpc::changes::{Change, publish_change, record_changes};
+use crate::rpc::proxies::{get_record_proxies, ProxyChange, publish_proxy_changes};
+use crate::util::auth::{authorise_record, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct DeleteRecordInput {
+    record_id: String
+}
+
+pub async fn delete(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<DeleteRecordInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let (requested_record, requested_zone) = match authorise_record(&user.id, &payload.record_id, TeamPermissions::EDITOR, connection).await {
+        Ok(requested) => requested,
+        Err(err) => return err
+    };
+
+    if let Ok(RecordTypes::SOA { .. }) = RecordTypes::from_msgpack(&requested_record.value) {
+        return (StatusCode::BAD_REQUEST, "The zone's SOA record cannot be deleted".to_string());
+    }
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin record deletion transaction!");
+
+    // The record's proxies are deleted with it, and their certificates retired
+    let attached_proxies = get_record_proxies(&requested_record.id, &txn)
+        .await
+        .expect("Failed to retrieve proxies from the database.");
+
+    let record_delete = record::Entity::delete_by_id(requested_record.id.clone())
+        .exec(&txn)
+        .await
+        .expect("Failed to delete record!");
+
+    if record_delete.rows_affected.eq(&0) {
+        error!("Could not delete record {}!", requested_record.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    for attached_proxy in &attached_proxies {
+        if retire_certificate(&attached_proxy.certificate, RevocationReason::CESSATIONOFOPERATION, &txn).await.is_err() {
+            error!("Could not retire the cert of proxy {}!", attached_proxy.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+        }
+    }
+
+    if bump_serial(&requested_zone, &txn).await.is_err() {
+        error!("Could not bump the serial of zone {}!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    let deleted = Change::RecordDeleted { record_id: requested_record.id.clone() };
+
+    let change_event = match record_changes(&requested_zone, vec![deleted], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Could not record the deletion of record {}!", requested_record.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit record deletion transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    let deleted_proxies = attached_proxies.into_iter()
+        .map(|attached_proxy| ProxyChange::ProxyDeleted { proxy_id: attached_proxy.id })
+        .collect();
+
+    publish_proxy_changes(amqp_channel, deleted_proxies).await;
+
+    (StatusCode::OK, "Deleted record".to_string())
+}
@@ -0,0 +1,39 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::routes::records::{get_zone_records, RecordResponse};
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ListRecordsInput {
+    zone_id: String
+}
+
+#[derive(Serialize)]
+pub struct ListRecordsResponse {
+    records: Vec<RecordResponse>
+}
+
+pub async fn list(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<ListRecordsInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &query.zone_id, TeamPermissions::VIEWER, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    let records = get_zone_records(&requested_zone.id, connection)
+        .await
+        .into_iter()
+        .map(|(model, record)| RecordResponse::new(model, record))
+        .collect();
+
+    (StatusCode::OK, Json(ListRecordsResponse { records })).into_response()
+}
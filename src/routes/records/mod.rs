@@ -0,0 +1,79 @@
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter};
+use serde::Serialize;
+
+use crate::dns::records::RecordTypes;
+use crate::dns::validation::{normalise_record, RecordIssues, validate_record, validate_zone_conflicts};
+use crate::entities::{record, zone};
+use crate::entities::prelude::Record;
+
+pub mod create;
+pub mod update;
+pub mod list;
+pub mod delete;
+
+#[derive(Serialize)]
+pub struct RecordFormResponse {
+    pub id: Option<String>,
+    pub issues: Option<RecordIssues>
+}
+
+#[derive(Serialize)]
+pub struct RecordResponse {
+    id: String,
+    zone: String,
+    active: bool,
+    record: RecordTypes
+}
+
+impl RecordResponse {
+    pub fn new(model: record::Model, record: RecordTypes) -> Self {
+        RecordResponse {
+            id: model.id,
+            zone: model.zone,
+            active: model.active,
+            record
+        }
+    }
+}
+
+/// Gets every record in a zone alongside its decoded value
+pub async fn get_zone_records<C: ConnectionTrait>(zone_id: &str, connection: &C) -> Vec<(record::Model, RecordTypes)> {
+    let records: Vec<record::Model> = Record::find()
+        .filter(record::Column::Zone.eq(zone_id))
+        .all(connection)
+        .await
+        .expect("Failed to retrieve records from the database.");
+
+    records.into_iter()
+        .filter_map(|model| {
+            match RecordTypes::from_msgpack(&model.value) {
+                Ok(decoded) => Some((model, decoded)),
+                Err(_) => {
+                    error!("Record {} in zone {} could not be decoded!", model.id, model.zone);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Normalises a record and checks it against its zone and the zone's other records
+///
+/// `replacing` is the id of the record being updated, so it isn't compared against itself.
+pub fn check_record(
+    record: &mut RecordTypes,
+    zone: &zone::Model,
+    existing: &[(record::Model, RecordTypes)],
+    replacing: Option<&str>
+) -> RecordIssues {
+    normalise_record(record, &zone.origin);
+
+    let existing: Vec<(String, RecordTypes)> = existing.iter()
+        .map(|(model, other)| (model.id.clone(), other.clone()))
+        .collect();
+
+    let mut issues = validate_record(record, &zone.origin);
+    issues.merge(validate_zone_conflicts(record, &zone.origin, &existing, replacing));
+
+    issues
+}
@@ -0,0 +1,130 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::acme::SharedAcmeIssuer;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::{bump_serial, lock_zone};
+use crate::dns::validation::RecordIssues;
+use crate::entities::record;
+use crate::rpc::changes::{Change, publish_change, record_changes, SyncedRecord};
+use crate::rpc::proxies::get_record_proxies;
+use crate::routes::proxies::{proxied_hostname, sync_record_proxies};
+use crate::routes::records::{check_record, get_zone_records, RecordFormResponse};
+use crate::util::auth::{authorise_record, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct UpdateRecordInput {
+    record_id: String,
+    record: RecordTypes,
+    active: Option<bool>
+}
+
+pub async fn update(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    Extension(ref acme_issuer): Extension<SharedAcmeIssuer>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<UpdateRecordInput>
+) -> impl IntoResponse {
+    let user = user.0;
+    let mut updated_record = payload.record;
+
+    let (requested_record, requested_zone) = match authorise_record(&user.id, &payload.record_id, TeamPermissions::EDITOR, connection).await {
+        Ok(requested) => requested,
+        Err((status, reason)) => {
+            let mut validation_issues = RecordIssues::default();
+            validation_issues.push("record_id", reason);
+            return (status, Json(RecordFormResponse { id: None, issues: Some(validation_issues) }));
+        }
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin record update transaction!");
+
+    // Checked under the zone's lock, so a concurrent change can't slip a conflicting record in
+    if lock_zone(&requested_zone, &txn).await.is_err() {
+        error!("Failed to lock zone {} for record update!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+    }
+
+    let existing_records = get_zone_records(&requested_zone.id, &txn).await;
+
+    // The zone's SOA can be edited, but it can't be replaced and its serial is managed for it
+    let existing_record = existing_records.iter()
+        .find(|(model, _)| model.id == requested_record.id)
+        .map(|(_, existing_record)| existing_record.clone());
+
+    if let Some(RecordTypes::SOA { serial: existing_serial, .. }) = existing_record {
+        let mut soa_issues = RecordIssues::default();
+
+        match &mut updated_record {
+            RecordTypes::SOA { serial, .. } => *serial = existing_serial,
+            _ => soa_issues.push("value", "The zone's SOA record cannot be replaced with another type.".to_string())
+        }
+
+        if payload.active == Some(false) {
+            soa_issues.push("active", "The zone's SOA record cannot be deactivated.".to_string());
+        }
+
+        if !soa_issues.is_empty() {
+            return (StatusCode::BAD_REQUEST, Json(RecordFormResponse { id: None, issues: Some(soa_issues) }));
+        }
+    }
+
+    let mut validation_issues = check_record(&mut updated_record, &requested_zone, &existing_records, Some(&requested_record.id));
+
+    if proxied_hostname(&updated_record).is_none() {
+        let attached_proxies = get_record_proxies(&requested_record.id, &txn)
+            .await
+            .expect("Failed to retrieve proxies from the database.");
+
+        if !attached_proxies.is_empty() {
+            validation_issues.push("value", "Proxies are attached to this record, so it must stay an A, AAAA or CNAME record.".to_string());
+        }
+    }
+
+    if !validation_issues.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(RecordFormResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    let record_id = requested_record.id.clone();
+    let mut changed_record: record::ActiveModel = requested_record.into();
+    changed_record.value = ActiveValue::Set(updated_record.to_msgpack().expect("Failed to encode record!"));
+    if let Some(active) = payload.active {
+        changed_record.active = ActiveValue::Set(active);
+    }
+
+    let changed_record = match changed_record.update(&txn).await {
+        Ok(changed_record) if bump_serial(&requested_zone, &txn).await.is_ok() => changed_record,
+        _ => {
+            error!("Failed to update record {}!", record_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+        }
+    };
+
+    let updated = Change::RecordUpserted {
+        record: SyncedRecord { id: record_id.clone(), active: changed_record.active, record: updated_record }
+    };
+
+    let change_event = match record_changes(&requested_zone, vec![updated], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Failed to record the update of record {}!", record_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(RecordFormResponse { id: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit record update transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+    sync_record_proxies(&changed_record, connection, amqp_channel, acme_issuer).await;
+
+    (StatusCode::OK, Json(RecordFormResponse { id: Some(record_id), issues: None }))
+}
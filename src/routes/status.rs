@@ -1,16 +1,30 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use axum::{Extension, Json};
 use axum::http::StatusCode;
-use axum::Json;
 use axum::response::IntoResponse;
 use serde::{Serialize, Deserialize};
+use crate::cert::renewal::{ExpiringCertificate, ExpiryReport};
 use crate::VERSION;
 
-#[derive(Serialize, Deserialize)]
-enum Health {
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Health {
     HEALTHY,
     UNHEALTHY,
     DEAD
 }
 
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::HEALTHY => write!(f, "HEALTHY"),
+            Health::UNHEALTHY => write!(f, "UNHEALTHY"),
+            Health::DEAD => write!(f, "DEAD")
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 enum Context {
     CONTROLLER,
@@ -21,14 +35,27 @@ enum Context {
 struct Status {
     pub context: Context,
     pub version: String,
-    pub health: Health
+    pub health: Health,
+    /// Certificates expiring within the renewal threshold, as of the last renewal run
+    pub expiring_certificates: Vec<ExpiringCertificate>
 }
 
-pub async fn status() -> impl IntoResponse {
+pub async fn status(
+    Extension(ref expiry_report): Extension<ExpiryReport>
+) -> impl IntoResponse {
+    let expiring_certificates = expiry_report.read().await.clone();
+
+    // Something failed to renew if a certificate has actually expired
+    let health = match expiring_certificates.iter().any(|certificate| certificate.expired) {
+        true => Health::UNHEALTHY,
+        false => Health::HEALTHY
+    };
+
     (StatusCode::OK, Json(Status {
         context: Context::CONTROLLER,
         version: VERSION.to_string(),
-        health: Health::HEALTHY
+        health,
+        expiring_certificates
     })
     )
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::entities::team_member;
+use crate::util::auth::{authorise_team, get_team_permission, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ChangeRoleInput {
+    team_id: String,
+    user_id: String,
+    permission: String
+}
+
+pub async fn change_role(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<ChangeRoleInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    if payload.user_id == user.id {
+        return (StatusCode::BAD_REQUEST, "You cannot change your own permissions".to_string());
+    }
+
+    let caller_permission = match authorise_team(&user.id, &payload.team_id, TeamPermissions::ADMIN, connection).await {
+        Ok((_, caller_permission)) => caller_permission,
+        Err(err) => return err
+    };
+
+    let permission = match TeamPermissions::from_str(&payload.permission) {
+        Ok(permission) => permission,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Requested permission is invalid".to_string())
+    };
+
+    let (membership, member_permission) = match get_team_permission(&payload.user_id, &payload.team_id, connection).await {
+        None => return (StatusCode::NOT_FOUND, "User is not a member of this team".to_string()),
+        Some(membership) => membership
+    };
+
+    // Members can only manage those below them, and only grant permissions below their own
+    if member_permission.at_least(caller_permission.clone()) || permission.at_least(caller_permission) {
+        return (StatusCode::FORBIDDEN, "Insufficient team permissions to change this member's permissions".to_string());
+    }
+
+    let mut changed_membership: team_member::ActiveModel = membership.into();
+    changed_membership.permission = ActiveValue::Set(permission.to_string());
+
+    match changed_membership.update(connection).await {
+        Ok(_) => (StatusCode::OK, format!("Member is now {}", permission)),
+        Err(_) => {
+            error!("Failed to change permissions of {} in team {}!", payload.user_id, payload.team_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string())
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::entities::{team, team_member};
+use crate::entities::prelude::{Team, TeamMember};
+use crate::routes::teams::validate_team_name;
+use crate::util::auth::{TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct NewTeamInput {
+    name: String
+}
+
+#[derive(Serialize)]
+pub struct NewTeamIssues {
+    name: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct NewTeamResponse {
+    id: Option<String>,
+    issues: Option<NewTeamIssues>
+}
+
+pub async fn create(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<NewTeamInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let validation_issues = NewTeamIssues {
+        name: validate_team_name(&payload.name)
+    };
+
+    if !validation_issues.name.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(NewTeamResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    let team_id = Ulid::new().to_string();
+
+    let new_team = team::ActiveModel {
+        id: ActiveValue::Set(team_id.clone()),
+        name: ActiveValue::Set(payload.name.trim().to_string()),
+        active: ActiveValue::Set(true),
+        personal: ActiveValue::Set(false)
+    };
+
+    let team_creation = Team::insert(new_team.clone())
+        .exec(connection)
+        .await;
+
+    if team_creation.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(NewTeamResponse { id: None, issues: None }));
+    }
+
+    // Make the creator the team's owner
+    let team_addition = TeamMember::insert(
+        team_member::ActiveModel {
+            id: ActiveValue::Set(Ulid::new().to_string()),
+            team_id: ActiveValue::Set(team_id.clone()),
+            user_id: ActiveValue::Set(user.id.clone()),
+            permission: ActiveValue::Set(TeamPermissions::OWNER.to_string())
+        }
+    ).exec(connection)
+        .await;
+
+    if team_addition.is_err() {
+        // Delete the team on permission addition error
+        new_team.delete(connection).await
+            .expect("Failed to delete team from database after failing to add its owner!");
+
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(NewTeamResponse { id: None, issues: None }));
+    }
+
+    (StatusCode::CREATED, Json(NewTeamResponse { id: Some(team_id), issues: None }))
+}
@@ -0,0 +1,42 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::entities::team;
+use crate::util::auth::{authorise_team, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct DeactivateTeamInput {
+    team_id: String
+}
+
+pub async fn deactivate(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<DeactivateTeamInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let requested_team = match authorise_team(&user.id, &payload.team_id, TeamPermissions::OWNER, connection).await {
+        Ok((requested_team, _)) => requested_team,
+        Err(err) => return err
+    };
+
+    // Personal teams live and die with their user
+    if requested_team.personal {
+        return (StatusCode::BAD_REQUEST, "Personal teams cannot be deactivated".to_string());
+    }
+
+    let mut deactivated_team: team::ActiveModel = requested_team.into();
+    deactivated_team.active = ActiveValue::Set(false);
+
+    match deactivated_team.update(connection).await {
+        Ok(team) => (StatusCode::OK, format!("Deactivated {}", team.name)),
+        Err(_) => {
+            error!("Failed to deactivate team {}!", payload.team_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string())
+        }
+    }
+}
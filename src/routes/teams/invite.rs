@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::entities::{team_member, user};
+use crate::entities::prelude::{TeamMember, User};
+use crate::util::auth::{authorise_team, get_team_permission, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct InviteMemberInput {
+    team_id: String,
+    email: String,
+    permission: String
+}
+
+pub async fn invite(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<InviteMemberInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let caller_permission = match authorise_team(&user.id, &payload.team_id, TeamPermissions::ADMIN, connection).await {
+        Ok((_, caller_permission)) => caller_permission,
+        Err(err) => return err
+    };
+
+    let permission = match TeamPermissions::from_str(&payload.permission) {
+        Ok(permission) => permission,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Requested permission is invalid".to_string())
+    };
+
+    // Members can only grant permissions below their own
+    if permission.at_least(caller_permission) {
+        return (StatusCode::FORBIDDEN, "Insufficient team permissions to grant the requested permission".to_string());
+    }
+
+    let invited_user: Option<user::Model> = User::find()
+        .filter(user::Column::Email.eq(payload.email.clone()))
+        .one(connection)
+        .await
+        .expect("Failed to check database.");
+
+    let invited_user = match invited_user {
+        None => return (StatusCode::NOT_FOUND, "An account with this email doesn't exist.".to_string()),
+        Some(invited_user) => invited_user
+    };
+
+    if get_team_permission(&invited_user.id, &payload.team_id, connection).await.is_some() {
+        return (StatusCode::BAD_REQUEST, "User is already a member of this team".to_string());
+    }
+
+    let team_addition = TeamMember::insert(
+        team_member::ActiveModel {
+            id: ActiveValue::Set(Ulid::new().to_string()),
+            team_id: ActiveValue::Set(payload.team_id.clone()),
+            user_id: ActiveValue::Set(invited_user.id.clone()),
+            permission: ActiveValue::Set(permission.to_string())
+        }
+    ).exec(connection)
+        .await;
+
+    match team_addition {
+        Ok(_) => (StatusCode::CREATED, format!("Added {} to the team", invited_user.name)),
+        Err(_) => {
+            error!("Failed to add {} to team {}!", invited_user.id, payload.team_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string())
+        }
+    }
+}
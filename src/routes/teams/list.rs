@@ -0,0 +1,57 @@
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+
+use crate::entities::{team, team_member};
+use crate::entities::prelude::TeamMember;
+use crate::util::auth::UserFromBearer;
+
+#[derive(Serialize)]
+pub struct ListTeamsResponse {
+    teams: Vec<ListTeam>
+}
+
+#[derive(Serialize)]
+pub struct ListTeam {
+    id: String,
+    name: String,
+    active: bool,
+    personal: bool,
+    permission: String
+}
+
+pub async fn list(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+) -> impl IntoResponse {
+    let user = user.0;
+    let mut listed_teams: Vec<ListTeam> = Vec::new();
+
+    let memberships: Vec<(team_member::Model, Option<team::Model>)> = TeamMember::find()
+        .filter(team_member::Column::UserId.eq(user.clone().id))
+        .find_also_related(team::Entity)
+        .all(connection)
+        .await
+        .expect("Failed to access database");
+
+    for (membership, team) in memberships {
+        match team {
+            None => {
+                error!("team_member {} still exists for {} but the team doesn't exist!", membership.id, user.id);
+            }
+            Some(team) => {
+                listed_teams.push(ListTeam {
+                    id: team.id,
+                    name: team.name,
+                    active: team.active,
+                    personal: team.personal,
+                    permission: membership.permission
+                });
+            }
+        }
+    }
+
+    (StatusCode::OK, Json(ListTeamsResponse { teams: listed_teams }))
+}
@@ -0,0 +1,60 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{team_member, user};
+use crate::entities::prelude::TeamMember;
+use crate::util::auth::{authorise_team, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ListMembersInput {
+    team_id: String
+}
+
+#[derive(Serialize)]
+pub struct ListMembersResponse {
+    members: Vec<ListMember>
+}
+
+#[derive(Serialize)]
+pub struct ListMember {
+    user_id: String,
+    name: String,
+    email: String,
+    permission: String
+}
+
+pub async fn list_members(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<ListMembersInput>
+) -> impl IntoResponse {
+    let user = user.0;
+    let mut listed_members: Vec<ListMember> = Vec::new();
+
+    if let Err((status, _)) = authorise_team(&user.id, &query.team_id, TeamPermissions::VIEWER, connection).await {
+        return (status, Json(ListMembersResponse { members: listed_members }));
+    }
+
+    let memberships: Vec<(team_member::Model, Option<user::Model>)> = TeamMember::find()
+        .filter(team_member::Column::TeamId.eq(query.team_id.clone()))
+        .find_also_related(user::Entity)
+        .all(connection)
+        .await
+        .expect("Failed to access database");
+
+    for (membership, member) in memberships {
+        if let Some(member) = member {
+            listed_members.push(ListMember {
+                user_id: member.id,
+                name: member.name,
+                email: member.email,
+                permission: membership.permission
+            });
+        }
+    }
+
+    (StatusCode::OK, Json(ListMembersResponse { members: listed_members }))
+}
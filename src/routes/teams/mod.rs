@@ -0,0 +1,21 @@
+pub mod create;
+pub mod rename;
+pub mod list;
+pub mod list_members;
+pub mod deactivate;
+pub mod invite;
+pub mod remove_member;
+pub mod change_role;
+
+/// Checks that a team name is acceptable, returning any issues found
+pub fn validate_team_name(name: &str) -> Vec<String> {
+    let mut issues: Vec<String> = vec![];
+
+    if name.trim().is_empty() {
+        issues.push("Name cannot be empty.".to_string());
+    } else if name.len() > 128 {
+        issues.push("Name is too long.".to_string());
+    }
+
+    issues
+}
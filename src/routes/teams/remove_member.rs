@@ -0,0 +1,59 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::entities::team_member;
+use crate::util::auth::{authorise_team, get_team_permission, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct RemoveMemberInput {
+    team_id: String,
+    user_id: String
+}
+
+pub async fn remove_member(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<RemoveMemberInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    // Anyone can leave a team, otherwise the caller has to be able to manage members
+    let required = if payload.user_id == user.id {
+        TeamPermissions::VIEWER
+    } else {
+        TeamPermissions::ADMIN
+    };
+
+    let caller_permission = match authorise_team(&user.id, &payload.team_id, required, connection).await {
+        Ok((_, caller_permission)) => caller_permission,
+        Err(err) => return err
+    };
+
+    let (membership, member_permission) = match get_team_permission(&payload.user_id, &payload.team_id, connection).await {
+        None => return (StatusCode::NOT_FOUND, "User is not a member of this team".to_string()),
+        Some(membership) => membership
+    };
+
+    if member_permission == TeamPermissions::OWNER {
+        return (StatusCode::BAD_REQUEST, "The team owner cannot be removed".to_string());
+    }
+
+    if payload.user_id != user.id && member_permission.at_least(caller_permission) {
+        return (StatusCode::FORBIDDEN, "Insufficient team permissions to remove this member".to_string());
+    }
+
+    let member_delete = team_member::Entity::delete_by_id(membership.id.clone())
+        .exec(connection)
+        .await
+        .expect("Failed to delete team_member!");
+
+    if member_delete.rows_affected.eq(&0) {
+        error!("Could not delete team_member {}!", membership.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    (StatusCode::OK, "Removed member from the team".to_string())
+}
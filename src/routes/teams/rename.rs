@@ -0,0 +1,57 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entities::team;
+use crate::routes::teams::validate_team_name;
+use crate::util::auth::{authorise_team, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct RenameTeamInput {
+    team_id: String,
+    name: String
+}
+
+#[derive(Serialize)]
+pub struct RenameTeamIssues {
+    team_id: Vec<String>,
+    name: Vec<String>
+}
+
+pub async fn rename(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<RenameTeamInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let mut validation_issues = RenameTeamIssues {
+        team_id: vec![],
+        name: validate_team_name(&payload.name)
+    };
+
+    let requested_team = match authorise_team(&user.id, &payload.team_id, TeamPermissions::ADMIN, connection).await {
+        Ok((requested_team, _)) => requested_team,
+        Err((status, reason)) => {
+            validation_issues.team_id.push(reason);
+            return (status, Json(validation_issues));
+        }
+    };
+
+    if !validation_issues.name.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(validation_issues));
+    }
+
+    let mut renamed_team: team::ActiveModel = requested_team.into();
+    renamed_team.name = ActiveValue::Set(payload.name.trim().to_string());
+
+    match renamed_team.update(connection).await {
+        Ok(_) => (StatusCode::OK, Json(validation_issues)),
+        Err(_) => {
+            error!("Failed to rename team {}!", payload.team_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(validation_issues))
+        }
+    }
+}
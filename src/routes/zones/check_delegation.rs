@@ -0,0 +1,37 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::dns::delegation::{check_delegation as check_zone_delegation, SharedResolver};
+use crate::routes::zones::ZoneResponse;
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct CheckDelegationInput {
+    zone_id: String
+}
+
+pub async fn check_delegation(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(resolver): Extension<SharedResolver>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<CheckDelegationInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::EDITOR, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    match check_zone_delegation(requested_zone, resolver.as_ref(), connection).await {
+        Ok(checked_zone) => (StatusCode::OK, Json(ZoneResponse::from(checked_zone))).into_response(),
+        Err(_) => {
+            error!("Failed to save the delegation check for zone {}!", payload.zone_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response()
+        }
+    }
+}
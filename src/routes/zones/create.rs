@@ -0,0 +1,113 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::dns::soa::create_soa;
+use crate::entities::zone;
+use crate::entities::prelude::Zone;
+use crate::rpc::changes::{Change, publish_change, record_changes};
+use crate::routes::zones::{normalise_origin, validate_origin};
+use crate::util::auth::{authorise_team, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct NewZoneInput {
+    team_id: String,
+    origin: String
+}
+
+#[derive(Serialize)]
+pub struct NewZoneIssues {
+    team_id: Vec<String>,
+    origin: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct NewZoneResponse {
+    id: Option<String>,
+    issues: Option<NewZoneIssues>
+}
+
+pub async fn create(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<NewZoneInput>
+) -> impl IntoResponse {
+    let user = user.0;
+    let origin = normalise_origin(&payload.origin);
+
+    let mut validation_issues = NewZoneIssues {
+        team_id: vec![],
+        origin: validate_origin(&origin)
+    };
+
+    if let Err((status, reason)) = authorise_team(&user.id, &payload.team_id, TeamPermissions::ADMIN, connection).await {
+        validation_issues.team_id.push(reason);
+        return (status, Json(NewZoneResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    if !validation_issues.origin.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(NewZoneResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    // Check to see if the zone is already managed by anyone
+    let existing_zone = Zone::find()
+        .filter(zone::Column::Origin.eq(origin.clone()))
+        .one(connection)
+        .await
+        .expect("Failed to check database.");
+
+    if existing_zone.is_some() {
+        validation_issues.origin.push("This zone already exists.".to_string());
+
+        return (StatusCode::BAD_REQUEST, Json(NewZoneResponse { id: None, issues: Some(validation_issues) }));
+    }
+
+    let new_zone = zone::Model {
+        id: Ulid::new().to_string(),
+        owner: payload.team_id.clone(),
+        origin,
+        delegated: false,
+        delegation_checked: None,
+        delegation_error: None,
+        dnssec: false
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin zone creation transaction!");
+
+    let zone_creation = Zone::insert(zone::ActiveModel::from(new_zone.clone()))
+        .exec(&txn)
+        .await;
+
+    if zone_creation.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(NewZoneResponse { id: None, issues: None }));
+    }
+
+    // Every zone starts out with an SOA the controller manages from then on
+    if create_soa(&new_zone, &txn).await.is_err() {
+        error!("Failed to create SOA for new zone {}!", new_zone.origin);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(NewZoneResponse { id: None, issues: None }));
+    }
+
+    let change_event = match record_changes(&new_zone, vec![Change::ZoneCreated], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Failed to record the creation of zone {}!", new_zone.origin);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NewZoneResponse { id: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit zone creation transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    (StatusCode::CREATED, Json(NewZoneResponse { id: Some(new_zone.id), issues: None }))
+}
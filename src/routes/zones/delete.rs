@@ -0,0 +1,104 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::revocation::RevocationReason;
+use crate::entities::{proxy, record, zone};
+use crate::entities::prelude::{Proxy, Record};
+use crate::routes::proxies::retire_certificate;
+use crate::rpc::changes::{Change, publish_change, record_changes};
+use crate::rpc::proxies::{ProxyChange, publish_proxy_changes};
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct DeleteZoneInput {
+    zone_id: String
+}
+
+pub async fn delete(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<DeleteZoneInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::ADMIN, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin zone deletion transaction!");
+
+    let record_ids: Vec<String> = Record::find()
+        .filter(record::Column::Zone.eq(requested_zone.id.clone()))
+        .all(&txn)
+        .await
+        .expect("Failed to retrieve records during zone deletion!")
+        .into_iter()
+        .map(|record| record.id)
+        .collect();
+
+    let zone_proxies = Proxy::find()
+        .filter(proxy::Column::Record.is_in(record_ids.clone()))
+        .all(&txn)
+        .await
+        .expect("Failed to retrieve proxies during zone deletion!");
+
+    // Remove everything hanging off the zone before the zone itself
+    proxy::Entity::delete_many()
+        .filter(proxy::Column::Record.is_in(record_ids))
+        .exec(&txn)
+        .await
+        .expect("Failed to delete proxies during zone deletion!");
+
+    for zone_proxy in &zone_proxies {
+        if retire_certificate(&zone_proxy.certificate, RevocationReason::CESSATIONOFOPERATION, &txn).await.is_err() {
+            error!("Could not retire the cert of proxy {}!", zone_proxy.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+        }
+    }
+
+    record::Entity::delete_many()
+        .filter(record::Column::Zone.eq(requested_zone.id.clone()))
+        .exec(&txn)
+        .await
+        .expect("Failed to delete records during zone deletion!");
+
+    let zone_delete = zone::Entity::delete_by_id(requested_zone.id.clone())
+        .exec(&txn)
+        .await
+        .expect("Failed to delete zone!");
+
+    if zone_delete.rows_affected.eq(&0) {
+        error!("Could not delete zone {}!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+    }
+
+    let change_event = match record_changes(&requested_zone, vec![Change::ZoneDeleted], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Could not record the deletion of zone {}!", requested_zone.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string());
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit zone deletion transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    let deleted_proxies = zone_proxies.into_iter()
+        .map(|zone_proxy| ProxyChange::ProxyDeleted { proxy_id: zone_proxy.id })
+        .collect();
+
+    publish_proxy_changes(amqp_channel, deleted_proxies).await;
+
+    (StatusCode::OK, format!("Deleted {}", requested_zone.origin))
+}
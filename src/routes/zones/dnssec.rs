@@ -0,0 +1,86 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::dnssec::{get_zone_keys, key_dnskey, KeyTypes};
+use crate::dns::dnssec::ds_record;
+use crate::dns::records::RecordTypes;
+use crate::entities::zone;
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct GetDnssecInput {
+    zone_id: String
+}
+
+#[derive(Serialize)]
+pub struct DnssecResponse {
+    enabled: bool,
+    keys: Vec<DnssecKeyResponse>,
+    /// DS records to give the registrar, one for each key signing key
+    ds: Vec<RecordTypes>
+}
+
+#[derive(Serialize)]
+pub struct DnssecKeyResponse {
+    id: String,
+    key_type: String,
+    status: String,
+    key_tag: i32,
+    dnskey: RecordTypes,
+    created: i64
+}
+
+/// Describes a zone's DNSSEC keys and the DS material for its parent
+pub async fn describe_dnssec(requested_zone: &zone::Model, connection: &DatabaseConnection) -> Result<DnssecResponse, (StatusCode, String)> {
+    let keys = match get_zone_keys(&requested_zone.id, connection).await {
+        Ok(keys) => keys,
+        Err(_) => {
+            error!("Failed to retrieve DNSSEC keys of zone {}!", requested_zone.id);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()));
+        }
+    };
+
+    let mut ds: Vec<RecordTypes> = vec![];
+    let mut key_responses: Vec<DnssecKeyResponse> = vec![];
+
+    for key in keys {
+        let dnskey = key_dnskey(&key, &requested_zone.origin);
+
+        if key.key_type == KeyTypes::KSK.to_string() {
+            ds.push(ds_record(&requested_zone.origin, &dnskey).expect("Failed to build DS record!"));
+        }
+
+        key_responses.push(DnssecKeyResponse {
+            id: key.id,
+            key_type: key.key_type,
+            status: key.status,
+            key_tag: key.key_tag,
+            dnskey,
+            created: key.created.timestamp()
+        });
+    }
+
+    Ok(DnssecResponse { enabled: requested_zone.dnssec, keys: key_responses, ds })
+}
+
+pub async fn dnssec(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<GetDnssecInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &query.zone_id, TeamPermissions::VIEWER, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    match describe_dnssec(&requested_zone, connection).await {
+        Ok(described) => (StatusCode::OK, Json(described)).into_response(),
+        Err(err) => err.into_response()
+    }
+}
@@ -0,0 +1,64 @@
+use axum::{Extension, extract};
+use chrono::Utc;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::cert::dnssec::load_signing_keys;
+use crate::dns::dnssec::sign_zone;
+use crate::dns::records::RecordTypes;
+use crate::dns::zonefile::render_zone_file;
+use crate::routes::records::get_zone_records;
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ExportZoneInput {
+    zone_id: String,
+    /// Includes DNSKEY, NSEC3 and RRSIG records when the zone has DNSSEC enabled
+    #[serde(default)]
+    signed: bool
+}
+
+pub async fn export(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<ExportZoneInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &query.zone_id, TeamPermissions::VIEWER, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    // Only what is actually being served is exported
+    let records: Vec<RecordTypes> = get_zone_records(&requested_zone.id, connection)
+        .await
+        .into_iter()
+        .filter(|(model, _)| model.active)
+        .map(|(_, record)| record)
+        .collect();
+
+    let records = if query.signed && requested_zone.dnssec {
+        let keys = load_signing_keys(&requested_zone, connection)
+            .await
+            .expect("Failed to retrieve DNSSEC keys from the database.");
+
+        match sign_zone(&requested_zone.origin, &records, &keys, Utc::now().timestamp()) {
+            Ok(signed) => signed,
+            Err(err) => {
+                error!("Failed to sign zone {} for export: {}", requested_zone.id, err);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response();
+            }
+        }
+    } else {
+        records
+    };
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/dns; charset=utf-8")],
+        render_zone_file(&requested_zone.origin, &records)
+    ).into_response()
+}
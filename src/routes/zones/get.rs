@@ -0,0 +1,27 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::routes::zones::ZoneResponse;
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct GetZoneInput {
+    zone_id: String
+}
+
+pub async fn get(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Query(query): extract::Query<GetZoneInput>
+) -> Response {
+    let user = user.0;
+
+    match authorise_zone(&user.id, &query.zone_id, TeamPermissions::VIEWER, connection).await {
+        Ok((requested_zone, _)) => (StatusCode::OK, Json(ZoneResponse::from(requested_zone))).into_response(),
+        Err(err) => err.into_response()
+    }
+}
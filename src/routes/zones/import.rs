@@ -0,0 +1,224 @@
+use axum::{Extension, extract, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use lapin::Channel;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::cert::revocation::RevocationReason;
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::{bump_serial, lock_zone};
+use crate::dns::validation::{normalise_record, RecordIssues, validate_record, validate_zone_conflicts};
+use crate::dns::zonefile::parse_zone_file;
+use crate::entities::{proxy, record};
+use crate::entities::prelude::{Proxy, Record};
+use crate::routes::proxies::retire_certificate;
+use crate::routes::records::get_zone_records;
+use crate::rpc::changes::{Change, publish_change, record_changes, SyncedRecord};
+use crate::rpc::proxies::{ProxyChange, publish_proxy_changes};
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct ImportZoneInput {
+    zone_id: String,
+    zone_file: String,
+    /// Removes the zone's existing records before importing
+    #[serde(default)]
+    replace: bool
+}
+
+#[derive(Serialize)]
+pub struct ImportZoneIssue {
+    line: usize,
+    issues: RecordIssues
+}
+
+#[derive(Serialize)]
+pub struct ImportZoneResponse {
+    imported: Option<usize>,
+    issues: Option<Vec<ImportZoneIssue>>
+}
+
+fn single_issue(line: usize, field: &'static str, reason: String) -> Option<Vec<ImportZoneIssue>> {
+    let mut issues = RecordIssues::default();
+    issues.push(field, reason);
+
+    Some(vec![ImportZoneIssue { line, issues }])
+}
+
+pub async fn import(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<ImportZoneInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::EDITOR, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err((status, reason)) => {
+            return (status, Json(ImportZoneResponse { imported: None, issues: single_issue(0, "zone_id", reason) }));
+        }
+    };
+
+    let parsed_records = match parse_zone_file(&payload.zone_file, &requested_zone.origin) {
+        Ok(parsed_records) => parsed_records,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(ImportZoneResponse { imported: None, issues: single_issue(err.line, "zone_file", err.reason) }));
+        }
+    };
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin zone import transaction!");
+
+    // Checked under the zone's lock, so a concurrent change can't slip a conflicting record in
+    if lock_zone(&requested_zone, &txn).await.is_err() {
+        error!("Failed to lock zone {} for import!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+    }
+
+    let existing_records = get_zone_records(&requested_zone.id, &txn).await;
+
+    let existing_soa = existing_records.iter()
+        .find(|(_, existing)| matches!(existing, RecordTypes::SOA { .. }))
+        .map(|(model, existing)| (model.clone(), existing.clone()));
+
+    let replaced_ids: Vec<String> = existing_records.iter()
+        .filter(|(_, existing)| payload.replace && !matches!(existing, RecordTypes::SOA { .. }))
+        .map(|(model, _)| model.id.clone())
+        .collect();
+
+    // Imported records are checked against each other as well as what's already in the zone,
+    // the zone's SOA is kept when replacing as the file's SOA is merged into it
+    let mut accepted: Vec<(String, RecordTypes)> = existing_records.into_iter()
+        .filter(|(_, existing)| !payload.replace || matches!(existing, RecordTypes::SOA { .. }))
+        .map(|(model, existing)| (model.id, existing))
+        .collect();
+
+    let mut import_issues: Vec<ImportZoneIssue> = vec![];
+    let mut new_records: Vec<RecordTypes> = vec![];
+    let mut imported_soa: Option<RecordTypes> = None;
+
+    for (line, mut parsed_record) in parsed_records {
+        normalise_record(&mut parsed_record, &requested_zone.origin);
+
+        let mut issues = validate_record(&parsed_record, &requested_zone.origin);
+
+        if let RecordTypes::SOA { .. } = parsed_record {
+            if imported_soa.is_some() {
+                issues.push("value", "A zone file can only have one SOA record.".to_string());
+            } else if issues.is_empty() {
+                imported_soa = Some(parsed_record);
+                continue;
+            }
+        } else {
+            issues.merge(validate_zone_conflicts(&parsed_record, &requested_zone.origin, &accepted, None));
+        }
+
+        if !issues.is_empty() {
+            import_issues.push(ImportZoneIssue { line, issues });
+            continue;
+        }
+
+        accepted.push((format!("line {}", line), parsed_record.clone()));
+        new_records.push(parsed_record);
+    }
+
+    if !import_issues.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(ImportZoneResponse { imported: None, issues: Some(import_issues) }));
+    }
+
+    // Proxies of replaced records are deleted with them, and their certificates retired
+    let replaced_proxies = Proxy::find()
+        .filter(proxy::Column::Record.is_in(replaced_ids.clone()))
+        .all(&txn)
+        .await
+        .expect("Failed to retrieve proxies during zone import!");
+
+    if payload.replace {
+        record::Entity::delete_many()
+            .filter(record::Column::Zone.eq(requested_zone.id.clone()))
+            .filter(record::Column::Id.ne(existing_soa.as_ref().map_or(String::new(), |(model, _)| model.id.clone())))
+            .exec(&txn)
+            .await
+            .expect("Failed to delete records during zone import!");
+    }
+
+    for replaced_proxy in &replaced_proxies {
+        if retire_certificate(&replaced_proxy.certificate, RevocationReason::CESSATIONOFOPERATION, &txn).await.is_err() {
+            error!("Could not retire the cert of proxy {}!", replaced_proxy.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+        }
+    }
+
+    // The file's SOA replaces the zone's, apart from the serial which the controller manages
+    if let (Some(mut imported_soa), Some((soa_model, RecordTypes::SOA { serial: existing_serial, .. }))) = (imported_soa, existing_soa) {
+        if let RecordTypes::SOA { serial, .. } = &mut imported_soa {
+            *serial = existing_serial;
+        }
+
+        let mut merged_soa: record::ActiveModel = soa_model.into();
+        merged_soa.value = ActiveValue::Set(imported_soa.to_msgpack().expect("Failed to encode SOA!"));
+
+        if merged_soa.update(&txn).await.is_err() {
+            error!("Failed to merge imported SOA into zone {}!", requested_zone.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+        }
+    }
+
+    let imported = new_records.len();
+    let mut changes: Vec<Change> = replaced_ids.into_iter()
+        .map(|record_id| Change::RecordDeleted { record_id })
+        .collect();
+
+    for new_record in new_records {
+        let record_id = Ulid::new().to_string();
+
+        let record_creation = Record::insert(record::ActiveModel {
+            id: ActiveValue::Set(record_id.clone()),
+            zone: ActiveValue::Set(requested_zone.id.clone()),
+            value: ActiveValue::Set(new_record.to_msgpack().expect("Failed to encode record!")),
+            active: ActiveValue::Set(true)
+        })
+            .exec(&txn)
+            .await;
+
+        if record_creation.is_err() {
+            error!("Failed to insert record during import into zone {}!", requested_zone.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+        }
+
+        changes.push(Change::RecordUpserted {
+            record: SyncedRecord { id: record_id, active: true, record: new_record }
+        });
+    }
+
+    if bump_serial(&requested_zone, &txn).await.is_err() {
+        error!("Failed to bump the serial of zone {} after import!", requested_zone.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+    }
+
+    let change_event = match record_changes(&requested_zone, changes, &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Failed to record the import into zone {}!", requested_zone.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ImportZoneResponse { imported: None, issues: None }));
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit zone import transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    let deleted_proxies = replaced_proxies.into_iter()
+        .map(|replaced_proxy| ProxyChange::ProxyDeleted { proxy_id: replaced_proxy.id })
+        .collect();
+
+    publish_proxy_changes(amqp_channel, deleted_proxies).await;
+
+    (StatusCode::CREATED, Json(ImportZoneResponse { imported: Some(imported), issues: None }))
+}
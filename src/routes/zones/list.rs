@@ -0,0 +1,43 @@
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+
+use crate::entities::{team, team_member, zone};
+use crate::entities::prelude::{TeamMember, Zone};
+use crate::routes::zones::ZoneResponse;
+use crate::util::auth::UserFromBearer;
+
+#[derive(Serialize)]
+pub struct ListZonesResponse {
+    zones: Vec<ZoneResponse>
+}
+
+pub async fn list(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+) -> impl IntoResponse {
+    let user = user.0;
+
+    // Only zones belonging to active teams the user is part of are visible
+    let team_ids: Vec<String> = TeamMember::find()
+        .filter(team_member::Column::UserId.eq(user.clone().id))
+        .find_also_related(team::Entity)
+        .all(connection)
+        .await
+        .expect("Failed to access database")
+        .into_iter()
+        .filter_map(|(_, team)| team)
+        .filter(|team| team.active)
+        .map(|team| team.id)
+        .collect();
+
+    let zones: Vec<zone::Model> = Zone::find()
+        .filter(zone::Column::Owner.is_in(team_ids))
+        .all(connection)
+        .await
+        .expect("Failed to access database");
+
+    (StatusCode::OK, Json(ListZonesResponse { zones: zones.into_iter().map(ZoneResponse::from).collect() }))
+}
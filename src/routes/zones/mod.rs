@@ -0,0 +1,58 @@
+use serde::Serialize;
+
+use crate::dns::validation::{normalise_name, validate_name};
+use crate::entities::zone;
+
+pub mod create;
+pub mod list;
+pub mod get;
+pub mod transfer;
+pub mod delete;
+pub mod import;
+pub mod export;
+pub mod check_delegation;
+pub mod dnssec;
+pub mod set_dnssec;
+
+#[derive(Serialize)]
+pub struct ZoneResponse {
+    id: String,
+    owner: String,
+    origin: String,
+    delegated: bool,
+    delegation_checked: Option<i64>,
+    delegation_error: Option<String>,
+    dnssec: bool
+}
+
+impl From<zone::Model> for ZoneResponse {
+    fn from(zone: zone::Model) -> Self {
+        ZoneResponse {
+            id: zone.id,
+            owner: zone.owner,
+            origin: zone.origin,
+            delegated: zone.delegated,
+            delegation_checked: zone.delegation_checked.map(|checked| checked.timestamp()),
+            delegation_error: zone.delegation_error,
+            dnssec: zone.dnssec
+        }
+    }
+}
+
+/// Lowercases an origin and strips its trailing root label
+pub fn normalise_origin(origin: &str) -> String {
+    normalise_name(origin)
+}
+
+/// Checks that a normalised origin is a valid domain name, returning any issues found
+pub fn validate_origin(origin: &str) -> Vec<String> {
+    let mut issues = validate_name(origin, false);
+
+    if issues.is_empty() && (origin == "." || !origin.contains('.')) {
+        issues.push("Origin must have at least two labels.".to_string());
+    } else if origin.contains('*') {
+        issues.push("Origin cannot contain a wildcard.".to_string());
+    }
+
+    issues
+}
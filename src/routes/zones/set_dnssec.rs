@@ -0,0 +1,74 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use lapin::Channel;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::cert::dnssec::{disable_dnssec, enable_dnssec};
+use crate::rpc::changes::{Change, publish_change, record_changes};
+use crate::routes::zones::dnssec::describe_dnssec;
+use crate::util::auth::{authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct SetDnssecInput {
+    zone_id: String,
+    enabled: bool
+}
+
+pub async fn set_dnssec(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref amqp_channel): Extension<Channel>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<SetDnssecInput>
+) -> Response {
+    let user = user.0;
+
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::ADMIN, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err.into_response()
+    };
+
+    if requested_zone.dnssec == payload.enabled {
+        let state = if payload.enabled { "enabled" } else { "disabled" };
+        return (StatusCode::BAD_REQUEST, format!("DNSSEC is already {} for this zone", state)).into_response();
+    }
+
+    let txn = connection.begin()
+        .await
+        .expect("Failed to begin DNSSEC transaction!");
+
+    let updated_zone = if payload.enabled {
+        enable_dnssec(requested_zone, &txn).await
+    } else {
+        disable_dnssec(requested_zone, &txn).await
+    };
+
+    let updated_zone = match updated_zone {
+        Ok(updated_zone) => updated_zone,
+        Err(_) => {
+            error!("Failed to change DNSSEC for zone {}!", payload.zone_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response();
+        }
+    };
+
+    let change_event = match record_changes(&updated_zone, vec![Change::ZoneResync], &txn).await {
+        Ok(change_event) => change_event,
+        Err(_) => {
+            error!("Failed to record the DNSSEC change for zone {}!", payload.zone_id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response();
+        }
+    };
+
+    txn.commit()
+        .await
+        .expect("Failed to commit DNSSEC transaction!");
+
+    publish_change(amqp_channel, &change_event).await;
+
+    match describe_dnssec(&updated_zone, connection).await {
+        Ok(described) => (StatusCode::OK, Json(described)).into_response(),
+        Err(err) => err.into_response()
+    }
+}
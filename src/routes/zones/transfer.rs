@@ -0,0 +1,52 @@
+use axum::{Extension, extract};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use sea_orm::*;
+use serde::Deserialize;
+
+use crate::entities::zone;
+use crate::util::auth::{authorise_team, authorise_zone, TeamPermissions, UserFromBearer};
+
+#[derive(Deserialize)]
+pub struct TransferZoneInput {
+    zone_id: String,
+    team_id: String
+}
+
+/// Moves a zone to another team
+///
+/// Ownership only decides who can manage the zone through the API. Change events and snapshots
+/// don't carry it and nothing served changes, so no change is journaled or published.
+pub async fn transfer(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    UserFromBearer(user): UserFromBearer,
+    extract::Json(payload): extract::Json<TransferZoneInput>
+) -> impl IntoResponse {
+    let user = user.0;
+
+    // The user has to be able to manage zones in both teams
+    let requested_zone = match authorise_zone(&user.id, &payload.zone_id, TeamPermissions::ADMIN, connection).await {
+        Ok((requested_zone, _)) => requested_zone,
+        Err(err) => return err
+    };
+
+    let target_team = match authorise_team(&user.id, &payload.team_id, TeamPermissions::ADMIN, connection).await {
+        Ok((target_team, _)) => target_team,
+        Err(err) => return err
+    };
+
+    if requested_zone.owner == target_team.id {
+        return (StatusCode::BAD_REQUEST, "Zone already belongs to this team".to_string());
+    }
+
+    let mut transferred_zone: zone::ActiveModel = requested_zone.into();
+    transferred_zone.owner = ActiveValue::Set(target_team.id.clone());
+
+    match transferred_zone.update(connection).await {
+        Ok(zone) => (StatusCode::OK, format!("Transferred {} to {}", zone.origin, target_team.name)),
+        Err(_) => {
+            error!("Failed to transfer zone {} to team {}!", payload.zone_id, payload.team_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string())
+        }
+    }
+}
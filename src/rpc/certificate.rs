@@ -0,0 +1,59 @@
+use picky::x509::Cert;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::load_issuer_chain;
+use crate::cert::rotation::mark_fetched;
+use crate::cert::Types::CLIENTINTER;
+use crate::entities::client;
+use crate::entities::prelude::Certificate;
+use crate::rpc::{RpcError, RpcErrorCode};
+
+/// A client's current certificate, fetched after it is told its certificate was renewed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CertificateBundle {
+    pub certificate_id: String,
+    /// PEM encoded client certificate
+    pub certificate: String,
+    /// PEM encoded intermediate and root, in that order, with the cross-signed root in between while
+    /// the previous root is retiring. A certificate that hasn't been re-issued since gets the retiring
+    /// intermediate and root instead.
+    pub chain: Vec<String>
+}
+
+pub fn pem(data: &[u8]) -> Result<String, RpcError> {
+    Cert::from_der(data)
+        .and_then(|cert| cert.to_pem())
+        .map(|pem| pem.to_string())
+        .map_err(|err| {
+            error!("Failed to encode stored certificate: {}", err);
+            RpcError::internal()
+        })
+}
+
+pub async fn client_certificate(caller: &client::Model, connection: &DatabaseConnection) -> Result<CertificateBundle, RpcError> {
+    let leaf_model = Certificate::find_by_id(caller.certificate.clone())
+        .one(connection)
+        .await?
+        .ok_or_else(|| RpcError::new(RpcErrorCode::NOTFOUND, "Client has no certificate"))?;
+
+    let leaf_cert = Cert::from_der(&leaf_model.data).map_err(|err| {
+        error!("Failed to decode client cert {}: {}", leaf_model.id, err);
+        RpcError::internal()
+    })?;
+
+    let mut chain = vec![];
+
+    for chain_cert in load_issuer_chain(&leaf_cert, CLIENTINTER, connection).await?.ok_or_else(RpcError::internal)? {
+        chain.push(pem(&chain_cert.to_der().map_err(|_| RpcError::internal())?)?);
+    }
+
+    // A rotation keeps the old chain around until holders have picked up their re-issued leaf
+    mark_fetched(&leaf_model.id, connection).await?;
+
+    Ok(CertificateBundle {
+        certificate_id: leaf_model.id,
+        certificate: pem(&leaf_model.data)?,
+        chain
+    })
+}
@@ -0,0 +1,313 @@
+use chrono::Utc;
+use lapin::{BasicProperties, Channel, ExchangeKind};
+use lapin::options::{BasicPublishOptions, ExchangeDeclareOptions};
+use lapin::types::FieldTable;
+use sea_orm::*;
+use sea_orm::sea_query::Expr;
+use serde::{Deserialize, Serialize};
+
+use crate::dns::records::RecordTypes;
+use crate::dns::soa::find_soa;
+use crate::entities::{change_journal, change_sequence, record, zone};
+use crate::entities::prelude::{ChangeJournal, ChangeSequence};
+
+/// Bumped whenever the layout of change events changes in a way clients need to know about
+pub const CHANGE_EVENT_VERSION: u8 = 1;
+/// Fanout exchange every change event is published to, clients bind their own queues to it
+pub const CHANGE_EXCHANGE: &str = "driptorch.changes";
+
+/// A record as clients see it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncedRecord {
+    pub id: String,
+    pub active: bool,
+    pub record: RecordTypes
+}
+
+impl SyncedRecord {
+    pub fn from_model(model: &record::Model) -> Option<SyncedRecord> {
+        RecordTypes::from_msgpack(&model.value).ok().map(|record| SyncedRecord {
+            id: model.id.clone(),
+            active: model.active,
+            record
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Change {
+    ZoneCreated,
+    ZoneDeleted,
+    RecordUpserted { record: SyncedRecord },
+    RecordDeleted { record_id: String },
+    /// The zone changed in a way that can't be described record by record, such as being
+    /// re-signed, so clients should fetch the whole zone again
+    ZoneResync
+}
+
+/// Everything that changed about a zone in one transaction
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub version: u8,
+    /// Counts up by one for every event across all zones, so clients can spot ones they missed
+    pub sequence: i64,
+    pub zone_id: String,
+    pub origin: String,
+    /// The zone's serial after the change, which is missing once the zone has been deleted
+    pub serial: Option<u32>,
+    pub changes: Vec<Change>
+}
+
+impl ChangeEvent {
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    pub fn from_msgpack(data: &[u8]) -> Result<ChangeEvent, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(data)
+    }
+}
+
+/// Takes the next sequence number, to be called within the transaction making the change
+///
+/// The counter's row stays locked until the transaction ends, so sequence numbers are handed out
+/// in commit order and a rolled back change doesn't leave a gap.
+pub async fn next_sequence<C: ConnectionTrait>(connection: &C) -> Result<i64, DbErr> {
+    advance_sequence(1, connection).await
+}
+
+/// Gets the latest sequence number while holding the counter's lock, so no further changes can
+/// be committed until the transaction ends
+pub async fn lock_sequence<C: ConnectionTrait>(connection: &C) -> Result<i64, DbErr> {
+    advance_sequence(0, connection).await
+}
+
+async fn advance_sequence<C: ConnectionTrait>(step: i64, connection: &C) -> Result<i64, DbErr> {
+    ChangeSequence::update_many()
+        .col_expr(change_sequence::Column::Sequence, Expr::col(change_sequence::Column::Sequence).add(step))
+        .exec(connection)
+        .await?;
+
+    ChangeSequence::find()
+        .one(connection)
+        .await?
+        .map(|counter| counter.sequence)
+        .ok_or_else(|| DbErr::RecordNotFound("The change sequence is missing its counter".to_string()))
+}
+
+/// Settles the serial and changes a zone's event carries, given its SOA after the change
+///
+/// The zone's SOA is always included as its serial moves with every change. Signed zones are
+/// always resynced, as any change to them also changes their signatures.
+fn settle_changes(changed_zone: &zone::Model, mut changes: Vec<Change>, soa: Option<(record::Model, RecordTypes)>) -> (Option<u32>, Vec<Change>) {
+    let serial = match &soa {
+        Some((_, RecordTypes::SOA { serial, .. })) => Some(*serial),
+        _ => None
+    };
+
+    if changed_zone.dnssec && !changes.iter().any(|change| matches!(change, Change::ZoneDeleted)) {
+        changes = vec![Change::ZoneResync];
+    } else if let Some((soa_model, _)) = soa {
+        changes.retain(|change| !matches!(change, Change::RecordUpserted { record } if record.id == soa_model.id));
+
+        if let Some(soa_record) = SyncedRecord::from_model(&soa_model) {
+            changes.push(Change::RecordUpserted { record: soa_record });
+        }
+    }
+
+    (serial, changes)
+}
+
+/// Builds the change event for a zone and writes it to the journal, to be called within the
+/// transaction that changed it, after the serial has been bumped
+pub async fn record_changes<C: ConnectionTrait>(changed_zone: &zone::Model, changes: Vec<Change>, connection: &C) -> Result<ChangeEvent, DbErr> {
+    let soa = find_soa(&changed_zone.id, connection).await?;
+    let (serial, changes) = settle_changes(changed_zone, changes, soa);
+
+    let event = ChangeEvent {
+        version: CHANGE_EVENT_VERSION,
+        sequence: next_sequence(connection).await?,
+        zone_id: changed_zone.id.clone(),
+        origin: changed_zone.origin.clone(),
+        serial,
+        changes
+    };
+
+    ChangeJournal::insert(change_journal::ActiveModel {
+        sequence: ActiveValue::Set(event.sequence),
+        zone: ActiveValue::Set(event.zone_id.clone()),
+        data: ActiveValue::Set(event.to_msgpack().expect("Failed to encode change event!")),
+        created: ActiveValue::Set(Utc::now().naive_utc())
+    })
+        .exec(connection)
+        .await?;
+
+    Ok(event)
+}
+
+/// Declares the exchange change events are published to
+pub async fn declare_exchange(channel: &Channel) -> Result<(), lapin::Error> {
+    channel.exchange_declare(
+        CHANGE_EXCHANGE,
+        ExchangeKind::Fanout,
+        ExchangeDeclareOptions { durable: true, ..ExchangeDeclareOptions::default() },
+        FieldTable::default()
+    ).await
+}
+
+/// Publishes a change event, to be called once the transaction that made it has committed
+///
+/// Failing to publish is only logged, clients notice the missing sequence number and catch up
+/// from the journal.
+pub async fn publish_change(channel: &Channel, event: &ChangeEvent) {
+    let payload = match event.to_msgpack() {
+        Ok(payload) => payload,
+        Err(err) => {
+            error!("Failed to encode change event {}: {}", event.sequence, err);
+            return;
+        }
+    };
+
+    let published = channel.basic_publish(
+        CHANGE_EXCHANGE,
+        &event.zone_id,
+        BasicPublishOptions::default(),
+        &payload,
+        BasicProperties::default()
+            .with_content_type("application/msgpack".into())
+            .with_delivery_mode(2)
+    ).await;
+
+    if let Err(err) = published {
+        error!("Failed to publish change event {} for zone {}: {}", event.sequence, event.zone_id, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn test_zone(dnssec: bool) -> zone::Model {
+        zone::Model {
+            id: "zone".to_string(),
+            owner: "team".to_string(),
+            origin: "example.com".to_string(),
+            delegated: true,
+            delegation_checked: None,
+            delegation_error: None,
+            dnssec
+        }
+    }
+
+    fn soa(serial: u32) -> (record::Model, RecordTypes) {
+        let soa = RecordTypes::SOA {
+            ttl: 3600,
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300
+        };
+
+        let model = record::Model {
+            id: "soa".to_string(),
+            zone: "zone".to_string(),
+            value: soa.to_msgpack().unwrap(),
+            active: true
+        };
+
+        (model, soa)
+    }
+
+    fn upserted(id: &str, record: RecordTypes) -> Change {
+        Change::RecordUpserted { record: SyncedRecord { id: id.to_string(), active: true, record } }
+    }
+
+    fn a_record() -> RecordTypes {
+        RecordTypes::A { hostname: "www.example.com".to_string(), ttl: 300, address: Ipv4Addr::new(192, 0, 2, 1) }
+    }
+
+    /// Changes by their record id, or their variant for those without one
+    fn summary(changes: &[Change]) -> Vec<String> {
+        changes.iter().map(|change| match change {
+            Change::ZoneCreated => "ZoneCreated".to_string(),
+            Change::ZoneDeleted => "ZoneDeleted".to_string(),
+            Change::RecordUpserted { record } => format!("upserted {}", record.id),
+            Change::RecordDeleted { record_id } => format!("deleted {}", record_id),
+            Change::ZoneResync => "ZoneResync".to_string()
+        }).collect()
+    }
+
+    #[test]
+    fn settle_changes_appends_the_soa() {
+        let (serial, changes) = settle_changes(&test_zone(false), vec![upserted("a", a_record())], Some(soa(2022100902)));
+
+        assert_eq!(serial, Some(2022100902));
+        assert_eq!(summary(&changes), vec!["upserted a", "upserted soa"]);
+        assert!(matches!(&changes[1], Change::RecordUpserted { record } if record.record == soa(2022100902).1));
+    }
+
+    #[test]
+    fn settle_changes_deduplicates_the_soa() {
+        // The SOA the change was made with is replaced by the one carrying the bumped serial
+        let changes = vec![upserted("soa", soa(2022100901).1), Change::RecordDeleted { record_id: "b".to_string() }];
+        let (serial, changes) = settle_changes(&test_zone(false), changes, Some(soa(2022100902)));
+
+        assert_eq!(serial, Some(2022100902));
+        assert_eq!(summary(&changes), vec!["deleted b", "upserted soa"]);
+        assert!(matches!(&changes[1], Change::RecordUpserted { record } if record.record == soa(2022100902).1));
+    }
+
+    #[test]
+    fn settle_changes_without_an_soa() {
+        let (serial, changes) = settle_changes(&test_zone(false), vec![Change::ZoneDeleted], None);
+
+        assert_eq!(serial, None);
+        assert_eq!(summary(&changes), vec!["ZoneDeleted"]);
+    }
+
+    #[test]
+    fn settle_changes_resyncs_signed_zones() {
+        let (serial, changes) = settle_changes(&test_zone(true), vec![upserted("a", a_record())], Some(soa(2022100902)));
+
+        assert_eq!(serial, Some(2022100902));
+        assert_eq!(summary(&changes), vec!["ZoneResync"]);
+    }
+
+    #[test]
+    fn settle_changes_keeps_signed_zone_deletions() {
+        let changes = vec![Change::RecordDeleted { record_id: "a".to_string() }, Change::ZoneDeleted];
+        let (_, changes) = settle_changes(&test_zone(true), changes, None);
+
+        assert_eq!(summary(&changes), vec!["deleted a", "ZoneDeleted"]);
+    }
+
+    #[test]
+    fn change_event_msgpack_round_trip() {
+        let event = ChangeEvent {
+            version: CHANGE_EVENT_VERSION,
+            sequence: 42,
+            zone_id: "zone".to_string(),
+            origin: "example.com".to_string(),
+            serial: Some(2022100902),
+            changes: vec![
+                Change::ZoneCreated,
+                upserted("a", a_record()),
+                Change::RecordDeleted { record_id: "b".to_string() },
+                Change::ZoneResync,
+                Change::ZoneDeleted
+            ]
+        };
+
+        let decoded = ChangeEvent::from_msgpack(&event.to_msgpack().unwrap()).unwrap();
+
+        assert_eq!((decoded.version, decoded.sequence, decoded.serial), (CHANGE_EVENT_VERSION, 42, Some(2022100902)));
+        assert_eq!((decoded.zone_id.as_str(), decoded.origin.as_str()), ("zone", "example.com"));
+        assert_eq!(summary(&decoded.changes), summary(&event.changes));
+        assert!(matches!(&decoded.changes[1], Change::RecordUpserted { record } if record.record == a_record() && record.active));
+    }
+}
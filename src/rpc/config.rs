@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dns::nameservers;
+use crate::entities::client;
+use crate::rpc::changes::{CHANGE_EVENT_VERSION, CHANGE_EXCHANGE};
+use crate::rpc::proxies::{PROXY_EVENT_VERSION, PROXY_EXCHANGE};
+
+/// What a client needs to know to do its job
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClientConfig {
+    pub client_id: String,
+    pub name: String,
+    /// Whether the client serves zones
+    pub dns: bool,
+    /// Whether the client proxies traffic
+    pub proxy: bool,
+    pub nameservers: Vec<String>,
+    /// Exchange to bind to for change events
+    pub change_exchange: String,
+    pub change_event_version: u8,
+    /// Exchange proxy-capable clients bind to for proxy events
+    pub proxy_exchange: String,
+    pub proxy_event_version: u8
+}
+
+pub fn client_config(caller: &client::Model) -> ClientConfig {
+    ClientConfig {
+        client_id: caller.id.clone(),
+        name: caller.name.clone(),
+        dns: caller.dns,
+        proxy: caller.proxy,
+        nameservers: nameservers(),
+        change_exchange: CHANGE_EXCHANGE.to_string(),
+        change_event_version: CHANGE_EVENT_VERSION,
+        proxy_exchange: PROXY_EXCHANGE.to_string(),
+        proxy_event_version: PROXY_EVENT_VERSION
+    }
+}
@@ -0,0 +1,48 @@
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entities::client;
+use crate::entities::prelude::ChangeSequence;
+use crate::routes::status::Health;
+use crate::rpc::RpcError;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HeartbeatRequest {
+    pub health: Health,
+    pub version: String,
+    /// The last change sequence number the client has applied, if it serves DNS
+    #[serde(default)]
+    pub sequence: Option<i64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HeartbeatReply {
+    /// The latest change sequence number, clients behind it should catch up from the journal
+    pub sequence: i64
+}
+
+/// Records a client's reported health
+pub async fn heartbeat(caller: client::Model, request: HeartbeatRequest, connection: &DatabaseConnection) -> Result<HeartbeatReply, RpcError> {
+    let client_id = caller.id.clone();
+
+    if caller.health != request.health.to_string() {
+        info!("Client {} ({}) is now {}", caller.name, request.version, request.health);
+
+        let mut updated_client: client::ActiveModel = caller.into();
+        updated_client.health = ActiveValue::Set(request.health.to_string());
+        updated_client.update(connection).await?;
+    }
+
+    let sequence = ChangeSequence::find()
+        .one(connection)
+        .await?
+        .map_or(0, |counter| counter.sequence);
+
+    if let Some(client_sequence) = request.sequence {
+        if client_sequence < sequence {
+            debug!("Client {} is {} changes behind", client_id, sequence - client_sequence);
+        }
+    }
+
+    Ok(HeartbeatReply { sequence })
+}
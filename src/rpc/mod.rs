@@ -1,13 +1,311 @@
-use std::net::SocketAddr;
+pub mod certificate;
+pub mod changes;
+pub mod sync;
+pub mod heartbeat;
+pub mod config;
+pub mod proxies;
+pub mod tls;
+
+use std::net::{IpAddr, SocketAddr};
+
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use axum::body::Bytes;
 use axum::Extension;
 use axum::extract::ConnectInfo;
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
-use sea_orm::DatabaseConnection;
+use picky::x509::Cert;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::Types::CLIENTLEAF;
+use crate::entities::client;
+use crate::entities::prelude::{Certificate, Client};
+use crate::rpc::certificate::CertificateBundle;
+use crate::rpc::config::ClientConfig;
+use crate::rpc::heartbeat::{HeartbeatReply, HeartbeatRequest};
+use crate::rpc::proxies::{ProxyCertificateBundle, ProxyCertificateRequest, ProxySnapshot};
+use crate::rpc::sync::{Journal, JournalRequest, Snapshot, SnapshotRequest, SyncError};
+use crate::rpc::tls::PeerCertificate;
+
+/// A call from a client, sent MessagePack encoded as the body of a POST to /rpc
+#[derive(Deserialize)]
+pub struct RpcRequest {
+    /// Chosen by the client and echoed back, so it can match up responses
+    pub id: u64,
+    /// Optional over mutual TLS, where the certificate identifies the client
+    #[serde(default)]
+    pub client_id: String,
+    /// Unused over mutual TLS
+    #[serde(default)]
+    pub key: String,
+    pub method: RpcMethod
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "name", content = "payload", rename_all = "snake_case")]
+pub enum RpcMethod {
+    Heartbeat(HeartbeatRequest),
+    Config,
+    Certificate,
+    Snapshot(SnapshotRequest),
+    Journal(JournalRequest),
+    Proxies,
+    ProxyCertificate(ProxyCertificateRequest)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "name", content = "payload", rename_all = "snake_case")]
+pub enum RpcReply {
+    Heartbeat(HeartbeatReply),
+    Config(ClientConfig),
+    Certificate(CertificateBundle),
+    Snapshot(Snapshot),
+    Journal(Journal),
+    Proxies(ProxySnapshot),
+    ProxyCertificate(ProxyCertificateBundle)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RpcErrorCode {
+    MALFORMED,
+    UNAUTHORISED,
+    FORBIDDEN,
+    NOTFOUND,
+    INTERNAL
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RpcError {
+    pub code: RpcErrorCode,
+    pub message: String
+}
+
+impl RpcError {
+    pub fn new(code: RpcErrorCode, message: &str) -> RpcError {
+        RpcError { code, message: message.to_string() }
+    }
+
+    pub fn internal() -> RpcError {
+        RpcError::new(RpcErrorCode::INTERNAL, "An internal server error has occurred")
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.code {
+            RpcErrorCode::MALFORMED => StatusCode::BAD_REQUEST,
+            RpcErrorCode::UNAUTHORISED => StatusCode::UNAUTHORIZED,
+            RpcErrorCode::FORBIDDEN => StatusCode::FORBIDDEN,
+            RpcErrorCode::NOTFOUND => StatusCode::NOT_FOUND,
+            RpcErrorCode::INTERNAL => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+impl From<DbErr> for RpcError {
+    fn from(err: DbErr) -> Self {
+        error!("Database error while handling RPC: {}", err);
+        RpcError::internal()
+    }
+}
+
+impl From<SyncError> for RpcError {
+    fn from(err: SyncError) -> Self {
+        match err {
+            SyncError::UnknownZone(_) => RpcError { code: RpcErrorCode::NOTFOUND, message: err.to_string() },
+            err => {
+                error!("Failed to sync zones to a client: {}", err);
+                RpcError::internal()
+            }
+        }
+    }
+}
+
+/// The answer to an RpcRequest, holding either a reply or an error
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RpcResponse {
+    /// Missing when the request was too malformed to read its id
+    pub id: Option<u64>,
+    pub reply: Option<RpcReply>,
+    pub error: Option<RpcError>
+}
+
+impl IntoResponse for RpcResponse {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.error.as_ref().map_or(StatusCode::OK, |err| err.status());
+
+        match rmp_serde::to_vec_named(&self) {
+            Ok(body) => (status, [(header::CONTENT_TYPE, "application/msgpack")], body).into_response(),
+            Err(err) => {
+                error!("Failed to encode RPC response: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()).into_response()
+            }
+        }
+    }
+}
+
+/// Checks a client's key, and that it is active and calling from its own address
+///
+/// Keys are stored as Argon2 hashes, the same as user passwords.
+pub async fn authenticate_client(client_id: &str, key: &str, ip: IpAddr, connection: &DatabaseConnection) -> Result<client::Model, RpcError> {
+    let unauthorised = || RpcError::new(RpcErrorCode::UNAUTHORISED, "Client credentials are invalid");
+
+    let requested_client = Client::find_by_id(client_id.to_string())
+        .one(connection)
+        .await?
+        .ok_or_else(unauthorised)?;
+
+    let key_hash = PasswordHash::new(&requested_client.key).map_err(|_| {
+        error!("Client {} has a malformed key hash!", requested_client.id);
+        RpcError::internal()
+    })?;
+
+    if Argon2::default().verify_password(key.as_bytes(), &key_hash).is_err() {
+        return Err(unauthorised());
+    }
+
+    if requested_client.ip.parse::<IpAddr>().ok() != Some(ip) {
+        warn!("Client {} called from unexpected address {}", requested_client.id, ip);
+        return Err(unauthorised());
+    }
+
+    if !requested_client.active {
+        return Err(RpcError::new(RpcErrorCode::FORBIDDEN, "Client is deactivated"));
+    }
+
+    Ok(requested_client)
+}
+
+/// Finds the client a verified certificate was issued to
+///
+/// Renewals re-sign the same key and subject, so a client's previous certificate keeps working
+/// until it expires or the client fetches the renewed one.
+pub async fn authenticate_certificate(peer_certificate: &PeerCertificate, connection: &DatabaseConnection) -> Result<client::Model, RpcError> {
+    let unauthorised = || RpcError::new(RpcErrorCode::UNAUTHORISED, "Client certificate is not recognised");
+
+    let presented_certificate = Cert::from_der(&peer_certificate.0).map_err(|_| unauthorised())?;
+    let client_name = presented_certificate.subject_name()
+        .find_common_name()
+        .map(|common_name| common_name.to_string())
+        .ok_or_else(unauthorised)?;
+
+    let requested_client = Client::find()
+        .filter(client::Column::Name.eq(client_name))
+        .one(connection)
+        .await?
+        .ok_or_else(unauthorised)?;
+
+    let stored_model = Certificate::find_by_id(requested_client.certificate.clone())
+        .one(connection)
+        .await?
+        .filter(|stored_model| stored_model.cert_type == CLIENTLEAF.to_string())
+        .ok_or_else(unauthorised)?;
+
+    if stored_model.revoked.is_some() {
+        warn!("Client {} presented its revoked certificate", requested_client.id);
+        return Err(RpcError::new(RpcErrorCode::UNAUTHORISED, "Client certificate has been revoked"));
+    }
+
+    let stored_certificate = Cert::from_der(&stored_model.data).map_err(|_| unauthorised())?;
+
+    if stored_certificate.public_key() != presented_certificate.public_key() {
+        warn!("Client {} presented a certificate for a different key", requested_client.id);
+        return Err(unauthorised());
+    }
+
+    if !requested_client.active {
+        return Err(RpcError::new(RpcErrorCode::FORBIDDEN, "Client is deactivated"));
+    }
+
+    Ok(requested_client)
+}
+
+/// Runs a method on behalf of an authenticated client
+pub async fn dispatch(caller: client::Model, method: RpcMethod, connection: &DatabaseConnection) -> Result<RpcReply, RpcError> {
+    match method {
+        RpcMethod::Heartbeat(request) => Ok(RpcReply::Heartbeat(heartbeat::heartbeat(caller, request, connection).await?)),
+        RpcMethod::Config => Ok(RpcReply::Config(config::client_config(&caller))),
+        RpcMethod::Certificate => Ok(RpcReply::Certificate(certificate::client_certificate(&caller, connection).await?)),
+        RpcMethod::Snapshot(request) => {
+            require_dns(&caller)?;
+            Ok(RpcReply::Snapshot(sync::snapshot(&request, connection).await?))
+        }
+        RpcMethod::Journal(request) => {
+            require_dns(&caller)?;
+            Ok(RpcReply::Journal(sync::journal(&request, connection).await?))
+        }
+        RpcMethod::Proxies => {
+            require_proxy(&caller)?;
+            Ok(RpcReply::Proxies(proxies::proxy_snapshot(connection).await?))
+        }
+        RpcMethod::ProxyCertificate(request) => {
+            require_proxy(&caller)?;
+            Ok(RpcReply::ProxyCertificate(proxies::proxy_certificate(&request, connection).await?))
+        }
+    }
+}
+
+fn require_dns(caller: &client::Model) -> Result<(), RpcError> {
+    match caller.dns {
+        true => Ok(()),
+        false => Err(RpcError::new(RpcErrorCode::FORBIDDEN, "Client doesn't serve DNS"))
+    }
+}
+
+fn require_proxy(caller: &client::Model) -> Result<(), RpcError> {
+    match caller.proxy {
+        true => Ok(()),
+        false => Err(RpcError::new(RpcErrorCode::FORBIDDEN, "Client doesn't proxy traffic"))
+    }
+}
+
+fn decode_request(body: &[u8]) -> Result<RpcRequest, RpcError> {
+    rmp_serde::from_slice(body).map_err(|err| RpcError { code: RpcErrorCode::MALFORMED, message: err.to_string() })
+}
+
+fn respond(id: u64, result: Result<RpcReply, RpcError>) -> RpcResponse {
+    match result {
+        Ok(reply) => RpcResponse { id: Some(id), reply: Some(reply), error: None },
+        Err(err) => RpcResponse { id: Some(id), reply: None, error: Some(err) }
+    }
+}
 
 pub async fn rpc(
     Extension(ref connection): Extension<DatabaseConnection>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse{
-    (StatusCode::INTERNAL_SERVER_ERROR, "NOT IMPLEMENTED")
-}
\ No newline at end of file
+    body: Bytes
+) -> RpcResponse {
+    let request = match decode_request(&body) {
+        Ok(request) => request,
+        Err(err) => return RpcResponse { id: None, reply: None, error: Some(err) }
+    };
+
+    let result = match authenticate_client(&request.client_id, &request.key, addr.ip(), connection).await {
+        Ok(caller) => dispatch(caller, request.method, connection).await,
+        Err(err) => Err(err)
+    };
+
+    respond(request.id, result)
+}
+
+/// Serves /rpc on the mutual TLS listener, where clients are identified by their certificate
+pub async fn rpc_mtls(
+    Extension(ref connection): Extension<DatabaseConnection>,
+    Extension(ref peer_certificate): Extension<PeerCertificate>,
+    body: Bytes
+) -> RpcResponse {
+    let request = match decode_request(&body) {
+        Ok(request) => request,
+        Err(err) => return RpcResponse { id: None, reply: None, error: Some(err) }
+    };
+
+    let result = match authenticate_certificate(peer_certificate, connection).await {
+        Ok(caller) if !request.client_id.is_empty() && caller.id != request.client_id => {
+            warn!("Client {} presented its certificate as client {}", caller.id, request.client_id);
+            Err(RpcError::new(RpcErrorCode::UNAUTHORISED, "Client certificate doesn't belong to this client"))
+        }
+        Ok(caller) => dispatch(caller, request.method, connection).await,
+        Err(err) => Err(err)
+    };
+
+    respond(request.id, result)
+}
@@ -0,0 +1,217 @@
+use lapin::{BasicProperties, Channel, ExchangeKind};
+use lapin::options::{BasicPublishOptions, ExchangeDeclareOptions};
+use lapin::types::FieldTable;
+use picky::pem::Pem;
+use picky::x509::Cert;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::{decrypt_priv_key, load_issuer_chain};
+use crate::cert::acme::split_chain;
+use crate::cert::rotation::mark_fetched;
+use crate::cert::Types::PROXYINTER;
+use crate::dns::records::RecordTypes;
+use crate::entities::{proxy, record};
+use crate::entities::prelude::{Certificate, Proxy, Record};
+use crate::routes::proxies::{proxied_hostname, split_upstreams};
+use crate::rpc::{RpcError, RpcErrorCode};
+use crate::rpc::certificate::pem;
+
+/// Bumped whenever the layout of proxy events changes in a way clients need to know about
+pub const PROXY_EVENT_VERSION: u8 = 1;
+/// Fanout exchange proxy events are published to, proxy-capable clients bind their own queues to it
+pub const PROXY_EXCHANGE: &str = "driptorch.proxies";
+
+/// A proxy as proxy-capable clients see it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncedProxy {
+    pub id: String,
+    /// Name of the record the proxy is attached to, which its certificate is issued for
+    pub hostname: String,
+    pub port: u16,
+    /// Where traffic is forwarded to, each as `host:port`
+    pub upstreams: Vec<String>,
+    /// Cleared when either the proxy or its record is deactivated
+    pub active: bool,
+    /// Changes whenever the proxy gets a new certificate, which is then fetched with `proxy_certificate`
+    pub certificate_id: String
+}
+
+impl SyncedProxy {
+    /// Returns None when the record isn't one proxies can be attached to
+    pub fn from_models(model: &proxy::Model, record_model: &record::Model) -> Option<SyncedProxy> {
+        let hostname = RecordTypes::from_msgpack(&record_model.value)
+            .ok()
+            .and_then(|record| proxied_hostname(&record))?;
+
+        Some(SyncedProxy {
+            id: model.id.clone(),
+            hostname,
+            port: u16::try_from(model.port).ok()?,
+            upstreams: split_upstreams(&model.upstreams),
+            active: model.active && record_model.active,
+            certificate_id: model.certificate.clone()
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ProxyChange {
+    ProxyUpserted { proxy: SyncedProxy },
+    ProxyDeleted { proxy_id: String }
+}
+
+/// Proxies that changed together, such as every proxy on a deleted record
+///
+/// Events aren't journaled, clients that missed one take a snapshot instead.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProxyEvent {
+    pub version: u8,
+    pub changes: Vec<ProxyChange>
+}
+
+/// Every proxy, including deactivated ones
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProxySnapshot {
+    pub proxies: Vec<SyncedProxy>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProxyCertificateRequest {
+    pub proxy_id: String
+}
+
+/// A proxy's certificate alongside its key, which was generated by the controller
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProxyCertificateBundle {
+    pub certificate_id: String,
+    /// PEM encoded proxy certificate
+    pub certificate: String,
+    /// PEM encoded certificates above it, either the ACME CA's chain or our intermediate and root
+    pub chain: Vec<String>,
+    /// PEM encoded PKCS#8 private key
+    pub key: String
+}
+
+/// Declares the exchange proxy events are published to
+pub async fn declare_proxy_exchange(channel: &Channel) -> Result<(), lapin::Error> {
+    channel.exchange_declare(
+        PROXY_EXCHANGE,
+        ExchangeKind::Fanout,
+        ExchangeDeclareOptions { durable: true, ..ExchangeDeclareOptions::default() },
+        FieldTable::default()
+    ).await
+}
+
+/// Publishes changes to proxies, to be called once the transaction that made them has committed
+pub async fn publish_proxy_changes(channel: &Channel, changes: Vec<ProxyChange>) {
+    if changes.is_empty() {
+        return;
+    }
+
+    let event = ProxyEvent { version: PROXY_EVENT_VERSION, changes };
+
+    let payload = match rmp_serde::to_vec_named(&event) {
+        Ok(payload) => payload,
+        Err(err) => {
+            error!("Failed to encode proxy event: {}", err);
+            return;
+        }
+    };
+
+    let published = channel.basic_publish(
+        PROXY_EXCHANGE,
+        "proxy",
+        BasicPublishOptions::default(),
+        &payload,
+        BasicProperties::default()
+            .with_content_type("application/msgpack".into())
+            .with_delivery_mode(2)
+    ).await;
+
+    if let Err(err) = published {
+        error!("Failed to publish proxy event: {}", err);
+    }
+}
+
+/// Builds the upserts for proxies attached to the same record
+pub fn proxy_upserts(proxies: &[proxy::Model], record_model: &record::Model) -> Vec<ProxyChange> {
+    proxies.iter()
+        .filter_map(|model| SyncedProxy::from_models(model, record_model))
+        .map(|proxy| ProxyChange::ProxyUpserted { proxy })
+        .collect()
+}
+
+/// Gets the proxies attached to a record
+pub async fn get_record_proxies<C: ConnectionTrait>(record_id: &str, connection: &C) -> Result<Vec<proxy::Model>, DbErr> {
+    Proxy::find()
+        .filter(proxy::Column::Record.eq(record_id))
+        .all(connection)
+        .await
+}
+
+pub async fn proxy_snapshot(connection: &DatabaseConnection) -> Result<ProxySnapshot, RpcError> {
+    let proxies = Proxy::find()
+        .find_also_related(Record)
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter_map(|(model, record_model)| SyncedProxy::from_models(&model, &record_model?))
+        .collect();
+
+    Ok(ProxySnapshot { proxies })
+}
+
+pub async fn proxy_certificate(request: &ProxyCertificateRequest, connection: &DatabaseConnection) -> Result<ProxyCertificateBundle, RpcError> {
+    let requested_proxy = Proxy::find_by_id(request.proxy_id.clone())
+        .one(connection)
+        .await?
+        .ok_or_else(|| RpcError::new(RpcErrorCode::NOTFOUND, "Proxy does not exist"))?;
+
+    let leaf_model = Certificate::find_by_id(requested_proxy.certificate.clone())
+        .one(connection)
+        .await?
+        .ok_or_else(|| RpcError::new(RpcErrorCode::NOTFOUND, "Proxy has no certificate"))?;
+
+    let pkcs8 = match (&leaf_model.nonce, &leaf_model.key) {
+        (Some(nonce), Some(key)) => decrypt_priv_key(nonce, key).await,
+        _ => {
+            error!("Proxy cert {} has no private key!", leaf_model.id);
+            return Err(RpcError::internal());
+        }
+    };
+
+    let mut chain = vec![];
+
+    match &leaf_model.chain {
+        Some(acme_chain) => {
+            let acme_chain = split_chain(acme_chain).map_err(|err| {
+                error!("Failed to split the chain of ACME cert {}: {}", leaf_model.id, err);
+                RpcError::internal()
+            })?;
+
+            for chain_cert in acme_chain {
+                chain.push(pem(&chain_cert)?);
+            }
+        }
+        None => {
+            let leaf_cert = Cert::from_der(&leaf_model.data).map_err(|err| {
+                error!("Failed to decode proxy cert {}: {}", leaf_model.id, err);
+                RpcError::internal()
+            })?;
+
+            for chain_cert in load_issuer_chain(&leaf_cert, PROXYINTER, connection).await?.ok_or_else(RpcError::internal)? {
+                chain.push(pem(&chain_cert.to_der().map_err(|_| RpcError::internal())?)?);
+            }
+        }
+    }
+
+    mark_fetched(&leaf_model.id, connection).await?;
+
+    Ok(ProxyCertificateBundle {
+        certificate_id: leaf_model.id.clone(),
+        certificate: pem(&leaf_model.data)?,
+        chain,
+        key: Pem::new("PRIVATE KEY", pkcs8.as_slice()).to_string()
+    })
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+use chrono::Utc;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cert::dnssec::load_signing_keys;
+use crate::dns::dnssec::{DnssecError, sign_zone, SigningKey};
+use crate::dns::records::RecordTypes;
+use crate::entities::{change_journal, zone};
+use crate::entities::prelude::{ChangeJournal, ChangeSequence, Zone};
+use crate::routes::records::get_zone_records;
+use crate::rpc::changes::{ChangeEvent, lock_sequence, SyncedRecord};
+
+/// Most events handed out by one journal request, clients ask again for the rest
+const JOURNAL_PAGE_SIZE: u64 = 500;
+/// How long journal entries are kept, clients that fall further behind take a snapshot instead
+const JOURNAL_RETENTION_DAYS: i64 = 7;
+/// How often old journal entries are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub enum SyncError {
+    Database(DbErr),
+    /// A signed zone couldn't be signed for the snapshot
+    Signing(String, DnssecError),
+    UnknownZone(String)
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Database(err) => write!(f, "{}", err),
+            SyncError::Signing(zone_id, err) => write!(f, "Failed to sign zone {}: {}", zone_id, err),
+            SyncError::UnknownZone(zone_id) => write!(f, "Zone {} does not exist", zone_id)
+        }
+    }
+}
+
+impl From<DbErr> for SyncError {
+    fn from(err: DbErr) -> Self {
+        SyncError::Database(err)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotRequest {
+    /// Limits the snapshot to one zone, such as after it was resynced
+    #[serde(default)]
+    pub zone_id: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ZoneSnapshot {
+    pub zone_id: String,
+    pub origin: String,
+    pub serial: Option<u32>,
+    /// The zone's active records
+    pub records: Vec<SyncedRecord>,
+    /// What signed zones are served as, their records alongside DNSKEY, NSEC3 and RRSIG records
+    pub signed: Option<Vec<RecordTypes>>
+}
+
+/// Every zone as it stood at `sequence`, journal requests carry on from there
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub sequence: i64,
+    pub zones: Vec<ZoneSnapshot>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalRequest {
+    /// The last sequence number the client has applied
+    pub since: i64
+}
+
+/// The changes following a client's sequence number, in order
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Journal {
+    /// The last sequence number included, which the client is at once these are applied
+    pub sequence: i64,
+    pub events: Vec<ChangeEvent>,
+    /// Set when the journal no longer goes back far enough and a snapshot is needed instead
+    pub resync: bool,
+    /// Set when there are more events than fit in one response
+    pub more: bool
+}
+
+/// Takes a snapshot of every zone's active records, or only one zone's
+///
+/// The change sequence is locked while reading so the snapshot lines up exactly with its
+/// sequence number. Signing happens afterwards so writers aren't held up by it.
+pub async fn snapshot(request: &SnapshotRequest, connection: &DatabaseConnection) -> Result<Snapshot, SyncError> {
+    let txn = connection.begin().await?;
+
+    let sequence = lock_sequence(&txn).await?;
+
+    let zones: Vec<zone::Model> = match &request.zone_id {
+        Some(zone_id) => vec![Zone::find_by_id(zone_id.clone())
+            .one(&txn)
+            .await?
+            .ok_or_else(|| SyncError::UnknownZone(zone_id.clone()))?],
+        None => Zone::find().all(&txn).await?
+    };
+
+    let mut read_zones: Vec<(zone::Model, Vec<SyncedRecord>, Vec<SigningKey>)> = vec![];
+
+    for read_zone in zones {
+        let records: Vec<SyncedRecord> = get_zone_records(&read_zone.id, &txn)
+            .await
+            .into_iter()
+            .filter(|(model, _)| model.active)
+            .map(|(model, record)| SyncedRecord { id: model.id, active: model.active, record })
+            .collect();
+
+        let keys = if read_zone.dnssec {
+            load_signing_keys(&read_zone, &txn).await?
+        } else {
+            vec![]
+        };
+
+        read_zones.push((read_zone, records, keys));
+    }
+
+    txn.commit().await?;
+
+    let now = Utc::now().timestamp();
+    let mut zones: Vec<ZoneSnapshot> = vec![];
+
+    for (read_zone, records, keys) in read_zones {
+        let serial = records.iter().find_map(|synced| match synced.record {
+            RecordTypes::SOA { serial, .. } => Some(serial),
+            _ => None
+        });
+
+        let signed = if read_zone.dnssec {
+            let unsigned: Vec<RecordTypes> = records.iter().map(|synced| synced.record.clone()).collect();
+
+            Some(sign_zone(&read_zone.origin, &unsigned, &keys, now)
+                .map_err(|err| SyncError::Signing(read_zone.id.clone(), err))?)
+        } else {
+            None
+        };
+
+        zones.push(ZoneSnapshot {
+            zone_id: read_zone.id,
+            origin: read_zone.origin,
+            serial,
+            records,
+            signed
+        });
+    }
+
+    Ok(Snapshot { sequence, zones })
+}
+
+/// Gets the journal of changes since a client's sequence number
+pub async fn journal(request: &JournalRequest, connection: &DatabaseConnection) -> Result<Journal, SyncError> {
+    let latest = ChangeSequence::find()
+        .one(connection)
+        .await?
+        .map_or(0, |counter| counter.sequence);
+
+    let entries = ChangeJournal::find()
+        .filter(change_journal::Column::Sequence.gt(request.since))
+        .order_by_asc(change_journal::Column::Sequence)
+        .limit(JOURNAL_PAGE_SIZE)
+        .all(connection)
+        .await?;
+
+    Ok(journal_page(request.since, latest, entries))
+}
+
+/// Turns a page of journal entries following `since` into a journal, or asks the client to
+/// resync when they don't carry on from it
+fn journal_page(since: i64, latest: i64, entries: Vec<change_journal::Model>) -> Journal {
+    let resync = Journal { sequence: latest, events: vec![], resync: true, more: false };
+
+    // A client ahead of the controller is following a different history
+    if since > latest {
+        return resync;
+    }
+
+    // Sequence numbers have no gaps, so anything but the next one means it was pruned
+    if since < latest && entries.first().map(|entry| entry.sequence) != Some(since + 1) {
+        return resync;
+    }
+
+    let mut events: Vec<ChangeEvent> = vec![];
+    for entry in entries {
+        match ChangeEvent::from_msgpack(&entry.data) {
+            Ok(event) => events.push(event),
+            Err(_) => {
+                error!("Journal entry {} could not be decoded!", entry.sequence);
+                return resync;
+            }
+        }
+    }
+
+    let sequence = events.last().map_or(since, |event| event.sequence);
+
+    Journal { sequence, events, resync: false, more: sequence < latest }
+}
+
+/// Removes journal entries past their retention on an interval, for the lifetime of the controller
+pub async fn run_journal_pruning(connection: DatabaseConnection) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let cutoff = Utc::now().naive_utc() - chrono::Duration::days(JOURNAL_RETENTION_DAYS);
+
+        let pruned = ChangeJournal::delete_many()
+            .filter(change_journal::Column::Created.lt(cutoff))
+            .exec(&connection)
+            .await;
+
+        match pruned {
+            Ok(pruned) if pruned.rows_affected > 0 => debug!("Pruned {} change journal entries", pruned.rows_affected),
+            Ok(_) => {}
+            Err(err) => error!("Failed to prune the change journal: {}", err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rpc::changes::{Change, CHANGE_EVENT_VERSION};
+
+    use super::*;
+
+    fn entry(sequence: i64) -> change_journal::Model {
+        let event = ChangeEvent {
+            version: CHANGE_EVENT_VERSION,
+            sequence,
+            zone_id: "zone".to_string(),
+            origin: "example.com".to_string(),
+            serial: Some(2022100900 + sequence as u32),
+            changes: vec![Change::ZoneResync]
+        };
+
+        change_journal::Model {
+            sequence,
+            zone: event.zone_id.clone(),
+            data: event.to_msgpack().unwrap(),
+            created: Utc::now().naive_utc()
+        }
+    }
+
+    fn entries(sequences: std::ops::RangeInclusive<i64>) -> Vec<change_journal::Model> {
+        sequences.map(entry).collect()
+    }
+
+    fn sequences(journal: &Journal) -> Vec<i64> {
+        journal.events.iter().map(|event| event.sequence).collect()
+    }
+
+    #[test]
+    fn journal_page_follows_since() {
+        let journal = journal_page(3, 5, entries(4..=5));
+
+        assert_eq!(sequences(&journal), vec![4, 5]);
+        assert_eq!((journal.sequence, journal.resync, journal.more), (5, false, false));
+    }
+
+    #[test]
+    fn journal_page_up_to_date() {
+        let journal = journal_page(5, 5, vec![]);
+
+        assert!(journal.events.is_empty());
+        assert_eq!((journal.sequence, journal.resync, journal.more), (5, false, false));
+    }
+
+    #[test]
+    fn journal_page_ahead_of_latest() {
+        let journal = journal_page(7, 5, vec![]);
+
+        assert!(journal.events.is_empty());
+        assert_eq!((journal.sequence, journal.resync, journal.more), (5, true, false));
+    }
+
+    #[test]
+    fn journal_page_pruned_head() {
+        // Entries 4 and 5 were pruned, so the client can't carry on from 3
+        let journal = journal_page(3, 8, entries(6..=8));
+
+        assert!(journal.events.is_empty());
+        assert_eq!((journal.sequence, journal.resync, journal.more), (8, true, false));
+
+        let journal = journal_page(3, 8, vec![]);
+        assert!(journal.resync);
+    }
+
+    #[test]
+    fn journal_page_undecodable_entry() {
+        let mut page = entries(4..=5);
+        page[1].data = vec![0xc1];
+
+        assert!(journal_page(3, 5, page).resync);
+    }
+
+    #[test]
+    fn journal_page_sets_more() {
+        let page_size = JOURNAL_PAGE_SIZE as i64;
+        let journal = journal_page(0, page_size + 100, entries(1..=page_size));
+
+        assert_eq!(journal.events.len(), JOURNAL_PAGE_SIZE as usize);
+        assert_eq!((journal.sequence, journal.resync, journal.more), (page_size, false, true));
+
+        let journal = journal_page(page_size, page_size + 100, entries(page_size + 1..=page_size + 100));
+        assert_eq!((journal.sequence, journal.resync, journal.more), (page_size + 100, false, false));
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{Extension, Router};
+use axum::routing::post;
+use hyper::server::conn::Http;
+use sea_orm::*;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::TlsAcceptor;
+
+use crate::cert::Types::CLIENTINTER;
+use crate::entities::certificate;
+use crate::rpc::{authenticate_certificate, rpc_mtls};
+
+/// The certificate a client presented during the handshake, already verified against a
+/// client intermediate
+#[derive(Clone)]
+pub struct PeerCertificate(pub Vec<u8>);
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Reads a PEM certificate chain, leaf first
+fn load_certificates(path: &Path) -> io::Result<Vec<Certificate>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+
+    if certificates.is_empty() {
+        return Err(invalid_data(format!("No certificates found in {}", path.display())));
+    }
+
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first PKCS#8, PKCS#1 or SEC1 private key from a PEM file
+fn load_private_key(path: &Path) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(invalid_data(format!("No private key found in {}", path.display())))
+        }
+    }
+}
+
+/// Every client intermediate that hasn't been revoked, retiring ones included so clients keep
+/// connecting with their old certificate until they fetch the re-issued one
+async fn load_client_cas(connection: &DatabaseConnection) -> Result<Vec<Vec<u8>>, DbErr> {
+    Ok(certificate::Entity::find()
+        .filter(certificate::Column::CertType.eq(CLIENTINTER.to_string()))
+        .filter(certificate::Column::Revoked.is_null())
+        .order_by_asc(certificate::Column::Id)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|inter_model| inter_model.data)
+        .collect())
+}
+
+fn build_acceptor(certificates: &[Certificate], private_key: &PrivateKey, client_cas: &[Vec<u8>]) -> io::Result<TlsAcceptor> {
+    let mut client_roots = RootCertStore::empty();
+
+    for client_ca in client_cas {
+        client_roots.add(&Certificate(client_ca.clone()))
+            .map_err(|err| invalid_data(format!("Failed to trust the client intermediate: {}", err)))?;
+    }
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(client_roots))
+        .with_single_cert(certificates.to_vec(), private_key.clone())
+        .map_err(|err| invalid_data(err.to_string()))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// How often the trusted client intermediates are checked for changes
+const CLIENT_CA_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The acceptor handshakes go through, swapped out whenever the client intermediates change
+type SharedAcceptor = Arc<RwLock<TlsAcceptor>>;
+
+/// Rebuilds the shared acceptor in the background, so the accept loop never waits on the database
+struct AcceptorRefresher {
+    certificates: Vec<Certificate>,
+    private_key: PrivateKey,
+    client_cas: Vec<Vec<u8>>,
+    acceptor: SharedAcceptor
+}
+
+impl AcceptorRefresher {
+    /// Rebuilds the acceptor if the client intermediates changed, keeping the current one on failure
+    async fn refresh(&mut self, connection: &DatabaseConnection) {
+        let client_cas = match load_client_cas(connection).await {
+            Ok(client_cas) if client_cas != self.client_cas => client_cas,
+            Ok(_) => return,
+            Err(err) => {
+                error!("Failed to reload the client intermediates: {}", err);
+                return;
+            }
+        };
+
+        match build_acceptor(&self.certificates, &self.private_key, &client_cas) {
+            Ok(acceptor) => {
+                info!("Reloaded the mutual TLS listener with {} client intermediates", client_cas.len());
+                *self.acceptor.write().await = acceptor;
+                self.client_cas = client_cas;
+            }
+            Err(err) => error!("Failed to reload the client intermediates: {}", err)
+        }
+    }
+
+    async fn run(mut self, connection: DatabaseConnection) {
+        let mut interval = tokio::time::interval(CLIENT_CA_REFRESH_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.refresh(&connection).await;
+        }
+    }
+}
+
+/// Serves the client RPC over TLS, only accepting clients with a certificate issued by a client
+/// intermediate
+///
+/// Clients are identified by their certificate rather than by a key and address. The trusted
+/// intermediates are reloaded in the background, so a CA rotation adding or retiring one is picked
+/// up without a restart.
+pub struct RpcTlsServer {
+    listener: TcpListener,
+    acceptor: SharedAcceptor,
+    refresher: AcceptorRefresher
+}
+
+impl RpcTlsServer {
+    pub async fn bind(addr: SocketAddr, cert_path: &Path, key_path: &Path, connection: &DatabaseConnection) -> io::Result<RpcTlsServer> {
+        let certificates = load_certificates(cert_path)?;
+        let private_key = load_private_key(key_path)?;
+        let client_cas = load_client_cas(connection)
+            .await
+            .map_err(|err| invalid_data(format!("Failed to load the client intermediates: {}", err)))?;
+
+        let acceptor = Arc::new(RwLock::new(build_acceptor(&certificates, &private_key, &client_cas)?));
+
+        Ok(RpcTlsServer {
+            listener: TcpListener::bind(addr).await?,
+            acceptor: acceptor.clone(),
+            refresher: AcceptorRefresher {
+                certificates,
+                private_key,
+                client_cas,
+                acceptor
+            }
+        })
+    }
+
+    pub async fn run(self, connection: DatabaseConnection) {
+        tokio::spawn(self.refresher.run(connection.clone()));
+
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    error!("Failed to accept RPC connection: {}", err);
+                    continue;
+                }
+            };
+
+            let acceptor = self.acceptor.read().await.clone();
+            let connection = connection.clone();
+
+            tokio::spawn(async move {
+                let tls_stream = match acceptor.accept(stream).await {
+                    Ok(tls_stream) => tls_stream,
+                    Err(err) => {
+                        debug!("TLS handshake with {} failed: {}", peer, err);
+                        return;
+                    }
+                };
+
+                // The verifier has already rejected connections without a certificate
+                let peer_certificate = match tls_stream.get_ref().1.peer_certificates() {
+                    Some([leaf, ..]) => PeerCertificate(leaf.0.clone()),
+                    _ => return
+                };
+
+                // Checked again on every call, but revoked clients shouldn't get a connection at all
+                if let Err(err) = authenticate_certificate(&peer_certificate, &connection).await {
+                    debug!("Refused RPC connection from {}: {}", peer, err.message);
+                    return;
+                }
+
+                let app = Router::new()
+                    .route("/rpc", post(rpc_mtls))
+                    .layer(Extension(connection))
+                    .layer(Extension(peer_certificate));
+
+                if let Err(err) = Http::new().http1_only(true).serve_connection(tls_stream, app).await {
+                    debug!("RPC connection from {} ended with an error: {}", peer, err);
+                }
+            });
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::borrow::Cow;
 use std::env;
 use std::fmt;
 use std::fmt::Formatter;
+use std::str::FromStr;
 
 use async_trait::async_trait;
 use axum::extract::FromRequestParts;
@@ -12,9 +13,10 @@ use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use user_agent_parser::{OS, Product};
 use user_agent_parser::UserAgentParser;
 
-use crate::entities::{session, user};
-use crate::entities::prelude::{Session, User};
+use crate::entities::{proxy, record, session, team, team_member, user, zone};
+use crate::entities::prelude::{Proxy, Record, Session, Team, TeamMember, User, Zone};
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum TeamPermissions {
     OWNER,
     ADMIN,
@@ -33,6 +35,59 @@ impl fmt::Display for TeamPermissions {
     }
 }
 
+impl FromStr for TeamPermissions {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OWNER" => Ok(TeamPermissions::OWNER),
+            "ADMIN" => Ok(TeamPermissions::ADMIN),
+            "EDITOR" => Ok(TeamPermissions::EDITOR),
+            "VIEWER" => Ok(TeamPermissions::VIEWER),
+            _ => Err(())
+        }
+    }
+}
+
+impl TeamPermissions {
+    fn rank(&self) -> u8 {
+        match self {
+            TeamPermissions::OWNER => 3,
+            TeamPermissions::ADMIN => 2,
+            TeamPermissions::EDITOR => 1,
+            TeamPermissions::VIEWER => 0
+        }
+    }
+
+    /// Checks if this permission grants at least as much as the required one
+    pub fn at_least(&self, required: TeamPermissions) -> bool {
+        self.rank() >= required.rank()
+    }
+}
+
+/// Gets a user's membership and permission within a team, if they are a member
+pub async fn get_team_permission(user_id: &str, team_id: &str, connection: &DatabaseConnection) -> Option<(team_member::Model, TeamPermissions)> {
+    let membership: Option<team_member::Model> = TeamMember::find()
+        .filter(team_member::Column::UserId.eq(user_id))
+        .filter(team_member::Column::TeamId.eq(team_id))
+        .one(connection)
+        .await
+        .expect("Failed to retrieve team membership from the database.");
+
+    match membership {
+        None => None,
+        Some(membership) => {
+            match TeamPermissions::from_str(&membership.permission) {
+                Ok(permission) => Some((membership, permission)),
+                Err(_) => {
+                    error!("team_member {} has an unknown permission {}!", membership.id, membership.permission);
+                    None
+                }
+            }
+        }
+    }
+}
+
 /// Gets a user model and session id from a supplied session token
 pub async fn get_user_from_token(token: String, connection: &DatabaseConnection) -> Option<(user::Model, String)> {
     let requested_session: Option<session::Model> = Session::find()
@@ -67,6 +122,96 @@ pub async fn get_user_from_token(token: String, connection: &DatabaseConnection)
     }
 }
 
+/// Ensures a user administers the controller itself
+pub fn authorise_admin(user: &user::Model) -> Result<(), (StatusCode, String)> {
+    match user.admin {
+        true => Ok(()),
+        false => Err((StatusCode::FORBIDDEN, "Only administrators can do this".to_string()))
+    }
+}
+
+/// Ensures a user holds at least the required permission within an active team
+pub async fn authorise_team(user_id: &str, team_id: &str, required: TeamPermissions, connection: &DatabaseConnection) -> Result<(team::Model, TeamPermissions), (StatusCode, String)> {
+    let (_, permission) = get_team_permission(user_id, team_id, connection)
+        .await
+        .ok_or((StatusCode::NOT_FOUND, "Requested team doesn't exist".to_string()))?;
+
+    let requested_team: Option<team::Model> = Team::find_by_id(team_id.to_string())
+        .one(connection)
+        .await
+        .expect("Failed to retrieve team from the database.");
+
+    let requested_team = match requested_team {
+        None => {
+            error!("team_member still exists for {} in {} but the team doesn't exist!", user_id, team_id);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "An internal server error has occurred".to_string()));
+        }
+        Some(requested_team) => requested_team
+    };
+
+    if !requested_team.active {
+        return Err((StatusCode::BAD_REQUEST, "Requested team is deactivated".to_string()));
+    }
+
+    if !permission.at_least(required) {
+        return Err((StatusCode::FORBIDDEN, "Insufficient team permissions".to_string()));
+    }
+
+    Ok((requested_team, permission))
+}
+
+/// Ensures a user holds at least the required permission within the team owning a zone
+pub async fn authorise_zone(user_id: &str, zone_id: &str, required: TeamPermissions, connection: &DatabaseConnection) -> Result<(zone::Model, TeamPermissions), (StatusCode, String)> {
+    let requested_zone: Option<zone::Model> = Zone::find_by_id(zone_id.to_string())
+        .one(connection)
+        .await
+        .expect("Failed to retrieve zone from the database.");
+
+    let requested_zone = requested_zone
+        .ok_or((StatusCode::NOT_FOUND, "Requested zone doesn't exist".to_string()))?;
+
+    match authorise_team(user_id, &requested_zone.owner, required, connection).await {
+        Ok((_, permission)) => Ok((requested_zone, permission)),
+        // Don't leak the existence of zones to non-members
+        Err((StatusCode::NOT_FOUND, _)) => Err((StatusCode::NOT_FOUND, "Requested zone doesn't exist".to_string())),
+        Err(err) => Err(err)
+    }
+}
+
+/// Ensures a user holds at least the required permission within the team owning a record's zone
+pub async fn authorise_record(user_id: &str, record_id: &str, required: TeamPermissions, connection: &DatabaseConnection) -> Result<(record::Model, zone::Model), (StatusCode, String)> {
+    let requested_record: Option<record::Model> = Record::find_by_id(record_id.to_string())
+        .one(connection)
+        .await
+        .expect("Failed to retrieve record from the database.");
+
+    let requested_record = requested_record
+        .ok_or((StatusCode::NOT_FOUND, "Requested record doesn't exist".to_string()))?;
+
+    match authorise_zone(user_id, &requested_record.zone, required, connection).await {
+        Ok((requested_zone, _)) => Ok((requested_record, requested_zone)),
+        Err((StatusCode::NOT_FOUND, _)) => Err((StatusCode::NOT_FOUND, "Requested record doesn't exist".to_string())),
+        Err(err) => Err(err)
+    }
+}
+
+/// Ensures a user holds at least the required permission within the team owning a proxy's zone
+pub async fn authorise_proxy(user_id: &str, proxy_id: &str, required: TeamPermissions, connection: &DatabaseConnection) -> Result<(proxy::Model, record::Model, zone::Model), (StatusCode, String)> {
+    let requested_proxy: Option<proxy::Model> = Proxy::find_by_id(proxy_id.to_string())
+        .one(connection)
+        .await
+        .expect("Failed to retrieve proxy from the database.");
+
+    let requested_proxy = requested_proxy
+        .ok_or((StatusCode::NOT_FOUND, "Requested proxy doesn't exist".to_string()))?;
+
+    match authorise_record(user_id, &requested_proxy.record, required, connection).await {
+        Ok((requested_record, requested_zone)) => Ok((requested_proxy, requested_record, requested_zone)),
+        Err((StatusCode::NOT_FOUND, _)) => Err((StatusCode::NOT_FOUND, "Requested proxy doesn't exist".to_string())),
+        Err(err) => Err(err)
+    }
+}
+
 #[derive(Clone)]
 pub struct UserFromBearer(pub (user::Model, String));
 
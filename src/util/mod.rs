@@ -22,4 +22,66 @@ pub fn hash_password(password: String) -> String {
     argon2.hash_password(password.as_bytes(), &salt)
         .expect("Failed to hash password!")
         .to_string()
-}
\ No newline at end of file
+}
+
+/// Encodes bytes as lowercase hex
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hex in either case, returning None if it is malformed
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None
+        })
+        .collect()
+}
+
+const BASE32HEX_ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Encodes bytes as unpadded lowercase base32hex (RFC 4648 section 7), as used by NSEC3
+pub fn encode_base32hex(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+
+    for chunk in bytes.chunks(5) {
+        let mut block = [0u8; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let value = block.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64);
+
+        // Each byte contributes 8 bits, rounded up to whole 5 bit characters
+        let characters = [0, 2, 4, 5, 7, 8][chunk.len()];
+        for i in 0..characters {
+            encoded.push(BASE32HEX_ALPHABET[((value >> (35 - i * 5)) & 0x1F) as usize] as char);
+        }
+    }
+
+    encoded
+}
+
+/// Decodes unpadded base32hex in either case, returning None if it is malformed
+pub fn decode_base32hex(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = vec![];
+
+    for chunk in encoded.as_bytes().chunks(8) {
+        let mut value = 0u64;
+        for (i, character) in chunk.iter().enumerate() {
+            let digit = BASE32HEX_ALPHABET.iter().position(|c| *c == character.to_ascii_lowercase())?;
+            value |= (digit as u64) << (35 - i * 5);
+        }
+
+        let bytes = match chunk.len() {
+            8 => 5,
+            7 => 4,
+            5 => 3,
+            4 => 2,
+            2 => 1,
+            _ => return None
+        };
+        decoded.extend_from_slice(&value.to_be_bytes()[3..3 + bytes]);
+    }
+
+    Some(decoded)
+}